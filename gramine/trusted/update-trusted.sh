@@ -1,6 +1,54 @@
 mkdir -p ./etc/ssl/certs/
 cp -f /etc/ssl/certs/ca-certificates.crt ./etc/ssl/certs/
 
+mkdir -p ./etc/sgx/
+
+# Intel SGX Root CA, every quote chained to it is trusted : the download must match the pinned hash
+INTEL_ROOT_CA_SHA256=${INTEL_ROOT_CA_SHA256:?"pin the SHA-256 of the Intel SGX Root CA certificate, verified out of band"}
+curl -sSf -o ./etc/sgx/Intel_SGX_Provisioning_Certification_RootCA.cer https://certificates.trustedservices.intel.com/Intel_SGX_Provisioning_Certification_RootCA.cer || exit 1
+if [ "$(sha256sum ./etc/sgx/Intel_SGX_Provisioning_Certification_RootCA.cer | cut -d ' ' -f 1)" != "$INTEL_ROOT_CA_SHA256" ]; then
+  echo "Intel SGX Root CA does not match the pinned hash" >&2
+  rm -f ./etc/sgx/Intel_SGX_Provisioning_Certification_RootCA.cer
+  exit 1
+fi
+
+# DCAP collateral : CRLs of the Root and PCK CAs, TCB info of the platforms listed in DCAP_FMSPCS
+PCS_URL="https://api.trustedservices.intel.com/sgx/certification/v4"
+ROOT_CRL=$(curl -sSf https://certificates.trustedservices.intel.com/IntelSGXRootCA.der | openssl crl -inform DER -outform PEM) || exit 1
+PROCESSOR_CRL=$(curl -sSf "$PCS_URL/pckcrl?ca=processor&encoding=pem") || exit 1
+PLATFORM_CRL=$(curl -sSf "$PCS_URL/pckcrl?ca=platform&encoding=pem") || exit 1
+
+TCB_INFO="[]"
+for FMSPC in ${DCAP_FMSPCS:?"list the FMSPCs of the enclave platforms"}; do
+  TCB_INFO=$(curl -sSf "$PCS_URL/tcb?fmspc=$FMSPC" | jq --argjson list "$TCB_INFO" '$list + [.tcbInfo]') || exit 1
+done
+
+jq -n --arg root "$ROOT_CRL" --arg processor "$PROCESSOR_CRL" --arg platform "$PLATFORM_CRL" --argjson tcb "$TCB_INFO" \
+  '{pck_crls: [$root, $processor, $platform], tcb_info: $tcb}' > ./etc/sgx/dcap_collateral.json || exit 1
+
+# GRANDPA checkpoint : finalized block, authority set and set id, finality is only verified from it
+CHAIN=${CHAIN:-${ENCLAVE_CHAIN:-alphanet}}
+case "$CHAIN" in
+  mainnet) CHECKPOINT_RPC="https://mainnet.ternoa.network:443" ;;
+  alphanet) CHECKPOINT_RPC="https://alphanet.ternoa.com:443" ;;
+  dev0) CHECKPOINT_RPC="https://dev-0.ternoa.com:443" ;;
+esac
+CHECKPOINT_RPC=${GRANDPA_CHECKPOINT_RPC:-$CHECKPOINT_RPC}
+
+rpc() {
+  curl -sSf -H "Content-Type: application/json" -d "{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"$1\",\"params\":$2}" "$CHECKPOINT_RPC" | jq -er '.result'
+}
+
+mkdir -p ./etc/grandpa/
+CHECKPOINT_HASH=$(rpc chain_getFinalizedHead '[]') || exit 1
+CHECKPOINT_NUMBER=$(printf '%d' "$(rpc chain_getHeader "[\"$CHECKPOINT_HASH\"]" | jq -r '.number')") || exit 1
+AUTHORITIES=$(rpc state_call "[\"GrandpaApi_grandpa_authorities\", \"0x\", \"$CHECKPOINT_HASH\"]") || exit 1
+SET_ID_LE=$(rpc state_call "[\"GrandpaApi_current_set_id\", \"0x\", \"$CHECKPOINT_HASH\"]") || exit 1
+SET_ID=$(printf '%d' "0x$(echo "${SET_ID_LE#0x}" | fold -w2 | tac | tr -d '\n')") || exit 1
+
+jq -n --arg hash "$CHECKPOINT_HASH" --argjson number "$CHECKPOINT_NUMBER" --argjson set_id "$SET_ID" --arg authorities "$AUTHORITIES" \
+  '{block_number: $number, block_hash: $hash, set_id: $set_id, authorities: $authorities}' > ./etc/grandpa/checkpoint.json || exit 1
+
 mkdir -p ./arch_libdir/
 cp -f /lib/x86_64-linux-gnu/libcrypto.so.3 ./arch_libdir/
 cp -f /lib/x86_64-linux-gnu/libgcc_s.so.1 ./arch_libdir/
@@ -27,6 +27,9 @@ use tracing::{debug, error, info, warn};
 
 use serde::{Deserialize, Serialize};
 
+mod stream_cipher;
+use stream_cipher::decrypt_backup;
+
 #[cfg_attr(
 	feature = "mainnet",
 	subxt::subxt(runtime_metadata_path = "../artifacts/ternoa_mainnet.scale")
@@ -108,14 +111,17 @@ pub async fn get_current_block_number() -> Result<u32, Error> {
 pub struct FetchAuthenticationToken {
 	pub block_number: u32,
 	pub block_validation: u32,
+	pub data_hash: String,
+	pub since_block: Option<u32>,
 }
 
 /// Fetch Bulk Data
 #[derive(Serialize, Deserialize)]
 pub struct FetchBulkPacket {
-	admin_account: String,
+	admin_address: String,
 	auth_token: String, //FetchAuthenticationToken,
 	signature: String,
+	encryption_keys: Vec<String>,
 }
 
 /// Fetch Bulk Response
@@ -171,6 +177,73 @@ pub struct IdPacket {
 	id_vec: String,
 	auth_token: String,
 	signature: String,
+	proposal_id: String,
+}
+
+/* *************************************
+		ADMIN QUORUM DATA STRUCTURES
+**************************************** */
+
+// Destructive operations approved by the admin quorum
+const ADMIN_OPERATIONS: [&str; 5] =
+	["push_bulk", "push_id", "enclave_key", "maintenance", "quorum_policy"];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProposalRequest {
+	pub operation: String,
+	pub data_hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProposalPacket {
+	admin_address: String,
+	proposal: String,
+	auth_token: String,
+	signature: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApprovalPacket {
+	admin_address: String,
+	proposal_id: String,
+	signature: String,
+}
+
+/* *************************************
+		ENCLAVE UPGRADE DATA STRUCTURES
+**************************************** */
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UpgradeRequest {
+	pub step: String,
+	pub enclave_account: String,
+	pub admin_account: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpgradeAuthorization {
+	operator_address: String,
+	request: String,
+	auth_token: String,
+	signature: String,
+}
+
+/* *************************************
+		ADMIN AUDIT DATA STRUCTURES
+**************************************** */
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditLogRequest {
+	pub from: u64,
+	pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdminReadPacket {
+	admin_address: String,
+	request: String,
+	auth_token: String,
+	signature: String,
 }
 
 /* *************************************
@@ -202,6 +275,12 @@ pub struct ReconPacket {
 struct Args {
 	/// Request type : [retrieve, store] for secrets
 	/// Request type : [fetch-bulk, push-bulk, fetch-id, push-id] for backup
+	/// Request type : [generate-key, decrypt-bulk] for encrypted bulk backups
+	/// Request type : [propose, approve] for the admin quorum
+	/// Request type : [upgrade-escrow, upgrade-install, upgrade-rollback] for the enclave upgrade
+	/// Request type : [audit-log] for the admin audit trail
+	/// Request type : [peer-stats] for the sync statistics of the peers
+	/// Request type : [sync-state] for the pending and failed NFTs of the synchronization
 	/// Request type : [reconcilliation] for metrics
 	#[arg(short, long, default_value_t = String::new())]
 	request: String,
@@ -247,6 +326,49 @@ struct Args {
 	/// Custom Data, right format is "NFTID_SecretShare_CurrentBlockNumber_Expire"
 	#[arg(short, long, default_value_t = String::new())]
 	custom_data: String,
+
+	/// Comma separated hex encryption keys of the backup custodians,
+	/// public-keys for fetch-bulk and private-keys for decrypt-bulk
+	#[arg(short, long, default_value_t = String::new())]
+	keys: String,
+
+	/// Path of the decrypted backup
+	#[arg(short, long, default_value_t = String::new())]
+	output: String,
+
+	/// Incremental bulk backup of the keyshares changed after this block (Optional)
+	#[arg(long, default_value_t = 0)]
+	since_block: u32,
+
+	/// Proposal approved by the admin quorum, for push-bulk, push-id and approve
+	#[arg(long, default_value_t = String::new())]
+	proposal_id: String,
+
+	/// Proposed operation : [push_bulk, push_id, enclave_key, maintenance, quorum_policy]
+	#[arg(long, default_value_t = String::new())]
+	operation: String,
+
+	/// Payload of a proposal without file or id vector, i.e a quorum policy
+	#[arg(long, default_value_t = String::new())]
+	payload: String,
+
+	/// Registered account of the upgraded enclave, for upgrade-escrow, upgrade-install and
+	/// upgrade-rollback
+	#[arg(long, default_value_t = String::new())]
+	enclave_account: String,
+
+	/// Admin enclave keeping the escrowed account, for upgrade-escrow, upgrade-install and
+	/// upgrade-rollback
+	#[arg(long, default_value_t = String::new())]
+	admin_account: String,
+
+	/// Index of the first audit entry, for audit-log
+	#[arg(long, default_value_t = 0)]
+	audit_from: u64,
+
+	/// Number of audit entries, for audit-log
+	#[arg(long, default_value_t = 100)]
+	audit_count: usize,
 }
 
 /* *************************************
@@ -256,11 +378,30 @@ struct Args {
 async fn main() {
 	let args = Args::parse();
 
+	match args.request.to_lowercase().as_str() {
+		"generate-key" => return generate_encryption_key(),
+		"decrypt-bulk" => return decrypt_bulk(args.file, args.keys, args.output),
+		_ => {},
+	}
+
 	if args.seed.is_empty() {
 		println!("\n Seed-phrase can not be empty! \n");
 		return;
 	}
 
+	match args.request.to_lowercase().as_str() {
+		"propose" => return generate_proposal(args).await,
+		"approve" => return generate_approval(args.seed, args.proposal_id),
+		"upgrade-escrow" => return generate_upgrade(args, "escrow").await,
+		"upgrade-install" => return generate_upgrade(args, "release").await,
+		"upgrade-rollback" => return generate_upgrade(args, "rollback").await,
+		"audit-log" =>
+			return generate_audit_log(args.seed, args.audit_from, args.audit_count).await,
+		"peer-stats" => return generate_admin_read(args.seed, "peer-stats".to_string()).await,
+		"sync-state" => return generate_admin_read(args.seed, "sync-state".to_string()).await,
+		_ => {},
+	}
+
 	if args.nftid > 0 || !args.custom_data.is_empty() {
 		match args.request.to_lowercase().as_str() {
 			"retrieve" => generate_retrieve_request(args.clone()).await,
@@ -268,16 +409,18 @@ async fn main() {
 			_ => println!("\n Please provide a valid request type \n"),
 		}
 		return;
+	} else if args.request.to_lowercase() == "fetch-bulk" {
+		generate_fetch_bulk(args.seed.clone(), args.keys, args.since_block).await;
+		return;
 	} else if std::path::Path::new(&args.file).exists() {
 		match args.request.to_lowercase().as_str() {
-			"push-bulk" => generate_push_bulk(args.seed.clone(), args.file).await,
-			"fetch-bulk" => generate_fetch_bulk(args.seed.clone()).await,
+			"push-bulk" => generate_push_bulk(args.seed.clone(), args.file, args.proposal_id).await,
 			_ => println!("\n Please provide a valid request type \n"),
 		}
 		return;
 	} else if !args.id_vec.is_empty() {
 		match args.request.to_lowercase().as_str() {
-			"push-id" => generate_push_id(args.seed.clone(), args.id_vec).await,
+			"push-id" => generate_push_id(args.seed.clone(), args.id_vec, args.proposal_id).await,
 			"fetch-id" => generate_fetch_id(args.seed.clone(), args.id_vec).await,
 			_ => println!("\n Please provide a valid request type \n"),
 		}
@@ -305,21 +448,32 @@ async fn main() {
 	 ADMIN FETCH BULK
 *************************/
 
-async fn generate_fetch_bulk(seed_phrase: String) {
+async fn generate_fetch_bulk(seed_phrase: String, keys: String, since_block: u32) {
+	let encryption_keys = split_keys(&keys);
+	if encryption_keys.is_empty() {
+		println!("\n At least one encryption public-key is required, see generate-key \n");
+		return;
+	}
+
 	let admin = sr25519::Pair::from_phrase(&seed_phrase, None).unwrap().0;
 
 	let current_block_number = get_current_block_number().await.unwrap();
 
-	let admin_account = admin.public().to_ss58check();
-	let auth =
-		FetchAuthenticationToken { block_number: current_block_number, block_validation: 10 };
+	let admin_address = admin.public().to_ss58check();
+	let auth = FetchAuthenticationToken {
+		block_number: current_block_number,
+		block_validation: 10,
+		data_hash: sha256::digest(encryption_keys.join(",").as_bytes()),
+		since_block: if since_block > 0 { Some(since_block) } else { None },
+	};
 	let auth_str = serde_json::to_string(&auth).unwrap();
 	let signature = admin.sign(auth_str.as_bytes());
 
 	let packet = FetchBulkPacket {
-		admin_account,
+		admin_address,
 		auth_token: auth_str,
 		signature: format!("{}{:?}", "0x", signature),
+		encryption_keys,
 	};
 
 	println!(
@@ -328,10 +482,75 @@ async fn generate_fetch_bulk(seed_phrase: String) {
 	);
 }
 
+/* ************************
+	 BACKUP ENCRYPTION KEYS
+*************************/
+
+fn split_keys(keys: &str) -> Vec<String> {
+	keys.split(',')
+		.map(|key| key.trim().to_string())
+		.filter(|key| !key.is_empty())
+		.collect()
+}
+
+fn generate_encryption_key() {
+	let (private_key, public_key) = ecies::utils::generate_keypair();
+
+	println!(
+		"================================== Backup Custodian Key = \n Public-Key:\t {} \n Private-Key:\t {} \n",
+		hex::encode(public_key.serialize()),
+		hex::encode(private_key.serialize())
+	);
+}
+
+fn decrypt_bulk(file_path: String, keys: String, output: String) {
+	if file_path.is_empty() || output.is_empty() {
+		println!("\n Encrypted backup file and output path are required \n");
+		return;
+	}
+
+	let mut private_keys = Vec::new();
+	for key in split_keys(&keys) {
+		match hex::decode(key.strip_prefix("0x").unwrap_or(&key)) {
+			Ok(private_key) => private_keys.push(private_key),
+			Err(err) => {
+				println!("\n Invalid private-key {key} : {err:?} \n");
+				return;
+			},
+		}
+	}
+
+	if private_keys.is_empty() {
+		println!("\n Custodian private-keys are required \n");
+		return;
+	}
+
+	let data = match std::fs::read(&file_path) {
+		Ok(data) => data,
+		Err(err) => {
+			println!("\n Can not read the encrypted backup {file_path} : {err:?} \n");
+			return;
+		},
+	};
+
+	let plaintext = match decrypt_backup(&data, &private_keys) {
+		Ok(plaintext) => plaintext,
+		Err(err) => {
+			println!("\n Can not decrypt the backup : {err} \n");
+			return;
+		},
+	};
+
+	match std::fs::write(&output, plaintext) {
+		Ok(_) => println!("\n Backup is decrypted to {output} \n"),
+		Err(err) => println!("\n Can not write the decrypted backup {output} : {err:?} \n"),
+	}
+}
+
 /* ************************
 	 ADMIN PUSH BULK
 *************************/
-async fn generate_push_bulk(seed_phrase: String, file_path: String) {
+async fn generate_push_bulk(seed_phrase: String, file_path: String, proposal_id: String) {
 	let admin = sr25519::Pair::from_phrase(&seed_phrase, None).unwrap().0;
 
 	let current_block_number = get_current_block_number().await.unwrap();
@@ -355,10 +574,11 @@ async fn generate_push_bulk(seed_phrase: String, file_path: String) {
 	let sig_str = format!("{}{:?}", "0x", sig);
 
 	println!(
-		"================================== Push Bulk Packet = \n Admin:\t\t {} \n Auth_Token:\t {} \n Signature:\t {} \n ",
+		"================================== Push Bulk Packet = \n Admin:\t\t {} \n Auth_Token:\t {} \n Signature:\t {} \n Proposal_Id:\t {} \n ",
 		admin.public(),
 		auth_str,
-		sig_str
+		sig_str,
+		proposal_id
 	);
 }
 
@@ -382,7 +602,13 @@ async fn generate_fetch_id(seed_phrase: String, id_vec: String) {
 	let sig = admin.sign(auth_str.as_bytes());
 	let signature = format!("0x{:?}", sig);
 
-	let packet = IdPacket { admin_account, id_vec, auth_token: auth_str, signature };
+	let packet = IdPacket {
+		admin_account,
+		id_vec,
+		auth_token: auth_str,
+		signature,
+		proposal_id: String::new(),
+	};
 
 	println!(
 		"================================== Backup Fetch ID Packet = \n{}\n",
@@ -393,7 +619,7 @@ async fn generate_fetch_id(seed_phrase: String, id_vec: String) {
 /* ************************
 	 ADMIN PUSH ID
 *************************/
-async fn generate_push_id(seed_phrase: String, id_vec: String) {
+async fn generate_push_id(seed_phrase: String, id_vec: String, proposal_id: String) {
 	let admin = sr25519::Pair::from_phrase(&seed_phrase, None).unwrap().0;
 
 	let block_number = get_current_block_number().await.unwrap();
@@ -408,7 +634,7 @@ async fn generate_push_id(seed_phrase: String, id_vec: String) {
 	let sig = admin.sign(auth_str.as_bytes());
 	let signature = format!("0x{:?}", sig);
 
-	let packet = IdPacket { admin_account, id_vec, auth_token: auth_str, signature };
+	let packet = IdPacket { admin_account, id_vec, auth_token: auth_str, signature, proposal_id };
 
 	println!(
 		"================================== Backup Push ID Packet = \n{}\n",
@@ -416,6 +642,158 @@ async fn generate_push_id(seed_phrase: String, id_vec: String) {
 	);
 }
 
+/* ************************
+	 ADMIN QUORUM
+*************************/
+
+async fn generate_proposal(args: Args) {
+	let operation = args.operation.to_lowercase();
+	if !ADMIN_OPERATIONS.contains(&operation.as_str()) {
+		println!("\n Please provide a valid operation : {ADMIN_OPERATIONS:?} \n");
+		return;
+	}
+
+	// The proposal binds the payload of the operation
+	let data_hash = if !args.file.is_empty() {
+		match std::fs::read(&args.file) {
+			Ok(data) => sha256::digest(data.as_slice()),
+			Err(err) => {
+				println!("\n Can not read {} : {err:?} \n", args.file);
+				return;
+			},
+		}
+	} else if !args.id_vec.is_empty() {
+		sha256::digest(args.id_vec.as_bytes())
+	} else if !args.payload.is_empty() {
+		sha256::digest(args.payload.as_bytes())
+	} else {
+		println!("\n Please provide the file, id vector or payload of the operation \n");
+		return;
+	};
+
+	let admin = sr25519::Pair::from_phrase(&args.seed, None).unwrap().0;
+
+	let block_number = get_current_block_number().await.unwrap();
+
+	let proposal = serde_json::to_string(&ProposalRequest { operation, data_hash }).unwrap();
+
+	let auth = IdAuthenticationToken {
+		block_number,
+		block_validation: 10,
+		data_hash: sha256::digest(proposal.as_bytes()),
+	};
+	let auth_str = serde_json::to_string(&auth).unwrap();
+	let signature = format!("0x{:?}", admin.sign(auth_str.as_bytes()));
+
+	let packet = ProposalPacket {
+		admin_address: admin.public().to_ss58check(),
+		proposal,
+		auth_token: auth_str,
+		signature,
+	};
+
+	println!(
+		"================================== Admin Proposal Packet = \n{}\n",
+		serde_json::to_string_pretty(&packet).unwrap()
+	);
+}
+
+fn generate_approval(seed_phrase: String, proposal_id: String) {
+	if proposal_id.is_empty() {
+		println!("\n Please provide the proposal id created by the enclave \n");
+		return;
+	}
+
+	let admin = sr25519::Pair::from_phrase(&seed_phrase, None).unwrap().0;
+	let signature = format!("0x{:?}", admin.sign(proposal_id.as_bytes()));
+
+	let packet =
+		ApprovalPacket { admin_address: admin.public().to_ss58check(), proposal_id, signature };
+
+	println!(
+		"================================== Admin Approval Packet = \n{}\n",
+		serde_json::to_string_pretty(&packet).unwrap()
+	);
+}
+
+/* ************************
+	 ENCLAVE UPGRADE
+*************************/
+
+async fn generate_upgrade(args: Args, step: &str) {
+	if args.enclave_account.is_empty() || args.admin_account.is_empty() {
+		println!("\n Please provide the enclave account and the admin account \n");
+		return;
+	}
+
+	let operator = sr25519::Pair::from_phrase(&args.seed, None).unwrap().0;
+
+	let block_number = get_current_block_number().await.unwrap();
+
+	let request = serde_json::to_string(&UpgradeRequest {
+		step: step.to_string(),
+		enclave_account: args.enclave_account,
+		admin_account: args.admin_account,
+	})
+	.unwrap();
+
+	let auth = IdAuthenticationToken {
+		block_number,
+		block_validation: 10,
+		data_hash: sha256::digest(request.as_bytes()),
+	};
+	let auth_str = serde_json::to_string(&auth).unwrap();
+	let signature = format!("0x{:?}", operator.sign(auth_str.as_bytes()));
+
+	let packet = UpgradeAuthorization {
+		operator_address: operator.public().to_ss58check(),
+		request,
+		auth_token: auth_str,
+		signature,
+	};
+
+	println!(
+		"================================== Operator Upgrade Packet = \n{}\n",
+		serde_json::to_string_pretty(&packet).unwrap()
+	);
+}
+
+/* ************************
+	 ADMIN AUDIT TRAIL
+*************************/
+
+async fn generate_audit_log(seed_phrase: String, from: u64, count: usize) {
+	let request = serde_json::to_string(&AuditLogRequest { from, count }).unwrap();
+	generate_admin_read(seed_phrase, request).await
+}
+
+// Signed read request of an admin, the request is the endpoint name or its serialized range
+async fn generate_admin_read(seed_phrase: String, request: String) {
+	let admin = sr25519::Pair::from_phrase(&seed_phrase, None).unwrap().0;
+
+	let block_number = get_current_block_number().await.unwrap();
+
+	let auth = IdAuthenticationToken {
+		block_number,
+		block_validation: 10,
+		data_hash: sha256::digest(request.as_bytes()),
+	};
+	let auth_str = serde_json::to_string(&auth).unwrap();
+	let signature = format!("0x{:?}", admin.sign(auth_str.as_bytes()));
+
+	let packet = AdminReadPacket {
+		admin_address: admin.public().to_ss58check(),
+		request,
+		auth_token: auth_str,
+		signature,
+	};
+
+	println!(
+		"================================== Admin Read Packet = \n{}\n",
+		serde_json::to_string_pretty(&packet).unwrap()
+	);
+}
+
 /* ************************
   METRIC RECONCILLIATION
 *************************/
@@ -0,0 +1,139 @@
+use aes_gcm::{
+	aead::{generic_array::GenericArray, Aead},
+	Aes256Gcm, KeyInit,
+};
+use anyhow::{anyhow, Result};
+
+/* ---------------------------------------
+	ENCLAVE BACKUP STREAM DECRYPTION
+------------------------------------------ */
+
+// Same format as the enclave stream cipher :
+// HEADER : MAGIC(4) | VERSION(1) | WRAPPED_KEY_LENGTH(2) | ECIES(CONTENT_KEY)(n) | NONCE_PREFIX(7)
+// SPLIT  : SPLIT_MAGIC(4) | VERSION(1) | SHARE_COUNT(1) |
+//          [WRAPPED_KEY_LENGTH(2) | ECIES(KEY_SHARE)(n)]... | NONCE_PREFIX(7)
+// FRAME  : LAST_FLAG(1) | CIPHERTEXT_LENGTH(4) | AES-256-GCM(CHUNK)
+// The content key of a split header is the XOR of all key shares.
+
+const MAGIC: &[u8; 4] = b"TSE1";
+const SPLIT_MAGIC: &[u8; 4] = b"TSE2";
+const STREAM_VERSION: u8 = 1;
+const CONTENT_KEY_LENGTH: usize = 32;
+const MAX_KEY_SHARES: usize = 16;
+const NONCE_PREFIX_LENGTH: usize = 7;
+const FRAME_HEADER_LENGTH: usize = 5;
+const TAG_LENGTH: usize = 16;
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+fn frame_nonce(nonce_prefix: &[u8], counter: u32, last: bool) -> [u8; 12] {
+	let mut nonce = [0u8; 12];
+	nonce[..NONCE_PREFIX_LENGTH].copy_from_slice(nonce_prefix);
+	nonce[NONCE_PREFIX_LENGTH..11].copy_from_slice(&counter.to_be_bytes());
+	nonce[11] = last as u8;
+	nonce
+}
+
+fn read_bytes<'a>(data: &'a [u8], offset: &mut usize, length: usize) -> Result<&'a [u8]> {
+	if data.len() < *offset + length {
+		return Err(anyhow!("backup is truncated at byte {}", data.len()));
+	}
+
+	let bytes = &data[*offset..*offset + length];
+	*offset += length;
+	Ok(bytes)
+}
+
+/// Decrypt an encrypted enclave backup
+/// # Arguments
+/// * `data` - The encrypted backup
+/// * `private_keys` - Serialized secp256k1 private-keys of the custodians, in any order
+/// # Returns
+/// * `Vec<u8>` - The plain backup archive
+pub fn decrypt_backup(data: &[u8], private_keys: &[Vec<u8>]) -> Result<Vec<u8>> {
+	let mut offset = 0;
+
+	let magic = read_bytes(data, &mut offset, MAGIC.len())?;
+	if magic != MAGIC && magic != SPLIT_MAGIC {
+		return Err(anyhow!("not an encrypted enclave backup, invalid magic"));
+	}
+
+	let version = read_bytes(data, &mut offset, 1)?[0];
+	if version != STREAM_VERSION {
+		return Err(anyhow!(
+			"unsupported backup version {version}, supported version is {STREAM_VERSION}"
+		));
+	}
+
+	let share_count = if magic == MAGIC {
+		1
+	} else {
+		let count = read_bytes(data, &mut offset, 1)?[0] as usize;
+		if !(2..=MAX_KEY_SHARES).contains(&count) {
+			return Err(anyhow!("invalid number of key shares {count}"));
+		}
+		count
+	};
+
+	let mut content_key = [0u8; CONTENT_KEY_LENGTH];
+	for index in 0..share_count {
+		let length_bytes = read_bytes(data, &mut offset, 2)?;
+		let wrapped_length = u16::from_be_bytes([length_bytes[0], length_bytes[1]]) as usize;
+		let wrapped_key = read_bytes(data, &mut offset, wrapped_length)?;
+
+		let share = private_keys
+			.iter()
+			.find_map(|private_key| ecies::decrypt(private_key, wrapped_key).ok())
+			.ok_or_else(|| anyhow!("no provided private-key can unwrap the key share {index}"))?;
+
+		if share.len() != CONTENT_KEY_LENGTH {
+			return Err(anyhow!("invalid content key length {}", share.len()));
+		}
+
+		content_key.iter_mut().zip(share.iter()).for_each(|(k, s)| *k ^= s);
+	}
+
+	let nonce_prefix = read_bytes(data, &mut offset, NONCE_PREFIX_LENGTH)?;
+	let cipher = Aes256Gcm::new_from_slice(&content_key)
+		.map_err(|err| anyhow!("invalid content key : {err:?}"))?;
+
+	let mut plaintext = Vec::new();
+	let mut counter: u32 = 0;
+	loop {
+		let frame_header = read_bytes(data, &mut offset, FRAME_HEADER_LENGTH)?;
+		let last = match frame_header[0] {
+			0 => false,
+			1 => true,
+			flag => return Err(anyhow!("invalid frame flag {flag}")),
+		};
+
+		let length = u32::from_be_bytes([
+			frame_header[1],
+			frame_header[2],
+			frame_header[3],
+			frame_header[4],
+		]) as usize;
+
+		if length > STREAM_CHUNK_SIZE + TAG_LENGTH {
+			return Err(anyhow!("frame length {length} is larger than a chunk"));
+		}
+
+		let ciphertext = read_bytes(data, &mut offset, length)?;
+		let nonce = frame_nonce(nonce_prefix, counter, last);
+		let chunk = cipher
+			.decrypt(GenericArray::from_slice(&nonce), ciphertext)
+			.map_err(|_| anyhow!("authentication failed for frame {counter}"))?;
+
+		plaintext.extend_from_slice(&chunk);
+		counter = counter.checked_add(1).ok_or_else(|| anyhow!("too many frames"))?;
+
+		if last {
+			break;
+		}
+	}
+
+	if offset != data.len() {
+		return Err(anyhow!("data found after the last frame"));
+	}
+
+	Ok(plaintext)
+}
@@ -0,0 +1,738 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use ring::{
+	digest,
+	signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1, ECDSA_P256_SHA256_FIXED},
+};
+use serde::Deserialize;
+use tracing::{debug, trace, warn};
+use x509_parser::{
+	certificate::X509Certificate,
+	der_parser::{ber::BerObject, parse_der},
+	parse_x509_certificate, parse_x509_crl,
+	pem::Pem,
+	revocation_list::CertificateRevocationList,
+};
+
+use crate::constants::{DCAP_COLLATERAL_FILE, INTEL_ROOT_CA_FILE};
+
+/* ---------------------------------------
+	SGX DCAP QUOTE VERIFICATION
+------------------------------------------ */
+
+// Quote v3 layout, Intel SGX ECDSA Quote Library Reference
+const QUOTE_VERSION: u16 = 3;
+const QUOTE_HEADER_LENGTH: usize = 48;
+const REPORT_BODY_LENGTH: usize = 384;
+const SIGNATURE_LENGTH: usize = 64;
+const ATTESTATION_KEY_LENGTH: usize = 64;
+const ECDSA_P256_KEY_TYPE: u16 = 2;
+const PCK_CERT_CHAIN_TYPE: u16 = 5;
+
+// Report body offsets
+const REPORT_ATTRIBUTES: usize = 48;
+const REPORT_MRENCLAVE: usize = 64;
+const REPORT_MRSIGNER: usize = 128;
+const REPORT_ISV_PROD_ID: usize = 256;
+const REPORT_ISV_SVN: usize = 258;
+const REPORT_DATA: usize = 320;
+
+// Attributes flag of enclaves launched in debug mode
+const DEBUG_FLAG: u8 = 0x02;
+
+// Intel Quoting Enclave identity
+const INTEL_QE_MRSIGNER: &str = "8c4f5775d796503e96137f77c68a829a0056ac8ded70140b081b094490c57bff";
+const INTEL_QE_ISV_PROD_ID: u16 = 1;
+
+// SGX extension of the PCK certificate, Intel SGX PCK Certificate and CRL Profile
+const SGX_EXTENSION_OID: &str = "1.2.840.113741.1.13.1";
+const SGX_TCB_OID: &str = "1.2.840.113741.1.13.1.2";
+const SGX_FMSPC_OID: &str = "1.2.840.113741.1.13.1.4";
+const SGX_TCB_COMPONENTS: usize = 16;
+
+/// Enclave report body, verified by the quote signatures
+#[derive(Debug, Clone)]
+pub struct ReportBody {
+	pub debug: bool,
+	pub mr_enclave: [u8; 32],
+	pub mr_signer: [u8; 32],
+	pub isv_prod_id: u16,
+	pub isv_svn: u16,
+	pub report_data: [u8; 64],
+}
+
+impl ReportBody {
+	fn parse(body: &[u8]) -> ReportBody {
+		let mut mr_enclave = [0u8; 32];
+		mr_enclave.copy_from_slice(&body[REPORT_MRENCLAVE..REPORT_MRENCLAVE + 32]);
+
+		let mut mr_signer = [0u8; 32];
+		mr_signer.copy_from_slice(&body[REPORT_MRSIGNER..REPORT_MRSIGNER + 32]);
+
+		let mut report_data = [0u8; 64];
+		report_data.copy_from_slice(&body[REPORT_DATA..REPORT_DATA + 64]);
+
+		ReportBody {
+			debug: body[REPORT_ATTRIBUTES] & DEBUG_FLAG != 0,
+			mr_enclave,
+			mr_signer,
+			isv_prod_id: read_u16(body, REPORT_ISV_PROD_ID),
+			isv_svn: read_u16(body, REPORT_ISV_SVN),
+			report_data,
+		}
+	}
+}
+
+/// Collateral of local quote verification, bundled as a trusted file of the enclave
+#[derive(Deserialize, Debug, Clone)]
+pub struct DcapCollateral {
+	// Quoting Enclave identity
+	#[serde(default = "default_qe_mrsigner")]
+	pub qe_mrsigner: String,
+	#[serde(default = "default_qe_isv_prod_id")]
+	pub qe_isv_prod_id: u16,
+	#[serde(default)]
+	pub qe_min_isv_svn: u16,
+	// PEM encoded CRLs of the Root CA and the PCK Platform/Processor CAs, one for each CA
+	#[serde(default)]
+	pub pck_crls: Vec<String>,
+	// TCB info of the platforms, by FMSPC, as served by the Intel PCS
+	#[serde(default)]
+	pub tcb_info: Vec<TcbInfo>,
+	// TCB statuses of the accepted platforms
+	#[serde(default = "default_tcb_statuses")]
+	pub accepted_tcb_statuses: Vec<String>,
+	// Use the attestation server when a quote can not be verified locally, i.e EPID quotes
+	#[serde(default)]
+	pub attestation_server_fallback: bool,
+}
+
+/// TCB levels of a platform family
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TcbInfo {
+	pub fmspc: String,
+	// Ordered from the highest level
+	pub tcb_levels: Vec<TcbLevel>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TcbLevel {
+	pub tcb: Tcb,
+	pub tcb_status: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Tcb {
+	pub sgxtcbcomponents: Vec<TcbComponent>,
+	pub pcesvn: u16,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TcbComponent {
+	pub svn: u8,
+}
+
+/// TCB of the platform, from the SGX extension of its PCK certificate
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformTcb {
+	pub fmspc: String,
+	pub components: [u8; SGX_TCB_COMPONENTS],
+	pub pce_svn: u16,
+}
+
+fn default_qe_mrsigner() -> String {
+	INTEL_QE_MRSIGNER.to_string()
+}
+
+fn default_qe_isv_prod_id() -> u16 {
+	INTEL_QE_ISV_PROD_ID
+}
+
+fn default_tcb_statuses() -> Vec<String> {
+	vec!["UpToDate".to_string()]
+}
+
+impl Default for DcapCollateral {
+	fn default() -> Self {
+		DcapCollateral {
+			qe_mrsigner: default_qe_mrsigner(),
+			qe_isv_prod_id: default_qe_isv_prod_id(),
+			qe_min_isv_svn: 0,
+			pck_crls: Vec::new(),
+			tcb_info: Vec::new(),
+			accepted_tcb_statuses: default_tcb_statuses(),
+			attestation_server_fallback: false,
+		}
+	}
+}
+
+/// Read the configured collateral, defaults are used if the file does not exist
+pub fn load_collateral() -> Result<DcapCollateral> {
+	if !Path::new(DCAP_COLLATERAL_FILE).exists() {
+		debug!("DCAP : no collateral file, using defaults");
+		return Ok(DcapCollateral::default());
+	}
+
+	let content = std::fs::read_to_string(DCAP_COLLATERAL_FILE)?;
+	let collateral = serde_json::from_str(&content)?;
+
+	Ok(collateral)
+}
+
+/// Read the bundled Intel SGX Root CA certificate, DER or PEM encoded
+pub fn load_root_certificate() -> Result<Vec<u8>> {
+	let content = std::fs::read(INTEL_ROOT_CA_FILE)
+		.map_err(|err| anyhow!("Intel SGX Root CA is not available : {err:?}"))?;
+
+	if content.starts_with(b"-----BEGIN") {
+		match Pem::iter_from_buffer(&content).next() {
+			Some(Ok(pem)) => Ok(pem.contents),
+			_ => Err(anyhow!("Intel SGX Root CA is not a valid PEM file")),
+		}
+	} else {
+		Ok(content)
+	}
+}
+
+/// Parse the enclave report of a quote, EPID and DCAP quotes share the same header and
+/// report layout. The report is NOT verified.
+/// # Arguments
+/// * `quote` - The raw quote or quote body
+/// # Returns
+/// * `ReportBody` - The enclave report
+pub fn parse_report_body(quote: &[u8]) -> Result<ReportBody> {
+	let body = slice(quote, QUOTE_HEADER_LENGTH, REPORT_BODY_LENGTH)?;
+	Ok(ReportBody::parse(body))
+}
+
+/// Check the header of a quote, only DCAP quotes can be verified locally
+pub fn is_dcap_quote(quote: &[u8]) -> bool {
+	quote.len() > QUOTE_HEADER_LENGTH &&
+		read_u16(quote, 0) == QUOTE_VERSION &&
+		read_u16(quote, 2) == ECDSA_P256_KEY_TYPE
+}
+
+/// Verify an SGX DCAP quote inside the enclave
+/// - PCK certificate chain up to the bundled Intel SGX Root CA, with a CRL for each CA
+/// - TCB status of the platform
+/// - QE report signed by the PCK certificate, and the QE identity
+/// - Attestation key bound to the QE report
+/// - Enclave report signed by the attestation key
+/// # Arguments
+/// * `quote` - The raw quote
+/// * `root_certificate` - DER encoded Intel SGX Root CA
+/// * `collateral` - The configured collateral
+/// * `now` - Unix time of the last finalized block, the host clock is not trusted
+/// # Returns
+/// * `ReportBody` - The verified enclave report
+pub fn verify_dcap_quote(
+	quote: &[u8],
+	root_certificate: &[u8],
+	collateral: &DcapCollateral,
+	now: i64,
+) -> Result<ReportBody> {
+	if !is_dcap_quote(quote) {
+		return Err(anyhow!("quote is not an ECDSA-P256 DCAP quote"));
+	}
+
+	// Header and enclave report are signed by the attestation key
+	let signed_length = QUOTE_HEADER_LENGTH + REPORT_BODY_LENGTH;
+	let signed_part = slice(quote, 0, signed_length)?;
+	let isv_report = ReportBody::parse(&signed_part[QUOTE_HEADER_LENGTH..]);
+
+	let signature_data_length = read_u32(slice(quote, signed_length, 4)?, 0) as usize;
+	let signature_data = slice(quote, signed_length + 4, signature_data_length)?;
+
+	let mut cursor = 0;
+	let isv_signature = slice(signature_data, cursor, SIGNATURE_LENGTH)?;
+	cursor += SIGNATURE_LENGTH;
+	let attestation_key = slice(signature_data, cursor, ATTESTATION_KEY_LENGTH)?;
+	cursor += ATTESTATION_KEY_LENGTH;
+	let qe_report_raw = slice(signature_data, cursor, REPORT_BODY_LENGTH)?;
+	cursor += REPORT_BODY_LENGTH;
+	let qe_signature = slice(signature_data, cursor, SIGNATURE_LENGTH)?;
+	cursor += SIGNATURE_LENGTH;
+
+	let qe_auth_length = read_u16(slice(signature_data, cursor, 2)?, 0) as usize;
+	cursor += 2;
+	let qe_auth_data = slice(signature_data, cursor, qe_auth_length)?;
+	cursor += qe_auth_length;
+
+	let certification_type = read_u16(slice(signature_data, cursor, 2)?, 0);
+	cursor += 2;
+	let certification_length = read_u32(slice(signature_data, cursor, 4)?, 0) as usize;
+	cursor += 4;
+	let certification_data = slice(signature_data, cursor, certification_length)?;
+
+	if certification_type != PCK_CERT_CHAIN_TYPE {
+		return Err(anyhow!("unsupported QE certification data type {certification_type}"));
+	}
+
+	// PCK certificate chain
+	let chain = parse_certificate_chain(certification_data)?;
+	let certificates = chain
+		.iter()
+		.map(|der| match parse_x509_certificate(der) {
+			Ok((_, certificate)) => Ok(certificate),
+			Err(err) => Err(anyhow!("invalid PCK chain certificate : {err:?}")),
+		})
+		.collect::<Result<Vec<X509Certificate>>>()?;
+
+	verify_certificate_chain(&chain, &certificates, root_certificate, collateral, now)?;
+
+	let platform_tcb = parse_platform_tcb(&certificates[0])?;
+	let tcb_status = check_tcb_status(&platform_tcb, collateral)?;
+	debug!("DCAP : platform {} TCB status is {tcb_status}", platform_tcb.fmspc);
+
+	// QE report is signed by the PCK certificate
+	let pck_key = certificates[0].public_key().subject_public_key.data;
+	UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, pck_key)
+		.verify(qe_report_raw, qe_signature)
+		.map_err(|_| anyhow!("invalid QE report signature"))?;
+
+	let qe_report = ReportBody::parse(qe_report_raw);
+
+	// QE identity
+	if hex::encode(qe_report.mr_signer) != collateral.qe_mrsigner.to_lowercase() ||
+		qe_report.isv_prod_id != collateral.qe_isv_prod_id ||
+		qe_report.isv_svn < collateral.qe_min_isv_svn
+	{
+		return Err(anyhow!(
+			"untrusted quoting enclave : mrsigner {} prodid {} svn {}",
+			hex::encode(qe_report.mr_signer),
+			qe_report.isv_prod_id,
+			qe_report.isv_svn
+		));
+	}
+
+	// Attestation key is bound to the QE report
+	let mut hash_context = digest::Context::new(&digest::SHA256);
+	hash_context.update(attestation_key);
+	hash_context.update(qe_auth_data);
+	let key_hash = hash_context.finish();
+
+	if qe_report.report_data[..32] != *key_hash.as_ref() ||
+		qe_report.report_data[32..].iter().any(|byte| *byte != 0)
+	{
+		return Err(anyhow!("attestation key is not bound to the QE report"));
+	}
+
+	// Enclave report is signed by the attestation key
+	let mut uncompressed_key = Vec::with_capacity(1 + ATTESTATION_KEY_LENGTH);
+	uncompressed_key.push(0x04);
+	uncompressed_key.extend_from_slice(attestation_key);
+
+	UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, uncompressed_key)
+		.verify(signed_part, isv_signature)
+		.map_err(|_| anyhow!("invalid enclave report signature"))?;
+
+	if isv_report.debug && cfg!(any(feature = "mainnet", feature = "alphanet")) {
+		return Err(anyhow!("enclave is running in debug mode"));
+	}
+
+	trace!(
+		"DCAP : verified quote of mrenclave {} , mrsigner {}",
+		hex::encode(isv_report.mr_enclave),
+		hex::encode(isv_report.mr_signer)
+	);
+
+	Ok(isv_report)
+}
+
+// PEM certificates of the QE certification data : PCK, Platform/Processor CA, Root CA
+fn parse_certificate_chain(certification_data: &[u8]) -> Result<Vec<Vec<u8>>> {
+	// Certification data may be null terminated
+	let end = certification_data.iter().rposition(|byte| *byte != 0).map_or(0, |pos| pos + 1);
+
+	let chain = Pem::iter_from_buffer(&certification_data[..end])
+		.map(|pem| match pem {
+			Ok(pem) => Ok(pem.contents),
+			Err(err) => Err(anyhow!("invalid PCK certificate chain : {err:?}")),
+		})
+		.collect::<Result<Vec<Vec<u8>>>>()?;
+
+	if chain.len() < 2 {
+		return Err(anyhow!("PCK certificate chain is incomplete"));
+	}
+
+	Ok(chain)
+}
+
+fn verify_certificate_chain(
+	chain: &[Vec<u8>],
+	certificates: &[X509Certificate],
+	root_certificate: &[u8],
+	collateral: &DcapCollateral,
+	now: i64,
+) -> Result<()> {
+	// The chain must end with the bundled root
+	match chain.last() {
+		Some(root) if root.as_slice() == root_certificate => {},
+		_ => return Err(anyhow!("PCK certificate chain is not issued by Intel SGX Root CA")),
+	}
+
+	for (index, certificate) in certificates.iter().enumerate() {
+		let validity = certificate.validity();
+		if now < validity.not_before.timestamp() || now > validity.not_after.timestamp() {
+			return Err(anyhow!(
+				"certificate {} of the PCK chain is expired or not valid yet",
+				certificate.subject()
+			));
+		}
+
+		// Root is self-signed
+		let issuer = certificates.get(index + 1).unwrap_or(certificate);
+		verify_signature(
+			issuer,
+			certificate.tbs_certificate.as_ref(),
+			certificate.signature_value.data,
+		)
+		.map_err(|_| anyhow!("invalid signature of certificate {}", certificate.subject()))?;
+	}
+
+	// Every CA of the chain, the root included, needs its CRL
+	let mut checked_issuers = vec![false; certificates.len()];
+
+	for crl_pem in &collateral.pck_crls {
+		let crl_der = match Pem::iter_from_buffer(crl_pem.as_bytes()).next() {
+			Some(Ok(pem)) => pem.contents,
+			_ => return Err(anyhow!("configured PCK CRL is not a valid PEM")),
+		};
+
+		let crl = match parse_x509_crl(&crl_der) {
+			Ok((_, crl)) => crl,
+			Err(err) => return Err(anyhow!("configured PCK CRL is invalid : {err:?}")),
+		};
+
+		if let Some(issuer_index) = check_revocation(certificates, &crl, now)? {
+			checked_issuers[issuer_index] = true;
+		}
+	}
+
+	if let Some(index) = (1..certificates.len()).find(|index| !checked_issuers[*index]) {
+		return Err(anyhow!("no CRL is configured for the CA {}", certificates[index].subject()));
+	}
+
+	Ok(())
+}
+
+// Check the certificates issued by the CRL issuer against the CRL
+// Returns the index of the issuer in the chain, None for a CRL of another CA
+fn check_revocation(
+	certificates: &[X509Certificate],
+	crl: &CertificateRevocationList,
+	now: i64,
+) -> Result<Option<usize>> {
+	let issuer_index = match certificates
+		.iter()
+		.position(|certificate| certificate.subject().as_raw() == crl.issuer().as_raw())
+	{
+		Some(index) => index,
+		None => return Ok(None),
+	};
+
+	verify_signature(
+		&certificates[issuer_index],
+		crl.tbs_cert_list.as_ref(),
+		crl.signature_value.data,
+	)
+	.map_err(|_| anyhow!("invalid signature of CRL issued by {}", crl.issuer()))?;
+
+	if let Some(next_update) = crl.next_update() {
+		// CRLs are measured with the enclave, an outdated CRL is reported until the next release
+		if next_update.timestamp() < now {
+			warn!("DCAP : configured CRL of {} is outdated", crl.issuer());
+		}
+	}
+
+	if issuer_index == 0 {
+		return Ok(Some(issuer_index));
+	}
+
+	let issued = &certificates[issuer_index - 1];
+	let revoked = crl
+		.iter_revoked_certificates()
+		.any(|revoked| revoked.raw_serial() == issued.tbs_certificate.raw_serial());
+
+	if revoked {
+		return Err(anyhow!("certificate {} is revoked", issued.subject()));
+	}
+
+	Ok(Some(issuer_index))
+}
+
+/// Read the FMSPC and the TCB of the platform from the SGX extension of its PCK certificate
+pub fn parse_platform_tcb(pck_certificate: &X509Certificate) -> Result<PlatformTcb> {
+	let extension = pck_certificate
+		.extensions()
+		.iter()
+		.find(|extension| extension.oid.to_id_string() == SGX_EXTENSION_OID)
+		.ok_or_else(|| anyhow!("PCK certificate has no SGX extension"))?;
+
+	let (_, sgx_extension) =
+		parse_der(extension.value).map_err(|err| anyhow!("invalid SGX extension : {err:?}"))?;
+
+	let mut fmspc = None;
+	let mut components = [0u8; SGX_TCB_COMPONENTS];
+	let mut pce_svn = None;
+
+	for entry in der_sequence(&sgx_extension)? {
+		let (oid, value) = der_oid_value(entry)?;
+
+		if oid == SGX_TCB_OID {
+			for component in der_sequence(value)? {
+				let (component_oid, value) = der_oid_value(component)?;
+				let svn = || value.as_u32().map_err(|err| anyhow!("invalid TCB svn : {err:?}"));
+
+				// TCB components are SGX_TCB_OID.1 to .16, followed by the PCE svn and the CPU svn
+				match component_oid
+					.strip_prefix(SGX_TCB_OID)
+					.and_then(|suffix| suffix.strip_prefix('.'))
+					.and_then(|index| index.parse::<usize>().ok())
+				{
+					Some(index @ 1..=SGX_TCB_COMPONENTS) => components[index - 1] = svn()? as u8,
+					Some(index) if index == SGX_TCB_COMPONENTS + 1 => pce_svn = Some(svn()? as u16),
+					_ => {},
+				}
+			}
+		} else if oid == SGX_FMSPC_OID {
+			let bytes = value.as_slice().map_err(|err| anyhow!("invalid FMSPC : {err:?}"))?;
+			fmspc = Some(hex::encode(bytes));
+		}
+	}
+
+	match (fmspc, pce_svn) {
+		(Some(fmspc), Some(pce_svn)) => Ok(PlatformTcb { fmspc, components, pce_svn }),
+		_ => Err(anyhow!("SGX extension of the PCK certificate is incomplete")),
+	}
+}
+
+/// TCB status of the platform, it must be one of the accepted statuses
+/// The first TCB level whose components are all below the platform ones applies
+pub fn check_tcb_status(platform_tcb: &PlatformTcb, collateral: &DcapCollateral) -> Result<String> {
+	let tcb_info = collateral
+		.tcb_info
+		.iter()
+		.find(|tcb_info| tcb_info.fmspc.eq_ignore_ascii_case(&platform_tcb.fmspc))
+		.ok_or_else(|| anyhow!("no TCB info is configured for FMSPC {}", platform_tcb.fmspc))?;
+
+	let level = tcb_info
+		.tcb_levels
+		.iter()
+		.find(|level| {
+			level.tcb.sgxtcbcomponents.len() == SGX_TCB_COMPONENTS &&
+				level.tcb.pcesvn <= platform_tcb.pce_svn &&
+				level
+					.tcb
+					.sgxtcbcomponents
+					.iter()
+					.zip(platform_tcb.components.iter())
+					.all(|(component, svn)| component.svn <= *svn)
+		})
+		.ok_or_else(|| anyhow!("TCB of platform {} is below every level", platform_tcb.fmspc))?;
+
+	if !collateral.accepted_tcb_statuses.contains(&level.tcb_status) {
+		return Err(anyhow!(
+			"TCB status {} of platform {} is not accepted",
+			level.tcb_status,
+			platform_tcb.fmspc
+		));
+	}
+
+	Ok(level.tcb_status.clone())
+}
+
+fn der_sequence<'a, 'b>(object: &'b BerObject<'a>) -> Result<&'b Vec<BerObject<'a>>> {
+	object
+		.as_sequence()
+		.map_err(|err| anyhow!("invalid SGX extension sequence : {err:?}"))
+}
+
+// Entries of the SGX extension are (OID, value) sequences
+fn der_oid_value<'a, 'b>(entry: &'b BerObject<'a>) -> Result<(String, &'b BerObject<'a>)> {
+	match der_sequence(entry)?.as_slice() {
+		[oid, value] => {
+			let oid = oid.as_oid().map_err(|err| anyhow!("invalid SGX extension oid : {err:?}"))?;
+			Ok((oid.to_id_string(), value))
+		},
+		_ => Err(anyhow!("invalid SGX extension entry")),
+	}
+}
+
+// Certificates of the PCK chain are signed with ECDSA P-256
+fn verify_signature(issuer: &X509Certificate, message: &[u8], signature: &[u8]) -> Result<()> {
+	let issuer_key = issuer.public_key().subject_public_key.data;
+
+	UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, issuer_key)
+		.verify(message, signature)
+		.map_err(|_| anyhow!("invalid ECDSA signature"))
+}
+
+fn slice(data: &[u8], offset: usize, length: usize) -> Result<&[u8]> {
+	match offset.checked_add(length) {
+		Some(end) if end <= data.len() => Ok(&data[offset..end]),
+		_ => Err(anyhow!("quote is truncated at offset {offset}")),
+	}
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/* ----------------------------------
+DCAP TEST
+----------------------------------*/
+#[cfg(test)]
+mod test {
+	use super::*;
+	use serde_json::json;
+
+	// Fixture of test/dcap/generate.py, the certificates are valid from 2024 to 2034
+	const FIXTURE_TIME: i64 = 1_760_000_000;
+
+	fn fixture() -> (Vec<u8>, Vec<u8>, DcapCollateral) {
+		let quote = std::fs::read("./test/dcap/quote.dat").unwrap();
+		let root_certificate = std::fs::read("./test/dcap/root_ca.der").unwrap();
+		let collateral =
+			serde_json::from_str(&std::fs::read_to_string("./test/dcap/collateral.json").unwrap())
+				.unwrap();
+		(quote, root_certificate, collateral)
+	}
+
+	#[test]
+	fn verify_quote_test() {
+		let (quote, root_certificate, collateral) = fixture();
+
+		let report =
+			verify_dcap_quote(&quote, &root_certificate, &collateral, FIXTURE_TIME).unwrap();
+		assert_eq!(report.mr_enclave, [0xE1; 32]);
+		assert_eq!(report.mr_signer, [0x5D; 32]);
+		assert_eq!((report.isv_prod_id, report.isv_svn), (12, 103));
+		assert_eq!(report.report_data[..], (0..64).collect::<Vec<u8>>()[..]);
+		assert!(!report.debug);
+
+		// Only the bundled root is trusted
+		let result = verify_dcap_quote(&quote, &root_certificate[1..], &collateral, FIXTURE_TIME);
+		assert!(result.unwrap_err().to_string().contains("Intel SGX Root CA"));
+
+		// Certificates are checked at the given time
+		let result = verify_dcap_quote(&quote, &root_certificate, &collateral, 2_100_000_000);
+		assert!(result.unwrap_err().to_string().contains("expired"));
+	}
+
+	#[test]
+	fn bad_signature_test() {
+		let (mut quote, root_certificate, collateral) = fixture();
+
+		// Enclave report signed by the attestation key
+		let signed_length = QUOTE_HEADER_LENGTH + REPORT_BODY_LENGTH;
+		quote[signed_length + 4] ^= 0x01;
+
+		let result = verify_dcap_quote(&quote, &root_certificate, &collateral, FIXTURE_TIME);
+		assert!(result.unwrap_err().to_string().contains("invalid enclave report signature"));
+
+		// Enclave report is altered after signing
+		let (mut quote, ..) = fixture();
+		quote[QUOTE_HEADER_LENGTH + REPORT_MRENCLAVE] ^= 0x01;
+
+		let result = verify_dcap_quote(&quote, &root_certificate, &collateral, FIXTURE_TIME);
+		assert!(result.unwrap_err().to_string().contains("invalid enclave report signature"));
+	}
+
+	#[test]
+	fn revoked_pck_test() {
+		let (quote, root_certificate, mut collateral) = fixture();
+
+		collateral.pck_crls[1] =
+			std::fs::read_to_string("./test/dcap/revoked_pck_crl.pem").unwrap();
+		let result = verify_dcap_quote(&quote, &root_certificate, &collateral, FIXTURE_TIME);
+		assert!(result.unwrap_err().to_string().contains("revoked"));
+
+		// Every CA needs its CRL
+		collateral.pck_crls.truncate(1);
+		let result = verify_dcap_quote(&quote, &root_certificate, &collateral, FIXTURE_TIME);
+		assert!(result.unwrap_err().to_string().contains("no CRL"));
+	}
+
+	#[test]
+	fn qe_identity_test() {
+		let (quote, root_certificate, collateral) = fixture();
+
+		let other_signer =
+			DcapCollateral { qe_mrsigner: hex::encode([0u8; 32]), ..collateral.clone() };
+		let result = verify_dcap_quote(&quote, &root_certificate, &other_signer, FIXTURE_TIME);
+		assert!(result.unwrap_err().to_string().contains("untrusted quoting enclave"));
+
+		let outdated_qe = DcapCollateral { qe_min_isv_svn: 9, ..collateral };
+		let result = verify_dcap_quote(&quote, &root_certificate, &outdated_qe, FIXTURE_TIME);
+		assert!(result.unwrap_err().to_string().contains("untrusted quoting enclave"));
+	}
+
+	#[test]
+	fn truncated_quote_test() {
+		// EPID quote header
+		let mut quote = vec![0u8; 1200];
+		quote[0] = 2;
+		assert!(!is_dcap_quote(&quote));
+
+		// DCAP header, signature data longer than the quote
+		quote[0] = 3;
+		quote[2] = 2;
+		assert!(is_dcap_quote(&quote));
+		quote[432..436].copy_from_slice(&5000u32.to_le_bytes());
+
+		let result = verify_dcap_quote(&quote, &[], &DcapCollateral::default(), 0);
+		assert!(result.unwrap_err().to_string().contains("truncated"));
+	}
+
+	#[test]
+	fn default_collateral_test() {
+		let collateral: DcapCollateral = serde_json::from_str("{}").unwrap();
+		assert_eq!(collateral.qe_mrsigner, INTEL_QE_MRSIGNER);
+		assert_eq!(collateral.qe_isv_prod_id, 1);
+		assert!(!collateral.attestation_server_fallback);
+		assert_eq!(collateral.accepted_tcb_statuses, ["UpToDate"]);
+	}
+
+	#[test]
+	fn tcb_status_test() {
+		let level = |svn: u8, pcesvn: u16, status: &str| {
+			json!({
+				"tcb": {
+					"sgxtcbcomponents": vec![json!({ "svn": svn }); SGX_TCB_COMPONENTS],
+					"pcesvn": pcesvn,
+				},
+				"tcbStatus": status,
+			})
+		};
+
+		let collateral: DcapCollateral = serde_json::from_value(json!({
+			"tcb_info": [{
+				"fmspc": "00906ED50000",
+				"tcbLevels": [level(5, 13, "UpToDate"), level(3, 11, "OutOfDate")],
+			}],
+		}))
+		.unwrap();
+
+		let platform = |svn: u8, pce_svn: u16| PlatformTcb {
+			fmspc: "00906ed50000".to_string(),
+			components: [svn; SGX_TCB_COMPONENTS],
+			pce_svn,
+		};
+
+		assert_eq!(check_tcb_status(&platform(6, 13), &collateral).unwrap(), "UpToDate");
+		// A lower PCE svn falls to the next level
+		assert!(check_tcb_status(&platform(6, 12), &collateral).is_err());
+		assert!(check_tcb_status(&platform(2, 13), &collateral).is_err());
+
+		let unknown = PlatformTcb { fmspc: "00606a000000".to_string(), ..platform(6, 13) };
+		assert!(check_tcb_status(&unknown, &collateral).is_err());
+	}
+}
@@ -1,2 +1,4 @@
 /// Attestation
+pub mod dcap;
+pub mod policy;
 pub mod ra;
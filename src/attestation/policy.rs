@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Result};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use hex::FromHex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+	attestation::{dcap, dcap::ReportBody, ra::get_quote_content},
+	constants::ENCLAVE_POLICY_FILE,
+	replication::admin_quorum::{consume_approved_proposal, AdminOperation},
+	server::state::{get_enclave_policy, set_enclave_policy, SharedState},
+};
+
+/* ---------------------------------------
+	ENCLAVE ALLOW-LIST POLICY
+------------------------------------------ */
+
+/// Approved enclave binary, unset fields match any value
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AllowedEnclave {
+	#[serde(default)]
+	pub mr_enclave: Option<String>,
+	#[serde(default)]
+	pub mr_signer: Option<String>,
+	#[serde(default)]
+	pub isv_prod_id: Option<u16>,
+	// Vulnerable versions are retired by increasing the minimum SVN
+	#[serde(default)]
+	pub min_isv_svn: u16,
+}
+
+/// Allow-list of enclave binaries that can synchronize keyshares
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct EnclavePolicy {
+	// Monotonic version, an older policy can not be replayed
+	pub version: u32,
+	pub allowed: Vec<AllowedEnclave>,
+}
+
+/// New allow-list, approved by the admin quorum
+#[derive(Serialize, Deserialize)]
+pub struct EnclavePolicyPacket {
+	policy: String,
+	proposal_id: String,
+}
+
+impl AllowedEnclave {
+	fn matches(&self, report: &ReportBody) -> bool {
+		let measurement_matches = |expected: &Option<String>, measurement: &[u8; 32]| match expected
+		{
+			Some(expected) =>
+				expected.trim_start_matches("0x").to_lowercase() == hex::encode(measurement),
+			None => true,
+		};
+
+		measurement_matches(&self.mr_enclave, &report.mr_enclave) &&
+			measurement_matches(&self.mr_signer, &report.mr_signer) &&
+			self.isv_prod_id.map_or(true, |prod_id| prod_id == report.isv_prod_id) &&
+			report.isv_svn >= self.min_isv_svn
+	}
+}
+
+impl EnclavePolicy {
+	pub fn is_allowed(&self, report: &ReportBody) -> bool {
+		self.allowed.iter().any(|allowed| allowed.matches(report))
+	}
+
+	/// Every entry must pin the binary or its signer
+	pub fn validate(&self) -> Result<(), String> {
+		if self.allowed.is_empty() {
+			return Err("policy does not allow any enclave".to_string());
+		}
+
+		for allowed in &self.allowed {
+			if allowed.mr_enclave.is_none() && allowed.mr_signer.is_none() {
+				return Err("every entry needs a mr_enclave or a mr_signer".to_string());
+			}
+
+			for measurement in [&allowed.mr_enclave, &allowed.mr_signer].into_iter().flatten() {
+				if <[u8; 32]>::from_hex(measurement.trim_start_matches("0x")).is_err() {
+					return Err(format!("invalid measurement {measurement}"));
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Read the sealed policy, None if admins have not set any policy
+pub fn load_enclave_policy() -> Result<Option<EnclavePolicy>> {
+	if !std::path::Path::new(ENCLAVE_POLICY_FILE).exists() {
+		return Ok(None);
+	}
+
+	let content = std::fs::read_to_string(ENCLAVE_POLICY_FILE)?;
+	let policy = serde_json::from_str(&content)?;
+
+	Ok(Some(policy))
+}
+
+fn seal_enclave_policy(policy: &EnclavePolicy) -> Result<()> {
+	let temporary_file = format!("{ENCLAVE_POLICY_FILE}.tmp");
+	std::fs::write(&temporary_file, serde_json::to_string(policy)?)?;
+	std::fs::rename(&temporary_file, ENCLAVE_POLICY_FILE)?;
+
+	Ok(())
+}
+
+// Report of the running enclave
+fn own_report() -> Result<ReportBody> {
+	let quote = get_quote_content().map_err(|err| anyhow!("can not read own quote : {err:?}"))?;
+	dcap::parse_report_body(&quote)
+}
+
+/// Policy of an enclave whose admins have not set any, sealed on its first creation
+/// Releases of the same signer and product are allowed from the SVN of the first binary, an
+/// upgraded enclave can then take over the keyshares but never widens the policy with its own
+/// report. An approved policy replaces it, its version is zero.
+pub fn seed_enclave_policy() -> Result<EnclavePolicy> {
+	let own = own_report()?;
+
+	let policy = EnclavePolicy {
+		version: 0,
+		allowed: vec![AllowedEnclave {
+			mr_enclave: None,
+			mr_signer: Some(hex::encode(own.mr_signer)),
+			isv_prod_id: Some(own.isv_prod_id),
+			min_isv_svn: own.isv_svn,
+		}],
+	};
+
+	seal_enclave_policy(&policy)?;
+	info!(
+		"ENCLAVE POLICY : seed policy of signer {} from svn {} is sealed",
+		hex::encode(own.mr_signer),
+		own.isv_svn
+	);
+
+	Ok(policy)
+}
+
+/// Check the verified report of a peer against the allow-list
+/// Without a policy, i.e the seed policy could not be read, only peers running the same binary
+/// are allowed : an upgraded binary is then refused until the admins approve a policy
+/// # Arguments
+/// * `state` - The enclave state
+/// * `report` - Verified report of the peer enclave
+/// # Returns
+/// * `Result<(), String>` - The error message if the peer is not allowed
+pub async fn verify_enclave_policy(state: &SharedState, report: &ReportBody) -> Result<(), String> {
+	let allowed = match get_enclave_policy(state).await {
+		Some(policy) => policy.is_allowed(report),
+
+		None => match own_report() {
+			Ok(own) => own.mr_enclave == report.mr_enclave,
+			Err(err) => return Err(format!("ENCLAVE POLICY : {err:?}")),
+		},
+	};
+
+	if !allowed {
+		return Err(format!(
+			"ENCLAVE POLICY : enclave is not allowed : mrenclave {} , mrsigner {} , prodid {} , svn {}",
+			hex::encode(report.mr_enclave),
+			hex::encode(report.mr_signer),
+			report.isv_prod_id,
+			report.isv_svn
+		));
+	}
+
+	Ok(())
+}
+
+fn policy_error(status: StatusCode, message: String) -> axum::response::Response {
+	warn!(message);
+	(status, Json(json!({ "error": message }))).into_response()
+}
+
+/* ----------------------------------
+		POLICY ENDPOINTS
+----------------------------------*/
+
+/// Current allow-list of peer enclaves
+pub async fn get_enclave_policy_handler(State(state): State<SharedState>) -> impl IntoResponse {
+	match get_enclave_policy(&state).await {
+		Some(policy) => (StatusCode::OK, Json(json!(policy))),
+		None => (
+			StatusCode::OK,
+			Json(json!({
+				"version": 0,
+				"allowed": [],
+				"description": "No policy is set, only peers with the same MRENCLAVE are allowed, upgraded binaries are refused",
+			})),
+		),
+	}
+}
+
+/// Replace the allow-list of peer enclaves, approved by the admin quorum
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The policy and its approved proposal
+/// # Returns
+/// * `Json` - The new policy or the error
+pub async fn set_enclave_policy_handler(
+	State(state): State<SharedState>,
+	Json(request): Json<EnclavePolicyPacket>,
+) -> impl IntoResponse {
+	let policy: EnclavePolicy = match serde_json::from_str(&request.policy) {
+		Ok(policy) => policy,
+		Err(err) => {
+			let message = format!("ENCLAVE POLICY : Policy is not parsable : {err}");
+			return policy_error(StatusCode::BAD_REQUEST, message);
+		},
+	};
+
+	if let Err(err) = policy.validate() {
+		return policy_error(StatusCode::BAD_REQUEST, format!("ENCLAVE POLICY : {err}"));
+	}
+
+	let current_version = get_enclave_policy(&state).await.map_or(0, |current| current.version);
+	if policy.version <= current_version {
+		let message = format!(
+			"ENCLAVE POLICY : Policy version {} is not newer than {current_version}",
+			policy.version
+		);
+		return policy_error(StatusCode::CONFLICT, message);
+	}
+
+	let data_hash = sha256::digest(request.policy.as_bytes());
+	if let Err(err) = consume_approved_proposal(
+		&state,
+		&request.proposal_id,
+		&[AdminOperation::EnclavePolicy],
+		&data_hash,
+	)
+	.await
+	{
+		return policy_error(StatusCode::FORBIDDEN, format!("ENCLAVE POLICY : {err}"));
+	}
+
+	if let Err(err) = seal_enclave_policy(&policy) {
+		let message = format!("ENCLAVE POLICY : Can not seal the policy : {err:?}");
+		return policy_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	info!(
+		"ENCLAVE POLICY : version {} with {} allowed enclaves is set",
+		policy.version,
+		policy.allowed.len()
+	);
+
+	set_enclave_policy(&state, Some(policy.clone())).await;
+
+	(StatusCode::OK, Json(json!(policy))).into_response()
+}
+
+/* ----------------------------------
+ENCLAVE POLICY TEST
+----------------------------------*/
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn report(mr_enclave: u8, mr_signer: u8, isv_svn: u16) -> ReportBody {
+		ReportBody {
+			debug: false,
+			mr_enclave: [mr_enclave; 32],
+			mr_signer: [mr_signer; 32],
+			isv_prod_id: 0,
+			isv_svn,
+			report_data: [0; 64],
+		}
+	}
+
+	#[test]
+	fn enclave_policy_test() {
+		let policy = EnclavePolicy {
+			version: 1,
+			allowed: vec![
+				AllowedEnclave {
+					mr_enclave: Some(hex::encode([1u8; 32])),
+					mr_signer: None,
+					isv_prod_id: None,
+					min_isv_svn: 0,
+				},
+				AllowedEnclave {
+					mr_enclave: None,
+					mr_signer: Some(format!("0x{}", hex::encode([2u8; 32]))),
+					isv_prod_id: Some(0),
+					min_isv_svn: 3,
+				},
+			],
+		};
+
+		assert!(policy.validate().is_ok());
+		assert!(policy.is_allowed(&report(1, 9, 0)));
+		assert!(policy.is_allowed(&report(9, 2, 3)));
+		// Retired SVN
+		assert!(!policy.is_allowed(&report(9, 2, 2)));
+		assert!(!policy.is_allowed(&report(9, 9, 9)));
+
+		let open_policy = EnclavePolicy {
+			version: 2,
+			allowed: vec![AllowedEnclave {
+				mr_enclave: None,
+				mr_signer: None,
+				isv_prod_id: None,
+				min_isv_svn: 0,
+			}],
+		};
+		assert!(open_policy.validate().is_err());
+		assert!(EnclavePolicy::default().validate().is_err());
+	}
+}
@@ -15,16 +15,71 @@ pub const SENTRY_URL: &str = "https://089e5c79239442bfb6af6e5d7676644c@error.ter
 // ---------- SYNC
 pub const RETRY_COUNT: u8 = 5;
 pub const RETRY_DELAY: u8 = 6;
+pub const SYNC_PAGE_SIZE: u32 = 500; // Keyshares per page of setup (wildcard) synchronization
+pub const MAX_SYNC_PAGE_SIZE: u32 = 5000;
+pub const MAX_PEER_BACKOFF: u32 = 100; // Blocks a failing peer is ranked last
+pub const ANTI_ENTROPY_INTERVAL: u32 = 600; // Blocks between reconciliations with the slot (~1 hour)
+pub const ANTI_ENTROPY_RANGE: u32 = 1000; // NFT ids per digest range
+pub const SYNC_RETRY_INTERVAL: u32 = 50; // Blocks between retries of the failed nfts (~5 minutes)
+pub const MAX_SYNC_ATTEMPTS: u32 = 10; // Retries of a failed nft, then only anti-entropy recovers it
+pub const MAX_SYNC_BACKOFF: u32 = 3600; // Blocks a failed nft waits before the next retry
+pub const CRAWL_PARALLELISM: usize = 8; // Blocks downloaded concurrently by the crawler
+pub const CRAWL_CHECKPOINT_INTERVAL: u32 = 500; // Crawled blocks between checkpoints of the sync state
+pub const MIN_PURGE_CONFIRMATIONS: usize = 2; // Peers holding a keyshare before a reassigned enclave removes it
 pub const _MAX_STREAM_SIZE: usize = 1000 * 3 * 1024; // 3KB is the size of keyshare, 1000 is maximum number of extrinsics in block
 
 // ---------- HTTP SERVER
 pub const SEALPATH: &str = "/nft";
 pub const SYNC_STATE_FILE: &str = "/nft/sync.state";
 pub const ENCLAVE_ACCOUNT_FILE: &str = "/nft/enclave_account.key";
+pub const ENCLAVE_POLICY_FILE: &str = "/nft/enclave_policy.json";
+pub const REASSIGNMENT_PATH: &str = "/nft/reassignment"; // Keyshares of a previous slot
+pub const ADMIN_QUORUM_FILE: &str = "/nft/admin_quorum.json"; // Quorum of the destructive admin operations
+pub const RESTORE_STAGING_PATH: &str = "/nft/restore-staging"; // Uploads and extracted files of the restores
+pub const UPGRADE_ESCROW_FILE: &str = "/nft/upgrade_escrow.json"; // Enclave accounts escrowed by an admin enclave
+pub const UPGRADE_HANDOVER_FILE: &str = "/nft/upgrade_handover.json"; // Account of this enclave handed over for an upgrade
+pub const ADMIN_AUDIT_FILE: &str = "/nft/admin_audit.log"; // Hash-chained trail of the privileged requests
+pub const MAINTENANCE_MODE_FILE: &str = "/nft/maintenance_mode.json"; // Mode switched by the admin quorum
+pub const KEYSHARE_TOMBSTONE_FILE: &str = "/nft/keyshare_tombstones.json"; // Removed keyshares, listed by the incremental backups
+pub const BACKUP_PATH: &str = "/backups"; // Host-mounted directory of the scheduled encrypted backups
+pub const BACKUP_SCHEDULE_FILE: &str = "/nft/backup_schedule.json"; // Schedule set by the admin quorum
+pub const BACKUP_KEEP_DAILY: usize = 7; // Days whose last scheduled backup is kept
+pub const BACKUP_KEEP_WEEKLY: usize = 4; // Weeks whose last scheduled backup is kept
 pub const CONTENT_LENGTH_LIMIT: usize = 400 * 1024 * 1024; // 400MB for 6 millions of keyshares
+pub const REQUEST_TIMEOUT: u64 = 30; // Seconds
+pub const BULK_REQUEST_TIMEOUT: u64 = 30 * 60; // Seconds, bulk restores and attested upgrade transfers
+pub const MAX_BACKUP_MANIFEST_SIZE: u64 = 64 * 1024 * 1024; // Signed manifest of a backup archive
+pub const MAX_BACKUP_FILE_SIZE: u64 = 64 * 1024 * 1024; // File of a backup archive, logs and sync state are the largest
+pub const MAX_DRY_RUN_UPLOAD_SIZE: u64 = 64 * 1024 * 1024; // Backup verified in memory by a dry-run
 
 // ----------- VERIFY
 pub const MAX_VALIDATION_PERIOD: u32 = 20;
 pub const MAX_BLOCK_VARIATION: u32 = 2;
 pub const MAX_KEYSHARE_SIZE: u16 = 3000;
 pub const MIN_KEYSHARE_SIZE: u16 = 16;
+
+// ----------- ADMIN QUORUM
+pub const ADMIN_PROPOSAL_EXPIRATION: u32 = 600; // Blocks before a proposal expires, without a sealed quorum policy
+pub const MAX_ADMIN_PROPOSAL_EXPIRATION: u32 = 14_400; // A day of blocks
+pub const UPGRADE_ESCROW_EXPIRATION: u32 = 14_400; // Blocks an escrowed account is kept, a day
+pub const MAX_ADMIN_PROPOSALS: usize = 64; // Pending proposals of all admins
+pub const MAX_AUDIT_PAGE_SIZE: usize = 1000; // Audit entries returned by a request
+pub const MAX_MAINTENANCE_REASON_LENGTH: usize = 256; // Reason of a maintenance, reported by the health check
+
+// ----------- BALANCE
+// CAPS has 18 decimals
+pub const LOW_BALANCE_WARNING: u128 = 1_000_000_000_000_000_000; // 1 CAPS
+pub const MIN_BALANCE_FOR_STORE: u128 = 100_000_000_000_000_000; // 0.1 CAPS
+
+// ----------- ATTESTATION
+// Trusted files of the enclave, /etc is mounted from gramine/trusted/etc
+pub const INTEL_ROOT_CA_FILE: &str = "/etc/sgx/Intel_SGX_Provisioning_Certification_RootCA.cer";
+pub const DCAP_COLLATERAL_FILE: &str = "/etc/sgx/dcap_collateral.json";
+
+// ----------- CACHE
+pub const NFT_CACHE_SIZE: usize = 10_000; // Entries of the NFT ownership cache
+
+// ----------- FINALITY
+pub const MAX_FINALITY_LAG: u64 = 120; // Seconds without a verified GRANDPA justification
+pub const GRANDPA_CHECKPOINT_FILE: &str = "/etc/grandpa/checkpoint.json"; // Trusted file, finality is only verified from it
+pub const GRANDPA_ANCHOR_FILE: &str = "/nft/grandpa_anchor.json"; // Last authority set enacted after the checkpoint
@@ -10,16 +10,27 @@ use serde::Serialize;
 //use jsonrpsee_ws_client;
 //use jsonrpsee_ws_client::WsClientBuilder;
 
-use std::fmt;
+use anyhow::anyhow;
+use cached::{Cached, SizedCache};
+use sp_trie::{LayoutV1, StorageProof};
+use std::{collections::HashMap, fmt};
 use subxt::{
-	ext::sp_core::H256,
-	storage::address::{Address, StaticStorageMapKey, Yes},
+	blocks::Block,
+	ext::{
+		codec::Decode,
+		scale_value::Composite,
+		sp_core::{Blake2Hasher, Pair, H256},
+	},
+	storage::{
+		address::{Address, StaticStorageMapKey, Yes},
+		StorageAddress,
+	},
 	tx::{PairSigner, Signer},
 	utils::AccountId32,
 	Error, OnlineClient, PolkadotConfig,
 };
 
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
 #[cfg_attr(
 	feature = "mainnet",
@@ -142,9 +153,279 @@ pub async fn get_current_block_number_new_api() -> Result<u32, Error> {
 	Ok(last_block.block.header.number)
 }
 
+// -------------- OWNERSHIP CACHE --------------
+
+/// Storages of an NFT/Capsule kept in the ownership cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CachedStorage {
+	NftData,
+	Delegatee,
+	RentContract,
+}
+
+/// Cache of verified NFT ownership storages, keyed by NFT id.
+/// Entries are invalidated by the events of the imported finalized blocks, an entry is only
+/// served if no event touched the NFT after the block it has been verified at.
+pub struct OwnershipCache {
+	// (nft_id, storage) -> (verified at block, SCALE encoded value)
+	entries: SizedCache<(u32, CachedStorage), (u32, Option<Vec<u8>>)>,
+	// Last imported block with an event about the nft_id
+	last_change: HashMap<u32, u32>,
+	// Values verified below this block may miss invalidations and are not inserted
+	pruned_up_to: u32,
+}
+
+impl OwnershipCache {
+	pub fn new(cache_size: usize) -> OwnershipCache {
+		OwnershipCache {
+			entries: SizedCache::with_size(cache_size),
+			last_change: HashMap::new(),
+			pruned_up_to: 0,
+		}
+	}
+
+	fn get(&mut self, key: &(u32, CachedStorage)) -> Option<Option<Vec<u8>>> {
+		let last_change = self.last_change.get(&key.0).copied().unwrap_or_default();
+		match self.entries.cache_get(key) {
+			Some((verified_at, value)) if *verified_at >= last_change => Some(value.clone()),
+			_ => None,
+		}
+	}
+
+	fn insert(&mut self, key: (u32, CachedStorage), verified_at: u32, value: Option<Vec<u8>>) {
+		let last_change = self.last_change.get(&key.0).copied().unwrap_or_default();
+		// An event may have changed the value after the verified block
+		if verified_at >= last_change && verified_at >= self.pruned_up_to {
+			self.entries.cache_set(key, (verified_at, value));
+		}
+	}
+
+	/// Record a change of the NFT at the given block and drop its cached storages
+	pub fn invalidate(&mut self, nft_id: u32, block_number: u32) {
+		self.last_change.insert(nft_id, block_number);
+		for storage in
+			[CachedStorage::NftData, CachedStorage::Delegatee, CachedStorage::RentContract]
+		{
+			self.entries.cache_remove(&(nft_id, storage));
+		}
+	}
+
+	/// Drop every entry, used when events of some blocks can not be evaluated
+	pub fn clear(&mut self, block_number: u32) {
+		self.entries.cache_clear();
+		self.last_change.clear();
+		self.pruned_up_to = block_number;
+	}
+
+	/// Forget the changes already covered by the finalized state-root
+	pub fn prune(&mut self, finalized_number: u32) {
+		self.last_change.retain(|_, changed_at| *changed_at > finalized_number);
+		self.pruned_up_to = self.pruned_up_to.max(finalized_number);
+	}
+}
+
+/// Invalidate the ownership cache with the events of an imported finalized block.
+/// Every event with an 'nft_id' field (transfer, delegation, rent, burn, ...) drops the
+/// cached storages of this NFT.
+/// # Arguments
+/// * `state` - The shared state
+/// * `block` - The imported finalized block
+pub async fn invalidate_ownership_cache(
+	state: &SharedState,
+	block: &Block<PolkadotConfig, DefaultApi>,
+) {
+	let cache = match get_ownership_cache(state).await {
+		Some(cache) => cache,
+		None => return,
+	};
+
+	let block_number = block.header().number;
+
+	let nft_ids = match block.events().await {
+		Ok(events) => events
+			.iter()
+			.map(|event| {
+				let fields = event?.field_values()?;
+				Ok(event_nft_id(&fields))
+			})
+			.collect::<Result<Vec<Option<u32>>, Error>>(),
+		Err(err) => Err(err),
+	};
+
+	let mut ownership_cache = match cache.lock() {
+		Ok(ownership_cache) => ownership_cache,
+		Err(poisoned) => poisoned.into_inner(),
+	};
+
+	match nft_ids {
+		Ok(nft_ids) =>
+			for nft_id in nft_ids.into_iter().flatten() {
+				trace!("CHAIN : ownership cache, nft_id {nft_id} changed at block {block_number}");
+				ownership_cache.invalidate(nft_id, block_number);
+			},
+		Err(err) => {
+			warn!("CHAIN : unable to decode events of block {block_number}, clear ownership cache : {err:?}");
+			ownership_cache.clear(block_number);
+		},
+	}
+}
+
+fn event_nft_id<T>(fields: &Composite<T>) -> Option<u32> {
+	match fields {
+		Composite::Named(named) => named
+			.iter()
+			.find(|(name, _)| name == "nft_id")
+			.and_then(|(_, value)| value.as_u128())
+			.and_then(|nft_id| u32::try_from(nft_id).ok()),
+		Composite::Unnamed(_) => None,
+	}
+}
+
+/// Drop every entry of the ownership cache
+/// # Arguments
+/// * `state` - The shared state
+/// * `block_number` - First block of which events have not been evaluated
+pub async fn clear_ownership_cache(state: &SharedState, block_number: u32) {
+	if let Some(cache) = get_ownership_cache(state).await {
+		match cache.lock() {
+			Ok(mut ownership_cache) => ownership_cache.clear(block_number),
+			Err(poisoned) => poisoned.into_inner().clear(block_number),
+		}
+	}
+}
+
+/// Prune the ownership cache when the finalized state-root moves forward
+/// # Arguments
+/// * `state` - The shared state
+/// * `finalized_number` - Block number of the new finalized state-root
+pub async fn prune_ownership_cache(state: &SharedState, finalized_number: u32) {
+	if let Some(cache) = get_ownership_cache(state).await {
+		match cache.lock() {
+			Ok(mut ownership_cache) => ownership_cache.prune(finalized_number),
+			Err(poisoned) => poisoned.into_inner().prune(finalized_number),
+		}
+	}
+}
+
+// -------------- STORAGE PROOF --------------
+
+/// Fetch a storage entry together with its merkle proof, and verify the proof inside the enclave
+/// against the state-root of the last finalized header tracked by the enclave
+/// # Arguments
+/// * `storage_address` - The static storage address of the entry
+/// * `cache_key` - Key of the entry in the ownership cache, None to bypass the cache
+/// # Returns
+/// * `Option<Target>` - The verified value, None if the proof shows the entry does not exist
+pub async fn fetch_verified_storage<Addr>(
+	state: &SharedState,
+	storage_address: &Addr,
+	cache_key: Option<(u32, CachedStorage)>,
+) -> Result<Option<Addr::Target>, anyhow::Error>
+where
+	Addr: StorageAddress<IsFetchable = Yes>,
+	Addr::Target: Decode,
+{
+	let (block_number, block_hash, state_root) = match get_finalized_root(state).await {
+		Some(root) => root,
+		None => return Err(anyhow!("no finalized state-root is tracked yet")),
+	};
+
+	let ownership_cache = match cache_key {
+		Some(_) => get_ownership_cache(state).await,
+		None => None,
+	};
+
+	if let (Some(cache), Some(key)) = (&ownership_cache, &cache_key) {
+		let cached_value = match cache.lock() {
+			Ok(mut cache) => cache.get(key),
+			Err(poisoned) => poisoned.into_inner().get(key),
+		};
+
+		if let Some(value) = cached_value {
+			trace!("CHAIN : ownership cache hit for {key:?}");
+			return decode_verified_value::<Addr::Target>(value);
+		}
+	}
+
+	let api = get_chain_api(state).await;
+
+	// Static types are decoded, they must match the metadata of the running chain
+	api.storage().validate(storage_address)?;
+	let storage_key = api.storage().address_bytes(storage_address)?;
+
+	// The RPC node only provides the trie nodes, the root comes from the tracked finalized header
+	let value = match read_verified_storage(&api, &storage_key, block_hash, state_root).await {
+		Ok(value) => value,
+		Err(err) => {
+			if matches!(err.downcast_ref::<Error>(), Some(Error::Rpc(_))) {
+				set_chain_api_renew(state, true).await;
+			}
+			return Err(err);
+		},
+	};
+
+	if let (Some(cache), Some(key)) = (ownership_cache, cache_key) {
+		match cache.lock() {
+			Ok(mut cache) => cache.insert(key, block_number, value.clone()),
+			Err(poisoned) => poisoned.into_inner().insert(key, block_number, value.clone()),
+		}
+	}
+
+	decode_verified_value::<Addr::Target>(value)
+}
+
+/// Read a raw storage value with its merkle proof, verified against a trusted state-root
+/// # Arguments
+/// * `api` - The chain API
+/// * `storage_key` - Key of the storage entry
+/// * `block_hash` - Block of the state-root
+/// * `state_root` - State-root of a verified header
+/// # Returns
+/// * `Option<Vec<u8>>` - The verified value, None if the proof shows the entry does not exist
+pub async fn read_verified_storage(
+	api: &DefaultApi,
+	storage_key: &[u8],
+	block_hash: H256,
+	state_root: H256,
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+	let read_proof = api.rpc().read_proof([storage_key], Some(block_hash)).await?;
+
+	if read_proof.at != block_hash {
+		return Err(anyhow!(
+			"proof is generated at block {:?} instead of block {:?}",
+			read_proof.at,
+			block_hash
+		));
+	}
+
+	let proof_db = StorageProof::new(read_proof.proof.into_iter().map(|node| node.0))
+		.into_memory_db::<Blake2Hasher>();
+
+	sp_trie::read_trie_value::<LayoutV1<Blake2Hasher>, _>(
+		&proof_db,
+		&state_root,
+		storage_key,
+		None,
+		None,
+	)
+	.map_err(|err| anyhow!("invalid storage proof : {err:?}"))
+}
+
+fn decode_verified_value<Target: Decode>(
+	value: Option<Vec<u8>>,
+) -> Result<Option<Target>, anyhow::Error> {
+	match value {
+		Some(encoded) => match Target::decode(&mut encoded.as_slice()) {
+			Ok(decoded) => Ok(Some(decoded)),
+			Err(err) => Err(anyhow!("unable to decode verified storage value : {err:?}")),
+		},
+		None => Ok(None),
+	}
+}
+
 // -------------- GET NFT/CAPSULE DATA --------------
 
-/// Get the NFT/Capsule data
+/// Get the NFT/Capsule data, verified by storage proof
 /// # Arguments
 /// * `nft_id` - The NFT/Capsule ID
 pub async fn get_onchain_nft_data(
@@ -152,28 +433,19 @@ pub async fn get_onchain_nft_data(
 	nft_id: u32,
 ) -> Option<NFTData<AccountId32>> {
 	debug!("CHAIN : get chain NFT DATA");
-	let api = get_chain_api(state).await;
 
 	let storage_address = ternoa::storage().nft().nfts(nft_id);
 
-	// Get storage
-	let storage: subxt::storage::Storage<PolkadotConfig, OnlineClient<PolkadotConfig>> =
-		match api.storage().at_latest().await {
-			Ok(storage) => storage,
-			Err(err) => {
-				set_chain_api_renew(state, true).await;
-				error!("CHAIN : Failed to get nft storage: {err:?}");
-				sentry::capture_error(&err);
-				return None;
-			},
-		};
-
-	// Fetch data
-	match storage.fetch(&storage_address).await {
+	match fetch_verified_storage(state, &storage_address, Some((nft_id, CachedStorage::NftData)))
+		.await
+	{
 		Ok(nft_data) => nft_data,
 		Err(err) => {
-			error!("CHAIN : Failed to fetch NFT data: {err:?}");
-			sentry::capture_error(&err);
+			error!("CHAIN : Failed to verify NFT data for nft_id {nft_id} : {err:?}");
+			sentry::capture_message(
+				format!("CHAIN : Failed to verify NFT data for nft_id {nft_id} : {err:?}").as_str(),
+				sentry::Level::Error,
+			);
 			None
 		},
 	}
@@ -181,38 +453,30 @@ pub async fn get_onchain_nft_data(
 
 // -------------- GET DELGATEE --------------
 
-/// Get the NFT/Capsule delegatee
+/// Get the NFT/Capsule delegatee, verified by storage proof
 /// # Arguments
 /// * `nft_id` - The NFT/Capsule ID
 pub async fn get_onchain_delegatee(state: &SharedState, nft_id: u32) -> Option<AccountId32> {
 	debug!("CHAIN : Delegate");
 
-	let api = get_chain_api(state).await;
-
 	let storage_address = ternoa::storage().nft().delegated_nf_ts(nft_id);
 
-	let storage = match api.storage().at_latest().await {
-		Ok(storage) => storage,
-		Err(err) => {
-			error!("CHAIN : Failed to get storage for delegatee: {err:?}");
-			set_chain_api_renew(state, true).await;
-			sentry::capture_error(&err);
-			return None;
-		},
-	};
-
-	match storage.fetch(&storage_address).await {
+	match fetch_verified_storage(state, &storage_address, Some((nft_id, CachedStorage::Delegatee)))
+		.await
+	{
 		Ok(delegated) => delegated,
 		Err(err) => {
-			error!("CHAIN : Failed to fetch NFT data for delegatee : {err:?}");
-			set_chain_api_renew(state, true).await;
-			sentry::capture_error(&err);
+			error!("CHAIN : Failed to verify NFT data for delegatee : {err:?}");
+			sentry::capture_message(
+				format!("CHAIN : Failed to verify NFT data for delegatee : {err:?}").as_str(),
+				sentry::Level::Error,
+			);
 			None
 		},
 	}
 }
 
-/// Get the NFT/Capsule rent contract
+/// Get the NFT/Capsule rent contract, verified by storage proof
 /// # Arguments
 /// * `nft_id` - The NFT/Capsule ID
 /// # Returns
@@ -220,21 +484,10 @@ pub async fn get_onchain_delegatee(state: &SharedState, nft_id: u32) -> Option<A
 pub async fn get_onchain_rent_contract(state: &SharedState, nft_id: u32) -> Option<AccountId32> {
 	debug!("CHAIN : Rent contract");
 
-	let api = get_chain_api(state).await;
-
 	let storage_address = ternoa::storage().rent().contracts(nft_id);
 
-	let storage = match api.storage().at_latest().await {
-		Ok(storage) => storage,
-		Err(err) => {
-			error!("CHAIN : Failed to get storage for rentee: {err:?}");
-			set_chain_api_renew(state, true).await;
-			sentry::capture_error(&err);
-			return None;
-		},
-	};
-
-	match storage.fetch(&storage_address).await {
+	let cache_key = Some((nft_id, CachedStorage::RentContract));
+	match fetch_verified_storage(state, &storage_address, cache_key).await {
 		Ok(rent_contract) => match rent_contract {
 			Some(data) => data.rentee,
 			_ => {
@@ -247,14 +500,60 @@ pub async fn get_onchain_rent_contract(state: &SharedState, nft_id: u32) -> Opti
 			},
 		},
 		Err(err) => {
-			error!("CHAIN : Failed to fetch NFT data: {err:?}");
-			set_chain_api_renew(state, true).await;
-			sentry::capture_error(&err);
+			error!("CHAIN : Failed to verify NFT data for rentee : {err:?}");
+			sentry::capture_message(
+				format!("CHAIN : Failed to verify NFT data for rentee : {err:?}").as_str(),
+				sentry::Level::Error,
+			);
 			None
 		},
 	}
 }
 
+// -------------- ENCLAVE BALANCE --------------
+
+/// Get the free balance of the enclave account, it pays for the oracle extrinsics
+/// The balance is read at the tracked finalized block
+/// # Returns
+/// * `u128` - The free balance of the enclave account
+pub async fn get_enclave_balance(state: &SharedState) -> Result<u128, anyhow::Error> {
+	debug!("CHAIN : Enclave balance");
+
+	let (_, block_hash, _) = match get_finalized_root(state).await {
+		Some(root) => root,
+		None => return Err(anyhow!("no finalized block is tracked yet")),
+	};
+
+	let api = get_chain_api(state).await;
+	let enclave_account = AccountId32::from(get_keypair(state).await.public().0);
+
+	let storage_address = ternoa::storage().system().account(enclave_account);
+
+	match api.storage().at(block_hash).fetch_or_default(&storage_address).await {
+		Ok(account) => Ok(account.data.free),
+		Err(err) => {
+			error!("CHAIN : Failed to fetch enclave account data : {err:?}");
+			set_chain_api_renew(state, true).await;
+			Err(err.into())
+		},
+	}
+}
+
+// -------------- CHAIN TIME --------------
+
+/// Get the time of the tracked finalized block, verified by storage proof
+/// The host clock is not trusted, certificates and CRLs are checked against this time
+/// # Returns
+/// * `i64` - Unix time in seconds
+pub async fn get_verified_chain_time(state: &SharedState) -> Result<i64, anyhow::Error> {
+	let storage_address = ternoa::storage().timestamp().now();
+
+	match fetch_verified_storage(state, &storage_address, None).await? {
+		Some(milliseconds) => Ok((milliseconds / 1000) as i64),
+		None => Err(anyhow!("timestamp of the finalized block is not set")),
+	}
+}
+
 // -------------- SECRET-NFT SYNC (ORACLE) --------------
 
 // TODO [code style] : Define macro for nft/capsule
@@ -271,6 +570,11 @@ pub async fn get_onchain_rent_contract(state: &SharedState, nft_id: u32) -> Opti
 pub async fn nft_keyshare_oracle(state: &SharedState, nft_id: u32) -> Result<H256, subxt::Error> {
 	debug!("CHAIN : NFT ORACLE");
 
+	// The account is handed over to a new enclave, it submits the extrinsics
+	if crate::replication::upgrade::is_handed_over() {
+		return Err(Error::Other("Secret-NFT Oracle : enclave account is handed over".to_string()));
+	}
+
 	let api = get_chain_api(state).await;
 
 	// Create a transaction to submit:
@@ -324,6 +628,11 @@ pub async fn capsule_keyshare_oracle(
 ) -> Result<H256, subxt::Error> {
 	debug!("CHAIN : CAPSULE ORACLE");
 
+	// The account is handed over to a new enclave, it submits the extrinsics
+	if crate::replication::upgrade::is_handed_over() {
+		return Err(Error::Other("Capsule Oracle : enclave account is handed over".to_string()));
+	}
+
 	let api = get_chain_api(state).await;
 
 	// Create a transaction to submit:
@@ -547,4 +856,32 @@ mod test {
 			);
 		}
 	}
+
+	#[test]
+	fn ownership_cache_invalidation_test() {
+		let mut cache = OwnershipCache::new(16);
+		let key = (7, CachedStorage::Delegatee);
+
+		cache.insert(key, 100, Some(vec![1]));
+		assert_eq!(cache.get(&key), Some(Some(vec![1])));
+
+		// Delegation event at block 105, value verified at block 100 is stale
+		cache.invalidate(7, 105);
+		assert_eq!(cache.get(&key), None);
+
+		// Proof at an older finalized root can not be cached anymore
+		cache.insert(key, 103, None);
+		assert_eq!(cache.get(&key), None);
+
+		cache.insert(key, 105, None);
+		assert_eq!(cache.get(&key), Some(None));
+
+		// Pruned changes do not accept proofs verified before the pruning block
+		cache.prune(110);
+		cache.insert((8, CachedStorage::NftData), 104, Some(vec![2]));
+		assert_eq!(cache.get(&(8, CachedStorage::NftData)), None);
+
+		cache.clear(120);
+		assert_eq!(cache.get(&key), None);
+	}
 }
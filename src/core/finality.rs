@@ -0,0 +1,1037 @@
+use std::{
+	collections::{BTreeMap, HashMap, VecDeque},
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use parity_scale_codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use subxt::{
+	config::{
+		substrate::{BlakeTwo256, DigestItem, SubstrateHeader},
+		Header,
+	},
+	ext::sp_core::{ed25519, Pair, H256},
+	rpc::types::Bytes,
+	rpc_params,
+};
+use tokio::{sync::Mutex, time::timeout};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	constants::{GRANDPA_ANCHOR_FILE, GRANDPA_CHECKPOINT_FILE, RETRY_DELAY},
+	core::chain::{read_verified_storage, ternoa, DefaultApi},
+	server::state::{get_chain_api, SharedState},
+};
+
+pub const GRANDPA_ENGINE_ID: [u8; 4] = *b"FRNK";
+
+// Maximum number of headers waiting for a justification, before giving up on the rpc node
+const MAX_UNJUSTIFIED_HEADERS: usize = 1024;
+// Maximum number of received justifications waiting for their target header
+const MAX_STORED_JUSTIFICATIONS: usize = 64;
+// Maximum number of headers linked to a fetched finality proof, after a long outage
+const MAX_CATCH_UP_HEADERS: u32 = 65_536;
+
+type ChainHeader = SubstrateHeader<u32, BlakeTwo256>;
+type AuthorityList = Vec<(ed25519::Public, u64)>;
+
+/* ---------------
+ GRANDPA TYPES
+----------------*/
+
+// Same SCALE layout as finality-grandpa Precommit
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct Precommit {
+	pub target_hash: H256,
+	pub target_number: u32,
+}
+
+#[derive(Decode, Debug, Clone)]
+pub struct SignedPrecommit {
+	pub precommit: Precommit,
+	pub signature: ed25519::Signature,
+	pub id: ed25519::Public,
+}
+
+#[derive(Decode, Debug, Clone)]
+pub struct Commit {
+	pub target_hash: H256,
+	pub target_number: u32,
+	pub precommits: Vec<SignedPrecommit>,
+}
+
+#[derive(Decode, Debug, Clone)]
+pub struct GrandpaJustification {
+	pub round: u64,
+	pub commit: Commit,
+	pub votes_ancestries: Vec<ChainHeader>,
+}
+
+#[derive(Encode, Decode, Debug, Clone)]
+struct ScheduledChange {
+	next_authorities: AuthorityList,
+	delay: u32,
+}
+
+// Header digest messages of pallet-grandpa
+#[allow(dead_code)]
+#[derive(Encode, Decode, Debug, Clone)]
+enum ConsensusLog {
+	#[codec(index = 1)]
+	ScheduledChange(ScheduledChange),
+	#[codec(index = 2)]
+	ForcedChange(u32, ScheduledChange),
+	#[codec(index = 3)]
+	OnDisabled(u64),
+	#[codec(index = 4)]
+	Pause(u32),
+	#[codec(index = 5)]
+	Resume(u32),
+}
+
+// Pallet-grandpa PendingChange storage
+#[derive(Decode, Debug, Clone)]
+struct StoredPendingChange {
+	scheduled_at: u32,
+	delay: u32,
+	next_authorities: AuthorityList,
+	forced: Option<u32>,
+}
+
+// Response of grandpa_proveFinality : justification of the last block of an authority set,
+// or of the latest justified block of the current set
+#[allow(dead_code)]
+#[derive(Decode, Debug, Clone)]
+struct FinalityProof {
+	block: H256,
+	justification: Vec<u8>,
+	unknown_headers: Vec<ChainHeader>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthoritySet {
+	pub set_id: u64,
+	pub authorities: AuthorityList,
+}
+
+/// Trusted start of the tracked chain : the checkpoint bundled in the trusted files,
+/// or the last authority set enacted after it, sealed by the enclave
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GrandpaCheckpoint {
+	pub block_number: u32,
+	pub block_hash: H256,
+	pub set_id: u64,
+	// SCALE encoded authority list, as returned by GrandpaApi_grandpa_authorities
+	pub authorities: Bytes,
+}
+
+/// Justifications received from the rpc node, indexed by target block hash
+pub type JustificationStore = Arc<Mutex<HashMap<H256, GrandpaJustification>>>;
+
+/* ---------------
+ VERIFIER
+----------------*/
+
+/// Follows the finalized header chain and only advances when a GRANDPA justification
+/// of the current authority set is verified for it.
+pub struct FinalityVerifier {
+	authority_set: AuthoritySet,
+	// Scheduled authority-set changes in activation order : (Activation block number, Next
+	// authorities)
+	pending_changes: VecDeque<(u32, AuthorityList)>,
+	// Last header linked to the tracked chain : (BlockNumber, BlockHash)
+	last_imported: (u32, H256),
+	// Headers linked to the tracked chain, waiting for a justification
+	unjustified: BTreeMap<u32, (H256, H256)>,
+	// Last GRANDPA verified header : (BlockNumber, BlockHash, StateRoot)
+	last_verified: (u32, H256, H256),
+	last_verified_at: Instant,
+	// A finality proof advanced the verified header since the last report
+	caught_up: bool,
+	justifications: JustificationStore,
+}
+
+impl FinalityVerifier {
+	/// Create the verifier from the pinned checkpoint, or from the sealed anchor following it
+	/// Nothing is trusted from the rpc node : the checkpoint header must match the pinned hash,
+	/// and its pending authority-set change is read with a storage proof against its state-root.
+	/// # Arguments
+	/// * `api` - The chain API
+	/// * `justifications` - Store filled by the justification subscription
+	pub async fn new(
+		api: &DefaultApi,
+		justifications: JustificationStore,
+	) -> Result<FinalityVerifier> {
+		let checkpoint = load_checkpoint()?;
+
+		let anchor = match api.rpc().header(Some(checkpoint.block_hash)).await? {
+			Some(header)
+				if header.hash() == checkpoint.block_hash &&
+					header.number == checkpoint.block_number =>
+				header,
+			Some(_) => return Err(anyhow!("FINALITY : checkpoint header does not match its hash")),
+			None => return Err(anyhow!("FINALITY : checkpoint header not found")),
+		};
+
+		let authorities = AuthorityList::decode(&mut checkpoint.authorities.0.as_slice())?;
+
+		let pending_key =
+			api.storage().address_bytes(&ternoa::storage().grandpa().pending_change())?;
+		let pending_changes = match read_verified_storage(
+			api,
+			&pending_key,
+			checkpoint.block_hash,
+			anchor.state_root,
+		)
+		.await?
+		{
+			Some(raw) => {
+				let stored = StoredPendingChange::decode(&mut raw.as_slice())?;
+				if stored.forced.is_some() {
+					return Err(anyhow!(
+							"FINALITY : a forced authority-set change is pending at the checkpoint, pin a checkpoint after it"
+						));
+				}
+				let activation = stored.scheduled_at.saturating_add(stored.delay);
+				VecDeque::from([(activation, stored.next_authorities)])
+			},
+			None => VecDeque::new(),
+		};
+
+		info!(
+			"FINALITY : anchor block {} , authority set id {} with {} authorities",
+			anchor.number,
+			checkpoint.set_id,
+			authorities.len()
+		);
+
+		Ok(FinalityVerifier::from_anchor(
+			AuthoritySet { set_id: checkpoint.set_id, authorities },
+			(anchor.number, checkpoint.block_hash, anchor.state_root),
+			pending_changes,
+			justifications,
+		))
+	}
+
+	// Verifier tracking the chain from a trusted header : (BlockNumber, BlockHash, StateRoot)
+	fn from_anchor(
+		authority_set: AuthoritySet,
+		anchor: (u32, H256, H256),
+		pending_changes: VecDeque<(u32, AuthorityList)>,
+		justifications: JustificationStore,
+	) -> FinalityVerifier {
+		FinalityVerifier {
+			authority_set,
+			pending_changes,
+			last_imported: (anchor.0, anchor.1),
+			unjustified: BTreeMap::new(),
+			last_verified: anchor,
+			last_verified_at: Instant::now(),
+			caught_up: false,
+			justifications,
+		}
+	}
+
+	/// Last GRANDPA verified header : (BlockNumber, BlockHash, StateRoot)
+	pub fn last_verified(&self) -> (u32, H256, H256) {
+		self.last_verified
+	}
+
+	/// Last header linked to the tracked chain : (BlockNumber, BlockHash)
+	pub fn last_imported(&self) -> (u32, H256) {
+		self.last_imported
+	}
+
+	/// Time since the verified finalized block has been advanced
+	pub fn verified_elapsed(&self) -> Duration {
+		self.last_verified_at.elapsed()
+	}
+
+	/// Link a finalized header to the tracked chain
+	/// Missing headers (i.e after rpc reconnection) are fetched by walking back parent hashes,
+	/// a gap longer than the unjustified window is closed with finality proofs of the node
+	/// # Arguments
+	/// * `api` - The chain API
+	/// * `header` - The finalized header received from rpc node
+	/// # Returns
+	/// * `bool` - false if the header is already part of the tracked chain
+	pub async fn import_header(&mut self, api: &DefaultApi, header: &ChainHeader) -> Result<bool> {
+		if header.number <= self.last_imported.0 {
+			return Ok(false);
+		}
+
+		if (header.number - self.last_imported.0) as usize > MAX_UNJUSTIFIED_HEADERS {
+			self.catch_up(api, header.number).await?;
+
+			// The header is part of a linked finality proof segment
+			if header.number <= self.last_imported.0 {
+				return Ok(true);
+			}
+		}
+
+		self.import_segment(api, header).await?;
+
+		if self.unjustified.len() > MAX_UNJUSTIFIED_HEADERS {
+			return Err(anyhow!(
+				"FINALITY : {} headers are waiting for a justification",
+				self.unjustified.len()
+			));
+		}
+
+		Ok(true)
+	}
+
+	// Walk back from a header to the last imported one, and link the segment to the tracked chain
+	async fn import_segment(&mut self, api: &DefaultApi, header: &ChainHeader) -> Result<()> {
+		let segment = fetch_segment(api, header, self.last_imported.0).await?;
+		self.link_segment(segment)
+	}
+
+	// Link headers, newest first, to the last imported one with their authority-set changes
+	fn link_segment(&mut self, segment: Vec<ChainHeader>) -> Result<()> {
+		let mut parent = self.last_imported;
+		let mut linked = Vec::with_capacity(segment.len());
+		let mut changes = Vec::new();
+
+		// Hashes are computed locally, so parent links can not be forged by the rpc node
+		for header in segment.iter().rev() {
+			if header.number != parent.0 + 1 || header.parent_hash != parent.1 {
+				return Err(anyhow!(
+					"FINALITY : header {} does not extend the tracked chain at block {}",
+					header.number,
+					parent.0
+				));
+			}
+
+			for (next_authorities, delay) in scheduled_changes(header)? {
+				debug!(
+					"FINALITY : authority-set change scheduled at block {} with delay {delay}",
+					header.number
+				);
+				changes.push((header.number.saturating_add(delay), next_authorities));
+			}
+
+			let hash = header.hash();
+			linked.push((header.number, hash, header.state_root));
+			parent = (header.number, hash);
+		}
+
+		for (number, hash, state_root) in linked {
+			self.unjustified.insert(number, (hash, state_root));
+		}
+		self.last_imported = parent;
+		self.pending_changes.extend(changes);
+
+		Ok(())
+	}
+
+	/// Re-anchor the tracked chain after a long outage
+	/// The node proves the finality of a block after the last imported one, the headers up to
+	/// it are linked to the tracked chain and the proof is verified like any justification.
+	/// A rejected proof leaves the tracked chain as it was.
+	/// # Arguments
+	/// * `api` - The chain API
+	/// * `target_number` - Number of the received finalized header
+	async fn catch_up(&mut self, api: &DefaultApi, target_number: u32) -> Result<()> {
+		while target_number.saturating_sub(self.last_imported.0) as usize > MAX_UNJUSTIFIED_HEADERS
+		{
+			let from_number = self.last_imported.0 + 1;
+			let proof = fetch_finality_proof(api, from_number).await?;
+
+			let proof_header = match api.rpc().header(Some(proof.block)).await? {
+				Some(header) if header.hash() == proof.block => header,
+				_ => return Err(anyhow!("FINALITY : header of finality proof is not found")),
+			};
+
+			if proof_header.number < from_number ||
+				proof_header.number - self.last_imported.0 > MAX_CATCH_UP_HEADERS
+			{
+				return Err(anyhow!(
+					"FINALITY : finality proof of block {} can not close the gap from block {}",
+					proof_header.number,
+					self.last_imported.0
+				));
+			}
+
+			let justification = GrandpaJustification::decode(&mut proof.justification.as_slice())?;
+
+			info!(
+				"FINALITY : catching up from block {} with the finality proof of block {}",
+				self.last_imported.0, proof_header.number
+			);
+
+			let segment = fetch_segment(api, &proof_header, self.last_imported.0).await?;
+			self.accept_finality_proof(Some(api), segment, justification).await?;
+		}
+
+		Ok(())
+	}
+
+	// Link a finality proof segment and verify the justification of its last header,
+	// a rejected proof leaves the tracked chain as it was
+	async fn accept_finality_proof(
+		&mut self,
+		api: Option<&DefaultApi>,
+		segment: Vec<ChainHeader>,
+		justification: GrandpaJustification,
+	) -> Result<()> {
+		let (proof_number, proof_hash) = match segment.first() {
+			Some(header) => (header.number, header.hash()),
+			None => return Err(anyhow!("FINALITY : finality proof without header")),
+		};
+
+		let snapshot = (
+			self.authority_set.clone(),
+			self.pending_changes.clone(),
+			self.last_imported,
+			self.unjustified.clone(),
+			self.last_verified,
+		);
+
+		let verified = match self.link_segment(segment) {
+			Ok(_) => {
+				self.justifications.lock().await.insert(proof_hash, justification);
+				self.advance_verified(api).await
+			},
+			Err(err) => Err(err),
+		};
+
+		if verified.is_ok() && self.last_verified.0 == proof_number {
+			self.caught_up = true;
+			return Ok(());
+		}
+
+		(
+			self.authority_set,
+			self.pending_changes,
+			self.last_imported,
+			self.unjustified,
+			self.last_verified,
+		) = snapshot;
+		self.justifications.lock().await.remove(&proof_hash);
+
+		Err(anyhow!(
+			"FINALITY : finality proof of block {proof_number} is not verified : {:?}",
+			verified.err()
+		))
+	}
+
+	/// Advance the verified finalized block with available justifications
+	/// An authority-set change block is always justified by the old set before enacting the change
+	/// # Arguments
+	/// * `api` - The chain API, used to fetch mandatory justifications
+	/// # Returns
+	/// * `Option<(u32, H256, H256)>` - New verified header, None if nothing could be verified
+	pub async fn try_finalize(&mut self, api: &DefaultApi) -> Result<Option<(u32, H256, H256)>> {
+		self.advance_verified(Some(api)).await
+	}
+
+	// Mandatory justifications which are not received are fetched from the node, if an api is given
+	async fn advance_verified(
+		&mut self,
+		api: Option<&DefaultApi>,
+	) -> Result<Option<(u32, H256, H256)>> {
+		let mut advanced = std::mem::take(&mut self.caught_up);
+
+		loop {
+			// Authority-set change must be justified with the mandatory justification
+			if let Some((activation, next_authorities)) = self.pending_changes.front().cloned() {
+				if let Some((hash, state_root)) = self.unjustified.get(&activation).copied() {
+					let justification = match (self.take_justification(&hash).await, api) {
+						(Some(justification), _) => justification,
+						(None, Some(api)) => fetch_block_justification(api, hash).await?,
+						(None, None) => break,
+					};
+
+					self.verify_justification(&justification, activation, hash)?;
+					self.advance(activation, hash, state_root);
+
+					self.authority_set = AuthoritySet {
+						set_id: self.authority_set.set_id + 1,
+						authorities: next_authorities,
+					};
+					self.pending_changes.pop_front();
+					advanced = true;
+
+					info!(
+						"FINALITY : authority-set {} enacted at block {}",
+						self.authority_set.set_id, activation
+					);
+
+					// A restart resumes from the enacted set instead of the checkpoint
+					if let Err(err) = self.seal_anchor() {
+						warn!("FINALITY : unable to seal the enacted authority set : {err:?}");
+					}
+					continue;
+				}
+			}
+
+			// Highest header with a received justification, before any pending set change
+			let limit = match self.pending_changes.front() {
+				Some((activation, _)) => *activation,
+				None => u32::MAX,
+			};
+
+			let store = self.justifications.lock().await;
+			let candidate = self
+				.unjustified
+				.range(..limit)
+				.rev()
+				.find(|(_, (hash, _))| store.contains_key(hash))
+				.map(|(number, (hash, state_root))| (*number, *hash, *state_root));
+			drop(store);
+
+			let (number, hash, state_root) = match candidate {
+				Some(candidate) => candidate,
+				None => break,
+			};
+
+			let justification = match self.take_justification(&hash).await {
+				Some(justification) => justification,
+				None => break,
+			};
+
+			match self.verify_justification(&justification, number, hash) {
+				Ok(_) => {
+					self.advance(number, hash, state_root);
+					advanced = true;
+				},
+				Err(err) => {
+					// Keep the header unjustified, a later justification can finalize it
+					warn!("FINALITY : rejected justification for block {number} : {err:?}");
+					break;
+				},
+			}
+		}
+
+		if advanced {
+			Ok(Some(self.last_verified))
+		} else {
+			Ok(None)
+		}
+	}
+
+	fn seal_anchor(&self) -> Result<()> {
+		let (block_number, block_hash, _) = self.last_verified;
+		let anchor = GrandpaCheckpoint {
+			block_number,
+			block_hash,
+			set_id: self.authority_set.set_id,
+			authorities: Bytes(self.authority_set.authorities.encode()),
+		};
+
+		let temporary_file = format!("{GRANDPA_ANCHOR_FILE}.tmp");
+		std::fs::write(&temporary_file, serde_json::to_string(&anchor)?)?;
+		std::fs::rename(&temporary_file, GRANDPA_ANCHOR_FILE)?;
+
+		Ok(())
+	}
+
+	async fn take_justification(&self, hash: &H256) -> Option<GrandpaJustification> {
+		let mut store = self.justifications.lock().await;
+		store.remove(hash)
+	}
+
+	fn advance(&mut self, number: u32, hash: H256, state_root: H256) {
+		self.unjustified = self.unjustified.split_off(&(number + 1));
+		self.last_verified = (number, hash, state_root);
+		self.last_verified_at = Instant::now();
+	}
+
+	/// Verify a GRANDPA justification against the current authority set
+	/// # Arguments
+	/// * `justification` - Decoded GRANDPA justification
+	/// * `number` - Expected target block number
+	/// * `hash` - Expected target block hash
+	pub fn verify_justification(
+		&self,
+		justification: &GrandpaJustification,
+		number: u32,
+		hash: H256,
+	) -> Result<()> {
+		let commit = &justification.commit;
+
+		if commit.target_hash != hash || commit.target_number != number {
+			return Err(anyhow!("justification target does not match block {number}"));
+		}
+
+		let ancestry: HashMap<H256, &ChainHeader> = justification
+			.votes_ancestries
+			.iter()
+			.map(|header| (header.hash(), header))
+			.collect();
+
+		let mut voters = Vec::<ed25519::Public>::new();
+		let mut weight: u64 = 0;
+
+		for signed in &commit.precommits {
+			let voter_weight = match self
+				.authority_set
+				.authorities
+				.iter()
+				.find(|(authority, _)| *authority == signed.id)
+			{
+				Some((_, voter_weight)) => *voter_weight,
+				None => return Err(anyhow!("precommit from unknown authority {:?}", signed.id)),
+			};
+
+			if !is_descendant(&ancestry, &signed.precommit, &commit.target_hash, number) {
+				return Err(anyhow!("precommit target is not a descendant of the commit target"));
+			}
+
+			// Localized payload : (Message::Precommit, round, set_id)
+			let mut payload = vec![1u8];
+			signed.precommit.encode_to(&mut payload);
+			justification.round.encode_to(&mut payload);
+			self.authority_set.set_id.encode_to(&mut payload);
+
+			if !ed25519::Pair::verify(&signed.signature, &payload, &signed.id) {
+				return Err(anyhow!("invalid precommit signature from {:?}", signed.id));
+			}
+
+			// Equivocating voters are only counted once
+			if !voters.contains(&signed.id) {
+				voters.push(signed.id);
+				weight += voter_weight;
+			}
+		}
+
+		let total: u64 = self.authority_set.authorities.iter().map(|(_, w)| w).sum();
+		let threshold = total - total.saturating_sub(1) / 3;
+
+		if weight < threshold {
+			return Err(anyhow!("not enough precommits, weight {weight} < threshold {threshold}"));
+		}
+
+		Ok(())
+	}
+}
+
+/* ---------------
+ HELPERS
+----------------*/
+
+// Authority-set changes announced in a header digest : (Next authorities, Delay)
+// A forced change is enacted without a justification of the old set, it is refused : finality
+// is verified again from a checkpoint pinned after it.
+fn scheduled_changes(header: &ChainHeader) -> Result<Vec<(AuthorityList, u32)>> {
+	let mut changes = Vec::new();
+
+	for log in &header.digest.logs {
+		if let DigestItem::Consensus(engine_id, data) = log {
+			if *engine_id != GRANDPA_ENGINE_ID {
+				continue;
+			}
+
+			match ConsensusLog::decode(&mut data.as_slice()) {
+				Ok(ConsensusLog::ScheduledChange(change)) =>
+					changes.push((change.next_authorities, change.delay)),
+				Ok(ConsensusLog::ForcedChange(..)) =>
+					return Err(anyhow!(
+						"FINALITY : forced authority-set change at block {}, a checkpoint after it must be pinned",
+						header.number
+					)),
+				Ok(_) => {},
+				Err(err) => warn!("FINALITY : unable to decode grandpa digest : {err:?}"),
+			}
+		}
+	}
+
+	Ok(changes)
+}
+
+// Walk back from the precommit target to the commit target using the votes ancestries
+fn is_descendant(
+	ancestry: &HashMap<H256, &ChainHeader>,
+	precommit: &Precommit,
+	base_hash: &H256,
+	base_number: u32,
+) -> bool {
+	let mut current = precommit.target_hash;
+
+	loop {
+		if current == *base_hash {
+			return true;
+		}
+
+		match ancestry.get(&current) {
+			Some(header) if header.number > base_number => current = header.parent_hash,
+			_ => return false,
+		}
+	}
+}
+
+// Headers from a finalized header back to the one after the last imported block, newest first
+async fn fetch_segment(
+	api: &DefaultApi,
+	header: &ChainHeader,
+	last_number: u32,
+) -> Result<Vec<ChainHeader>> {
+	let mut segment = vec![header.clone()];
+	let mut current = header.clone();
+
+	while current.number > last_number + 1 {
+		let parent_hash = current.parent_hash;
+		current = match api.rpc().header(Some(parent_hash)).await? {
+			Some(parent) if parent.hash() == parent_hash => parent,
+			Some(_) => return Err(anyhow!("FINALITY : wrong parent header {parent_hash:?}")),
+			None => return Err(anyhow!("FINALITY : missing parent header {parent_hash:?}")),
+		};
+		segment.push(current.clone());
+	}
+
+	Ok(segment)
+}
+
+// Pinned checkpoint, or the sealed anchor when a later authority set was enacted after it
+fn load_checkpoint() -> Result<GrandpaCheckpoint> {
+	let content = std::fs::read_to_string(GRANDPA_CHECKPOINT_FILE)
+		.map_err(|err| anyhow!("FINALITY : no checkpoint at {GRANDPA_CHECKPOINT_FILE} : {err}"))?;
+	let checkpoint: GrandpaCheckpoint = serde_json::from_str(&content)?;
+
+	let anchor: Option<GrandpaCheckpoint> = match std::fs::read_to_string(GRANDPA_ANCHOR_FILE) {
+		Ok(content) => Some(serde_json::from_str(&content)?),
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+		Err(err) => return Err(err.into()),
+	};
+
+	match anchor {
+		Some(anchor)
+			if anchor.block_number > checkpoint.block_number &&
+				anchor.set_id > checkpoint.set_id =>
+		{
+			debug!(
+				"FINALITY : resume from the authority set sealed at block {}",
+				anchor.block_number
+			);
+			Ok(anchor)
+		},
+		_ => Ok(checkpoint),
+	}
+}
+
+// Finality proof of the first justified block from a block number
+async fn fetch_finality_proof(api: &DefaultApi, block_number: u32) -> Result<FinalityProof> {
+	let encoded: Option<Bytes> =
+		api.rpc().request("grandpa_proveFinality", rpc_params![block_number]).await?;
+
+	match encoded {
+		Some(encoded) => Ok(FinalityProof::decode(&mut encoded.0.as_slice())?),
+		None => Err(anyhow!("FINALITY : no finality proof from block {block_number}")),
+	}
+}
+
+// Stored justification of a block, mandatory for authority-set change blocks
+async fn fetch_block_justification(api: &DefaultApi, hash: H256) -> Result<GrandpaJustification> {
+	let block = match api.rpc().block(Some(hash)).await? {
+		Some(block) => block,
+		None => return Err(anyhow!("FINALITY : block {hash:?} not found")),
+	};
+
+	let encoded = block
+		.justifications
+		.unwrap_or_default()
+		.into_iter()
+		.find(|(engine_id, _)| *engine_id == GRANDPA_ENGINE_ID)
+		.map(|(_, encoded)| encoded)
+		.ok_or_else(|| anyhow!("FINALITY : no grandpa justification for block {hash:?}"))?;
+
+	Ok(GrandpaJustification::decode(&mut encoded.as_slice())?)
+}
+
+/// Subscribe to GRANDPA justifications of the rpc node and keep them for the verifier
+/// Resubscribes with the current chain API of the state when the subscription ends
+/// # Arguments
+/// * `state` - The shared state
+/// * `justifications` - Store read by the verifier
+pub fn spawn_justification_listener(state: SharedState, justifications: JustificationStore) {
+	tokio::spawn(async move {
+		loop {
+			let api = get_chain_api(&state).await;
+
+			let mut subscription = match api
+				.rpc()
+				.subscribe::<Bytes>(
+					"grandpa_subscribeJustifications",
+					rpc_params![],
+					"grandpa_unsubscribeJustifications",
+				)
+				.await
+			{
+				Ok(sub) => sub,
+				Err(err) => {
+					error!("FINALITY : Unable to subscribe to justifications {err:?}");
+					tokio::time::sleep(Duration::from_secs(RETRY_DELAY.into())).await;
+					continue;
+				},
+			};
+
+			while let Ok(Some(Ok(encoded))) =
+				timeout(Duration::from_secs(60), subscription.next()).await
+			{
+				let justification = match GrandpaJustification::decode(&mut encoded.0.as_slice()) {
+					Ok(justification) => justification,
+					Err(err) => {
+						warn!("FINALITY : unable to decode justification {err:?}");
+						continue;
+					},
+				};
+
+				let mut store = justifications.lock().await;
+				if store.len() >= MAX_STORED_JUSTIFICATIONS {
+					// Oldest targets are useless once a newer block is justified
+					if let Some(oldest) = store
+						.iter()
+						.min_by_key(|(_, j)| j.commit.target_number)
+						.map(|(hash, _)| *hash)
+					{
+						store.remove(&oldest);
+					}
+				}
+				store.insert(justification.commit.target_hash, justification);
+			}
+
+			warn!("FINALITY : justification subscription ended, resubscribing ...");
+			tokio::time::sleep(Duration::from_secs(RETRY_DELAY.into())).await;
+		}
+	});
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use subxt::config::substrate::Digest;
+
+	const ROUND: u64 = 7;
+
+	fn authorities(seeds: &[u8]) -> (Vec<ed25519::Pair>, AuthorityList) {
+		let pairs: Vec<ed25519::Pair> =
+			seeds.iter().map(|seed| ed25519::Pair::from_seed(&[*seed; 32])).collect();
+		let list = pairs.iter().map(|pair| (pair.public(), 1)).collect();
+		(pairs, list)
+	}
+
+	fn header(number: u32, parent_hash: H256, logs: Vec<DigestItem>) -> ChainHeader {
+		ChainHeader {
+			parent_hash,
+			number,
+			state_root: H256::from_low_u64_be(number.into()),
+			extrinsics_root: H256::zero(),
+			digest: Digest { logs },
+		}
+	}
+
+	fn grandpa_log(log: ConsensusLog) -> DigestItem {
+		DigestItem::Consensus(GRANDPA_ENGINE_ID, log.encode())
+	}
+
+	// Headers following a parent, newest first, with digest logs at given block numbers
+	fn segment(parent: &ChainHeader, count: u32, logs: &[(u32, ConsensusLog)]) -> Vec<ChainHeader> {
+		let mut headers = Vec::new();
+		let mut parent_hash = parent.hash();
+
+		for number in parent.number + 1..=parent.number + count {
+			let digest = logs
+				.iter()
+				.filter(|(at, _)| *at == number)
+				.map(|(_, log)| grandpa_log(log.clone()))
+				.collect();
+			let current = header(number, parent_hash, digest);
+			parent_hash = current.hash();
+			headers.push(current);
+		}
+
+		headers.reverse();
+		headers
+	}
+
+	fn justification(
+		pairs: &[ed25519::Pair],
+		set_id: u64,
+		number: u32,
+		hash: H256,
+	) -> GrandpaJustification {
+		let precommit = Precommit { target_hash: hash, target_number: number };
+		let precommits = pairs
+			.iter()
+			.map(|pair| {
+				let mut payload = vec![1u8];
+				precommit.encode_to(&mut payload);
+				ROUND.encode_to(&mut payload);
+				set_id.encode_to(&mut payload);
+				SignedPrecommit {
+					precommit: precommit.clone(),
+					signature: pair.sign(&payload),
+					id: pair.public(),
+				}
+			})
+			.collect();
+
+		GrandpaJustification {
+			round: ROUND,
+			commit: Commit { target_hash: hash, target_number: number, precommits },
+			votes_ancestries: vec![],
+		}
+	}
+
+	fn verifier(set_id: u64, list: AuthorityList, anchor: &ChainHeader) -> FinalityVerifier {
+		FinalityVerifier::from_anchor(
+			AuthoritySet { set_id, authorities: list },
+			(anchor.number, anchor.hash(), anchor.state_root),
+			VecDeque::new(),
+			Arc::new(Mutex::new(HashMap::new())),
+		)
+	}
+
+	fn standard_change(list: &AuthorityList, delay: u32) -> ConsensusLog {
+		ConsensusLog::ScheduledChange(ScheduledChange { next_authorities: list.clone(), delay })
+	}
+
+	#[test]
+	fn verify_justification_test() {
+		let (pairs, list) = authorities(&[1, 2, 3, 4]);
+		let anchor = header(0, H256::zero(), vec![]);
+		let target = header(1, anchor.hash(), vec![]);
+		let verifier = verifier(3, list, &anchor);
+
+		// 3 of 4 authorities reach the 2/3 + 1 threshold
+		let accepted = justification(&pairs[..3], 3, 1, target.hash());
+		assert!(verifier.verify_justification(&accepted, 1, target.hash()).is_ok());
+
+		let insufficient = justification(&pairs[..2], 3, 1, target.hash());
+		assert!(verifier.verify_justification(&insufficient, 1, target.hash()).is_err());
+
+		// Signed for another authority set
+		let wrong_set = justification(&pairs[..3], 2, 1, target.hash());
+		assert!(verifier.verify_justification(&wrong_set, 1, target.hash()).is_err());
+
+		let mut bad_signature = accepted.clone();
+		bad_signature.commit.precommits[0].signature = ed25519::Signature::from_raw([0u8; 64]);
+		assert!(verifier.verify_justification(&bad_signature, 1, target.hash()).is_err());
+
+		let (strangers, _) = authorities(&[5, 6, 7]);
+		let unknown = justification(&strangers, 3, 1, target.hash());
+		assert!(verifier.verify_justification(&unknown, 1, target.hash()).is_err());
+
+		assert!(verifier.verify_justification(&accepted, 2, target.hash()).is_err());
+		assert!(verifier.verify_justification(&accepted, 1, anchor.hash()).is_err());
+	}
+
+	#[test]
+	fn is_descendant_test() {
+		let base = header(10, H256::zero(), vec![]);
+		let child = header(11, base.hash(), vec![]);
+		let grandchild = header(12, child.hash(), vec![]);
+		let fork = header(11, H256::repeat_byte(9), vec![]);
+
+		let ancestry: HashMap<H256, &ChainHeader> =
+			[&child, &grandchild, &fork].into_iter().map(|h| (h.hash(), h)).collect();
+
+		let precommit = |target: &ChainHeader| Precommit {
+			target_hash: target.hash(),
+			target_number: target.number,
+		};
+
+		assert!(is_descendant(&ancestry, &precommit(&base), &base.hash(), 10));
+		assert!(is_descendant(&ancestry, &precommit(&grandchild), &base.hash(), 10));
+		assert!(!is_descendant(&ancestry, &precommit(&fork), &base.hash(), 10));
+
+		// Ancestry is not given
+		let partial: HashMap<H256, &ChainHeader> = [(grandchild.hash(), &grandchild)].into();
+		assert!(!is_descendant(&partial, &precommit(&grandchild), &base.hash(), 10));
+	}
+
+	#[test]
+	fn scheduled_changes_test() {
+		let (_, list) = authorities(&[1, 2]);
+
+		let scheduled = header(5, H256::zero(), vec![grandpa_log(standard_change(&list, 3))]);
+		let changes = scheduled_changes(&scheduled).unwrap();
+		assert_eq!(changes.len(), 1);
+		assert_eq!(changes[0], (list.clone(), 3));
+
+		let forced = header(
+			5,
+			H256::zero(),
+			vec![grandpa_log(ConsensusLog::ForcedChange(
+				4,
+				ScheduledChange { next_authorities: list.clone(), delay: 0 },
+			))],
+		);
+		assert!(scheduled_changes(&forced).is_err());
+
+		let other_engine = header(
+			5,
+			H256::zero(),
+			vec![DigestItem::Consensus(*b"BABE", standard_change(&list, 3).encode())],
+		);
+		assert!(scheduled_changes(&other_engine).unwrap().is_empty());
+
+		let none = header(5, H256::zero(), vec![grandpa_log(ConsensusLog::Pause(2))]);
+		assert!(scheduled_changes(&none).unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn catch_up_test() {
+		let (pairs, list) = authorities(&[1, 2, 3]);
+		let anchor = header(0, H256::zero(), vec![]);
+
+		// Accepted finality proof
+		let mut accepted = verifier(0, list.clone(), &anchor);
+		let headers = segment(&anchor, 5, &[]);
+		let proof = justification(&pairs, 0, 5, headers[0].hash());
+		accepted.accept_finality_proof(None, headers.clone(), proof).await.unwrap();
+		assert_eq!(accepted.last_verified().0, 5);
+		assert_eq!(accepted.last_imported(), (5, headers[0].hash()));
+		// The proof is reported once by the next finalization
+		assert!(accepted.advance_verified(None).await.unwrap().is_some());
+		assert!(accepted.advance_verified(None).await.unwrap().is_none());
+
+		// Rejected finality proof leaves the tracked chain as it was
+		let mut rejected = verifier(0, list.clone(), &anchor);
+		let (strangers, _) = authorities(&[4, 5, 6]);
+		let proof = justification(&strangers, 0, 5, headers[0].hash());
+		assert!(rejected.accept_finality_proof(None, headers.clone(), proof).await.is_err());
+		assert_eq!(rejected.last_imported(), (0, anchor.hash()));
+		assert_eq!(rejected.last_verified().0, 0);
+		assert!(rejected.unjustified.is_empty());
+
+		// Segment not linked to the anchor
+		let orphan = segment(&header(0, H256::repeat_byte(1), vec![]), 5, &[]);
+		let proof = justification(&pairs, 0, 5, orphan[0].hash());
+		assert!(rejected.accept_finality_proof(None, orphan, proof).await.is_err());
+		assert_eq!(rejected.last_imported(), (0, anchor.hash()));
+
+		// Every change of the segment is enacted in order, with the mandatory justifications
+		let (next_pairs, next_list) = authorities(&[7, 8, 9]);
+		let (last_pairs, last_list) = authorities(&[10, 11, 12]);
+		let mut changing = verifier(0, list.clone(), &anchor);
+		let headers = segment(
+			&anchor,
+			6,
+			&[(1, standard_change(&next_list, 1)), (3, standard_change(&last_list, 1))],
+		);
+		let hash_of = |number: u32| headers[(6 - number) as usize].hash();
+		{
+			let mut store = changing.justifications.lock().await;
+			store.insert(hash_of(2), justification(&pairs, 0, 2, hash_of(2)));
+			store.insert(hash_of(4), justification(&next_pairs, 1, 4, hash_of(4)));
+		}
+		let proof = justification(&last_pairs, 2, 6, hash_of(6));
+		changing.accept_finality_proof(None, headers.clone(), proof).await.unwrap();
+		assert_eq!(changing.authority_set.set_id, 2);
+		assert_eq!(changing.authority_set.authorities, last_list);
+		assert!(changing.pending_changes.is_empty());
+		assert_eq!(changing.last_verified().0, 6);
+
+		// Mandatory justification is missing : the change can not be enacted
+		let mut missing = verifier(0, list, &anchor);
+		let proof = justification(&last_pairs, 2, 6, hash_of(6));
+		assert!(missing.accept_finality_proof(None, headers, proof).await.is_err());
+		assert_eq!(missing.authority_set.set_id, 0);
+		assert!(missing.pending_changes.is_empty());
+		assert_eq!(missing.last_imported(), (0, anchor.hash()));
+	}
+}
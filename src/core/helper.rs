@@ -1,9 +1,10 @@
 use std::{collections::BTreeMap, path::Path};
 
 use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, warn};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum NftType {
 	Secret,
 	Capsule,
@@ -1,6 +1,8 @@
 pub mod capsule;
 pub mod chain;
+pub mod finality;
 pub mod helper;
 pub mod log;
 pub mod nft;
+pub mod runtime;
 pub mod verify;
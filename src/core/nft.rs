@@ -1,7 +1,7 @@
 use crate::{
 	core::helper,
 	server::state::{
-		get_accountid, get_blocknumber, get_nft_availability, remove_nft_availability,
+		get_accountid, get_balance, get_blocknumber, get_nft_availability, remove_nft_availability,
 		set_chain_api_renew, set_nft_availability, SharedState,
 	},
 };
@@ -16,7 +16,7 @@ use std::{
 use tracing::{debug, error, info, warn};
 
 use crate::{
-	constants::SEALPATH,
+	constants::{MIN_BALANCE_FOR_STORE, SEALPATH},
 	core::{
 		chain::{get_onchain_nft_data, nft_keyshare_oracle},
 		log::*,
@@ -351,6 +351,46 @@ pub async fn nft_store_keyshare(
 				);
 			};
 
+			// IS ENCLAVE ACCOUNT ABLE TO PAY FOR THE ORACLE EXTRINSIC?
+			// An unknown balance (i.e the rpc node was not reachable) is not checked
+			let enclave_balance = get_balance(&state).await.unwrap_or(u128::MAX);
+			if enclave_balance < MIN_BALANCE_FOR_STORE {
+				let status = ReturnStatus::LOWBALANCE;
+				let message = format!(
+					"TEE Key-share {:?}: enclave account balance is too low, nft_id : {}, requester : {}, balance : {}",
+					APICALL::NFTSTORE,
+					verified_data.nft_id,
+					request.owner_address,
+					enclave_balance
+				);
+
+				error!(message);
+
+				sentry::with_scope(
+					|scope| {
+						scope.set_tag("nft-store-keyshare", verified_data.nft_id.to_string());
+					},
+					|| sentry::capture_message(&message, sentry::Level::Error),
+				);
+
+				let description =
+					"Error storing NFT key-share to TEE : enclave account balance is too low, use another enclave please."
+						.to_string();
+
+				return (
+					StatusCode::SERVICE_UNAVAILABLE,
+					Json(
+						to_value(ApiErrorResponse {
+							status,
+							nft_id: verified_data.nft_id,
+							enclave_account,
+							description,
+						})
+						.unwrap(),
+					),
+				);
+			}
+
 			// Does NFTID exist as Secret-NFT ?
 			if let Some(av) = get_nft_availability(&state, verified_data.nft_id).await {
 				// Only Capsule is mutable
@@ -1074,6 +1114,19 @@ pub async fn nft_remove_keyshare(
 
 			remove_nft_availability(&state, request_data.nft_id).await;
 
+			// Incremental backups list the removal, a restore must not bring the keyshare back
+			let block_number = get_blocknumber(&state).await;
+			if let Err(err) = crate::replication::manifest::record_tombstone(
+				request_data.nft_id,
+				helper::NftType::Secret,
+				block_number,
+			) {
+				error!(
+					"REMOVE NFT : Error recording the removal of nft_id = {} : {:?}",
+					request_data.nft_id, err
+				);
+			}
+
 			info!(
 				"REMOVE NFT :  Keyshare is successfully removed from enclave. nft_id = {}",
 				request_data.nft_id
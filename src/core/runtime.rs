@@ -0,0 +1,407 @@
+use std::time::Duration;
+
+use subxt::{
+	client::OfflineClientT,
+	dynamic::Value,
+	events::StaticEvent,
+	ext::{
+		codec::Decode,
+		scale_decode::DecodeAsType,
+		scale_value::{Composite, ValueDef},
+	},
+	metadata::Metadata,
+	storage::Storage,
+	utils::AccountId32,
+	OnlineClient, PolkadotConfig,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	constants::RETRY_DELAY,
+	core::chain::ternoa,
+	server::state::{get_chain_api, set_runtime_status, SharedState},
+};
+
+/* ---------------
+ DYNAMIC EVENTS
+----------------*/
+
+// Events are decoded by field name against the metadata of the running chain,
+// not against the metadata used to build the binary.
+
+#[derive(DecodeAsType, Debug)]
+#[decode_as_type(crate_path = "::subxt::ext::scale_decode")]
+pub struct SecretNFTSynced {
+	pub nft_id: u32,
+}
+
+impl StaticEvent for SecretNFTSynced {
+	const PALLET: &'static str = "NFT";
+	const EVENT: &'static str = "SecretNFTSynced";
+}
+
+#[derive(DecodeAsType, Debug)]
+#[decode_as_type(crate_path = "::subxt::ext::scale_decode")]
+pub struct CapsuleSynced {
+	pub nft_id: u32,
+}
+
+impl StaticEvent for CapsuleSynced {
+	const PALLET: &'static str = "NFT";
+	const EVENT: &'static str = "CapsuleSynced";
+}
+
+#[derive(DecodeAsType, Debug)]
+#[decode_as_type(crate_path = "::subxt::ext::scale_decode")]
+pub struct ShardAdded {
+	pub nft_id: u32,
+	pub enclave: AccountId32,
+}
+
+impl StaticEvent for ShardAdded {
+	const PALLET: &'static str = "NFT";
+	const EVENT: &'static str = "ShardAdded";
+}
+
+#[derive(DecodeAsType, Debug)]
+#[decode_as_type(crate_path = "::subxt::ext::scale_decode")]
+pub struct CapsuleShardAdded {
+	pub nft_id: u32,
+	pub enclave: AccountId32,
+}
+
+impl StaticEvent for CapsuleShardAdded {
+	const PALLET: &'static str = "NFT";
+	const EVENT: &'static str = "CapsuleShardAdded";
+}
+
+/* ---------------
+ DYNAMIC STORAGE
+----------------*/
+
+/// Get the operator of an enclave account, with a storage address resolved at runtime
+pub async fn enclave_account_operator(
+	storage: &Storage<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+	enclave_account: &AccountId32,
+) -> Result<Option<AccountId32>, subxt::Error> {
+	let address = subxt::dynamic::storage(
+		"TEE",
+		"EnclaveAccountOperator",
+		vec![Value::from_bytes(enclave_account)],
+	);
+
+	match storage.fetch(&address).await? {
+		Some(value) => Ok(Some(AccountId32::decode(&mut value.encoded())?)),
+		None => Ok(None),
+	}
+}
+
+/// Get the cluster of an enclave operator, with a storage address resolved at runtime
+pub async fn enclave_cluster_id(
+	storage: &Storage<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+	operator_account: &AccountId32,
+) -> Result<Option<u32>, subxt::Error> {
+	let address = subxt::dynamic::storage(
+		"TEE",
+		"EnclaveClusterId",
+		vec![Value::from_bytes(operator_account)],
+	);
+
+	match storage.fetch(&address).await? {
+		Some(value) => Ok(Some(u32::decode(&mut value.encoded())?)),
+		None => Ok(None),
+	}
+}
+
+/// Get the active staking era, with a storage address resolved at runtime
+pub async fn active_era(
+	storage: &Storage<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+) -> Result<Option<u32>, subxt::Error> {
+	let address = subxt::dynamic::storage_root("Staking", "ActiveEra");
+
+	match storage.fetch(&address).await? {
+		// ActiveEraInfo { index, start }
+		Some(value) => Ok(Some(u32::decode(&mut value.encoded())?)),
+		None => Ok(None),
+	}
+}
+
+/// Get the average metric-server score of an operator in an era, with a storage address
+/// resolved at runtime. Reports are decoded by field name, every "param_*" field is a score.
+pub async fn operator_metric_score(
+	storage: &Storage<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+	era: u32,
+	operator_account: &AccountId32,
+) -> Result<Option<f64>, subxt::Error> {
+	let address = subxt::dynamic::storage(
+		"TEE",
+		"MetricsReports",
+		vec![Value::u128(era.into()), Value::from_bytes(operator_account)],
+	);
+
+	let reports = match storage.fetch(&address).await? {
+		Some(value) => value.to_value()?,
+		None => return Ok(None),
+	};
+
+	match metric_params(&reports.value) {
+		(_, 0) => Ok(None),
+		(sum, count) => Ok(Some(sum as f64 / count as f64)),
+	}
+}
+
+// Sum and count of the "param_*" fields of metric reports
+fn metric_params<T>(value: &ValueDef<T>) -> (u128, u32) {
+	let add = |(sum, count): (u128, u32), (field_sum, field_count): (u128, u32)| {
+		(sum.saturating_add(field_sum), count + field_count)
+	};
+
+	match value {
+		ValueDef::Composite(Composite::Named(fields)) =>
+			fields.iter().fold((0, 0), |total, (name, field)| {
+				if name.starts_with("param") {
+					field.as_u128().map_or(total, |param| add(total, (param, 1)))
+				} else {
+					add(total, metric_params(&field.value))
+				}
+			}),
+		ValueDef::Composite(Composite::Unnamed(values)) => values
+			.iter()
+			.fold((0, 0), |total, field| add(total, metric_params(&field.value))),
+		_ => (0, 0),
+	}
+}
+
+/* ---------------
+ COMPATIBILITY
+----------------*/
+
+// (Pallet, Event, Fields) decoded dynamically, block bodies are parsed by their events
+const REQUIRED_EVENTS: [(&str, &str, &[&str]); 4] = [
+	("NFT", "SecretNFTSynced", &["nft_id"]),
+	("NFT", "CapsuleSynced", &["nft_id"]),
+	("NFT", "ShardAdded", &["nft_id", "enclave"]),
+	("NFT", "CapsuleShardAdded", &["nft_id", "enclave"]),
+];
+
+// (Pallet, Storage) read with dynamic addresses
+const REQUIRED_STORAGE: [(&str, &str); 4] = [
+	("TEE", "EnclaveAccountOperator"),
+	("TEE", "EnclaveClusterId"),
+	("TEE", "MetricsReports"),
+	("Staking", "ActiveEra"),
+];
+
+/// Check the calls, events and storages used by the enclave against the metadata of the
+/// running chain
+/// # Arguments
+/// * `api` - The chain API, with up to date metadata
+/// # Returns
+/// * `Vec<String>` - Description of incompatibilities, empty if the runtime is compatible
+pub fn check_runtime_compatibility<C: OfflineClientT<PolkadotConfig>>(api: &C) -> Vec<String> {
+	let metadata = api.metadata();
+	let mut incompatibilities = Vec::<String>::new();
+
+	for (pallet, event, fields) in REQUIRED_EVENTS {
+		if let Some(missing) = missing_event_fields(&metadata, pallet, event, fields) {
+			incompatibilities.push(missing);
+		}
+	}
+
+	for (pallet, entry) in REQUIRED_STORAGE {
+		let exists = metadata
+			.pallet_by_name(pallet)
+			.and_then(|pallet_metadata| pallet_metadata.storage())
+			.and_then(|storage| storage.entry_by_name(entry))
+			.is_some();
+
+		if !exists {
+			incompatibilities.push(format!("missing storage {pallet}.{entry}"));
+		}
+	}
+
+	// Statically decoded storages and extrinsics, checked by type hash
+	let static_storages = [
+		("NFT.Nfts", api.storage().validate(&ternoa::storage().nft().nfts(0))),
+		("NFT.DelegatedNFTs", api.storage().validate(&ternoa::storage().nft().delegated_nf_ts(0))),
+		("Rent.Contracts", api.storage().validate(&ternoa::storage().rent().contracts(0))),
+		("TEE.NextClusterId", api.storage().validate(&ternoa::storage().tee().next_cluster_id())),
+		("TEE.ClusterData", api.storage().validate(&ternoa::storage().tee().cluster_data(0))),
+		(
+			"TEE.EnclaveData",
+			api.storage()
+				.validate(&ternoa::storage().tee().enclave_data(AccountId32([0; 32]))),
+		),
+		("TEE.MetricsServers", api.storage().validate(&ternoa::storage().tee().metrics_servers())),
+		(
+			"System.Account",
+			api.storage()
+				.validate(&ternoa::storage().system().account(AccountId32([0; 32]))),
+		),
+		("Timestamp.Now", api.storage().validate(&ternoa::storage().timestamp().now())),
+		(
+			"Grandpa.PendingChange",
+			api.storage().validate(&ternoa::storage().grandpa().pending_change()),
+		),
+	];
+
+	for (name, validation) in static_storages {
+		if let Err(err) = validation {
+			incompatibilities.push(format!("storage {name} has changed : {err}"));
+		}
+	}
+
+	let static_calls = [
+		("NFT.add_secret_shard", api.tx().validate(&ternoa::tx().nft().add_secret_shard(0))),
+		("NFT.add_capsule_shard", api.tx().validate(&ternoa::tx().nft().add_capsule_shard(0))),
+	];
+
+	for (name, validation) in static_calls {
+		if let Err(err) = validation {
+			incompatibilities.push(format!("extrinsic {name} has changed : {err}"));
+		}
+	}
+
+	incompatibilities
+}
+
+fn missing_event_fields(
+	metadata: &Metadata,
+	pallet: &str,
+	event: &str,
+	fields: &[&str],
+) -> Option<String> {
+	let variants = match metadata.pallet_by_name(pallet).and_then(|p| p.event_variants()) {
+		Some(variants) => variants,
+		None => return Some(format!("missing events of pallet {pallet}")),
+	};
+
+	let variant = match variants.iter().find(|variant| variant.name == event) {
+		Some(variant) => variant,
+		None => return Some(format!("missing event {pallet}.{event}")),
+	};
+
+	for field in fields {
+		if !variant.fields.iter().any(|f| f.name.as_deref() == Some(*field)) {
+			return Some(format!("missing field '{field}' in event {pallet}.{event}"));
+		}
+	}
+
+	None
+}
+
+/// Evaluate the runtime of the current chain API and update the shared-state
+/// An incompatible runtime puts the enclave in degraded mode
+pub async fn update_runtime_status(state: &SharedState) {
+	let api = get_chain_api(state).await;
+	let spec_version = api.runtime_version().spec_version;
+
+	let incompatibilities = check_runtime_compatibility(&api);
+
+	if incompatibilities.is_empty() {
+		info!("RUNTIME : spec version {spec_version} is compatible");
+		set_runtime_status(state, spec_version, String::new()).await;
+	} else {
+		let message = format!(
+			"Degraded mode : runtime spec version {spec_version} is incompatible : {}",
+			incompatibilities.join(", ")
+		);
+		error!(message);
+		sentry::capture_message(&message, sentry::Level::Error);
+		set_runtime_status(state, spec_version, message).await;
+	}
+}
+
+/// Follow runtime upgrades of the chain, update the metadata of the chain API
+/// and re-evaluate compatibility on every new runtime version
+/// # Arguments
+/// * `state` - The shared state
+pub fn spawn_runtime_updater(state: SharedState) {
+	tokio::spawn(async move {
+		loop {
+			// Rpc renew creates a new client, with a fresh metadata
+			update_runtime_status(&state).await;
+
+			let api = get_chain_api(&state).await;
+			let updater = api.updater();
+
+			let mut updates = match updater.runtime_updates().await {
+				Ok(updates) => updates,
+				Err(err) => {
+					error!("RUNTIME : Unable to subscribe to runtime versions {err:?}");
+					tokio::time::sleep(Duration::from_secs(RETRY_DELAY.into())).await;
+					continue;
+				},
+			};
+
+			while let Some(update) = updates.next().await {
+				let update = match update {
+					Ok(update) => update,
+					Err(err) => {
+						warn!("RUNTIME : Error getting runtime update {err:?}");
+						break;
+					},
+				};
+
+				let spec_version = update.runtime_version().spec_version;
+
+				// Initial value of the subscription is the current version
+				if updater.apply_update(update).is_err() {
+					debug!("RUNTIME : spec version {spec_version} is already applied");
+					continue;
+				}
+
+				info!("RUNTIME : runtime upgrade detected, new spec version {spec_version}");
+				update_runtime_status(&state).await;
+			}
+
+			warn!("RUNTIME : runtime version subscription ended, resubscribing ...");
+			tokio::time::sleep(Duration::from_secs(RETRY_DELAY.into())).await;
+		}
+	});
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use subxt::{rpc::types::RuntimeVersion, utils::H256, OfflineClient};
+
+	#[cfg(feature = "mainnet")]
+	const BUNDLED_METADATA: &str = "./artifacts/ternoa_mainnet.scale";
+	#[cfg(feature = "alphanet")]
+	const BUNDLED_METADATA: &str = "./artifacts/ternoa_alphanet.scale";
+	#[cfg(feature = "dev1")]
+	const BUNDLED_METADATA: &str = "./artifacts/ternoa_dev1.scale";
+	#[cfg(feature = "dev0")]
+	const BUNDLED_METADATA: &str = "./artifacts/ternoa_dev0.scale";
+
+	// Client of the runtime the binary is built against
+	fn bundled_api() -> OfflineClient<PolkadotConfig> {
+		let encoded = std::fs::read(BUNDLED_METADATA).unwrap();
+		let metadata = Metadata::decode(&mut &encoded[..]).unwrap();
+		let runtime_version =
+			RuntimeVersion { spec_version: 0, transaction_version: 0, other: Default::default() };
+
+		OfflineClient::new(H256::zero(), runtime_version, metadata)
+	}
+
+	#[test]
+	fn runtime_compatibility_test() {
+		let api = bundled_api();
+		assert_eq!(check_runtime_compatibility(&api), Vec::<String>::new());
+
+		let metadata = api.metadata();
+		assert_eq!(
+			missing_event_fields(&metadata, "NFT", "ShardAdded", &["nft_id", "enclave"]),
+			None
+		);
+		assert_eq!(
+			missing_event_fields(&metadata, "NFT", "ShardAdded", &["nft_id", "enclave_id"]),
+			Some("missing field 'enclave_id' in event NFT.ShardAdded".to_string())
+		);
+		assert_eq!(
+			missing_event_fields(&metadata, "NFT", "ShardRemoved", &["nft_id"]),
+			Some("missing event NFT.ShardRemoved".to_string())
+		);
+	}
+}
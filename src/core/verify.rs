@@ -83,6 +83,7 @@ pub enum ReturnStatus {
 
 	DATABASEFAILURE,
 	ORACLEFAILURE,
+	LOWBALANCE,
 
 	KEYNOTEXIST,
 	KEYNOTACCESSIBLE,
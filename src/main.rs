@@ -5,7 +5,7 @@ mod replication;
 mod server;
 
 use clap::Parser;
-use constants::{SENTRY_URL, VERSION};
+use constants::{NFT_CACHE_SIZE, SENTRY_URL, VERSION};
 use tracing::{error, info};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
@@ -23,6 +23,10 @@ struct Args {
 	/// Server Port
 	#[arg(short, long, default_value_t = 2)]
 	verbose: u8,
+
+	/// NFT ownership cache size, 0 disables the cache
+	#[arg(short, long, default_value_t = NFT_CACHE_SIZE)]
+	cache_size: usize,
 }
 
 /* MAIN */
@@ -104,7 +108,7 @@ async fn main() {
 	});
 
 	info!("MAIN : Define http-server");
-	let http_app = match server::http_server::http_server().await {
+	let http_app = match server::http_server::http_server(args.cache_size).await {
 		Ok(app) => app,
 		Err(err) => {
 			error!("MAIN : Error creating http application, exiting : {err:?}");
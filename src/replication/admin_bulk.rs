@@ -4,9 +4,9 @@
 
 use axum::{
 	body::{Bytes, StreamBody},
-	extract::{FromRequest, Multipart, State},
+	extract::{multipart::Field, ConnectInfo, FromRequest, Multipart, State},
 	http::{header, StatusCode},
-	response::IntoResponse,
+	response::{IntoResponse, Response},
 	Json,
 };
 
@@ -28,7 +28,8 @@ use serde_json::{json, Value};
 use std::{
 	collections::BTreeMap,
 	fs::{remove_file, File},
-	io::{Read, Write},
+	io::{Cursor, Read, Write},
+	net::SocketAddr,
 };
 
 use tracing::{debug, error, info, warn};
@@ -36,18 +37,30 @@ use tracing::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-	constants::{ENCLAVE_ACCOUNT_FILE, MAX_BLOCK_VARIATION, MAX_VALIDATION_PERIOD, SEALPATH},
+	constants::{
+		ENCLAVE_ACCOUNT_FILE, MAX_BLOCK_VARIATION, MAX_DRY_RUN_UPLOAD_SIZE, MAX_VALIDATION_PERIOD,
+		SEALPATH,
+	},
 	core::{chain::get_current_block_number, helper},
 	replication::sync::cluster_discovery,
 	server::state::{
-		get_blocknumber, get_clusters, reset_nft_availability, set_keypair, SharedState,
-		StateConfig,
+		get_accountid, get_blocknumber, get_clusters, get_keypair, get_nft_availability_map,
+		get_nft_changed_since, reset_nft_availability, set_keypair, SharedState, StateConfig,
 	},
 };
 
 use super::{
+	admin_quorum::{consume_approved_proposal, verify_approved_proposal, AdminOperation},
+	audit::{record_admin_audit, AdminAction, AuditSubject},
+	manifest::{
+		apply_tombstones, check_backup_chain, create_backup_archive, diff_restore,
+		verify_backup_reader, BackupChainHead, BackupIncrement, BackupManifest, DryRunReport,
+		RestoreReport,
+	},
+	staging::{RestoreLock, RestoreStaging},
+	stream_cipher::{encrypt_file_stream, StreamEncryptor, MAX_KEY_SHARES},
 	sync::{set_sync_state, ClusterType},
-	zipdir::{add_dir_zip, zip_extract},
+	sync_state::{load_sync_state, update_sync_state},
 };
 
 /* *************************************
@@ -59,6 +72,12 @@ use super::{
 pub struct FetchAuthenticationToken {
 	pub block_number: u32,
 	pub block_validation: u32,
+	// Hash of the encryption keys, binds them to the admin signature
+	#[serde(default)]
+	pub data_hash: String,
+	// Incremental backup of the keyshares changed after this block
+	#[serde(default)]
+	pub since_block: Option<u32>,
 }
 
 /// Fetch Bulk Data
@@ -67,6 +86,9 @@ pub struct FetchBulkPacket {
 	admin_address: String,
 	auth_token: String, //FetchAuthenticationToken,
 	signature: String,
+	// Hex secp256k1 public-keys, the backup is split between them when there are several
+	#[serde(default)]
+	encryption_keys: Vec<String>,
 }
 
 /// Fetch Bulk Response
@@ -144,6 +166,56 @@ impl FetchAuthenticationToken {
 	}
 }
 
+/// Hash of the backup encryption keys, as signed in the authentication token
+/// # Arguments
+/// * `encryption_keys` - Hex public-keys of the custodians, in the order of the request
+/// # Returns
+/// * `String` - Hex sha256 of the comma separated keys
+pub fn encryption_keys_hash(encryption_keys: &[String]) -> String {
+	sha256::digest(encryption_keys.join(",").as_bytes())
+}
+
+/// Decode and validate the backup encryption keys
+/// # Arguments
+/// * `encryption_keys` - Hex public-keys of the custodians
+/// # Returns
+/// * `Result<Vec<Vec<u8>>, String>` - Serialized public-keys or the error message
+pub fn parse_encryption_keys(encryption_keys: &[String]) -> Result<Vec<Vec<u8>>, String> {
+	if encryption_keys.len() > MAX_KEY_SHARES {
+		return Err(format!(
+			"Too many encryption keys {}, maximum is {MAX_KEY_SHARES}",
+			encryption_keys.len()
+		));
+	}
+
+	let mut public_keys: Vec<Vec<u8>> = Vec::with_capacity(encryption_keys.len());
+	for encryption_key in encryption_keys {
+		let stripped = encryption_key.strip_prefix("0x").unwrap_or(encryption_key);
+
+		let public_key = hex::decode(stripped)
+			.map_err(|err| format!("Encryption key is not a hex string : {err:?}"))?;
+
+		// Compressed and uncompressed encodings of a key are the same custodian
+		let public_key = ecies::PublicKey::parse_slice(&public_key, None)
+			.map_err(|_| {
+				format!("Encryption key is not a secp256k1 public-key : {encryption_key}")
+			})?
+			.serialize()
+			.to_vec();
+
+		if public_keys.contains(&public_key) {
+			return Err(format!("Duplicate encryption key : {encryption_key}"));
+		}
+
+		public_keys.push(public_key);
+	}
+
+	Ok(public_keys)
+}
+
+// Plain backups leave the enclave unprotected, they are only served by explicit dev builds
+const PLAINTEXT_BACKUP: bool = cfg!(all(feature = "plaintext-backup", not(feature = "mainnet")));
+
 impl StoreAuthenticationToken {
 	pub fn is_valid(&self, current_block_number: u32) -> ValidationResult {
 		if self.block_number > current_block_number + MAX_BLOCK_VARIATION {
@@ -215,6 +287,23 @@ async fn verify_account_id(state: &SharedState, account_id: &String) -> bool {
 	allowed_id.contains(&account_id.to_string())
 }
 
+/// Enclaves allowed to sign a restored backup : the registered enclaves and this enclave
+/// # Arguments
+/// * `state` - The enclave state
+/// # Returns
+/// * `Vec<String>` - SS58 accounts of the trusted enclaves
+async fn trusted_backup_signers(state: &SharedState) -> Vec<String> {
+	let mut accounts: Vec<String> = get_clusters(state)
+		.await
+		.into_iter()
+		.filter(|c| c.cluster_type != ClusterType::Admin)
+		.flat_map(|c| c.enclaves.into_iter().map(|e| e.enclave_account.to_string()))
+		.collect();
+
+	accounts.push(get_accountid(state).await);
+	accounts
+}
+
 /// Verifies the signature of the backup data
 /// # Arguments
 /// * `account_id` - Account ID
@@ -312,8 +401,19 @@ async fn update_health_status(state: &SharedState, message: String) {
 #[axum::debug_handler]
 pub async fn admin_backup_fetch_bulk(
 	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
 	Json(backup_request): Json<FetchBulkPacket>,
 ) -> impl IntoResponse {
+	let subject = AuditSubject {
+		account: backup_request.admin_address.clone(),
+		data_hash: sha256::digest(backup_request.auth_token.as_bytes()),
+	};
+
+	let response = fetch_bulk(state.clone(), backup_request).await;
+	record_admin_audit(&state, addr, AdminAction::FetchBulk, subject, response).await
+}
+
+async fn fetch_bulk(state: SharedState, backup_request: FetchBulkPacket) -> Response {
 	debug!("ADMIN FETCH BULK : backup fetch bulk");
 	//update_health_status(&state, "Enclave is doing backup, please wait...".to_string()).await;
 
@@ -384,6 +484,61 @@ pub async fn admin_backup_fetch_bulk(
 		},
 	}
 
+	if backup_request.encryption_keys.is_empty() {
+		if !PLAINTEXT_BACKUP {
+			let message = "ADMIN FETCH BULK : An encryption public-key is required for the backup"
+				.to_string();
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response();
+		}
+
+		warn!("ADMIN FETCH BULK : Plain backup is requested on a dev build");
+	} else if auth_token.data_hash != encryption_keys_hash(&backup_request.encryption_keys) {
+		warn!(
+			"ADMIN FETCH BULK : mismatch encryption keys hash : admin = {}",
+			backup_request.admin_address
+		);
+		return (
+			StatusCode::BAD_REQUEST,
+			Json(json!({"error": "ADMIN FETCH BULK : Mismatch Encryption Keys Hash"})),
+		)
+			.into_response();
+	}
+
+	let public_keys = match parse_encryption_keys(&backup_request.encryption_keys) {
+		Ok(public_keys) => public_keys,
+		Err(err) => {
+			let message = format!("ADMIN FETCH BULK : {err}");
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response();
+		},
+	};
+
+	let increment = match auth_token.since_block {
+		Some(since_block) if since_block >= current_block_number => {
+			let message = format!(
+				"ADMIN FETCH BULK : Incremental backup since block {since_block} is not before the current block {current_block_number}"
+			);
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response();
+		},
+		Some(since_block) => {
+			let changed = get_nft_changed_since(&state, since_block).await;
+			match BackupIncrement::collect(SEALPATH, since_block, changed) {
+				Ok(increment) => Some(increment),
+				Err(err) => {
+					let message = format!(
+						"ADMIN FETCH BULK : Incremental backup can not be collected : {err:?}"
+					);
+					error!(message);
+					return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+						.into_response();
+				},
+			}
+		},
+		None => None,
+	};
+
 	let mut backup_file = "/temporary/backup.zip".to_string();
 	let counter = 1;
 	// remove previously generated backup
@@ -405,11 +560,28 @@ pub async fn admin_backup_fetch_bulk(
 	}
 
 	debug!("ADMIN FETCH BULK : Start zippping file");
-	add_dir_zip(SEALPATH, &backup_file);
+	let keypair = get_keypair(&state).await;
+	match create_backup_archive(
+		SEALPATH,
+		&keypair,
+		current_block_number,
+		increment.as_ref(),
+		&backup_file,
+	) {
+		Ok(manifest) =>
+			debug!("ADMIN FETCH BULK : Manifest of {} files is signed", manifest.files.len()),
+		Err(err) => {
+			let _ = std::fs::remove_file(&backup_file);
+			let message = format!("ADMIN FETCH BULK : Can not create the backup archive : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+				.into_response();
+		},
+	}
 
 	// `File` implements `AsyncRead`
 	debug!("ADMIN FETCH BULK : Opening backup file");
-	let file = match tokio::fs::File::open(backup_file).await {
+	let file = match tokio::fs::File::open(backup_file.clone()).await {
 		Ok(file) => file,
 		Err(err) =>
 			return (
@@ -419,17 +591,51 @@ pub async fn admin_backup_fetch_bulk(
 				.into_response(),
 	};
 
-	// convert the `AsyncRead` into a `Stream`
-	debug!("ADMIN FETCH BULK : Create reader-stream");
-	let stream = ReaderStream::new(file);
+	if public_keys.is_empty() {
+		// convert the `AsyncRead` into a `Stream`
+		debug!("ADMIN FETCH BULK : Create reader-stream");
+		let stream = ReaderStream::new(file);
+
+		// convert the `Stream` into an `axum::body::HttpBody`
+		debug!("ADMIN FETCH BULK : Create body-stream");
+		let body = StreamBody::new(stream);
+
+		let headers = [
+			(header::CONTENT_TYPE, "application/zip"),
+			(header::CONTENT_DISPOSITION, "attachment; filename=\"Backup.zip\""),
+		];
+
+		debug!("ADMIN FETCH BULK : Sending the plain backup data to the client ...");
+		return (headers, body).into_response();
+	}
+
+	let (encryptor, stream_header) = match StreamEncryptor::new_split(&public_keys) {
+		Ok(encryptor) => encryptor,
+		Err(err) => {
+			let _ = std::fs::remove_file(&backup_file);
+			let message =
+				format!("ADMIN FETCH BULK : Failed to initialize the stream encryption : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+				.into_response();
+		},
+	};
 
-	// convert the `Stream` into an `axum::body::HttpBody`
-	debug!("ADMIN FETCH BULK : Create body-stream");
+	// The archive is encrypted chunk by chunk while streaming, the plain archive is removed
+	// when the stream ends or the admin disconnects.
+	debug!("ADMIN FETCH BULK : Create encrypted body-stream, custodians = {}", public_keys.len());
+	let stream = encrypt_file_stream(file, backup_file, encryptor, stream_header);
 	let body = StreamBody::new(stream);
 
+	// Increments are named by their block range, to be replayed in order after the base
+	let file_name = match auth_token.since_block {
+		Some(since_block) => format!("Backup-{since_block}-{current_block_number}.zip.enc"),
+		None => format!("Backup-{current_block_number}.zip.enc"),
+	};
+
 	let headers = [
-		(header::CONTENT_TYPE, "text/toml; charset=utf-8"),
-		(header::CONTENT_DISPOSITION, "attachment; filename=\"Backup.zip\""),
+		(header::CONTENT_TYPE, "application/octet-stream".to_string()),
+		(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{file_name}\"")),
 	];
 
 	//update_health_status(&state, String::new()).await;
@@ -472,18 +678,98 @@ fn get_json_response(status: String, data: Vec<u8>) -> Json<Value> {
 #[axum::debug_handler]
 pub async fn admin_backup_push_bulk(
 	State(state): State<SharedState>,
-	mut store_request: Multipart,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	store_request: Multipart,
 ) -> impl IntoResponse {
-	debug!("ADMIN PUSH BULK : backup push bulk");
+	let mut subject = AuditSubject::default();
+	let response = push_bulk(state.clone(), store_request, false, &mut subject).await;
+	record_admin_audit(&state, addr, AdminAction::PushBulk, subject, response).await
+}
+
+/// Dry-run of the bulk push
+/// The backup is parsed and verified like a push, then compared with the availability map.
+/// The upload is kept in memory, up to MAX_DRY_RUN_UPLOAD_SIZE, nothing is written on disk
+/// or in the state.
+/// # Arguments
+/// * `state` - StateConfig
+/// * `store_request` - StoreBulkPacket
+/// # Returns
+/// * `Json` - DryRunReport
+#[axum::debug_handler]
+pub async fn admin_backup_push_bulk_dry_run(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	store_request: Multipart,
+) -> impl IntoResponse {
+	let mut subject = AuditSubject::default();
+	let response = push_bulk(state.clone(), store_request, true, &mut subject).await;
+	record_admin_audit(&state, addr, AdminAction::PushBulkDryRun, subject, response).await
+}
+
+// Whitelisted admin and signature of the token, checked before the upload is read
+async fn authenticate_admin(
+	state: &SharedState,
+	admin_address: &String,
+	signature: &str,
+	auth_token: &str,
+) -> Result<(), Response> {
+	if !verify_account_id(state, admin_address).await {
+		let message = format!("ADMIN PUSH BULK : Requester is not whitelisted : {}", admin_address);
+		warn!(message);
+		return Err((StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response());
+	}
+
+	if !verify_signature(admin_address, signature.to_string(), auth_token.as_bytes()) {
+		warn!("Error restore backup keyshares : Invalid signature : admin = {}", admin_address);
+		return Err((StatusCode::FORBIDDEN, Json(json!({ "error": "Invalid token signature" })))
+			.into_response());
+	}
+
+	Ok(())
+}
+
+// Upload of a dry-run, read in memory up to a limit
+async fn read_upload(field: &mut Field<'_>, limit: u64) -> anyhow::Result<Vec<u8>> {
+	let mut data = Vec::new();
+
+	while let Some(chunk) = field
+		.chunk()
+		.await
+		.map_err(|err| anyhow::anyhow!("upload is interrupted : {err}"))?
+	{
+		if (data.len() + chunk.len()) as u64 > limit {
+			return Err(anyhow::anyhow!("dry-run upload is larger than {limit} bytes"));
+		}
+		data.extend_from_slice(&chunk);
+	}
+
+	Ok(data)
+}
+
+// The requester and the hash of the signed token are set in the audit subject once the
+// form-data is parsed
+async fn push_bulk(
+	state: SharedState,
+	mut store_request: Multipart,
+	dry_run: bool,
+	subject: &mut AuditSubject,
+) -> Response {
+	debug!("ADMIN PUSH BULK : backup push bulk, dry-run = {dry_run}");
 	debug!("ADMIN PUSH BULK : received request = {:?}", store_request);
 	//update_health_status(&state, "Restoring the backups".to_string()).await;
 
 	let mut admin_address = String::new();
-	let mut restore_file = Vec::<u8>::new();
+	let mut upload: Option<(String, u64)> = None;
+	let mut authenticated = false;
+	// A push spools the upload to the staging under the restore lock, a dry-run keeps it in memory
+	let mut restore_lock: Option<RestoreLock> = None;
+	let mut staging: Option<RestoreStaging> = None;
+	let mut upload_data: Option<Vec<u8>> = None;
 	let mut auth_token = String::new();
 	let mut signature = String::new();
+	let mut proposal_id = String::new();
 
-	while let Some(field) = match store_request.next_field().await {
+	while let Some(mut field) = match store_request.next_field().await {
 		Ok(field) => field,
 		Err(err) => {
 			let message = format!(
@@ -526,9 +812,61 @@ pub async fn admin_backup_push_bulk(
 					},
 				},
 
-			"restore_file" =>
-				restore_file = match field.bytes().await {
-					Ok(bytes) => bytes.to_vec(),
+			"restore_file" => {
+				// Nothing is stored before the requester is known, the file part comes last
+				if let Err(response) =
+					authenticate_admin(&state, &admin_address, &signature, &auth_token).await
+				{
+					return response;
+				}
+				authenticated = true;
+
+				if upload.is_some() {
+					let message = "ADMIN PUSH BULK : restore_file is sent twice".to_string();
+					warn!(message);
+					return (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+						.into_response();
+				}
+
+				let spooled = if dry_run {
+					read_upload(&mut field, MAX_DRY_RUN_UPLOAD_SIZE).await.map(|data| {
+						let spooled = (sha256::digest(data.as_slice()), data.len() as u64);
+						upload_data = Some(data);
+						spooled
+					})
+				} else {
+					restore_lock = match RestoreLock::acquire() {
+						Some(lock) => Some(lock),
+						None => {
+							let message =
+								"ADMIN PUSH BULK : Another restore is running".to_string();
+							warn!(message);
+							return (StatusCode::CONFLICT, Json(json!({ "error": message })))
+								.into_response();
+						},
+					};
+
+					// The staging is removed on every early return
+					let created = match RestoreStaging::create() {
+						Ok(created) => staging.insert(created),
+						Err(err) => {
+							let message = format!(
+								"ADMIN PUSH BULK : Can not create the restore staging : {err:?}"
+							);
+							error!(message);
+							return (
+								StatusCode::INTERNAL_SERVER_ERROR,
+								Json(json!({ "error": message })),
+							)
+								.into_response();
+						},
+					};
+
+					created.spool_upload(&mut field).await
+				};
+
+				upload = match spooled {
+					Ok(upload) => Some(upload),
 					Err(err) => {
 						info!("ADMIN PUSH BULK : Error request restore_file {err:?}");
 
@@ -540,7 +878,8 @@ pub async fn admin_backup_push_bulk(
 						)
 							.into_response();
 					},
-				},
+				};
+			},
 
 			"auth_token" =>
 				auth_token = match field.text().await {
@@ -588,6 +927,22 @@ pub async fn admin_backup_push_bulk(
 					},
 				},
 
+			"proposal_id" =>
+				proposal_id = match field.text().await {
+					Ok(text) => text,
+					Err(err) => {
+						info!("ADMIN PUSH BULK : Error request proposal_id {err:?}");
+
+						return (
+							StatusCode::BAD_REQUEST,
+							Json(json!({
+								"error": format!("ADMIN PUSH BULK : Error request proposal_id {err:?}"),
+							})),
+						)
+							.into_response();
+					},
+				},
+
 			_ => {
 				info!("Error restore backup keyshares : Error request field name {:?}", field);
 				return (
@@ -601,30 +956,15 @@ pub async fn admin_backup_push_bulk(
 		}
 	}
 
-	if !verify_account_id(&state, &admin_address.clone()).await {
-		let message = format!("ADMIN PUSH BULK : Requester is not whitelisted : {}", admin_address);
-
-		warn!(message);
-
-		return (
-			StatusCode::FORBIDDEN,
-			Json(json! ({
-				"error": message,
-			})),
-		)
-			.into_response();
-	}
+	subject.account = admin_address.clone();
+	subject.data_hash = sha256::digest(auth_token.as_bytes());
 
-	if !verify_signature(&admin_address, signature.clone(), auth_token.clone().as_bytes()) {
-		warn!("Error restore backup keyshares : Invalid signature : admin = {}", admin_address);
-
-		return (
-			StatusCode::FORBIDDEN,
-			Json(json! ({
-				"error": "Invalid token signature",
-			})),
-		)
-			.into_response();
+	if !authenticated {
+		if let Err(response) =
+			authenticate_admin(&state, &admin_address, &signature, &auth_token).await
+		{
+			return response;
+		}
 	}
 
 	if auth_token.starts_with("<Bytes>") && auth_token.ends_with("</Bytes>") {
@@ -672,7 +1012,14 @@ pub async fn admin_backup_push_bulk(
 		},
 	}
 
-	let hash = sha256::digest(restore_file.as_slice());
+	let (hash, size) = match upload {
+		Some(upload) => upload,
+		None => {
+			let message = "ADMIN PUSH BULK : Missing restore_file".to_string();
+			warn!(message);
+			return (StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response();
+		},
+	};
 
 	if token.data_hash != hash {
 		warn!("ADMIN PUSH BULK : mismatch data hash : admin = {}", admin_address);
@@ -686,61 +1033,173 @@ pub async fn admin_backup_push_bulk(
 			.into_response();
 	}
 
-	let backup_file = SEALPATH.to_string() + "/" + "backup.zip";
+	debug!("ADMIN PUSH BULK : {size} bytes are spooled");
 
-	let mut zipfile = match std::fs::File::create(backup_file.clone()) {
-		Ok(file) => file,
+	// A restore needs a proposal of this backup approved by the admin quorum
+	let proposal = if dry_run {
+		None
+	} else {
+		let operations = [AdminOperation::PushBulk, AdminOperation::EnclaveKey];
+		match verify_approved_proposal(&state, &proposal_id, &operations, &hash).await {
+			Ok(proposal) => Some(proposal),
+			Err(err) => {
+				let message = format!("ADMIN PUSH BULK : Restore is not approved : {err}");
+				warn!("{message} : admin = {admin_address}");
+				return (StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response();
+			},
+		}
+	};
+
+	// Nothing is moved in the sealed directory before the manifest, the signer and every digest
+	// are verified
+	let trusted_signers = trusted_backup_signers(&state).await;
+	let upload_path = staging.as_ref().map(RestoreStaging::upload_path);
+	let verified = tokio::task::spawn_blocking(move || match (upload_data, upload_path) {
+		(Some(data), _) => verify_backup_reader(Cursor::new(data), &trusted_signers),
+		(None, Some(upload_path)) => File::open(upload_path)
+			.map_err(anyhow::Error::from)
+			.and_then(|file| verify_backup_reader(file, &trusted_signers)),
+		(None, None) => Err(anyhow::anyhow!("upload is not spooled")),
+	})
+	.await;
+
+	let (manifest, report) = match verified {
+		Ok(Ok(verified)) => verified,
+		Ok(Err(err)) => {
+			let message = format!("ADMIN PUSH BULK : Backup verification failed : {err}");
+			warn!("{message} : admin = {admin_address}");
+			return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": message })))
+				.into_response();
+		},
 		Err(err) => {
-			let message = format!("ADMIN PUSH BULK : Can not create file on disk : {}", err);
-			warn!(message);
+			let message = format!("ADMIN PUSH BULK : Backup verification failed : {err:?}");
+			error!(message);
 			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
 				.into_response();
 		},
 	};
 
-	match zipfile.write_all(&restore_file) {
-		Ok(_) => debug!("zip file is stored on disk."),
+	info!(
+		"ADMIN PUSH BULK : Backup of {} at block {} is verified : {} files",
+		report.source_enclave, report.backup_block, report.files
+	);
+
+	let chain_head = load_sync_state().ok().and_then(|sync_state| sync_state.backup_chain);
+	let chain_check = check_backup_chain(chain_head.as_ref(), &report);
+
+	if dry_run {
+		let current_account = get_accountid(&state).await;
+		let dry_run_report = DryRunReport {
+			diff: diff_restore(&manifest.keyshares(), &get_nft_availability_map(&state).await),
+			account_change: report.enclave_account && report.source_enclave != current_account,
+			restored_account: report.enclave_account.then(|| report.source_enclave.clone()),
+			current_account,
+			chain_error: chain_check.err().map(|err| err.to_string()),
+			report: Some(report),
+			invalid: Vec::new(),
+		};
+
+		info!("ADMIN PUSH BULK : Dry-run by {admin_address} : {:?}", dry_run_report.diff);
+		return (StatusCode::OK, Json(json!({ "dry_run": dry_run_report }))).into_response();
+	}
+
+	let backup_chain = match chain_check {
+		Ok(backup_chain) => backup_chain,
 		Err(err) => {
-			let message = format!("ADMIN PUSH BULK : writing zip file to disk{err:?}");
+			let message = format!("ADMIN PUSH BULK : Backup can not be restored : {err}");
+			warn!(message);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message }))).into_response();
+		},
+	};
+
+	// Replacing the enclave account is approved on its own
+	let account_change =
+		report.enclave_account && report.source_enclave != get_accountid(&state).await;
+	if account_change &&
+		proposal.map(|proposal| proposal.operation) != Some(AdminOperation::EnclaveKey)
+	{
+		let message = format!(
+			"ADMIN PUSH BULK : Backup replaces the enclave account by {}, it needs an enclave_key proposal",
+			report.source_enclave
+		);
+		warn!(message);
+		return (StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response();
+	}
+
+	let operations = [AdminOperation::PushBulk, AdminOperation::EnclaveKey];
+	if let Err(err) = consume_approved_proposal(&state, &proposal_id, &operations, &hash).await {
+		let message = format!("ADMIN PUSH BULK : Restore is not approved : {err}");
+		warn!(message);
+		return (StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response();
+	}
+
+	let staging = match staging {
+		Some(staging) => staging,
+		None => {
+			let message = "ADMIN PUSH BULK : Upload is not staged".to_string();
 			error!(message);
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": message,
-				})),
-			)
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
 				.into_response();
 		},
+	};
+
+	// Dropping the request does not stop a restore half way, the task owns the staging and the lock
+	let commit = tokio::spawn(commit_bulk_restore(
+		state,
+		staging,
+		restore_lock,
+		manifest,
+		report,
+		backup_chain,
+	));
+
+	match commit.await {
+		Ok(response) => response,
+		Err(err) => {
+			let message = format!("ADMIN PUSH BULK : Restore task failed : {err:?}");
+			error!(message);
+			(StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message }))).into_response()
+		},
 	}
+}
 
-	// Check if the enclave_account or keyshares are invalid
-	match zip_extract(&backup_file, SEALPATH) {
-		Ok(_) => debug!("zip_extract success"),
+// Swap a verified backup in the sealed directory, then reload the enclave account and the
+// availability map from it
+async fn commit_bulk_restore(
+	state: SharedState,
+	staging: RestoreStaging,
+	_restore_lock: Option<RestoreLock>,
+	manifest: BackupManifest,
+	report: RestoreReport,
+	backup_chain: BackupChainHead,
+) -> Response {
+	let swapped = tokio::task::spawn_blocking(move || {
+		staging
+			.stage_backup(&manifest)
+			.and_then(|staged| staging.swap_into(&staged, SEALPATH))
+			.and_then(|_| apply_tombstones(SEALPATH, &manifest))
+	})
+	.await;
+
+	match swapped {
+		Ok(Ok(removed)) => info!(
+			"ADMIN PUSH BULK : {} files are swapped in, {removed} removed keyshares are deleted",
+			report.files
+		),
+		Ok(Err(err)) => {
+			let message = format!("ADMIN PUSH BULK : Backup can not be restored : {err:?}");
+			error!(message);
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
+				.into_response();
+		},
 		Err(err) => {
-			let message = format!("ADMIN PUSH BULK : extracting zip file {err:?}");
+			let message = format!("ADMIN PUSH BULK : Restore task failed : {err:?}");
 			error!(message);
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": message,
-				})),
-			)
+			return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": message })))
 				.into_response();
 		},
 	}
 
-	match remove_file(backup_file) {
-		Ok(_) => debug!("ADMIN PUSH BULK : remove zip file successful"),
-		Err(err) =>
-			return (
-				StatusCode::OK,
-				Json(json!({
-					"warning": format!("Backup success with Error in removing zip file, {:?}",err),
-				})),
-			)
-				.into_response(),
-	};
-
 	// Update Enclave Account, if it is updated.;
 	if !std::path::Path::new(&ENCLAVE_ACCOUNT_FILE).exists() {
 		return (
@@ -817,10 +1276,20 @@ pub async fn admin_backup_push_bulk(
 	reset_nft_availability(&state, keyshare_list).await;
 	let _ = set_sync_state(last_synced.to_string());
 
+	// A restored full backup carries the sync state of its source, the chain head is set after it
+	if let Err(err) = update_sync_state(|sync_state| {
+		sync_state.backup_chain = Some(backup_chain.clone());
+		Ok(())
+	}) {
+		error!("ADMIN PUSH BULK : Can not seal the backup chain : {err:?}");
+	}
+
 	(
 		StatusCode::OK,
 		Json(json!({
 			"success": format!("Success restoring backups"),
+			"report": report,
+			"backup_chain": backup_chain,
 		})),
 	)
 		.into_response()
@@ -844,8 +1313,13 @@ mod test {
 		let admin_keypair = sr25519::Pair::from_phrase(seed_phrase, None).unwrap().0;
 		let current_block_number = get_current_block_number_new_api().await.unwrap();
 
-		let auth =
-			FetchAuthenticationToken { block_number: current_block_number, block_validation: 10 };
+		let encryption_keys = vec![hex::encode(ecies::utils::generate_keypair().1.serialize())];
+		let auth = FetchAuthenticationToken {
+			block_number: current_block_number,
+			block_validation: 10,
+			data_hash: encryption_keys_hash(&encryption_keys),
+			since_block: None,
+		};
 		let auth_bytes = serde_json::to_vec(&auth).unwrap();
 		let sig = admin_keypair.sign(&auth_bytes);
 		let sig_str = serde_json::to_string(&sig).unwrap();
@@ -854,6 +1328,7 @@ mod test {
 			admin_address: admin_keypair.public().to_string(),
 			auth_token: serde_json::to_string(&auth).unwrap(),
 			signature: sig_str,
+			encryption_keys,
 		};
 	}
 
@@ -898,6 +1373,26 @@ mod test {
 		assert_eq!(results, expected);
 	}
 
+	#[test]
+	fn parse_encryption_keys_test() {
+		let (_, pk1) = ecies::utils::generate_keypair();
+		let (_, pk2) = ecies::utils::generate_keypair();
+		let key1 = hex::encode(pk1.serialize());
+		let key2 = format!("0x{}", hex::encode(pk2.serialize_compressed()));
+
+		let keys = parse_encryption_keys(&[key1.clone(), key2]).unwrap();
+		assert_eq!(keys.len(), 2);
+
+		assert!(parse_encryption_keys(&[key1.clone(), key1.clone()]).is_err());
+
+		// Keys are serialized uncompressed, both encodings of a key are duplicates
+		assert_eq!(keys[1], pk2.serialize().to_vec());
+		let key1_compressed = hex::encode(pk1.serialize_compressed());
+		assert!(parse_encryption_keys(&[key1.clone(), key1_compressed]).is_err());
+		assert!(parse_encryption_keys(&["0xabcd".to_string()]).is_err());
+		assert!(parse_encryption_keys(&["not-hex".to_string()]).is_err());
+	}
+
 	#[test]
 	fn test_get_public_key_valid() {
 		let account = "5DAENKLsmj9FbfxgKuWn81smhKz9dZg75fveUFSUtqrr4CPn";
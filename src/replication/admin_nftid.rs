@@ -4,9 +4,9 @@
 
 use axum::{
 	body::{Bytes, StreamBody},
-	extract::{FromRequest, Multipart, State},
+	extract::{ConnectInfo, FromRequest, Multipart, State},
 	http::{header, StatusCode},
-	response::IntoResponse,
+	response::{IntoResponse, Response},
 	Json,
 };
 
@@ -17,6 +17,8 @@ use serde_json::{json, Value};
 use std::{
 	collections::BTreeMap,
 	io::{Read, Write},
+	net::SocketAddr,
+	path::Path,
 };
 use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
 
@@ -31,12 +33,16 @@ use crate::{
 	core::{chain::get_current_block_number, helper},
 	replication::zipdir::add_list_zip,
 	server::state::{
-		get_blocknumber, get_clusters, get_nft_availability, set_nft_availability, SharedState,
-		StateConfig,
+		get_accountid, get_blocknumber, get_clusters, get_nft_availability,
+		get_nft_availability_map, set_nft_availability, SharedState, StateConfig,
 	},
 };
 
 use super::{
+	admin_quorum::{consume_approved_proposal, AdminOperation},
+	audit::{record_admin_audit, AdminAction, AuditSubject},
+	manifest::{diff_restore, DryRunReport},
+	staging::RestoreLock,
 	sync::ClusterType,
 	zipdir::{add_dir_zip, zip_extract},
 };
@@ -60,6 +66,9 @@ pub struct IdPacket {
 	id_vec: String,
 	auth_token: String,
 	signature: String,
+	// Approved proposal of a push, fetches do not need it
+	#[serde(default)]
+	proposal_id: String,
 }
 
 /// Fetch NFTID Response
@@ -257,8 +266,23 @@ pub async fn error_handler(message: String, state: &SharedState) -> impl IntoRes
 #[axum::debug_handler]
 pub async fn admin_backup_fetch_id(
 	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
 	Json(backup_request): Json<IdPacket>,
 ) -> impl IntoResponse {
+	let subject = id_audit_subject(&backup_request);
+	let response = fetch_id(state.clone(), backup_request).await;
+	record_admin_audit(&state, addr, AdminAction::FetchId, subject, response).await
+}
+
+// Requester and hash of the signed token of a request by nftid
+fn id_audit_subject(backup_request: &IdPacket) -> AuditSubject {
+	AuditSubject {
+		account: backup_request.admin_account.clone(),
+		data_hash: sha256::digest(backup_request.auth_token.as_bytes()),
+	}
+}
+
+async fn fetch_id(state: SharedState, backup_request: IdPacket) -> Response {
 	debug!("ADMIN FETCH ID : backup fetch NFTID");
 
 	update_health_status(
@@ -408,15 +432,68 @@ pub async fn admin_backup_fetch_id(
 #[axum::debug_handler]
 pub async fn admin_backup_push_id(
 	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
 	Json(backup_request): Json<IdPacket>,
 ) -> impl IntoResponse {
-	debug!("ADMIN PUSH ID : backup fetch NFTID");
+	let subject = id_audit_subject(&backup_request);
+	let response = push_id(state.clone(), backup_request, false).await;
+	record_admin_audit(&state, addr, AdminAction::PushId, subject, response).await
+}
 
-	update_health_status(
-		&state,
-		"ADMIN PUSH ID : Enclave is doing backup, please wait...".to_string(),
-	)
-	.await;
+/// Dry-run of the push by nftid
+/// The request is authenticated and parsed like a push, then compared with the availability
+/// map. No keyshare is written and the enclave is not put in maintenance.
+/// # Arguments
+/// * `state` - StateConfig
+/// * `backup_request` - IdPacket
+/// # Returns
+/// * `Json` - DryRunReport
+#[axum::debug_handler]
+pub async fn admin_backup_push_id_dry_run(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(backup_request): Json<IdPacket>,
+) -> impl IntoResponse {
+	let subject = id_audit_subject(&backup_request);
+	let response = push_id(state.clone(), backup_request, true).await;
+	record_admin_audit(&state, addr, AdminAction::PushIdDryRun, subject, response).await
+}
+
+// Dry-run of a push by nftid, a push never replaces the enclave account
+async fn push_id_report(state: &SharedState, nftidv: &[String]) -> DryRunReport {
+	let mut restored = Vec::new();
+	let mut invalid = Vec::new();
+
+	// Only file names are reported, never the keyshares
+	for (index, id_key) in nftidv.iter().enumerate() {
+		match id_key.rsplit_once('_') {
+			Some((filename, _)) =>
+				match helper::parse_keyshare_file(Path::new(&format!("{filename}.keyshare"))) {
+					Ok(entry) => restored.push(entry),
+					Err(_) => invalid.push(filename.to_string()),
+				},
+			None => invalid.push(format!("entry {index}")),
+		}
+	}
+
+	DryRunReport {
+		diff: diff_restore(&restored, &get_nft_availability_map(state).await),
+		current_account: get_accountid(state).await,
+		invalid,
+		..Default::default()
+	}
+}
+
+async fn push_id(state: SharedState, backup_request: IdPacket, dry_run: bool) -> Response {
+	debug!("ADMIN PUSH ID : backup fetch NFTID, dry-run = {dry_run}");
+
+	if !dry_run {
+		update_health_status(
+			&state,
+			"ADMIN PUSH ID : Enclave is doing backup, please wait...".to_string(),
+		)
+		.await;
+	}
 
 	if !verify_account_id(&state, &backup_request.admin_account).await {
 		let message = format!(
@@ -497,6 +574,37 @@ pub async fn admin_backup_push_id(
 		},
 	};
 
+	if dry_run {
+		let report = push_id_report(&state, &nftidv).await;
+		info!("ADMIN PUSH ID : Dry-run by {} : {:?}", backup_request.admin_account, report.diff);
+		return (StatusCode::OK, Json(json!({ "dry_run": report }))).into_response();
+	}
+
+	// Keyshares are not written while a bulk restore or an upgrade install rewrites the sealed
+	// directory, the proposal is kept for a later request
+	let _restore_lock = match RestoreLock::acquire() {
+		Some(lock) => lock,
+		None => {
+			let message = "ADMIN PUSH ID : Another restore is running".to_string();
+			warn!("{message} : admin = {}", backup_request.admin_account);
+			return (StatusCode::CONFLICT, Json(json!({ "error": message }))).into_response();
+		},
+	};
+
+	// A restore needs a proposal of these keyshares approved by the admin quorum
+	if let Err(err) = consume_approved_proposal(
+		&state,
+		&backup_request.proposal_id,
+		&[AdminOperation::PushId],
+		&hash,
+	)
+	.await
+	{
+		let message = format!("ADMIN PUSH ID : Restore is not approved : {err}");
+		warn!("{message} : admin = {}", backup_request.admin_account);
+		return (StatusCode::FORBIDDEN, Json(json!({ "error": message }))).into_response();
+	}
+
 	let id_keyshare: Vec<Option<(&str, &str)>> =
 		nftidv.iter().map(|x| x.rsplit_once('_')).collect();
 	for id_key in id_keyshare {
@@ -636,9 +744,12 @@ pub async fn admin_backup_push_id(
 
 #[cfg(test)]
 mod test {
-	use crate::core::{
-		chain::{create_chain_api, get_current_block_number_new_api},
-		helper,
+	use crate::{
+		constants::NFT_CACHE_SIZE,
+		core::{
+			chain::{create_chain_api, get_current_block_number_new_api},
+			helper,
+		},
 	};
 
 	use super::*;
@@ -691,6 +802,7 @@ mod test {
 			id_vec: nftids_str,
 			auth_token: auth_str,
 			signature: sig_str,
+			proposal_id: String::new(),
 		};
 
 		let request_body = serde_json::to_string(&request).unwrap();
@@ -710,7 +822,7 @@ mod test {
 
 		//let app = Router::new().route("/admin_backup_fetch_id",
 		// post(admin_backup_fetch_id)).with_state(state_config);
-		let mut app = match crate::server::http_server::http_server().await {
+		let mut app = match crate::server::http_server::http_server(NFT_CACHE_SIZE).await {
 			Ok(r) => r,
 			Err(err) => {
 				error!("Error creating http server {}", err);
@@ -0,0 +1,701 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use axum::{
+	extract::{Path, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use hex::FromHex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519};
+use tracing::{debug, info, warn};
+
+use crate::{
+	constants::{
+		ADMIN_PROPOSAL_EXPIRATION, ADMIN_QUORUM_FILE, MAX_ADMIN_PROPOSALS,
+		MAX_ADMIN_PROPOSAL_EXPIRATION,
+	},
+	replication::{
+		metric::AuthenticationToken,
+		sync::{verify_signature, ClusterType, ValidationResult},
+	},
+	server::state::{
+		add_admin_approval, get_admin_proposal, get_admin_proposal_count, get_admin_quorum,
+		get_blocknumber, get_clusters, insert_admin_proposal, remove_admin_proposal,
+		remove_expired_admin_proposals, set_admin_quorum, SharedState,
+	},
+};
+
+/* ---------------------------------------
+	ADMIN QUORUM
+------------------------------------------ */
+
+/// Destructive admin operations, approved by a quorum of admins
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminOperation {
+	PushBulk,
+	PushId,
+	// Bulk push of a backup carrying another enclave account
+	EnclaveKey,
+	Maintenance,
+	QuorumPolicy,
+	// Allow-list of the peer enclaves receiving keyshares
+	EnclavePolicy,
+	// Schedule and custodians of the local backups
+	BackupSchedule,
+}
+
+/// Sealed quorum policy, replaces the majority of the chain admins
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AdminQuorumPolicy {
+	// Monotonic version, an older policy can not be replayed
+	pub version: u32,
+	// Admins allowed to approve, empty for every admin of the chain
+	#[serde(default)]
+	pub admins: Vec<String>,
+	// Distinct approvals needed by an operation
+	pub threshold: usize,
+	// Blocks before a proposal expires
+	pub expiration: u32,
+}
+
+/// Quorum in force, admins of the chain restricted by the sealed policy
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AdminQuorum {
+	// Version of the sealed policy, 0 for the majority of the chain admins
+	pub version: u32,
+	pub admins: Vec<String>,
+	pub threshold: usize,
+	pub expiration: u32,
+}
+
+/// Destructive operation waiting for the approval of the quorum
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AdminProposal {
+	pub id: String,
+	pub operation: AdminOperation,
+	// Hash of the payload of the operation, i.e the uploaded backup
+	pub data_hash: String,
+	pub proposer: String,
+	pub created_block: u32,
+	pub expiry_block: u32,
+	// Signatures of the proposal id, by admin account
+	pub approvals: BTreeMap<String, String>,
+}
+
+/// Operation of a new proposal
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProposalRequest {
+	pub operation: AdminOperation,
+	pub data_hash: String,
+}
+
+/// Signed proposal of an admin
+#[derive(Serialize, Deserialize)]
+pub struct ProposalPacket {
+	admin_address: String,
+	// Serialized ProposalRequest
+	proposal: String,
+	auth_token: String,
+	signature: String,
+}
+
+/// Approval of an admin, the signature covers the proposal id
+#[derive(Serialize, Deserialize)]
+pub struct ApprovalPacket {
+	admin_address: String,
+	proposal_id: String,
+	signature: String,
+}
+
+/// New quorum policy, approved by the current quorum
+#[derive(Serialize, Deserialize)]
+pub struct QuorumPolicyPacket {
+	policy: String,
+	proposal_id: String,
+}
+
+impl AdminQuorumPolicy {
+	pub fn validate(&self) -> Result<(), String> {
+		if self.threshold == 0 {
+			return Err("threshold must be at least 1".to_string());
+		}
+
+		if !self.admins.is_empty() && self.threshold > self.admins.len() {
+			return Err(format!(
+				"threshold {} is larger than the {} admins",
+				self.threshold,
+				self.admins.len()
+			));
+		}
+
+		if !(1..=MAX_ADMIN_PROPOSAL_EXPIRATION).contains(&self.expiration) {
+			return Err(format!(
+				"expiration must be between 1 and {MAX_ADMIN_PROPOSAL_EXPIRATION} blocks"
+			));
+		}
+
+		let mut admins = BTreeSet::new();
+		for admin in &self.admins {
+			if sr25519::Public::from_ss58check(admin).is_err() {
+				return Err(format!("invalid admin account {admin}"));
+			}
+
+			if !admins.insert(admin) {
+				return Err(format!("admin {admin} is listed twice"));
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl AdminQuorum {
+	/// Quorum of the chain admins
+	/// Without a sealed policy, a majority of the admins must approve.
+	/// # Arguments
+	/// * `chain_admins` - Accounts of the admin clusters
+	/// * `policy` - The sealed policy
+	pub fn new(chain_admins: Vec<String>, policy: Option<&AdminQuorumPolicy>) -> AdminQuorum {
+		let mut admins: Vec<String> =
+			chain_admins.into_iter().collect::<BTreeSet<_>>().into_iter().collect();
+
+		match policy {
+			Some(policy) => {
+				// An admin removed from the chain loses its vote
+				if !policy.admins.is_empty() {
+					admins.retain(|admin| policy.admins.contains(admin));
+				}
+
+				AdminQuorum {
+					version: policy.version,
+					admins,
+					threshold: policy.threshold,
+					expiration: policy.expiration,
+				}
+			},
+
+			None => AdminQuorum {
+				version: 0,
+				threshold: admins.len() / 2 + 1,
+				admins,
+				expiration: ADMIN_PROPOSAL_EXPIRATION,
+			},
+		}
+	}
+
+	pub fn is_admin(&self, account: &str) -> bool {
+		self.admins.iter().any(|admin| admin == account)
+	}
+}
+
+impl AdminProposal {
+	/// Approvals of admins still in the quorum
+	pub fn approval_count(&self, quorum: &AdminQuorum) -> usize {
+		self.approvals.keys().filter(|admin| quorum.is_admin(admin)).count()
+	}
+
+	/// Check the proposal authorizes an operation on a payload
+	/// # Arguments
+	/// * `operations` - Operations allowed to authorize the request
+	/// * `data_hash` - Hash of the payload of the request
+	/// * `block_number` - Current block number
+	/// * `quorum` - Quorum in force
+	pub fn authorizes(
+		&self,
+		operations: &[AdminOperation],
+		data_hash: &str,
+		block_number: u32,
+		quorum: &AdminQuorum,
+	) -> Result<(), String> {
+		if !operations.contains(&self.operation) {
+			return Err(format!("proposal {} is not a {operations:?} operation", self.id));
+		}
+
+		if self.data_hash != data_hash {
+			return Err(format!("proposal {} is for another payload", self.id));
+		}
+
+		if block_number > self.expiry_block {
+			return Err(format!("proposal {} expired at block {}", self.id, self.expiry_block));
+		}
+
+		let approvals = self.approval_count(quorum);
+		if approvals < quorum.threshold {
+			return Err(format!(
+				"proposal {} has {approvals} of the {} approvals",
+				self.id, quorum.threshold
+			));
+		}
+
+		Ok(())
+	}
+}
+
+/// Read the sealed quorum policy, None if admins have not set any policy
+pub fn load_quorum_policy() -> Result<Option<AdminQuorumPolicy>> {
+	if !std::path::Path::new(ADMIN_QUORUM_FILE).exists() {
+		return Ok(None);
+	}
+
+	let content = std::fs::read_to_string(ADMIN_QUORUM_FILE)?;
+	let policy = serde_json::from_str(&content)?;
+
+	Ok(Some(policy))
+}
+
+fn seal_quorum_policy(policy: &AdminQuorumPolicy) -> Result<()> {
+	let temporary_file = format!("{ADMIN_QUORUM_FILE}.tmp");
+	std::fs::write(&temporary_file, serde_json::to_string(policy)?)?;
+	std::fs::rename(&temporary_file, ADMIN_QUORUM_FILE)?;
+
+	Ok(())
+}
+
+async fn chain_admins(state: &SharedState) -> Vec<String> {
+	get_clusters(state)
+		.await
+		.into_iter()
+		.filter(|cluster| cluster.cluster_type == ClusterType::Admin)
+		.flat_map(|cluster| cluster.enclaves)
+		.map(|enclave| enclave.enclave_account.to_string())
+		.collect()
+}
+
+/// Quorum in force on this enclave
+pub async fn get_quorum(state: &SharedState) -> AdminQuorum {
+	AdminQuorum::new(chain_admins(state).await, get_admin_quorum(state).await.as_ref())
+}
+
+/// Check an approved proposal authorizes an operation, the proposal stays usable
+/// # Arguments
+/// * `state` - The enclave state
+/// * `proposal_id` - Id of the approved proposal
+/// * `operations` - Operations allowed to authorize the request
+/// * `data_hash` - Hash of the payload of the request
+/// # Returns
+/// * `AdminProposal` - The approved proposal
+pub async fn verify_approved_proposal(
+	state: &SharedState,
+	proposal_id: &str,
+	operations: &[AdminOperation],
+	data_hash: &str,
+) -> Result<AdminProposal, String> {
+	let proposal = match get_admin_proposal(state, proposal_id).await {
+		Some(proposal) => proposal,
+		None => return Err(format!("unknown proposal '{proposal_id}'")),
+	};
+
+	let quorum = get_quorum(state).await;
+	proposal.authorizes(operations, data_hash, get_blocknumber(state).await, &quorum)?;
+
+	Ok(proposal)
+}
+
+/// Use an approved proposal, a proposal authorizes a single operation
+/// # Arguments
+/// * `state` - The enclave state
+/// * `proposal_id` - Id of the approved proposal
+/// * `operations` - Operations allowed to authorize the request
+/// * `data_hash` - Hash of the payload of the request
+/// # Returns
+/// * `AdminProposal` - The used proposal
+pub async fn consume_approved_proposal(
+	state: &SharedState,
+	proposal_id: &str,
+	operations: &[AdminOperation],
+	data_hash: &str,
+) -> Result<AdminProposal, String> {
+	verify_approved_proposal(state, proposal_id, operations, data_hash).await?;
+
+	// Two requests with the same proposal, only the first one removes it
+	match remove_admin_proposal(state, proposal_id).await {
+		Some(proposal) => {
+			info!(
+				"ADMIN QUORUM : proposal {proposal_id} of {} is used with {} approvals",
+				proposal.proposer,
+				proposal.approvals.len()
+			);
+			Ok(proposal)
+		},
+		None => Err(format!("proposal '{proposal_id}' is already used")),
+	}
+}
+
+fn proposal_id(request: &ProposalRequest, proposer: &str, block_number: u32) -> String {
+	sha256::digest(
+		format!(
+			"{proposer}:{block_number}:{:?}:{}:{:016x}",
+			request.operation,
+			request.data_hash,
+			rand::random::<u64>()
+		)
+		.as_bytes(),
+	)
+}
+
+// Wallet extensions sign the raw bytes wrapped in <Bytes> tags
+fn verify_approval(account_id: &str, signature: &str, proposal_id: &str) -> bool {
+	verify_signature(account_id, signature.to_string(), proposal_id.as_bytes()) ||
+		verify_signature(
+			account_id,
+			signature.to_string(),
+			format!("<Bytes>{proposal_id}</Bytes>").as_bytes(),
+		)
+}
+
+fn quorum_error(status: StatusCode, message: String) -> Response {
+	warn!(message);
+	(status, Json(json!({ "error": message }))).into_response()
+}
+
+fn proposal_status(proposal: &AdminProposal, quorum: &AdminQuorum) -> serde_json::Value {
+	json!({
+		"proposal": proposal,
+		"approvals": proposal.approval_count(quorum),
+		"threshold": quorum.threshold,
+	})
+}
+
+/* ----------------------------------
+		QUORUM ENDPOINTS
+----------------------------------*/
+
+/// Quorum in force for the destructive admin operations
+pub async fn get_quorum_handler(State(state): State<SharedState>) -> impl IntoResponse {
+	(StatusCode::OK, Json(json!(get_quorum(&state).await)))
+}
+
+/// Propose a destructive operation, signed by an admin of the quorum
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The signed proposal
+/// # Returns
+/// * `Json` - The proposal, its id is signed by the approving admins
+pub async fn create_proposal_handler(
+	State(state): State<SharedState>,
+	Json(request): Json<ProposalPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN QUORUM : proposal from {}", request.admin_address);
+
+	let quorum = get_quorum(&state).await;
+	if !quorum.is_admin(&request.admin_address) {
+		let message =
+			format!("ADMIN QUORUM : Requester is not an admin : {}", request.admin_address);
+		return quorum_error(StatusCode::FORBIDDEN, message);
+	}
+
+	if !verify_signature(
+		&request.admin_address,
+		request.signature.clone(),
+		request.auth_token.as_bytes(),
+	) {
+		return quorum_error(StatusCode::FORBIDDEN, "ADMIN QUORUM : Invalid Signature".into());
+	}
+
+	let auth = request
+		.auth_token
+		.strip_prefix("<Bytes>")
+		.and_then(|auth| auth.strip_suffix("</Bytes>"))
+		.unwrap_or(&request.auth_token);
+
+	let auth_token: AuthenticationToken = match serde_json::from_str(auth) {
+		Ok(token) => token,
+		Err(err) => {
+			let message = format!("ADMIN QUORUM : Authentication token is not parsable : {err}");
+			return quorum_error(StatusCode::BAD_REQUEST, message);
+		},
+	};
+
+	let block_number = get_blocknumber(&state).await;
+	let validity = auth_token.is_valid(block_number);
+	if !matches!(validity, ValidationResult::Success) {
+		let message = format!("ADMIN QUORUM : Authentication Token is not valid : {validity:?}");
+		return quorum_error(StatusCode::NOT_ACCEPTABLE, message);
+	}
+
+	if auth_token.data_hash != sha256::digest(request.proposal.as_bytes()) {
+		return quorum_error(StatusCode::BAD_REQUEST, "ADMIN QUORUM : Mismatch Data Hash".into());
+	}
+
+	let proposal_request: ProposalRequest = match serde_json::from_str(&request.proposal) {
+		Ok(proposal) => proposal,
+		Err(err) => {
+			let message = format!("ADMIN QUORUM : Proposal is not parsable : {err}");
+			return quorum_error(StatusCode::BAD_REQUEST, message);
+		},
+	};
+
+	if <[u8; 32]>::from_hex(&proposal_request.data_hash).is_err() {
+		let message = "ADMIN QUORUM : Proposal data hash is not a sha256 digest".to_string();
+		return quorum_error(StatusCode::BAD_REQUEST, message);
+	}
+
+	remove_expired_admin_proposals(&state, block_number).await;
+	if get_admin_proposal_count(&state).await >= MAX_ADMIN_PROPOSALS {
+		let message = "ADMIN QUORUM : Too many pending proposals".to_string();
+		return quorum_error(StatusCode::TOO_MANY_REQUESTS, message);
+	}
+
+	let proposal = AdminProposal {
+		id: proposal_id(&proposal_request, &request.admin_address, block_number),
+		operation: proposal_request.operation,
+		data_hash: proposal_request.data_hash,
+		proposer: request.admin_address,
+		created_block: block_number,
+		expiry_block: block_number + quorum.expiration,
+		approvals: BTreeMap::new(),
+	};
+
+	info!(
+		"ADMIN QUORUM : proposal {} of {:?} by {} expires at block {}",
+		proposal.id, proposal.operation, proposal.proposer, proposal.expiry_block
+	);
+
+	insert_admin_proposal(&state, proposal.clone()).await;
+
+	(StatusCode::OK, Json(proposal_status(&proposal, &quorum))).into_response()
+}
+
+/// Approve a proposal, the admin signs the proposal id
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The signed approval
+/// # Returns
+/// * `Json` - The proposal and its number of approvals
+pub async fn approve_proposal_handler(
+	State(state): State<SharedState>,
+	Json(request): Json<ApprovalPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN QUORUM : approval of {} by {}", request.proposal_id, request.admin_address);
+
+	let quorum = get_quorum(&state).await;
+	if !quorum.is_admin(&request.admin_address) {
+		let message =
+			format!("ADMIN QUORUM : Requester is not an admin : {}", request.admin_address);
+		return quorum_error(StatusCode::FORBIDDEN, message);
+	}
+
+	let proposal = match get_admin_proposal(&state, &request.proposal_id).await {
+		Some(proposal) => proposal,
+		None => {
+			let message = format!("ADMIN QUORUM : Unknown proposal '{}'", request.proposal_id);
+			return quorum_error(StatusCode::NOT_FOUND, message);
+		},
+	};
+
+	if get_blocknumber(&state).await > proposal.expiry_block {
+		let message = format!("ADMIN QUORUM : Proposal {} is expired", proposal.id);
+		return quorum_error(StatusCode::GONE, message);
+	}
+
+	if !verify_approval(&request.admin_address, &request.signature, &proposal.id) {
+		return quorum_error(StatusCode::FORBIDDEN, "ADMIN QUORUM : Invalid Signature".into());
+	}
+
+	let proposal = match add_admin_approval(
+		&state,
+		&proposal.id,
+		request.admin_address.clone(),
+		request.signature,
+	)
+	.await
+	{
+		Some(proposal) => proposal,
+		None => {
+			let message = format!("ADMIN QUORUM : Proposal {} is already used", proposal.id);
+			return quorum_error(StatusCode::NOT_FOUND, message);
+		},
+	};
+
+	info!(
+		"ADMIN QUORUM : proposal {} is approved by {} : {} of {}",
+		proposal.id,
+		request.admin_address,
+		proposal.approval_count(&quorum),
+		quorum.threshold
+	);
+
+	(StatusCode::OK, Json(proposal_status(&proposal, &quorum))).into_response()
+}
+
+/// Status of a proposal
+pub async fn get_proposal_handler(
+	State(state): State<SharedState>,
+	Path(proposal_id): Path<String>,
+) -> impl IntoResponse {
+	match get_admin_proposal(&state, &proposal_id).await {
+		Some(proposal) => {
+			let quorum = get_quorum(&state).await;
+			(StatusCode::OK, Json(proposal_status(&proposal, &quorum))).into_response()
+		},
+		None => quorum_error(
+			StatusCode::NOT_FOUND,
+			format!("ADMIN QUORUM : Unknown proposal '{proposal_id}'"),
+		),
+	}
+}
+
+/// Replace the quorum policy, approved by the current quorum
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The policy and its approved proposal
+/// # Returns
+/// * `Json` - The new quorum or the error
+pub async fn set_quorum_policy_handler(
+	State(state): State<SharedState>,
+	Json(request): Json<QuorumPolicyPacket>,
+) -> impl IntoResponse {
+	let policy: AdminQuorumPolicy = match serde_json::from_str(&request.policy) {
+		Ok(policy) => policy,
+		Err(err) => {
+			let message = format!("ADMIN QUORUM : Policy is not parsable : {err}");
+			return quorum_error(StatusCode::BAD_REQUEST, message);
+		},
+	};
+
+	if let Err(err) = policy.validate() {
+		return quorum_error(StatusCode::BAD_REQUEST, format!("ADMIN QUORUM : {err}"));
+	}
+
+	let current_version = get_admin_quorum(&state).await.map_or(0, |current| current.version);
+	if policy.version <= current_version {
+		let message = format!(
+			"ADMIN QUORUM : Policy version {} is not newer than {current_version}",
+			policy.version
+		);
+		return quorum_error(StatusCode::CONFLICT, message);
+	}
+
+	// A policy no admin of the chain can satisfy would lock every destructive operation
+	let quorum = AdminQuorum::new(chain_admins(&state).await, Some(&policy));
+	if quorum.admins.len() < quorum.threshold {
+		let message = format!(
+			"ADMIN QUORUM : Only {} admins of the policy are on chain, threshold is {}",
+			quorum.admins.len(),
+			quorum.threshold
+		);
+		return quorum_error(StatusCode::BAD_REQUEST, message);
+	}
+
+	let data_hash = sha256::digest(request.policy.as_bytes());
+	if let Err(err) = consume_approved_proposal(
+		&state,
+		&request.proposal_id,
+		&[AdminOperation::QuorumPolicy],
+		&data_hash,
+	)
+	.await
+	{
+		return quorum_error(StatusCode::FORBIDDEN, format!("ADMIN QUORUM : {err}"));
+	}
+
+	if let Err(err) = seal_quorum_policy(&policy) {
+		let message = format!("ADMIN QUORUM : Can not seal the policy : {err:?}");
+		return quorum_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	info!(
+		"ADMIN QUORUM : version {} with threshold {} of {} admins is set",
+		policy.version,
+		quorum.threshold,
+		quorum.admins.len()
+	);
+
+	set_admin_quorum(&state, Some(policy)).await;
+
+	(StatusCode::OK, Json(json!(quorum))).into_response()
+}
+
+/* ----------------------------------
+		ADMIN QUORUM TEST
+----------------------------------*/
+
+#[cfg(test)]
+mod test {
+	use subxt::ext::sp_core::Pair;
+
+	use super::*;
+
+	fn account() -> (sr25519::Pair, String) {
+		let pair = sr25519::Pair::generate().0;
+		let account = pair.public().to_ss58check();
+		(pair, account)
+	}
+
+	#[test]
+	fn quorum_policy_test() {
+		let admins: Vec<String> = (0..3).map(|_| account().1).collect();
+
+		// Majority of the chain admins
+		let quorum = AdminQuorum::new(admins.clone(), None);
+		assert_eq!(quorum.threshold, 2);
+		assert_eq!(quorum.expiration, ADMIN_PROPOSAL_EXPIRATION);
+
+		// Policy admins missing on chain do not count
+		let policy = AdminQuorumPolicy {
+			version: 1,
+			admins: vec![admins[0].clone(), account().1],
+			threshold: 2,
+			expiration: 100,
+		};
+		assert!(policy.validate().is_ok());
+		let quorum = AdminQuorum::new(admins.clone(), Some(&policy));
+		assert_eq!(quorum.admins, vec![admins[0].clone()]);
+
+		assert!(AdminQuorumPolicy { threshold: 0, ..policy.clone() }.validate().is_err());
+		assert!(AdminQuorumPolicy { threshold: 3, ..policy.clone() }.validate().is_err());
+		assert!(AdminQuorumPolicy { expiration: 0, ..policy.clone() }.validate().is_err());
+		let twice = vec![admins[0].clone(), admins[0].clone()];
+		assert!(AdminQuorumPolicy { admins: twice, ..policy }.validate().is_err());
+	}
+
+	#[test]
+	fn proposal_approval_test() {
+		let admins: Vec<(sr25519::Pair, String)> = (0..3).map(|_| account()).collect();
+		let quorum = AdminQuorum::new(admins.iter().map(|(_, a)| a.clone()).collect(), None);
+
+		let mut proposal = AdminProposal {
+			id: sha256::digest("proposal"),
+			operation: AdminOperation::PushBulk,
+			data_hash: sha256::digest("backup"),
+			proposer: admins[0].1.clone(),
+			created_block: 100,
+			expiry_block: 200,
+			approvals: BTreeMap::new(),
+		};
+
+		let hash = sha256::digest("backup");
+		let approve = |proposal: &mut AdminProposal, (pair, account): &(sr25519::Pair, String)| {
+			let signature = format!("0x{}", hex::encode(pair.sign(proposal.id.as_bytes()).0));
+			assert!(verify_approval(account, &signature, &proposal.id));
+			proposal.approvals.insert(account.clone(), signature);
+		};
+
+		approve(&mut proposal, &admins[0]);
+		assert!(proposal.authorizes(&[AdminOperation::PushBulk], &hash, 150, &quorum).is_err());
+
+		// An admin approving twice counts once, a stranger does not count
+		approve(&mut proposal, &admins[0]);
+		approve(&mut proposal, &account());
+		assert_eq!(proposal.approval_count(&quorum), 1);
+
+		approve(&mut proposal, &admins[1]);
+		assert!(proposal.authorizes(&[AdminOperation::PushBulk], &hash, 150, &quorum).is_ok());
+
+		// Other operation, other payload and expired proposal
+		assert!(proposal.authorizes(&[AdminOperation::PushId], &hash, 150, &quorum).is_err());
+		let other = sha256::digest("other");
+		assert!(proposal.authorizes(&[AdminOperation::PushBulk], &other, 150, &quorum).is_err());
+		assert!(proposal.authorizes(&[AdminOperation::PushBulk], &hash, 201, &quorum).is_err());
+
+		// Signature of another id
+		let (pair, account) = &admins[2];
+		let signature = format!("0x{}", hex::encode(pair.sign(b"another id").0));
+		assert!(!verify_approval(account, &signature, &proposal.id));
+	}
+}
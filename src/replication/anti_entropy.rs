@@ -0,0 +1,450 @@
+use std::{
+	collections::BTreeMap,
+	net::SocketAddr,
+	ops::RangeInclusive,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+	extract::{ConnectInfo, State},
+	http::{header, StatusCode},
+	response::IntoResponse,
+	Json,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::Pair;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	constants::{ANTI_ENTROPY_RANGE, MAX_SYNC_PAGE_SIZE},
+	core::helper::{Availability, NftType},
+	replication::{
+		maintenance::sealed_writes_paused,
+		metric::AuthenticationToken,
+		sync::{pull_keyshares, slot_discovery, sync_client, verify_signature, ValidationResult},
+	},
+	server::state::{
+		get_accountid, get_blocknumber, get_keypair, get_nft_availability,
+		get_nft_availability_range, SharedState,
+	},
+};
+
+/* ---------------------------------------
+	ANTI-ENTROPY RECONCILIATION
+------------------------------------------ */
+
+// Ranges of one entries request, bounded like a sync page
+const MAX_RANGES_PER_REQUEST: usize = (MAX_SYNC_PAGE_SIZE / ANTI_ENTROPY_RANGE) as usize;
+
+// Only one reconciliation runs at a time
+static RECONCILING: AtomicBool = AtomicBool::new(false);
+
+/// Digest of the keyshares in a range of NFT ids
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RangeDigest {
+	pub count: u32,
+	pub digest: String,
+}
+
+/// Keyshare of an NFT id, as stored in the nft_block_map
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct KeyshareEntry {
+	pub block_number: u32,
+	pub nft_type: NftType,
+}
+
+/// Anti-entropy request of an enclave of the same slot
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AntiEntropyPacket {
+	enclave_account: String,
+	ranges: String,
+	auth_token: String,
+	signature: String,
+}
+
+/// Digests of all the ranges stored on the enclave
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DigestsResponse {
+	pub block_number: u32,
+	pub digests: BTreeMap<u32, RangeDigest>,
+}
+
+/// Keyshares of the requested ranges
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EntriesResponse {
+	pub block_number: u32,
+	pub entries: BTreeMap<u32, KeyshareEntry>,
+}
+
+fn range_bounds(range: u32) -> RangeInclusive<u32> {
+	let start = range.saturating_mul(ANTI_ENTROPY_RANGE);
+	start..=start.saturating_add(ANTI_ENTROPY_RANGE - 1)
+}
+
+/// Digest of every non-empty range of the nft_block_map
+/// # Arguments
+/// * `nft_block_map` - The NFTs stored on the enclave
+/// # Returns
+/// * `BTreeMap<u32, RangeDigest>` - Digest per range index
+pub fn range_digests(nft_block_map: &BTreeMap<u32, Availability>) -> BTreeMap<u32, RangeDigest> {
+	let mut digests = BTreeMap::new();
+	let mut current: Option<(u32, u32, String)> = None;
+
+	// The map is ordered, ranges are completed one after the other
+	for (nftid, availability) in nft_block_map {
+		let range = nftid / ANTI_ENTROPY_RANGE;
+
+		if let Some((current_range, count, content)) = current.take() {
+			if current_range == range {
+				current = Some((current_range, count, content));
+			} else {
+				digests.insert(
+					current_range,
+					RangeDigest { count, digest: sha256::digest(content.as_bytes()) },
+				);
+			}
+		}
+
+		let (_, count, content) = current.get_or_insert((range, 0, String::new()));
+		*count += 1;
+		content.push_str(&format!(
+			"{nftid}:{}:{:?};",
+			availability.block_number, availability.nft_type
+		));
+	}
+
+	if let Some((range, count, content)) = current {
+		digests.insert(range, RangeDigest { count, digest: sha256::digest(content.as_bytes()) });
+	}
+
+	digests
+}
+
+/// A remote keyshare is pulled if it is missing, older or partial (i.e secret of a hybrid) locally
+fn needs_pull(local: Option<Availability>, remote: &KeyshareEntry) -> bool {
+	match local {
+		None => true,
+		Some(local) =>
+			local.block_number < remote.block_number ||
+				(local.nft_type != remote.nft_type && local.nft_type != NftType::Hybrid),
+	}
+}
+
+/* ----------------------------------
+		REQUEST VERIFICATION
+----------------------------------*/
+
+async fn verify_anti_entropy_request(
+	state: &SharedState,
+	request: &AntiEntropyPacket,
+) -> Result<Vec<u32>, String> {
+	let slot_enclaves = slot_discovery(state).await;
+	if !slot_enclaves
+		.iter()
+		.any(|(_, enclave)| enclave.enclave_account.to_string() == request.enclave_account)
+	{
+		return Err(format!(
+			"ANTI-ENTROPY : Requester is not an enclave of the same slot : {}",
+			request.enclave_account
+		));
+	}
+
+	if !verify_signature(
+		&request.enclave_account,
+		request.signature.clone(),
+		request.auth_token.as_bytes(),
+	) {
+		return Err("ANTI-ENTROPY : Invalid Signature".to_string());
+	}
+
+	let auth = request
+		.auth_token
+		.strip_prefix("<Bytes>")
+		.and_then(|auth| auth.strip_suffix("</Bytes>"))
+		.unwrap_or(&request.auth_token);
+
+	let auth_token: AuthenticationToken = serde_json::from_str(auth)
+		.map_err(|err| format!("ANTI-ENTROPY : Authentication token is not parsable : {err}"))?;
+
+	let validity = auth_token.is_valid(get_blocknumber(state).await);
+	if !matches!(validity, ValidationResult::Success) {
+		return Err(format!("ANTI-ENTROPY : Authentication Token is not valid : {validity:?}"));
+	}
+
+	if auth_token.data_hash != sha256::digest(request.ranges.as_bytes()) {
+		return Err("ANTI-ENTROPY : Mismatch Data Hash".to_string());
+	}
+
+	serde_json::from_str(&request.ranges)
+		.map_err(|err| format!("ANTI-ENTROPY : Ranges are not parsable : {err}"))
+}
+
+fn anti_entropy_error(message: String) -> axum::response::Response {
+	warn!(message);
+	(StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+}
+
+/* ----------------------------------
+	ANTI-ENTROPY ENDPOINTS (Server Side)
+----------------------------------*/
+
+/// Digests of the nft_block_map ranges, for an enclave of the same slot
+pub async fn anti_entropy_digests(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<AntiEntropyPacket>,
+) -> impl IntoResponse {
+	debug!("ANTI-ENTROPY : digests request from {addr}");
+
+	if let Err(message) = verify_anti_entropy_request(&state, &request).await {
+		return anti_entropy_error(message);
+	}
+
+	let block_number = get_blocknumber(&state).await;
+	let digests = range_digests(state.read().await.nft_availability_map());
+
+	(StatusCode::OK, Json(DigestsResponse { block_number, digests })).into_response()
+}
+
+/// Keyshare entries of the requested ranges, for an enclave of the same slot
+pub async fn anti_entropy_entries(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<AntiEntropyPacket>,
+) -> impl IntoResponse {
+	debug!("ANTI-ENTROPY : entries request from {addr}");
+
+	let ranges = match verify_anti_entropy_request(&state, &request).await {
+		Ok(ranges) => ranges,
+		Err(message) => return anti_entropy_error(message),
+	};
+
+	if ranges.is_empty() || ranges.len() > MAX_RANGES_PER_REQUEST {
+		let message = format!(
+			"ANTI-ENTROPY : between 1 and {MAX_RANGES_PER_REQUEST} ranges can be requested"
+		);
+		return anti_entropy_error(message);
+	}
+
+	let mut entries = BTreeMap::new();
+	for range in ranges {
+		for (nftid, availability) in get_nft_availability_range(&state, range_bounds(range)).await {
+			entries.insert(
+				nftid,
+				KeyshareEntry {
+					block_number: availability.block_number,
+					nft_type: availability.nft_type,
+				},
+			);
+		}
+	}
+
+	let block_number = get_blocknumber(&state).await;
+
+	(StatusCode::OK, Json(EntriesResponse { block_number, entries })).into_response()
+}
+
+/* ----------------------------------
+	RECONCILIATION (Client Side)
+----------------------------------*/
+
+/// Start a reconciliation with the enclaves of the same slot, if none is running
+pub fn spawn_anti_entropy(state: SharedState) {
+	if RECONCILING.swap(true, Ordering::SeqCst) {
+		debug!("ANTI-ENTROPY : previous reconciliation is still running");
+		return;
+	}
+
+	tokio::spawn(async move {
+		match reconcile_slot(&state).await {
+			Ok(pulled) => info!("ANTI-ENTROPY : reconciliation done, {pulled} keyshares pulled"),
+			Err(err) => {
+				let message = format!("ANTI-ENTROPY : reconciliation failed : {err:?}");
+				error!(message);
+				sentry::with_scope(
+					|scope| {
+						scope.set_tag("anti-entropy", "reconcile");
+					},
+					|| sentry::capture_message(&message, sentry::Level::Error),
+				);
+			},
+		}
+
+		RECONCILING.store(false, Ordering::SeqCst);
+	});
+}
+
+async fn reconcile_slot(state: &SharedState) -> Result<usize> {
+	let slot_enclaves = slot_discovery(state).await;
+	let client = sync_client()?;
+	let mut pulled = 0;
+
+	for (cluster_id, enclave) in slot_enclaves {
+		if let Some(reason) = sealed_writes_paused(state).await {
+			debug!("ANTI-ENTROPY : reconciliation is paused, {reason}");
+			break;
+		}
+
+		let enclave_url = enclave.enclave_url.trim_end_matches('/').to_string();
+
+		match reconcile_enclave(state, &client, &enclave_url).await {
+			Ok(count) => pulled += count,
+			// A failed peer does not stop the reconciliation with the others
+			Err(err) => warn!(
+				"ANTI-ENTROPY : reconciliation with cluster {cluster_id} , {enclave_url} failed : {err:?}"
+			),
+		}
+	}
+
+	Ok(pulled)
+}
+
+async fn reconcile_enclave(
+	state: &SharedState,
+	client: &reqwest::Client,
+	enclave_url: &str,
+) -> Result<usize> {
+	let remote: DigestsResponse = anti_entropy_request(
+		state,
+		client,
+		&format!("{enclave_url}/api/backup/anti-entropy/digests"),
+		&[],
+	)
+	.await?;
+
+	let local_digests = range_digests(state.read().await.nft_availability_map());
+
+	// Ranges only stored locally have nothing to pull
+	let differing: Vec<u32> = remote
+		.digests
+		.iter()
+		.filter(|(range, digest)| local_digests.get(range) != Some(digest))
+		.map(|(range, _)| *range)
+		.collect();
+
+	debug!("ANTI-ENTROPY : {} differing ranges with {enclave_url}", differing.len());
+
+	let mut pulled = 0;
+
+	for ranges in differing.chunks(MAX_RANGES_PER_REQUEST) {
+		let remote_entries: EntriesResponse = anti_entropy_request(
+			state,
+			client,
+			&format!("{enclave_url}/api/backup/anti-entropy/entries"),
+			ranges,
+		)
+		.await?;
+
+		let mut missing = Vec::new();
+		for (nftid, entry) in remote_entries.entries.iter() {
+			if needs_pull(get_nft_availability(state, *nftid).await, entry) {
+				missing.push(*nftid);
+			}
+		}
+
+		if missing.is_empty() {
+			continue;
+		}
+
+		// A restore or a maintenance may start while the entries are compared
+		if let Some(reason) = sealed_writes_paused(state).await {
+			return Err(anyhow!("pulls are paused, {reason}"));
+		}
+
+		info!("ANTI-ENTROPY : pulling {} keyshares from {enclave_url}", missing.len());
+		pull_keyshares(state, enclave_url, &missing).await?;
+		pulled += missing.len();
+	}
+
+	Ok(pulled)
+}
+
+// Signed request to an enclave of the same slot
+async fn anti_entropy_request<T: DeserializeOwned>(
+	state: &SharedState,
+	client: &reqwest::Client,
+	request_url: &str,
+	ranges: &[u32],
+) -> Result<T> {
+	let ranges = serde_json::to_string(ranges)?;
+
+	let auth_token = serde_json::to_string(&AuthenticationToken {
+		block_number: get_blocknumber(state).await,
+		block_validation: 15,
+		data_hash: sha256::digest(ranges.as_bytes()),
+	})?;
+
+	let signature = get_keypair(state).await.sign(auth_token.as_bytes());
+
+	let packet = AntiEntropyPacket {
+		enclave_account: get_accountid(state).await,
+		ranges,
+		auth_token,
+		signature: format!("0x{:?}", signature),
+	};
+
+	let response = client
+		.post(request_url)
+		.body(serde_json::to_string(&packet)?)
+		.header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+		.send()
+		.await?;
+
+	let status = response.status();
+	let body = response.text().await?;
+
+	if status != StatusCode::OK {
+		return Err(anyhow!("{request_url} responded {status} : {body}"));
+	}
+
+	Ok(serde_json::from_str(&body)?)
+}
+
+/* ----------------------------------
+ANTI-ENTROPY TEST
+----------------------------------*/
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn availability(block_number: u32, nft_type: NftType) -> Availability {
+		Availability { block_number, nft_type }
+	}
+
+	#[test]
+	fn range_digests_test() {
+		let mut local = BTreeMap::new();
+		local.insert(1, availability(10, NftType::Secret));
+		local.insert(2, availability(11, NftType::Capsule));
+		local.insert(ANTI_ENTROPY_RANGE + 5, availability(12, NftType::Secret));
+
+		let mut remote = local.clone();
+		remote.insert(ANTI_ENTROPY_RANGE + 5, availability(13, NftType::Secret));
+		remote.insert(3 * ANTI_ENTROPY_RANGE, availability(14, NftType::Hybrid));
+
+		let local_digests = range_digests(&local);
+		let remote_digests = range_digests(&remote);
+
+		assert_eq!(local_digests.len(), 2);
+		assert_eq!(local_digests[&0].count, 2);
+		assert_eq!(local_digests[&0], remote_digests[&0]);
+		assert_ne!(local_digests[&1], remote_digests[&1]);
+		assert!(!local_digests.contains_key(&3));
+		assert_eq!(range_bounds(3), 3 * ANTI_ENTROPY_RANGE..=4 * ANTI_ENTROPY_RANGE - 1);
+	}
+
+	#[test]
+	fn needs_pull_test() {
+		let remote = KeyshareEntry { block_number: 20, nft_type: NftType::Hybrid };
+
+		assert!(needs_pull(None, &remote));
+		assert!(needs_pull(Some(availability(19, NftType::Hybrid)), &remote));
+		assert!(needs_pull(Some(availability(20, NftType::Secret)), &remote));
+		assert!(!needs_pull(Some(availability(20, NftType::Hybrid)), &remote));
+
+		let secret = KeyshareEntry { block_number: 20, nft_type: NftType::Secret };
+		assert!(!needs_pull(Some(availability(21, NftType::Hybrid)), &secret));
+	}
+}
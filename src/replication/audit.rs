@@ -0,0 +1,583 @@
+use std::{
+	fs::{File, OpenOptions},
+	io::{BufRead, BufReader, Seek, SeekFrom, Write},
+	net::SocketAddr,
+	sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::Pair;
+use tracing::{debug, error, warn};
+
+use crate::{
+	constants::{ADMIN_AUDIT_FILE, MAX_AUDIT_PAGE_SIZE},
+	replication::{
+		metric::AuthenticationToken,
+		sync::{verify_signature, ClusterType, ValidationResult},
+	},
+	server::state::{get_accountid, get_blocknumber, get_clusters, get_keypair, SharedState},
+};
+
+/* ---------------------------------------
+	ADMIN AUDIT TRAIL
+------------------------------------------ */
+
+// Previous hash of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// Head and offsets of the sealed chain, appends are serialized by the lock
+static AUDIT_INDEX: Mutex<Option<AuditIndex>> = Mutex::new(None);
+
+/// Privileged requests recorded in the audit trail
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+	FetchBulk,
+	PushBulk,
+	PushBulkDryRun,
+	FetchId,
+	PushId,
+	PushIdDryRun,
+	SetCrawlBlock,
+	Reconciliation,
+	SetMaintenance,
+	SetBackupSchedule,
+}
+
+impl AdminAction {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			AdminAction::FetchBulk => "fetch_bulk",
+			AdminAction::PushBulk => "push_bulk",
+			AdminAction::PushBulkDryRun => "push_bulk_dry_run",
+			AdminAction::FetchId => "fetch_id",
+			AdminAction::PushId => "push_id",
+			AdminAction::PushIdDryRun => "push_id_dry_run",
+			AdminAction::SetCrawlBlock => "set_crawl_block",
+			AdminAction::Reconciliation => "reconciliation",
+			AdminAction::SetMaintenance => "set_maintenance",
+			AdminAction::SetBackupSchedule => "set_backup_schedule",
+		}
+	}
+}
+
+/// Requester and signed token of a privileged request, known once the request is parsed
+#[derive(Clone, Debug, Default)]
+pub struct AuditSubject {
+	pub account: String,
+	// Hash of the signed authentication token, it covers the payload of the request
+	pub data_hash: String,
+}
+
+/// Sealed record of a privileged request
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+	pub index: u64,
+	pub block_number: u32,
+	pub account: String,
+	pub action: AdminAction,
+	pub data_hash: String,
+	// HTTP status of the response
+	pub status: u16,
+	// IP address of the requester
+	pub source: String,
+	pub previous_hash: String,
+	pub hash: String,
+}
+
+/// Number of entries and hash of the last one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuditHead {
+	pub count: u64,
+	pub hash: String,
+}
+
+// Head of the chain and byte offset of each entry in the trail, pages are read from the offsets
+#[derive(Clone, Debug)]
+struct AuditIndex {
+	head: AuditHead,
+	offsets: Vec<u64>,
+}
+
+/// Range of the audit trail requested by an admin
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AuditLogRequest {
+	pub from: u64,
+	pub count: usize,
+}
+
+/// Signed read request of an admin : audit trail, peer statistics or sync state
+#[derive(Serialize, Deserialize)]
+pub struct AdminReadPacket {
+	pub admin_address: String,
+	// Serialized AuditLogRequest, or the name of the endpoint
+	pub request: String,
+	auth_token: String,
+	signature: String,
+}
+
+impl AuditEntry {
+	/// Hash of the entry, chained to the previous entry
+	/// sha256 of "index:previous_hash:block_number:account:action:data_hash:status:source"
+	pub fn digest(&self) -> String {
+		sha256::digest(
+			format!(
+				"{}:{}:{}:{}:{}:{}:{}:{}",
+				self.index,
+				self.previous_hash,
+				self.block_number,
+				self.account,
+				self.action.as_str(),
+				self.data_hash,
+				self.status,
+				self.source
+			)
+			.as_bytes(),
+		)
+	}
+}
+
+impl AuditHead {
+	fn genesis() -> AuditHead {
+		AuditHead { count: 0, hash: GENESIS_HASH.to_string() }
+	}
+
+	/// Message of the head signature
+	/// # Arguments
+	/// * `block_number` - Block number of the signature
+	pub fn message(&self, block_number: u32) -> String {
+		format!("{}_{}_{block_number}", self.count, self.hash)
+	}
+}
+
+/// Verify consecutive entries of the audit trail
+/// # Arguments
+/// * `entries` - Entries in order
+/// * `previous_hash` - Hash of the entry before the first one, GENESIS_HASH from the start
+pub fn verify_audit_chain(entries: &[AuditEntry], previous_hash: &str) -> Result<(), String> {
+	let mut previous_hash = previous_hash;
+	let mut previous_index: Option<u64> = None;
+
+	for entry in entries {
+		if let Some(index) = previous_index {
+			if entry.index != index + 1 {
+				return Err(format!("entry {} does not follow entry {index}", entry.index));
+			}
+		}
+
+		if entry.previous_hash != previous_hash {
+			return Err(format!("entry {} is not chained to the previous entry", entry.index));
+		}
+
+		if entry.hash != entry.digest() {
+			return Err(format!("hash of entry {} does not match its content", entry.index));
+		}
+
+		previous_hash = &entry.hash;
+		previous_index = Some(entry.index);
+	}
+
+	Ok(())
+}
+
+// Entries of the trail and the byte offset of each entry
+fn read_entries(path: &str) -> Result<(Vec<AuditEntry>, Vec<u64>)> {
+	let file = match File::open(path) {
+		Ok(file) => file,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound =>
+			return Ok((Vec::new(), Vec::new())),
+		Err(err) => return Err(err.into()),
+	};
+
+	let mut reader = BufReader::new(file);
+	let mut entries = Vec::new();
+	let mut offsets = Vec::new();
+	let mut offset = 0u64;
+	let mut line = String::new();
+
+	loop {
+		line.clear();
+		let read = reader.read_line(&mut line)?;
+		if read == 0 {
+			break
+		}
+
+		let entry = serde_json::from_str(line.trim_end()).map_err(|err| {
+			anyhow!("line {} of the audit trail is not parsable : {err}", entries.len() + 1)
+		})?;
+		entries.push(entry);
+		offsets.push(offset);
+		offset += read as u64;
+	}
+
+	Ok((entries, offsets))
+}
+
+// At most count entries from the byte offset of the first one
+fn read_page(path: &str, offset: u64, count: usize) -> Result<Vec<AuditEntry>> {
+	let mut file = File::open(path)?;
+	file.seek(SeekFrom::Start(offset))?;
+
+	BufReader::new(file)
+		.lines()
+		.take(count)
+		.map(|line| {
+			serde_json::from_str(&line?)
+				.map_err(|err| anyhow!("audit trail is not parsable from offset {offset} : {err}"))
+		})
+		.collect()
+}
+
+fn head_of(entries: &[AuditEntry]) -> AuditHead {
+	match entries.last() {
+		Some(last) => AuditHead { count: last.index + 1, hash: last.hash.clone() },
+		None => AuditHead::genesis(),
+	}
+}
+
+// Appended entry and its byte offset
+fn append_entry(path: &str, head: &AuditHead, mut entry: AuditEntry) -> Result<(AuditEntry, u64)> {
+	entry.index = head.count;
+	entry.previous_hash = head.hash.clone();
+	entry.hash = entry.digest();
+
+	let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+	let offset = file.metadata()?.len();
+	writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+	file.sync_data()?;
+
+	Ok((entry, offset))
+}
+
+fn load_index(path: &str) -> Result<AuditIndex> {
+	let (entries, offsets) = read_entries(path)?;
+	Ok(AuditIndex { head: head_of(&entries), offsets })
+}
+
+/// Read the sealed audit trail and verify its chain
+/// Appends continue from the last entry even when the chain is broken, auditors see the break.
+/// # Returns
+/// * `AuditHead` - Head of the chain
+pub fn open_audit_log() -> Result<AuditHead> {
+	let mut index = AUDIT_INDEX.lock().map_err(|_| anyhow!("audit lock is poisoned"))?;
+
+	let (entries, offsets) = read_entries(ADMIN_AUDIT_FILE)?;
+	*index = Some(AuditIndex { head: head_of(&entries), offsets });
+
+	verify_audit_chain(&entries, GENESIS_HASH)
+		.map_err(|err| anyhow!("audit trail is broken : {err}"))?;
+
+	Ok(head_of(&entries))
+}
+
+fn append_audit_entry(entry: AuditEntry) -> Result<AuditEntry> {
+	let mut guard = AUDIT_INDEX.lock().map_err(|_| anyhow!("audit lock is poisoned"))?;
+
+	let index = match guard.as_mut() {
+		Some(index) => index,
+		None => guard.insert(load_index(ADMIN_AUDIT_FILE)?),
+	};
+
+	let (entry, offset) = append_entry(ADMIN_AUDIT_FILE, &index.head, entry)?;
+	index.head = AuditHead { count: entry.index + 1, hash: entry.hash.clone() };
+	index.offsets.push(offset);
+
+	Ok(entry)
+}
+
+/// Record a privileged request in the sealed audit trail
+/// A request which can not be recorded fails, its response is not returned to the requester.
+/// # Arguments
+/// * `state` - The enclave state
+/// * `source` - Address of the requester
+/// * `action` - The privileged request
+/// * `subject` - Requester and payload of the request
+/// * `response` - Response of the request
+/// # Returns
+/// * `Response` - The response, or an error when the trail can not be written
+pub async fn record_admin_audit(
+	state: &SharedState,
+	source: SocketAddr,
+	action: AdminAction,
+	subject: AuditSubject,
+	response: Response,
+) -> Response {
+	let status = response.status();
+	let entry = AuditEntry {
+		index: 0,
+		block_number: get_blocknumber(state).await,
+		account: subject.account,
+		action,
+		data_hash: subject.data_hash,
+		status: status.as_u16(),
+		source: source.ip().to_string(),
+		previous_hash: String::new(),
+		hash: String::new(),
+	};
+
+	match append_audit_entry(entry) {
+		Ok(entry) => {
+			debug!(
+				"ADMIN AUDIT : entry {} : {} by {} from {} : {}",
+				entry.index,
+				entry.action.as_str(),
+				entry.account,
+				entry.source,
+				entry.status
+			);
+			response
+		},
+		Err(err) => {
+			let message = format!("ADMIN AUDIT : can not record {} : {err:?}", action.as_str());
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("admin-audit", "append");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				Json(json!({ "error": "ADMIN AUDIT : Request can not be recorded" })),
+			)
+				.into_response()
+		},
+	}
+}
+
+// Page of the trail and the head, the page is read from the offset of its first entry.
+// The trail is append-only, the entries up to the head are not changed by later appends.
+fn read_audit_page(from: u64, count: usize) -> Result<(Vec<AuditEntry>, AuditHead)> {
+	let (head, offset) = {
+		let mut guard = AUDIT_INDEX.lock().map_err(|_| anyhow!("audit lock is poisoned"))?;
+		let index = match guard.as_mut() {
+			Some(index) => index,
+			None => guard.insert(load_index(ADMIN_AUDIT_FILE)?),
+		};
+		(
+			index.head.clone(),
+			usize::try_from(from).ok().and_then(|from| index.offsets.get(from).copied()),
+		)
+	};
+
+	let page = match offset {
+		Some(offset) => {
+			let available = usize::try_from(head.count - from).unwrap_or(usize::MAX);
+			read_page(ADMIN_AUDIT_FILE, offset, count.min(available))?
+		},
+		None => Vec::new(),
+	};
+
+	Ok((page, head))
+}
+
+async fn verify_admin_account(state: &SharedState, account_id: &str) -> bool {
+	get_clusters(state)
+		.await
+		.into_iter()
+		.filter(|cluster| cluster.cluster_type == ClusterType::Admin)
+		.flat_map(|cluster| cluster.enclaves)
+		.any(|enclave| enclave.enclave_account.to_string() == account_id)
+}
+
+/// Verify a signed read request : admin account, signature and validity of the token
+/// # Arguments
+/// * `state` - The enclave state
+/// * `packet` - The signed request of an admin
+/// # Returns
+/// * `(StatusCode, String)` - Status and reason of a rejected request
+pub async fn verify_admin_read(
+	state: &SharedState,
+	packet: &AdminReadPacket,
+) -> Result<(), (StatusCode, String)> {
+	if !verify_admin_account(state, &packet.admin_address).await {
+		return Err((
+			StatusCode::FORBIDDEN,
+			format!("Requester is not an admin : {}", packet.admin_address),
+		));
+	}
+
+	if !verify_signature(
+		&packet.admin_address,
+		packet.signature.clone(),
+		packet.auth_token.as_bytes(),
+	) {
+		return Err((StatusCode::FORBIDDEN, "Invalid Signature".into()));
+	}
+
+	let auth = packet
+		.auth_token
+		.strip_prefix("<Bytes>")
+		.and_then(|auth| auth.strip_suffix("</Bytes>"))
+		.unwrap_or(&packet.auth_token);
+
+	let auth_token: AuthenticationToken = match serde_json::from_str(auth) {
+		Ok(token) => token,
+		Err(err) =>
+			return Err((
+				StatusCode::BAD_REQUEST,
+				format!("Authentication token is not parsable : {err}"),
+			)),
+	};
+
+	let validity = auth_token.is_valid(get_blocknumber(state).await);
+	if !matches!(validity, ValidationResult::Success) {
+		return Err((
+			StatusCode::NOT_ACCEPTABLE,
+			format!("Authentication Token is not valid : {validity:?}"),
+		));
+	}
+
+	if auth_token.data_hash != sha256::digest(packet.request.as_bytes()) {
+		return Err((StatusCode::BAD_REQUEST, "Mismatch Data Hash".into()));
+	}
+
+	Ok(())
+}
+
+fn audit_error(status: StatusCode, message: String) -> Response {
+	warn!(message);
+	(status, Json(json!({ "error": message }))).into_response()
+}
+
+/* ----------------------------------
+		AUDIT ENDPOINT
+----------------------------------*/
+
+/// Entries of the audit trail, with the head signed by the enclave
+/// Auditors check the chain of the entries, and the signed count and hash of the head
+/// against the last entry, a removed entry breaks the chain.
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The signed audit request of an admin
+pub async fn audit_log_handler(
+	State(state): State<SharedState>,
+	Json(request): Json<AdminReadPacket>,
+) -> impl IntoResponse {
+	debug!("ADMIN AUDIT : audit request of {}", request.admin_address);
+
+	let block_number = get_blocknumber(&state).await;
+
+	if let Err((status, message)) = verify_admin_read(&state, &request).await {
+		return audit_error(status, format!("ADMIN AUDIT : {message}"));
+	}
+
+	let range: AuditLogRequest = match serde_json::from_str(&request.request) {
+		Ok(range) => range,
+		Err(err) => {
+			let message = format!("ADMIN AUDIT : Request is not parsable : {err}");
+			return audit_error(StatusCode::BAD_REQUEST, message);
+		},
+	};
+
+	if range.count == 0 || range.count > MAX_AUDIT_PAGE_SIZE {
+		let message = format!("ADMIN AUDIT : count must be between 1 and {MAX_AUDIT_PAGE_SIZE}");
+		return audit_error(StatusCode::BAD_REQUEST, message);
+	}
+
+	let page = tokio::task::spawn_blocking(move || read_audit_page(range.from, range.count)).await;
+
+	let (entries, head) = match page {
+		Ok(Ok(page)) => page,
+		Ok(Err(err)) => {
+			let message = format!("ADMIN AUDIT : Can not read the audit trail : {err:?}");
+			error!(message);
+			return audit_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		},
+		Err(err) => {
+			let message = format!("ADMIN AUDIT : Reading the audit trail failed : {err:?}");
+			error!(message);
+			return audit_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		},
+	};
+
+	let signature = get_keypair(&state).await.sign(head.message(block_number).as_bytes());
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"entries": entries,
+			"head": head,
+			"block_number": block_number,
+			"enclave_account": get_accountid(&state).await,
+			"signature": format!("0x{}", hex::encode(signature.0)),
+		})),
+	)
+		.into_response()
+}
+
+/* ----------------------------------
+		ADMIN AUDIT TEST
+----------------------------------*/
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn entry(account: &str, action: AdminAction, status: u16) -> AuditEntry {
+		AuditEntry {
+			index: 0,
+			block_number: 100,
+			account: account.to_string(),
+			action,
+			data_hash: sha256::digest(account.as_bytes()),
+			status,
+			source: "127.0.0.1".to_string(),
+			previous_hash: String::new(),
+			hash: String::new(),
+		}
+	}
+
+	#[test]
+	fn audit_chain_test() {
+		let path = "/tmp/admin_audit_test.log";
+		let _ = std::fs::remove_file(path);
+
+		let mut head = AuditHead::genesis();
+		let mut offsets = Vec::new();
+		for (account, action, status) in [
+			("admin-1", AdminAction::FetchBulk, 200),
+			("admin-2", AdminAction::PushId, 403),
+			("metric", AdminAction::Reconciliation, 200),
+		] {
+			let (appended, offset) =
+				append_entry(path, &head, entry(account, action, status)).unwrap();
+			head = AuditHead { count: appended.index + 1, hash: appended.hash };
+			offsets.push(offset);
+		}
+
+		let (entries, read_offsets) = read_entries(path).unwrap();
+		assert_eq!(head_of(&entries), head);
+		assert_eq!(read_offsets, offsets);
+
+		// A page is read from the offset of its first entry
+		assert_eq!(read_page(path, offsets[1], 5).unwrap(), entries[1..].to_vec());
+		assert_eq!(read_page(path, offsets[1], 1).unwrap(), entries[1..2].to_vec());
+		assert!(verify_audit_chain(&entries, GENESIS_HASH).is_ok());
+
+		// A page is verified from the hash of the previous entry
+		assert!(verify_audit_chain(&entries[1..], &entries[0].hash).is_ok());
+
+		// A removed entry breaks the chain
+		let removed = vec![entries[0].clone(), entries[2].clone()];
+		assert!(verify_audit_chain(&removed, GENESIS_HASH).is_err());
+
+		// A modified entry does not match its hash
+		let mut modified = entries.clone();
+		modified[1].status = 200;
+		assert!(verify_audit_chain(&modified, GENESIS_HASH).is_err());
+
+		// A removed last entry is detected by the signed head
+		assert_ne!(head_of(&entries[..2]), head);
+
+		std::fs::remove_file(path).unwrap();
+	}
+}
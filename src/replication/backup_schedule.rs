@@ -0,0 +1,512 @@
+use std::{
+	collections::BTreeSet,
+	net::SocketAddr,
+	path::Path,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+	extract::{ConnectInfo, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use chrono::{Datelike, NaiveDateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	constants::{
+		BACKUP_KEEP_DAILY, BACKUP_KEEP_WEEKLY, BACKUP_PATH, BACKUP_SCHEDULE_FILE, SEALPATH,
+	},
+	replication::{
+		admin_bulk::parse_encryption_keys,
+		admin_quorum::{consume_approved_proposal, AdminOperation},
+		audit::{record_admin_audit, AdminAction, AuditSubject},
+		manifest::create_backup_archive,
+		stream_cipher::{encrypt_file_stream, StreamEncryptor},
+	},
+	server::state::{get_blocknumber, get_keypair, set_backup_status, SharedState},
+};
+
+/* ---------------------------------------
+	SCHEDULED LOCAL BACKUPS
+------------------------------------------ */
+
+static BACKING_UP: AtomicBool = AtomicBool::new(false);
+
+// Scheduler of the sealed schedule, replaced when the quorum sets a new one
+static SCHEDULER: Mutex<Option<JobScheduler>> = Mutex::const_new(None);
+
+// Scheduled backup name = backup-[YYYYMMDD-HHMMSS]-[blocknumber].zip.enc
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_SUFFIX: &str = ".zip.enc";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// Schedule and retention of the local backups, sealed once approved by the admin quorum
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BackupSchedule {
+	// Cron expression with seconds, i.e "0 0 3 * * *"
+	pub cron: String,
+	// Hex secp256k1 public-keys of the custodians
+	pub encryption_keys: Vec<String>,
+	// Number of days and weeks whose last backup is kept
+	#[serde(default = "default_keep_daily")]
+	pub keep_daily: usize,
+	#[serde(default = "default_keep_weekly")]
+	pub keep_weekly: usize,
+}
+
+/// Last scheduled backup, reported in health
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BackupStatus {
+	// UTC time of the backup
+	pub time: String,
+	pub block_number: u32,
+	pub success: bool,
+	// Backup file name, or the error message
+	pub result: String,
+}
+
+/// Schedule requested by the admins, bound by the data hash of a backup schedule proposal
+/// The serialized request is a schedule, or null to stop the scheduled backups.
+#[derive(Serialize, Deserialize)]
+pub struct BackupSchedulePacket {
+	request: String,
+	proposal_id: String,
+}
+
+impl BackupSchedule {
+	pub fn validate(&self) -> Result<(), String> {
+		if self.encryption_keys.is_empty() {
+			return Err("scheduled backups need at least one encryption public-key".to_string());
+		}
+
+		parse_encryption_keys(&self.encryption_keys)?;
+
+		if self.keep_daily == 0 && self.keep_weekly == 0 {
+			return Err("retention must keep at least one backup".to_string());
+		}
+
+		Job::new_async(self.cron.as_str(), |_uuid, _lock| Box::pin(async {}))
+			.map_err(|err| format!("invalid cron expression '{}' : {err:?}", self.cron))?;
+
+		Ok(())
+	}
+}
+
+/// Read the sealed schedule, None if the backups are not scheduled
+pub fn load_backup_schedule() -> Result<Option<BackupSchedule>> {
+	if !Path::new(BACKUP_SCHEDULE_FILE).exists() {
+		return Ok(None);
+	}
+
+	let content = std::fs::read_to_string(BACKUP_SCHEDULE_FILE)?;
+	let schedule = serde_json::from_str(&content)?;
+
+	Ok(Some(schedule))
+}
+
+fn seal_backup_schedule(schedule: Option<&BackupSchedule>) -> Result<()> {
+	let schedule = match schedule {
+		Some(schedule) => schedule,
+		None => {
+			if Path::new(BACKUP_SCHEDULE_FILE).exists() {
+				std::fs::remove_file(BACKUP_SCHEDULE_FILE)?;
+			}
+			return Ok(());
+		},
+	};
+
+	let temporary_file = format!("{BACKUP_SCHEDULE_FILE}.tmp");
+	std::fs::write(&temporary_file, serde_json::to_string(schedule)?)?;
+	std::fs::rename(&temporary_file, BACKUP_SCHEDULE_FILE)?;
+
+	Ok(())
+}
+
+fn default_keep_daily() -> usize {
+	BACKUP_KEEP_DAILY
+}
+
+fn default_keep_weekly() -> usize {
+	BACKUP_KEEP_WEEKLY
+}
+
+fn schedule_error(status: StatusCode, message: String) -> Response {
+	warn!(message);
+	(status, Json(json!({ "error": message }))).into_response()
+}
+
+fn backup_file_name(time: &NaiveDateTime, block_number: u32) -> String {
+	format!("{BACKUP_PREFIX}{}-{block_number}{BACKUP_SUFFIX}", time.format(BACKUP_TIME_FORMAT))
+}
+
+// Time of a scheduled backup from its name, other files of the directory are ignored
+fn parse_backup_time(name: &str) -> Option<NaiveDateTime> {
+	let stem = name.strip_prefix(BACKUP_PREFIX)?.strip_suffix(BACKUP_SUFFIX)?;
+	let time = stem.get(..15)?;
+	NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok()
+}
+
+/// Backups kept by the retention policy
+/// The last backup of each of the `keep_daily` most recent days is kept,
+/// and the last backup of each of the `keep_weekly` most recent ISO weeks.
+/// # Arguments
+/// * `backups` - File names and times of the backups
+/// * `keep_daily` - Number of days
+/// * `keep_weekly` - Number of weeks
+/// # Returns
+/// * `BTreeSet<String>` - File names of the kept backups
+pub fn retained_backups(
+	backups: &[(String, NaiveDateTime)],
+	keep_daily: usize,
+	keep_weekly: usize,
+) -> BTreeSet<String> {
+	let mut sorted: Vec<&(String, NaiveDateTime)> = backups.iter().collect();
+	sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(&a.0)));
+
+	let mut kept = BTreeSet::new();
+
+	let mut days = BTreeSet::new();
+	let mut weeks = BTreeSet::new();
+	for (name, time) in sorted {
+		// Newest first, the first backup of a period is its last one
+		if days.len() < keep_daily && days.insert(time.date()) {
+			kept.insert(name.clone());
+		}
+
+		let week = time.iso_week();
+		if weeks.len() < keep_weekly && weeks.insert((week.year(), week.week())) {
+			kept.insert(name.clone());
+		}
+	}
+
+	kept
+}
+
+/// Remove the backups out of the retention policy
+/// # Returns
+/// * `usize` - Number of removed backups
+fn apply_retention(directory: &str, keep_daily: usize, keep_weekly: usize) -> Result<usize> {
+	let backups: Vec<(String, NaiveDateTime)> = std::fs::read_dir(directory)?
+		.filter_map(|entry| entry.ok())
+		.filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+		.filter_map(|name| parse_backup_time(&name).map(|time| (name, time)))
+		.collect();
+
+	let kept = retained_backups(&backups, keep_daily, keep_weekly);
+
+	let mut removed = 0;
+	for (name, _) in backups.iter().filter(|(name, _)| !kept.contains(name)) {
+		match std::fs::remove_file(Path::new(directory).join(name)) {
+			Ok(_) => removed += 1,
+			Err(err) => warn!("SCHEDULED BACKUP : can not remove expired backup {name} : {err:?}"),
+		}
+	}
+
+	Ok(removed)
+}
+
+/// Create an encrypted and manifest-signed full backup in the backup directory
+/// # Arguments
+/// * `state` - The enclave state
+/// * `public_keys` - Serialized public-keys of the custodians
+/// # Returns
+/// * `String` - Name of the backup file
+async fn create_scheduled_backup(state: &SharedState, public_keys: &[Vec<u8>]) -> Result<String> {
+	let block_number = get_blocknumber(state).await;
+	let keypair = get_keypair(state).await;
+
+	let file_name = backup_file_name(&Utc::now().naive_utc(), block_number);
+	let plain_file = format!("/temporary/scheduled_{block_number}.zip");
+	let partial_file = format!("{BACKUP_PATH}/{file_name}.tmp");
+
+	let archive_file = plain_file.clone();
+	tokio::task::spawn_blocking(move || {
+		create_backup_archive(SEALPATH, &keypair, block_number, None, &archive_file)
+	})
+	.await??;
+
+	let (encryptor, header) = match StreamEncryptor::new_split(public_keys) {
+		Ok(encryptor) => encryptor,
+		Err(err) => {
+			let _ = std::fs::remove_file(&plain_file);
+			return Err(err);
+		},
+	};
+
+	// The plain archive is removed when the stream ends
+	let file = tokio::fs::File::open(&plain_file).await?;
+	let mut stream = Box::pin(encrypt_file_stream(file, plain_file, encryptor, header));
+
+	let mut output = tokio::fs::File::create(&partial_file).await?;
+	while let Some(frame) = stream.next().await {
+		if let Err(err) = async { output.write_all(&frame?).await }.await {
+			let _ = tokio::fs::remove_file(&partial_file).await;
+			return Err(anyhow!("can not write the encrypted backup : {err:?}"));
+		}
+	}
+	output.sync_all().await?;
+
+	tokio::fs::rename(&partial_file, format!("{BACKUP_PATH}/{file_name}")).await?;
+
+	Ok(file_name)
+}
+
+async fn run_scheduled_backup(state: SharedState, schedule: BackupSchedule) {
+	if BACKING_UP.swap(true, Ordering::SeqCst) {
+		warn!("SCHEDULED BACKUP : previous backup is still running, skip");
+		return;
+	}
+
+	let block_number = get_blocknumber(&state).await;
+	let time = Utc::now().to_rfc3339();
+
+	let result = match parse_encryption_keys(&schedule.encryption_keys) {
+		Ok(public_keys) => create_scheduled_backup(&state, &public_keys).await,
+		Err(err) => Err(anyhow!(err)),
+	};
+
+	let status = match result {
+		Ok(file_name) => {
+			info!("SCHEDULED BACKUP : {file_name} is created");
+
+			match apply_retention(BACKUP_PATH, schedule.keep_daily, schedule.keep_weekly) {
+				Ok(removed) => debug!("SCHEDULED BACKUP : {removed} expired backups are removed"),
+				Err(err) => warn!("SCHEDULED BACKUP : retention failed : {err:?}"),
+			}
+
+			BackupStatus { time, block_number, success: true, result: file_name }
+		},
+
+		Err(err) => {
+			let message = format!("SCHEDULED BACKUP : backup failed : {err:?}");
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("scheduled-backup", block_number.to_string());
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+
+			BackupStatus { time, block_number, success: false, result: message }
+		},
+	};
+
+	set_backup_status(&state, Some(status)).await;
+	BACKING_UP.store(false, Ordering::SeqCst);
+}
+
+/// Start the cron scheduler of the local backups, the running scheduler is stopped
+/// # Arguments
+/// * `state` - The enclave state
+/// * `schedule` - Schedule, custodians and retention of the backups, None to stop them
+pub async fn start_backup_scheduler(
+	state: SharedState,
+	schedule: Option<BackupSchedule>,
+) -> Result<()> {
+	let mut running = SCHEDULER.lock().await;
+
+	if let Some(mut scheduler) = running.take() {
+		scheduler.shutdown().await?;
+		info!("SCHEDULED BACKUP : previous schedule is stopped");
+	}
+
+	let schedule = match schedule {
+		Some(schedule) => schedule,
+		None => return Ok(()),
+	};
+
+	// Invalid schedules are reported at start, not at the first backup
+	schedule.validate().map_err(|err| anyhow!(err))?;
+
+	std::fs::create_dir_all(BACKUP_PATH)?;
+
+	let scheduler = JobScheduler::new().await?;
+
+	let cron = schedule.cron.clone();
+	let job = Job::new_async(cron.as_str(), move |_uuid, _lock| {
+		let state = state.clone();
+		let schedule = schedule.clone();
+		Box::pin(async move { run_scheduled_backup(state, schedule).await })
+	})?;
+
+	scheduler.add(job).await?;
+	scheduler.start().await?;
+
+	*running = Some(scheduler);
+
+	info!("SCHEDULED BACKUP : backups are scheduled at '{cron}' in {BACKUP_PATH}");
+
+	Ok(())
+}
+
+/* ----------------------------------
+		BACKUP SCHEDULE ENDPOINTS
+----------------------------------*/
+
+/// Sealed schedule of the local backups
+pub async fn get_backup_schedule_handler() -> impl IntoResponse {
+	match load_backup_schedule() {
+		Ok(schedule) => (StatusCode::OK, Json(json!(schedule))).into_response(),
+		Err(err) => schedule_error(
+			StatusCode::INTERNAL_SERVER_ERROR,
+			format!("BACKUP SCHEDULE : Can not read the schedule : {err:?}"),
+		),
+	}
+}
+
+/// Set the schedule of the local backups, approved by the admin quorum
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The requested schedule and its approved proposal
+/// # Returns
+/// * `Json` - The new schedule or the error
+pub async fn set_backup_schedule_handler(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<BackupSchedulePacket>,
+) -> impl IntoResponse {
+	let mut subject = AuditSubject {
+		account: String::new(),
+		data_hash: sha256::digest(request.request.as_bytes()),
+	};
+
+	let response = set_backup_schedule(&state, request, &mut subject).await;
+	record_admin_audit(&state, addr, AdminAction::SetBackupSchedule, subject, response).await
+}
+
+// The proposer of the used proposal is the requester of the audit subject
+async fn set_backup_schedule(
+	state: &SharedState,
+	request: BackupSchedulePacket,
+	subject: &mut AuditSubject,
+) -> Response {
+	let schedule: Option<BackupSchedule> = match serde_json::from_str(&request.request) {
+		Ok(schedule) => schedule,
+		Err(err) => {
+			let message = format!("BACKUP SCHEDULE : Request is not parsable : {err}");
+			return schedule_error(StatusCode::BAD_REQUEST, message);
+		},
+	};
+
+	if let Some(Err(err)) = schedule.as_ref().map(BackupSchedule::validate) {
+		return schedule_error(StatusCode::BAD_REQUEST, format!("BACKUP SCHEDULE : {err}"));
+	}
+
+	match consume_approved_proposal(
+		state,
+		&request.proposal_id,
+		&[AdminOperation::BackupSchedule],
+		&subject.data_hash,
+	)
+	.await
+	{
+		Ok(proposal) => subject.account = proposal.proposer,
+		Err(err) =>
+			return schedule_error(StatusCode::FORBIDDEN, format!("BACKUP SCHEDULE : {err}")),
+	}
+
+	if let Err(err) = seal_backup_schedule(schedule.as_ref()) {
+		let message = format!("BACKUP SCHEDULE : Can not seal the schedule : {err:?}");
+		return schedule_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	if let Err(err) = start_backup_scheduler(state.clone(), schedule.clone()).await {
+		let message = format!("BACKUP SCHEDULE : Schedule is sealed but not started : {err:?}");
+		error!(message);
+		return schedule_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	match &schedule {
+		Some(schedule) => info!("BACKUP SCHEDULE : backups are scheduled at '{}'", schedule.cron),
+		None => info!("BACKUP SCHEDULE : scheduled backups are stopped"),
+	}
+
+	(StatusCode::OK, Json(json!(schedule))).into_response()
+}
+
+/* ----------------------------------
+		SCHEDULED BACKUP TEST
+----------------------------------*/
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn at(time: &str) -> NaiveDateTime {
+		NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap()
+	}
+
+	#[test]
+	fn backup_name_test() {
+		let time = at("2024-03-05 03:00");
+		let name = backup_file_name(&time, 1234);
+		assert_eq!(name, "backup-20240305-030000-1234.zip.enc");
+		assert_eq!(parse_backup_time(&name), Some(time));
+		assert_eq!(parse_backup_time("Backup.zip.enc"), None);
+		assert_eq!(parse_backup_time("backup-20240305-030000-1234.zip.enc.tmp"), None);
+	}
+
+	#[test]
+	fn backup_schedule_test() {
+		let key = hex::encode(ecies::utils::generate_keypair().1.serialize());
+		let schedule = |cron: &str, encryption_keys: Vec<String>| BackupSchedule {
+			cron: cron.to_string(),
+			encryption_keys,
+			keep_daily: 7,
+			keep_weekly: 4,
+		};
+
+		assert!(schedule("0 0 3 * * *", vec![key.clone()]).validate().is_ok());
+		assert!(schedule("0 0 3 * * *", Vec::new()).validate().is_err());
+		assert!(schedule("every night", vec![key.clone()]).validate().is_err());
+		assert!(schedule("0 0 3 * * *", vec!["00".to_string()]).validate().is_err());
+
+		let no_retention =
+			BackupSchedule { keep_daily: 0, keep_weekly: 0, ..schedule("0 0 3 * * *", vec![key]) };
+		assert!(no_retention.validate().is_err());
+	}
+
+	#[test]
+	fn retention_test() {
+		let backups: Vec<(String, NaiveDateTime)> = [
+			// Monday to Sunday of a week, two backups on the last day
+			"2024-03-04 03:00",
+			"2024-03-05 03:00",
+			"2024-03-06 03:00",
+			"2024-03-07 03:00",
+			"2024-03-08 03:00",
+			"2024-03-09 03:00",
+			"2024-03-10 03:00",
+			"2024-03-10 15:00",
+			// Previous weeks
+			"2024-02-26 03:00",
+			"2024-02-19 03:00",
+		]
+		.iter()
+		.map(|time| (time.to_string(), at(time)))
+		.collect();
+
+		let kept = retained_backups(&backups, 3, 3);
+
+		// Last backup of the 3 most recent days
+		assert!(kept.contains("2024-03-10 15:00"));
+		assert!(!kept.contains("2024-03-10 03:00"));
+		assert!(kept.contains("2024-03-09 03:00"));
+		assert!(kept.contains("2024-03-08 03:00"));
+		assert!(!kept.contains("2024-03-07 03:00"));
+
+		// Last backup of the 3 most recent weeks
+		assert!(kept.contains("2024-02-26 03:00"));
+		assert!(kept.contains("2024-02-19 03:00"));
+
+		assert_eq!(kept.len(), 5);
+	}
+}
@@ -0,0 +1,350 @@
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use axum::{
+	extract::{ConnectInfo, State},
+	http::{Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::{
+	constants::{MAINTENANCE_MODE_FILE, MAX_MAINTENANCE_REASON_LENGTH},
+	replication::{
+		admin_quorum::{consume_approved_proposal, AdminOperation},
+		audit::{record_admin_audit, AdminAction, AuditSubject},
+		staging::is_restoring,
+		upgrade::is_handed_over,
+	},
+	server::state::{get_blocknumber, get_maintenance_mode, set_maintenance_mode, SharedState},
+};
+
+/* ---------------------------------------
+	MAINTENANCE MODES
+------------------------------------------ */
+
+/// Service level of the enclave, switched by the admin quorum
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceMode {
+	#[default]
+	Normal,
+	// Keyshares are retrieved, stores and removes are refused
+	ReadOnly,
+	// Only the health, attestation and admin APIs are served
+	Full,
+}
+
+/// Sealed mode of the enclave
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct MaintenanceState {
+	pub mode: MaintenanceMode,
+	pub reason: String,
+	// Block of the switch, zero if the mode was never switched
+	pub block_number: u32,
+}
+
+/// Mode requested by the admins, bound by the data hash of a maintenance proposal
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MaintenanceRequest {
+	pub mode: MaintenanceMode,
+	pub reason: String,
+}
+
+/// Serialized request and its approved proposal
+#[derive(Serialize, Deserialize)]
+pub struct MaintenancePacket {
+	request: String,
+	proposal_id: String,
+}
+
+/// Access of a route, refused by the modes above it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RouteAccess {
+	// Health, attestation and authenticated admin requests, a maintenance is left through them
+	Control,
+	Read,
+	Write,
+}
+
+impl MaintenanceRequest {
+	pub fn validate(&self) -> Result<(), String> {
+		if self.mode != MaintenanceMode::Normal && self.reason.trim().is_empty() {
+			return Err("a maintenance needs a reason".to_string());
+		}
+
+		if self.reason.len() > MAX_MAINTENANCE_REASON_LENGTH {
+			return Err(format!("reason is longer than {MAX_MAINTENANCE_REASON_LENGTH} bytes"));
+		}
+
+		Ok(())
+	}
+}
+
+impl MaintenanceMode {
+	pub fn allows(&self, access: RouteAccess) -> bool {
+		matches!(
+			(self, access),
+			(_, RouteAccess::Control) |
+				(MaintenanceMode::Normal, _) |
+				(MaintenanceMode::ReadOnly, RouteAccess::Read)
+		)
+	}
+}
+
+/// Access of a request path
+/// # Arguments
+/// * `path` - Path of the request
+pub fn route_access(path: &str) -> RouteAccess {
+	const CONTROL_PREFIXES: [&str; 7] = [
+		"/api/admin/",
+		"/api/attestation/",
+		"/api/backup/fetch-",
+		"/api/backup/push-",
+		"/api/upgrade/",
+		"/api/metric/",
+		"/api/quote",
+	];
+
+	const WRITE_ROUTES: [&str; 4] = [
+		"/api/secret-nft/store-keyshare",
+		"/api/secret-nft/remove-keyshare",
+		"/api/capsule-nft/set-keyshare",
+		"/api/capsule-nft/remove-keyshare",
+	];
+
+	if path == "/api/health" || CONTROL_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+		RouteAccess::Control
+	} else if WRITE_ROUTES.contains(&path) {
+		RouteAccess::Write
+	} else {
+		RouteAccess::Read
+	}
+}
+
+/// Read the sealed mode, None if the mode was never switched
+pub fn load_maintenance_mode() -> Result<Option<MaintenanceState>> {
+	if !std::path::Path::new(MAINTENANCE_MODE_FILE).exists() {
+		return Ok(None);
+	}
+
+	let content = std::fs::read_to_string(MAINTENANCE_MODE_FILE)?;
+	let maintenance = serde_json::from_str(&content)?;
+
+	Ok(Some(maintenance))
+}
+
+fn seal_maintenance_mode(maintenance: &MaintenanceState) -> Result<()> {
+	let temporary_file = format!("{MAINTENANCE_MODE_FILE}.tmp");
+	std::fs::write(&temporary_file, serde_json::to_string(maintenance)?)?;
+	std::fs::rename(&temporary_file, MAINTENANCE_MODE_FILE)?;
+
+	Ok(())
+}
+
+/// Seal and apply a mode switched by the enclave itself
+/// # Arguments
+/// * `state` - The enclave state
+/// * `mode` - The new mode
+/// * `reason` - Reason reported by the health check
+pub async fn enter_maintenance(
+	state: &SharedState,
+	mode: MaintenanceMode,
+	reason: String,
+) -> Result<MaintenanceState> {
+	let maintenance = MaintenanceState { mode, reason, block_number: get_blocknumber(state).await };
+
+	seal_maintenance_mode(&maintenance)?;
+	info!("MAINTENANCE : {:?} mode is entered : {}", maintenance.mode, maintenance.reason);
+
+	set_maintenance_mode(state, maintenance.clone()).await;
+
+	Ok(maintenance)
+}
+
+/// Reason the background workers must not write the sealed directory, None if they can
+/// A restore is rewriting the sealed directory, or a maintenance mode freezes the keyshares.
+/// # Arguments
+/// * `state` - The enclave state
+pub async fn sealed_writes_paused(state: &SharedState) -> Option<String> {
+	if is_restoring() {
+		return Some("a restore is rewriting the keyshares".to_string());
+	}
+
+	let maintenance = get_maintenance_mode(state).await;
+	if maintenance.mode != MaintenanceMode::Normal {
+		return Some(format!("{:?} maintenance : {}", maintenance.mode, maintenance.reason));
+	}
+
+	None
+}
+
+fn maintenance_error(status: StatusCode, message: String) -> Response {
+	warn!(message);
+	(status, Json(json!({ "error": message }))).into_response()
+}
+
+/* ----------------------------------
+		MAINTENANCE LAYER
+----------------------------------*/
+
+/// Refuse the requests the mode of the enclave does not allow
+/// A running restore rewrites the sealed directory, keyshares are neither read nor written.
+pub async fn enforce_maintenance<B>(
+	State(state): State<SharedState>,
+	request: Request<B>,
+	next: Next<B>,
+) -> Response {
+	let access = route_access(request.uri().path());
+	if access == RouteAccess::Control {
+		return next.run(request).await;
+	}
+
+	let maintenance = if is_restoring() {
+		MaintenanceState {
+			mode: MaintenanceMode::Full,
+			reason: "a restore is rewriting the keyshares".to_string(),
+			block_number: get_blocknumber(&state).await,
+		}
+	} else {
+		get_maintenance_mode(&state).await
+	};
+
+	if maintenance.mode.allows(access) {
+		return next.run(request).await;
+	}
+
+	debug!("MAINTENANCE : {:?} refuses {}", maintenance.mode, request.uri().path());
+	(
+		StatusCode::SERVICE_UNAVAILABLE,
+		Json(json!({
+			"error": format!("Enclave is in {:?} maintenance", maintenance.mode),
+			"mode": maintenance.mode,
+			"reason": maintenance.reason,
+		})),
+	)
+		.into_response()
+}
+
+/* ----------------------------------
+		MAINTENANCE ENDPOINTS
+----------------------------------*/
+
+/// Mode of the enclave
+pub async fn get_maintenance_handler(State(state): State<SharedState>) -> impl IntoResponse {
+	(StatusCode::OK, Json(json!(get_maintenance_mode(&state).await)))
+}
+
+/// Switch the mode of the enclave, approved by the admin quorum
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The requested mode and its approved proposal
+/// # Returns
+/// * `Json` - The new mode or the error
+pub async fn set_maintenance_handler(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<MaintenancePacket>,
+) -> impl IntoResponse {
+	let mut subject = AuditSubject {
+		account: String::new(),
+		data_hash: sha256::digest(request.request.as_bytes()),
+	};
+
+	let response = set_maintenance(&state, request, &mut subject).await;
+	record_admin_audit(&state, addr, AdminAction::SetMaintenance, subject, response).await
+}
+
+// The proposer of the used proposal is the requester of the audit subject
+async fn set_maintenance(
+	state: &SharedState,
+	request: MaintenancePacket,
+	subject: &mut AuditSubject,
+) -> Response {
+	let maintenance_request: MaintenanceRequest = match serde_json::from_str(&request.request) {
+		Ok(maintenance_request) => maintenance_request,
+		Err(err) => {
+			let message = format!("MAINTENANCE : Request is not parsable : {err}");
+			return maintenance_error(StatusCode::BAD_REQUEST, message);
+		},
+	};
+
+	if let Err(err) = maintenance_request.validate() {
+		return maintenance_error(StatusCode::BAD_REQUEST, format!("MAINTENANCE : {err}"));
+	}
+
+	// The new enclave owns the account, this one only serves reads until it is stopped
+	if is_handed_over() && maintenance_request.mode == MaintenanceMode::Normal {
+		let message = "MAINTENANCE : Account is handed over, the enclave stays read-only";
+		return maintenance_error(StatusCode::CONFLICT, message.to_string());
+	}
+
+	match consume_approved_proposal(
+		state,
+		&request.proposal_id,
+		&[AdminOperation::Maintenance],
+		&subject.data_hash,
+	)
+	.await
+	{
+		Ok(proposal) => subject.account = proposal.proposer,
+		Err(err) => return maintenance_error(StatusCode::FORBIDDEN, format!("MAINTENANCE : {err}")),
+	}
+
+	let maintenance = MaintenanceState {
+		mode: maintenance_request.mode,
+		reason: maintenance_request.reason,
+		block_number: get_blocknumber(state).await,
+	};
+
+	if let Err(err) = seal_maintenance_mode(&maintenance) {
+		let message = format!("MAINTENANCE : Can not seal the mode : {err:?}");
+		return maintenance_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	info!("MAINTENANCE : {:?} mode is set : {}", maintenance.mode, maintenance.reason);
+
+	set_maintenance_mode(state, maintenance.clone()).await;
+
+	(StatusCode::OK, Json(json!(maintenance))).into_response()
+}
+
+/* ----------------------------------
+		MAINTENANCE TEST
+----------------------------------*/
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn maintenance_mode_test() {
+		let store = route_access("/api/secret-nft/store-keyshare");
+		let retrieve = route_access("/api/capsule-nft/retrieve-keyshare");
+		let switch = route_access("/api/admin/maintenance");
+		assert_eq!(store, RouteAccess::Write);
+		assert_eq!(route_access("/api/capsule-nft/remove-keyshare"), RouteAccess::Write);
+		assert_eq!(retrieve, RouteAccess::Read);
+		assert_eq!(route_access("/api/backup/sync-keyshare"), RouteAccess::Read);
+		assert_eq!(switch, RouteAccess::Control);
+		assert_eq!(route_access("/api/health"), RouteAccess::Control);
+
+		assert!(MaintenanceMode::Normal.allows(store));
+		assert!(!MaintenanceMode::ReadOnly.allows(store));
+		assert!(MaintenanceMode::ReadOnly.allows(retrieve));
+		assert!(!MaintenanceMode::Full.allows(retrieve));
+		// Admins can always leave a maintenance
+		assert!(MaintenanceMode::Full.allows(switch));
+
+		let request = |mode, reason: &str| MaintenanceRequest { mode, reason: reason.to_string() };
+		assert!(request(MaintenanceMode::Normal, "").validate().is_ok());
+		assert!(request(MaintenanceMode::Full, " ").validate().is_err());
+		assert!(request(MaintenanceMode::ReadOnly, "disk migration").validate().is_ok());
+		let long = "x".repeat(MAX_MAINTENANCE_REASON_LENGTH + 1);
+		assert!(request(MaintenanceMode::ReadOnly, &long).validate().is_err());
+	}
+}
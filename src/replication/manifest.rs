@@ -0,0 +1,1020 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	io::{Read, Seek},
+	path::Path,
+	sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
+use tracing::{debug, warn};
+
+use crate::{
+	constants::{
+		ENCLAVE_ACCOUNT_FILE, ENCLAVE_POLICY_FILE, KEYSHARE_TOMBSTONE_FILE, MAX_BACKUP_FILE_SIZE,
+		MAX_BACKUP_MANIFEST_SIZE, SYNC_STATE_FILE,
+	},
+	core::{
+		helper::{parse_keyshare_file, Availability, NftType},
+		log::LogFile,
+	},
+	replication::zipdir::{add_files_zip_with_manifest, ArchivedFile},
+};
+
+/* ---------------------------------------
+	SIGNED BACKUP MANIFEST
+------------------------------------------ */
+
+// Name of the manifest entry inside a backup archive
+pub const BACKUP_MANIFEST_FILE: &str = "backup.manifest";
+const BACKUP_MANIFEST_VERSION: u32 = 1;
+const DIGEST_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Kind of a file in the sealed directory, other files are never backed up or restored
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupFileKind {
+	Keyshare,
+	Log,
+	EnclaveAccount,
+	SyncState,
+	EnclavePolicy,
+}
+
+/// A file of the backup archive
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ManifestFile {
+	pub name: String,
+	pub kind: BackupFileKind,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub nftid: Option<u32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub nft_type: Option<NftType>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub block_number: Option<u32>,
+	pub sha256: String,
+	pub size: u64,
+}
+
+/// Content of a backup archive, signed by the exporting enclave
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BackupManifest {
+	pub version: u32,
+	// SS58 account of the exporting enclave
+	pub enclave_account: String,
+	pub block_number: u32,
+	// Incremental backup of the keyshares changed after this block, None for a full backup
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub since_block: Option<u32>,
+	pub files: Vec<ManifestFile>,
+	// Keyshares removed after the since block, an increment removes them on restore
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub tombstones: Vec<Tombstone>,
+}
+
+impl BackupManifest {
+	/// Keyshares listed in the manifest, as they would appear in the availability map
+	pub fn keyshares(&self) -> Vec<(u32, Availability)> {
+		self.files
+			.iter()
+			.filter(|file| file.kind == BackupFileKind::Keyshare)
+			.filter_map(|file| match (file.nftid, file.nft_type, file.block_number) {
+				(Some(nftid), Some(nft_type), Some(block_number)) =>
+					Some((nftid, Availability { block_number, nft_type })),
+				_ => None,
+			})
+			.collect()
+	}
+}
+
+/// Keyshares changed after a block, selected from the availability map
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupIncrement {
+	pub since_block: u32,
+	pub nftids: BTreeSet<u32>,
+	pub tombstones: Vec<Tombstone>,
+}
+
+impl BackupIncrement {
+	/// Increment of the sealed directory since a block
+	/// # Arguments
+	/// * `src_dir` - The sealed directory
+	/// * `since_block` - Block of the previous backup
+	/// * `changed` - Nftids whose availability changed after the block
+	pub fn collect(src_dir: &str, since_block: u32, changed: BTreeSet<u32>) -> Result<Self> {
+		let mut nftids = changed;
+		// Views are logged without changing the availability of the keyshares
+		nftids.extend(changed_logs_since(src_dir, since_block)?);
+
+		let tombstones = load_tombstones()?
+			.into_iter()
+			.filter(|tombstone| tombstone.block_number > since_block)
+			.collect();
+
+		Ok(BackupIncrement { since_block, nftids, tombstones })
+	}
+
+	// Increments only carry keyshares and logs, the account of the chain comes with the base
+	fn contains(&self, file: &ManifestFile) -> bool {
+		let changed = file.nftid.is_some_and(|nftid| self.nftids.contains(&nftid));
+
+		match file.kind {
+			BackupFileKind::Keyshare =>
+				changed && file.block_number.is_some_and(|block| block > self.since_block),
+			BackupFileKind::Log => changed,
+			_ => false,
+		}
+	}
+}
+
+/// Last backup restored on this enclave, a restored increment must continue it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BackupChainHead {
+	pub source_enclave: String,
+	// Block of the base backup of the chain
+	pub base_block: u32,
+	// Block of the last restored backup of the chain
+	pub block_number: u32,
+}
+
+/// The manifest is signed as serialized, it is never re-serialized for the verification
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SignedManifest {
+	pub manifest: String,
+	pub signature: String,
+}
+
+/// Result of a verified restore
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RestoreReport {
+	pub source_enclave: String,
+	pub backup_block: u32,
+	#[serde(default)]
+	pub since_block: Option<u32>,
+	pub files: u32,
+	pub bytes: u64,
+	pub secret_keyshares: u32,
+	pub capsule_keyshares: u32,
+	pub logs: u32,
+	// Keyshares removed by an increment
+	#[serde(default)]
+	pub tombstones: u32,
+	pub enclave_account: bool,
+	pub sync_state: bool,
+	pub enclave_policy: bool,
+	// Highest block of the restored keyshares
+	pub last_keyshare_block: u32,
+}
+
+/* ---------------------------------------
+	KEYSHARE TOMBSTONES
+------------------------------------------ */
+
+// Only one request at a time can read-modify-write the tombstones
+static TOMBSTONE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Keyshare removed from the enclave
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Tombstone {
+	pub nftid: u32,
+	pub nft_type: NftType,
+	pub block_number: u32,
+}
+
+fn load_tombstones() -> Result<Vec<Tombstone>> {
+	match std::fs::read_to_string(KEYSHARE_TOMBSTONE_FILE) {
+		Ok(content) => Ok(serde_json::from_str(&content)?),
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+		Err(err) => Err(err.into()),
+	}
+}
+
+/// Record a removed keyshare, the incremental backups after the block list it
+/// # Arguments
+/// * `nftid` - The removed nft
+/// * `nft_type` - Type of the removed keyshare
+/// * `block_number` - Block of the removal
+pub fn record_tombstone(nftid: u32, nft_type: NftType, block_number: u32) -> Result<()> {
+	let _guard = TOMBSTONE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+	let mut tombstones = load_tombstones()?;
+	tombstones.retain(|tombstone| tombstone.nftid != nftid || tombstone.nft_type != nft_type);
+	tombstones.push(Tombstone { nftid, nft_type, block_number });
+
+	let temporary_file = format!("{KEYSHARE_TOMBSTONE_FILE}.tmp");
+	std::fs::write(&temporary_file, serde_json::to_string(&tombstones)?)?;
+	std::fs::rename(&temporary_file, KEYSHARE_TOMBSTONE_FILE)?;
+
+	Ok(())
+}
+
+// Last block of a view log, None if the log is not parsable
+fn log_last_block(path: &Path) -> Option<u32> {
+	let content = std::fs::read_to_string(path).ok()?;
+	let log_file: LogFile = serde_json::from_str(&content).ok()?;
+
+	Some(
+		log_file
+			.secret_nft
+			.values()
+			.chain(log_file.capsule.values())
+			.map(|log| log.block)
+			.max()
+			.unwrap_or(0),
+	)
+}
+
+/// Nftids of the view logs written after a block, an unparsable log is always included
+/// # Arguments
+/// * `src_dir` - The sealed directory
+/// * `since_block` - Block of the previous backup
+pub fn changed_logs_since(src_dir: &str, since_block: u32) -> Result<BTreeSet<u32>> {
+	let mut nftids = BTreeSet::new();
+
+	for entry in std::fs::read_dir(src_dir)? {
+		let path = entry?.path();
+		let nftid = match path.file_name().and_then(std::ffi::OsStr::to_str).and_then(classify_file)
+		{
+			Some(ManifestFile { kind: BackupFileKind::Log, nftid: Some(nftid), .. }) => nftid,
+			_ => continue,
+		};
+
+		if log_last_block(&path).map_or(true, |block| block > since_block) {
+			nftids.insert(nftid);
+		}
+	}
+
+	Ok(nftids)
+}
+
+/// Remove the keyshares and the logs of the tombstones of a restored increment
+/// Files restored by the increment itself are newer than the removal, they are kept.
+/// # Arguments
+/// * `target_dir` - The sealed directory
+/// * `manifest` - Manifest of the restored increment
+/// # Returns
+/// * `u32` - Number of removed keyshares
+pub fn apply_tombstones(target_dir: &str, manifest: &BackupManifest) -> Result<u32> {
+	if manifest.tombstones.is_empty() {
+		return Ok(0);
+	}
+
+	let restored: BTreeSet<&str> = manifest.files.iter().map(|file| file.name.as_str()).collect();
+	let mut removed = 0;
+
+	for entry in std::fs::read_dir(target_dir)? {
+		let path = entry?.path();
+		let name = match path.file_name().and_then(std::ffi::OsStr::to_str) {
+			Some(name) if !restored.contains(name) => name,
+			_ => continue,
+		};
+
+		let file = match classify_file(name) {
+			Some(file) => file,
+			None => continue,
+		};
+
+		let buried = manifest.tombstones.iter().any(|tombstone| {
+			file.nftid == Some(tombstone.nftid) &&
+				match file.kind {
+					BackupFileKind::Keyshare =>
+						file.nft_type == Some(tombstone.nft_type) &&
+							file.block_number
+								.is_some_and(|block| block <= tombstone.block_number),
+					BackupFileKind::Log => true,
+					_ => false,
+				}
+		});
+
+		if buried {
+			std::fs::remove_file(&path)?;
+			if file.kind == BackupFileKind::Keyshare {
+				removed += 1;
+			}
+		}
+	}
+
+	debug!("BACKUP MANIFEST : {removed} keyshares are removed by the tombstones");
+	Ok(removed)
+}
+
+fn sealed_file_name(path: &str) -> &str {
+	Path::new(path).file_name().and_then(std::ffi::OsStr::to_str).unwrap_or(path)
+}
+
+/// Classify a file of the sealed directory by its name
+/// # Arguments
+/// * `name` - File name, without directory
+/// # Returns
+/// * `Option<ManifestFile>` - Manifest entry without digest, None for unknown files
+pub fn classify_file(name: &str) -> Option<ManifestFile> {
+	let entry = |kind| ManifestFile {
+		name: name.to_string(),
+		kind,
+		nftid: None,
+		nft_type: None,
+		block_number: None,
+		sha256: String::new(),
+		size: 0,
+	};
+
+	if name.contains('/') || name.contains('\\') {
+		return None;
+	}
+
+	if name == sealed_file_name(ENCLAVE_ACCOUNT_FILE) {
+		return Some(entry(BackupFileKind::EnclaveAccount));
+	}
+
+	if name == sealed_file_name(SYNC_STATE_FILE) {
+		return Some(entry(BackupFileKind::SyncState));
+	}
+
+	if name == sealed_file_name(ENCLAVE_POLICY_FILE) {
+		return Some(entry(BackupFileKind::EnclavePolicy));
+	}
+
+	// Log file name = [nftid].log
+	if let Some(nftid) = name.strip_suffix(".log").and_then(|id| id.parse::<u32>().ok()) {
+		return Some(ManifestFile { nftid: Some(nftid), ..entry(BackupFileKind::Log) });
+	}
+
+	match parse_keyshare_file(Path::new(name)) {
+		Ok((nftid, availability)) => Some(ManifestFile {
+			nftid: Some(nftid),
+			nft_type: Some(availability.nft_type),
+			block_number: Some(availability.block_number),
+			..entry(BackupFileKind::Keyshare)
+		}),
+		Err(_) => None,
+	}
+}
+
+fn sign_manifest(manifest: &BackupManifest, keypair: &sr25519::Pair) -> Result<SignedManifest> {
+	let manifest = serde_json::to_string(manifest)?;
+	let signature = keypair.sign(manifest.as_bytes());
+
+	Ok(SignedManifest { manifest, signature: format!("0x{}", hex::encode(signature.0)) })
+}
+
+/// Verify the signature of a manifest by its exporting enclave
+/// # Arguments
+/// * `signed` - The signed manifest
+/// # Returns
+/// * `BackupManifest` - The manifest, when the signature is valid
+pub fn verify_manifest(signed: &SignedManifest) -> Result<BackupManifest> {
+	let manifest: BackupManifest = serde_json::from_str(&signed.manifest)
+		.map_err(|err| anyhow!("manifest is not parsable : {err}"))?;
+
+	if manifest.version != BACKUP_MANIFEST_VERSION {
+		return Err(anyhow!("unsupported manifest version {}", manifest.version));
+	}
+
+	let public = sr25519::Public::from_ss58check(&manifest.enclave_account)
+		.map_err(|err| anyhow!("invalid enclave account in manifest : {err:?}"))?;
+
+	let stripped = signed.signature.strip_prefix("0x").unwrap_or(&signed.signature);
+	let signature = <[u8; 64]>::try_from(hex::decode(stripped)?.as_slice())
+		.map_err(|_| anyhow!("invalid manifest signature length"))?;
+
+	if !sr25519::Pair::verify(
+		&sr25519::Signature::from_raw(signature),
+		signed.manifest.as_bytes(),
+		&public,
+	) {
+		return Err(anyhow!("invalid manifest signature"));
+	}
+
+	Ok(manifest)
+}
+
+/// Archive the sealed directory with a manifest signed by this enclave
+/// Unknown files (temporary files, sub-directories) are not archived
+/// # Arguments
+/// * `src_dir` - The sealed directory
+/// * `keypair` - Enclave keypair
+/// * `block_number` - Current block number
+/// * `increment` - Keyshares changed since a block, None for a full backup
+/// * `dst_file` - Path of the archive
+/// # Returns
+/// * `BackupManifest` - The signed manifest
+pub fn create_backup_archive(
+	src_dir: &str,
+	keypair: &sr25519::Pair,
+	block_number: u32,
+	increment: Option<&BackupIncrement>,
+	dst_file: &str,
+) -> Result<BackupManifest> {
+	let mut file_names: Vec<String> = std::fs::read_dir(src_dir)?
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.path().is_file())
+		.filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+		.filter(|name| match (classify_file(name), increment) {
+			(Some(entry), Some(increment)) => increment.contains(&entry),
+			(entry, None) => entry.is_some(),
+			(None, _) => false,
+		})
+		.collect();
+	file_names.sort();
+
+	let mut manifest = BackupManifest {
+		version: BACKUP_MANIFEST_VERSION,
+		enclave_account: keypair.public().to_ss58check(),
+		block_number,
+		since_block: increment.map(|increment| increment.since_block),
+		files: Vec::new(),
+		tombstones: increment.map(|increment| increment.tombstones.clone()).unwrap_or_default(),
+	};
+
+	let build = |archived: &[ArchivedFile]| -> Result<Vec<u8>, String> {
+		manifest.files = archived
+			.iter()
+			.filter_map(|file| {
+				classify_file(&file.name).map(|entry| ManifestFile {
+					sha256: file.sha256.clone(),
+					size: file.size,
+					..entry
+				})
+			})
+			.collect();
+
+		let signed = sign_manifest(&manifest, keypair).map_err(|err| err.to_string())?;
+		serde_json::to_vec(&signed).map_err(|err| err.to_string())
+	};
+
+	add_files_zip_with_manifest(src_dir, &file_names, dst_file, BACKUP_MANIFEST_FILE, build)
+		.map_err(|err| anyhow!("unable to create the backup archive : {err:?}"))?;
+
+	debug!("BACKUP MANIFEST : archive {dst_file} with {} files", manifest.files.len());
+
+	Ok(manifest)
+}
+
+/// Verify a backup archive before it is restored
+/// The manifest must be signed by a trusted enclave, every file must be listed in the manifest
+/// with a matching digest, and every listed file must be present.
+/// # Arguments
+/// * `archive_path` - Path of the uploaded archive
+/// * `trusted_accounts` - SS58 accounts of the enclaves allowed to sign a backup
+/// # Returns
+/// * `RestoreReport` - Summary of the verified content
+pub fn verify_backup_archive(
+	archive_path: &str,
+	trusted_accounts: &[String],
+) -> Result<RestoreReport> {
+	let file = std::fs::File::open(archive_path)?;
+	verify_backup_reader(file, trusted_accounts).map(|(_, report)| report)
+}
+
+/// Verify a backup archive from any reader, i.e an uploaded archive kept in memory
+/// # Arguments
+/// * `reader` - The backup archive
+/// * `trusted_accounts` - SS58 accounts of the enclaves allowed to sign a backup
+/// # Returns
+/// * `(BackupManifest, RestoreReport)` - The verified manifest and a summary of the content
+pub fn verify_backup_reader<R: Read + Seek>(
+	reader: R,
+	trusted_accounts: &[String],
+) -> Result<(BackupManifest, RestoreReport)> {
+	let mut archive = zip::ZipArchive::new(reader)
+		.map_err(|err| anyhow!("backup is not a zip archive : {err:?}"))?;
+
+	let signed: SignedManifest = {
+		let entry = archive
+			.by_name(BACKUP_MANIFEST_FILE)
+			.map_err(|_| anyhow!("backup has no manifest"))?;
+		if entry.size() > MAX_BACKUP_MANIFEST_SIZE {
+			return Err(anyhow!("manifest is larger than {MAX_BACKUP_MANIFEST_SIZE} bytes"));
+		}
+
+		// The size of the zip header is not trusted, the read is bounded too
+		let mut content = String::new();
+		entry.take(MAX_BACKUP_MANIFEST_SIZE + 1).read_to_string(&mut content)?;
+		if content.len() as u64 > MAX_BACKUP_MANIFEST_SIZE {
+			return Err(anyhow!("manifest is larger than {MAX_BACKUP_MANIFEST_SIZE} bytes"));
+		}
+		serde_json::from_str(&content).map_err(|err| anyhow!("manifest is not parsable : {err}"))?
+	};
+
+	let manifest = verify_manifest(&signed)?;
+
+	if !trusted_accounts.contains(&manifest.enclave_account) {
+		return Err(anyhow!("backup is signed by an unknown enclave {}", manifest.enclave_account));
+	}
+
+	let mut expected: BTreeMap<String, ManifestFile> = BTreeMap::new();
+	for file in &manifest.files {
+		match classify_file(&file.name) {
+			Some(entry)
+				if entry.kind == file.kind &&
+					entry.nftid == file.nftid &&
+					entry.nft_type == file.nft_type &&
+					entry.block_number == file.block_number => {},
+			_ => return Err(anyhow!("manifest lists an unknown file {}", file.name)),
+		}
+
+		if file.size > MAX_BACKUP_FILE_SIZE {
+			return Err(anyhow!("{} is larger than {MAX_BACKUP_FILE_SIZE} bytes", file.name));
+		}
+
+		if let Some(since_block) = manifest.since_block {
+			let changed = BackupIncrement {
+				since_block,
+				nftids: file.nftid.into_iter().collect::<BTreeSet<u32>>(),
+				tombstones: Vec::new(),
+			};
+
+			if !changed.contains(file) {
+				return Err(anyhow!(
+					"incremental backup since block {since_block} lists {}",
+					file.name
+				));
+			}
+		}
+
+		if expected.insert(file.name.clone(), file.clone()).is_some() {
+			return Err(anyhow!("manifest lists {} twice", file.name));
+		}
+	}
+
+	if manifest
+		.since_block
+		.is_some_and(|since_block| since_block >= manifest.block_number)
+	{
+		return Err(anyhow!("incremental backup starts after its own block"));
+	}
+
+	// A full backup is the whole directory, only increments remove keyshares
+	let mut buried = Vec::new();
+	for tombstone in &manifest.tombstones {
+		let in_increment = manifest.since_block.is_some_and(|since_block| {
+			tombstone.block_number > since_block && tombstone.block_number <= manifest.block_number
+		});
+
+		if !in_increment || buried.contains(&(tombstone.nftid, tombstone.nft_type)) {
+			return Err(anyhow!("invalid tombstone of nft {} in the manifest", tombstone.nftid));
+		}
+		buried.push((tombstone.nftid, tombstone.nft_type));
+	}
+
+	let mut report = RestoreReport {
+		source_enclave: manifest.enclave_account.clone(),
+		backup_block: manifest.block_number,
+		since_block: manifest.since_block,
+		tombstones: manifest.tombstones.len() as u32,
+		..Default::default()
+	};
+
+	let mut found = BTreeSet::new();
+	for index in 0..archive.len() {
+		let mut entry = archive.by_index(index)?;
+		let name = entry.name().to_string();
+
+		if name == BACKUP_MANIFEST_FILE {
+			continue;
+		}
+
+		let file = match expected.get(&name) {
+			Some(file) => file,
+			None => return Err(anyhow!("backup contains a file out of the manifest {name}")),
+		};
+
+		if !found.insert(name.clone()) {
+			return Err(anyhow!("backup contains {name} twice"));
+		}
+
+		if entry.size() != file.size {
+			return Err(anyhow!("size of {name} does not match the manifest"));
+		}
+
+		// Only the enclave account is kept, it is checked against the signer
+		let mut data = Vec::new();
+		let keep = file.kind == BackupFileKind::EnclaveAccount;
+		check_entry_digest(&mut entry, file, keep.then_some(&mut data))?;
+
+		match file.kind {
+			BackupFileKind::Keyshare => {
+				match file.nft_type {
+					Some(NftType::Capsule) => report.capsule_keyshares += 1,
+					_ => report.secret_keyshares += 1,
+				}
+				report.last_keyshare_block =
+					report.last_keyshare_block.max(file.block_number.unwrap_or(0));
+			},
+			BackupFileKind::Log => report.logs += 1,
+			BackupFileKind::EnclaveAccount => {
+				// The exporting enclave signs its own account
+				let phrase = String::from_utf8(data)
+					.map_err(|_| anyhow!("enclave account file is not a phrase"))?;
+				let account = sr25519::Pair::from_phrase(phrase.trim(), None)
+					.map_err(|err| anyhow!("enclave account file is invalid : {err:?}"))?
+					.0
+					.public()
+					.to_ss58check();
+
+				if account != manifest.enclave_account {
+					return Err(anyhow!("enclave account of the backup is not the signer"));
+				}
+
+				report.enclave_account = true;
+			},
+			BackupFileKind::SyncState => report.sync_state = true,
+			BackupFileKind::EnclavePolicy => report.enclave_policy = true,
+		}
+
+		report.files += 1;
+		report.bytes += file.size;
+	}
+
+	if let Some(missing) = expected.keys().find(|name| !found.contains(*name)) {
+		warn!("BACKUP MANIFEST : {missing} of the manifest is missing in the backup");
+		return Err(anyhow!("backup is missing {missing}"));
+	}
+
+	Ok((manifest, report))
+}
+
+/// Hash an archived file while it is read, never past one byte more than its manifest size
+/// # Arguments
+/// * `entry` - The archived file
+/// * `file` - The file of the manifest
+/// * `content` - Buffer of the content, None if the content is only hashed
+pub fn check_entry_digest<R: Read>(
+	entry: R,
+	file: &ManifestFile,
+	mut content: Option<&mut Vec<u8>>,
+) -> Result<()> {
+	let mut reader = entry.take(file.size + 1);
+	let mut context = digest::Context::new(&digest::SHA256);
+	let mut buffer = vec![0u8; DIGEST_CHUNK_SIZE];
+	let mut size = 0u64;
+
+	loop {
+		let count = reader.read(&mut buffer)?;
+		if count == 0 {
+			break;
+		}
+
+		context.update(&buffer[..count]);
+		if let Some(content) = content.as_mut() {
+			content.extend_from_slice(&buffer[..count]);
+		}
+		size += count as u64;
+	}
+
+	if size != file.size || hex::encode(context.finish()) != file.sha256 {
+		return Err(anyhow!("digest of {} does not match the manifest", file.name));
+	}
+
+	Ok(())
+}
+
+/// Check a verified backup continues the restored chain
+/// A full backup starts a new chain, an increment must come from the same enclave and start
+/// at or before the last restored block, otherwise keyshares changed in between are missing.
+/// # Arguments
+/// * `head` - Last restored backup of the chain
+/// * `report` - Report of the verified backup
+/// # Returns
+/// * `BackupChainHead` - Head of the chain once the backup is restored
+pub fn check_backup_chain(
+	head: Option<&BackupChainHead>,
+	report: &RestoreReport,
+) -> Result<BackupChainHead> {
+	let since_block = match report.since_block {
+		None =>
+			return Ok(BackupChainHead {
+				source_enclave: report.source_enclave.clone(),
+				base_block: report.backup_block,
+				block_number: report.backup_block,
+			}),
+		Some(since_block) => since_block,
+	};
+
+	let head = match head {
+		Some(head) => head,
+		None =>
+			return Err(anyhow!(
+				"incremental backup since block {since_block} needs a restored base backup"
+			)),
+	};
+
+	if head.source_enclave != report.source_enclave {
+		return Err(anyhow!(
+			"incremental backup of {} does not continue the chain of {}",
+			report.source_enclave,
+			head.source_enclave
+		));
+	}
+
+	if since_block > head.block_number {
+		return Err(anyhow!(
+			"gap in the backup chain : blocks {} to {since_block} are missing",
+			head.block_number + 1
+		));
+	}
+
+	if report.backup_block <= head.block_number {
+		return Err(anyhow!(
+			"incremental backup of block {} is already covered by the chain at block {}",
+			report.backup_block,
+			head.block_number
+		));
+	}
+
+	Ok(BackupChainHead { block_number: report.backup_block, ..head.clone() })
+}
+
+/* ---------------------------------------
+	RESTORE DRY-RUN
+------------------------------------------ */
+
+/// Keyshare of a backup older than the one of the enclave
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StaleKeyshare {
+	pub nftid: u32,
+	pub backup_block: u32,
+	pub current_block: u32,
+}
+
+/// Changes of the availability map if a backup was restored
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RestoreDiff {
+	// Nfts unknown to the enclave
+	pub new: Vec<u32>,
+	// Keyshares replaced by the same type at the same or a later block
+	pub overwritten: Vec<u32>,
+	// Keyshares of another type, i.e a secret keyshare over a capsule one
+	pub conflicting: Vec<u32>,
+	// Keyshares older than the ones of the enclave
+	pub stale: Vec<StaleKeyshare>,
+}
+
+/// Report of a restore dry-run, nothing is written on disk or in the state
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct DryRunReport {
+	// Verification report of a bulk backup
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub report: Option<RestoreReport>,
+	pub diff: RestoreDiff,
+	// True when the restore would replace the enclave account
+	pub account_change: bool,
+	pub current_account: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub restored_account: Option<String>,
+	// Reason the backup would be refused by the backup chain
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub chain_error: Option<String>,
+	// Entries of the request that would be rejected
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub invalid: Vec<String>,
+}
+
+/// Compare restored keyshares with the availability map of the enclave
+/// A keyshare older than the current one is stale whatever its type,
+/// a Hybrid nft accepts both types.
+/// # Arguments
+/// * `restored` - Keyshares of the backup, an nft may be listed once per type
+/// * `current` - Availability map of the enclave
+/// # Returns
+/// * `RestoreDiff` - Nftids per kind of change, sorted and without duplicates
+pub fn diff_restore(
+	restored: &[(u32, Availability)],
+	current: &BTreeMap<u32, Availability>,
+) -> RestoreDiff {
+	let mut new = BTreeSet::new();
+	let mut overwritten = BTreeSet::new();
+	let mut conflicting = BTreeSet::new();
+	let mut stale = BTreeMap::new();
+
+	for (nftid, availability) in restored {
+		let current = match current.get(nftid) {
+			Some(current) => current,
+			None => {
+				new.insert(*nftid);
+				continue;
+			},
+		};
+
+		if availability.block_number < current.block_number {
+			stale.entry(*nftid).or_insert(StaleKeyshare {
+				nftid: *nftid,
+				backup_block: availability.block_number,
+				current_block: current.block_number,
+			});
+		} else if current.nft_type == availability.nft_type || current.nft_type == NftType::Hybrid {
+			overwritten.insert(*nftid);
+		} else {
+			conflicting.insert(*nftid);
+		}
+	}
+
+	RestoreDiff {
+		new: new.into_iter().collect(),
+		overwritten: overwritten.into_iter().collect(),
+		conflicting: conflicting.into_iter().collect(),
+		stale: stale.into_values().collect(),
+	}
+}
+
+/* ----------------------------------
+		BACKUP MANIFEST TEST
+----------------------------------*/
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::core::{
+		log::{LogAccount, LogStruct, LogType},
+		verify::RequesterType,
+	};
+
+	#[test]
+	fn classify_file_test() {
+		let keyshare = classify_file("capsule_125_3400.keyshare").unwrap();
+		assert_eq!(keyshare.kind, BackupFileKind::Keyshare);
+		assert_eq!(keyshare.nftid, Some(125));
+		assert_eq!(keyshare.nft_type, Some(NftType::Capsule));
+		assert_eq!(keyshare.block_number, Some(3400));
+
+		assert_eq!(classify_file("125.log").unwrap().kind, BackupFileKind::Log);
+		assert_eq!(
+			classify_file("enclave_account.key").unwrap().kind,
+			BackupFileKind::EnclaveAccount
+		);
+
+		assert!(classify_file("sync.state.tmp").is_none());
+		assert!(classify_file("backup.zip").is_none());
+		assert!(classify_file("../nft_1_2.keyshare").is_none());
+	}
+
+	#[test]
+	fn backup_archive_test() {
+		let src_dir = "/tmp/manifest_test";
+		let _ = std::fs::remove_dir_all(src_dir);
+		std::fs::create_dir_all(src_dir).unwrap();
+		std::fs::write(format!("{src_dir}/nft_12_500.keyshare"), b"SECRET-SHARE").unwrap();
+		std::fs::write(format!("{src_dir}/12.log"), b"LOG").unwrap();
+		std::fs::write(format!("{src_dir}/unknown.tmp"), b"TEMPORARY").unwrap();
+
+		let keypair = sr25519::Pair::generate().0;
+		let account = keypair.public().to_ss58check();
+		let archive = "/tmp/manifest_test.zip";
+
+		let manifest = create_backup_archive(src_dir, &keypair, 600, None, archive).unwrap();
+		assert_eq!(manifest.files.len(), 2);
+
+		let report = verify_backup_archive(archive, &[account]).unwrap();
+		assert_eq!(report.secret_keyshares, 1);
+		assert_eq!(report.logs, 1);
+		assert_eq!(report.last_keyshare_block, 500);
+
+		// Signed by an enclave which is not trusted
+		let stranger = sr25519::Pair::generate().0.public().to_ss58check();
+		assert!(verify_backup_archive(archive, &[stranger]).is_err());
+
+		// Tampered manifest
+		let mut signed = sign_manifest(&manifest, &keypair).unwrap();
+		signed.manifest = signed.manifest.replace("500", "501");
+		assert!(verify_manifest(&signed).is_err());
+	}
+
+	#[test]
+	fn entry_digest_test() {
+		let data = b"KEYSHARE".to_vec();
+		let mut file = classify_file("nft_12_500.keyshare").unwrap();
+		file.size = data.len() as u64;
+		file.sha256 = sha256::digest(data.as_slice());
+
+		let mut content = Vec::new();
+		assert!(check_entry_digest(data.as_slice(), &file, Some(&mut content)).is_ok());
+		assert_eq!(content, data);
+
+		// A file longer than its manifest size is not read to the end
+		let longer = [data.as_slice(), &[0u8; 4096]].concat();
+		let mut content = Vec::new();
+		assert!(check_entry_digest(longer.as_slice(), &file, Some(&mut content)).is_err());
+		assert_eq!(content.len(), data.len() + 1);
+
+		assert!(check_entry_digest(&data[..4], &file, None).is_err());
+	}
+
+	#[test]
+	fn incremental_backup_test() {
+		let src_dir = "/tmp/manifest_increment_test";
+		let _ = std::fs::remove_dir_all(src_dir);
+		std::fs::create_dir_all(src_dir).unwrap();
+		std::fs::write(format!("{src_dir}/nft_12_500.keyshare"), b"OLD-SHARE").unwrap();
+		std::fs::write(format!("{src_dir}/capsule_13_700.keyshare"), b"NEW-SHARE").unwrap();
+		std::fs::write(format!("{src_dir}/13.log"), b"LOG").unwrap();
+		std::fs::write(format!("{src_dir}/enclave_account.key"), b"PHRASE").unwrap();
+
+		let keypair = sr25519::Pair::generate().0;
+		let account = keypair.public().to_ss58check();
+		let archive = "/tmp/manifest_increment_test.zip";
+
+		// Views of nft 14 are logged after the since block, the ones of nft 15 before it
+		let log = |block| {
+			let mut log_file = LogFile::new();
+			let account = LogAccount::new(String::new(), RequesterType::OWNER);
+			log_file.insert_new_nft_log(LogStruct::new(block, account, LogType::VIEW));
+			serde_json::to_vec(&log_file).unwrap()
+		};
+		std::fs::write(format!("{src_dir}/14.log"), log(650)).unwrap();
+		std::fs::write(format!("{src_dir}/15.log"), log(550)).unwrap();
+		assert_eq!(changed_logs_since(src_dir, 600).unwrap(), BTreeSet::from([13, 14]));
+
+		let increment = BackupIncrement {
+			since_block: 600,
+			nftids: BTreeSet::from([13, 14]),
+			tombstones: vec![Tombstone { nftid: 12, nft_type: NftType::Secret, block_number: 750 }],
+		};
+		let manifest =
+			create_backup_archive(src_dir, &keypair, 800, Some(&increment), archive).unwrap();
+		assert_eq!(manifest.since_block, Some(600));
+		assert_eq!(manifest.files.len(), 3);
+
+		let report = verify_backup_archive(archive, &[account.clone()]).unwrap();
+		assert_eq!(report.capsule_keyshares, 1);
+		assert_eq!(report.logs, 2);
+		assert_eq!(report.tombstones, 1);
+		assert_eq!(report.since_block, Some(600));
+
+		// The removed keyshare goes away, the restored files are kept
+		assert_eq!(apply_tombstones(src_dir, &manifest).unwrap(), 1);
+		assert!(!Path::new(&format!("{src_dir}/nft_12_500.keyshare")).exists());
+		assert!(Path::new(&format!("{src_dir}/capsule_13_700.keyshare")).exists());
+
+		// A tombstone out of the increment is refused
+		let increment = BackupIncrement {
+			tombstones: vec![Tombstone { nftid: 12, nft_type: NftType::Secret, block_number: 500 }],
+			..increment
+		};
+		create_backup_archive(src_dir, &keypair, 800, Some(&increment), archive).unwrap();
+		assert!(verify_backup_archive(archive, &[account]).is_err());
+	}
+
+	#[test]
+	fn backup_chain_test() {
+		let base = RestoreReport {
+			source_enclave: "A".to_string(),
+			backup_block: 1000,
+			..Default::default()
+		};
+		let head = check_backup_chain(None, &base).unwrap();
+
+		let increment = |since_block, backup_block| RestoreReport {
+			since_block: Some(since_block),
+			backup_block,
+			..base.clone()
+		};
+
+		// No base backup
+		assert!(check_backup_chain(None, &increment(1000, 1200)).is_err());
+
+		// Continuous and overlapping increments
+		let head = check_backup_chain(Some(&head), &increment(1000, 1200)).unwrap();
+		let head = check_backup_chain(Some(&head), &increment(1100, 1300)).unwrap();
+		assert_eq!(head.block_number, 1300);
+		assert_eq!(head.base_block, 1000);
+
+		// Gap, stale increment and other enclave
+		assert!(check_backup_chain(Some(&head), &increment(1400, 1500)).is_err());
+		assert!(check_backup_chain(Some(&head), &increment(1200, 1300)).is_err());
+		let other = RestoreReport { source_enclave: "B".to_string(), ..increment(1300, 1500) };
+		assert!(check_backup_chain(Some(&head), &other).is_err());
+	}
+
+	#[test]
+	fn diff_restore_test() {
+		let availability = |block_number, nft_type| Availability { block_number, nft_type };
+
+		let current = BTreeMap::from([
+			(1, availability(100, NftType::Secret)),
+			(2, availability(100, NftType::Secret)),
+			(3, availability(100, NftType::Hybrid)),
+			(4, availability(300, NftType::Capsule)),
+		]);
+
+		let restored = vec![
+			(1, availability(200, NftType::Secret)),
+			(2, availability(200, NftType::Capsule)),
+			(3, availability(100, NftType::Capsule)),
+			(4, availability(200, NftType::Capsule)),
+			(4, availability(250, NftType::Secret)),
+			(5, availability(200, NftType::Secret)),
+			(5, availability(200, NftType::Capsule)),
+		];
+
+		let diff = diff_restore(&restored, &current);
+		assert_eq!(diff.new, vec![5]);
+		assert_eq!(diff.overwritten, vec![1, 3]);
+		assert_eq!(diff.conflicting, vec![2]);
+		assert_eq!(
+			diff.stale,
+			vec![StaleKeyshare { nftid: 4, backup_block: 200, current_block: 300 }]
+		);
+	}
+}
@@ -1,14 +1,22 @@
 use crate::{
 	constants::{MAX_BLOCK_VARIATION, MAX_VALIDATION_PERIOD},
 	core::chain::{get_metric_server, MetricServer},
-	replication::sync::ValidationResult,
+	replication::{
+		audit::{record_admin_audit, AdminAction, AuditSubject},
+		sync::ValidationResult,
+	},
 	server::state::{get_blocknumber, set_processed_block, SharedState},
 };
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+	extract::{ConnectInfo, State},
+	response::{IntoResponse, Response},
+	Json,
+};
 use hex::{FromHex, FromHexError};
 use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::net::SocketAddr;
 use subxt::ext::sp_core::{
 	crypto::{PublicError, Ss58Codec},
 	sr25519::{Public, Signature},
@@ -152,8 +160,19 @@ pub async fn error_handler(message: String, _state: &SharedState) -> impl IntoRe
 --------------------*/
 pub async fn metric_reconcilliation(
 	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
 	Json(request): Json<MetricNftListRequest>,
 ) -> impl IntoResponse {
+	let subject = AuditSubject {
+		account: request.metric_account.clone(),
+		data_hash: sha256::digest(request.auth_token.as_bytes()),
+	};
+
+	let response = nft_list_in_interval(state.clone(), request).await;
+	record_admin_audit(&state, addr, AdminAction::Reconciliation, subject, response).await
+}
+
+async fn nft_list_in_interval(state: SharedState, request: MetricNftListRequest) -> Response {
 	debug!("\n\t**\nMETRIC GET NFT LIST IN BLOCK INTERVAL\n\t**\n");
 	let current_block_number = get_blocknumber(&state).await;
 
@@ -268,8 +287,22 @@ pub async fn metric_reconcilliation(
 
 pub async fn set_crawl_block(
 	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
 	Json(request): Json<MetricSetCrawlRequest>,
 ) -> impl IntoResponse {
+	let subject = AuditSubject {
+		account: request.metric_account.clone(),
+		data_hash: sha256::digest(request.auth_token.as_bytes()),
+	};
+
+	let response = set_processed_block_request(state.clone(), request).await;
+	record_admin_audit(&state, addr, AdminAction::SetCrawlBlock, subject, response).await
+}
+
+async fn set_processed_block_request(
+	state: SharedState,
+	request: MetricSetCrawlRequest,
+) -> Response {
 	debug!("METRIC CRAWL API : setting the last_processed_block");
 	let current_block_number = get_blocknumber(&state).await;
 
@@ -1,8 +1,19 @@
 /// Data Replication module
 pub mod admin_bulk;
 pub mod admin_nftid;
+pub mod admin_quorum;
+pub mod anti_entropy;
+pub mod audit;
+pub mod backup_schedule;
 //pub mod graphql;
+pub mod maintenance;
+pub mod manifest;
 pub mod metric;
+pub mod reassignment;
+pub mod scheduler;
+pub mod staging;
+pub mod stream_cipher;
 pub mod sync;
+pub mod sync_state;
+pub mod upgrade;
 pub mod zipdir;
-//pub mod upgrade;
@@ -0,0 +1,696 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fs,
+	io::Write,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+	extract::{ConnectInfo, State},
+	http::{header, StatusCode},
+	response::IntoResponse,
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::Pair;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	constants::{
+		MAX_BLOCK_VARIATION, MAX_SYNC_PAGE_SIZE, MIN_PURGE_CONFIRMATIONS, REASSIGNMENT_PATH,
+		SEALPATH,
+	},
+	core::helper::{parse_keyshare_file, query_keyshare_file, Availability, NftType},
+	replication::{
+		anti_entropy::{spawn_anti_entropy, KeyshareEntry},
+		maintenance::sealed_writes_paused,
+		metric::AuthenticationToken,
+		sync::{
+			set_sync_state, sync_client, verify_signature, ClusterType, Enclave, ValidationResult,
+		},
+		sync_state::{load_sync_state, update_sync_state, SyncPhase},
+	},
+	server::state::{
+		get_accountid, get_blocknumber, get_clusters, get_keypair, get_nft_availability,
+		reset_nft_availability, SharedState,
+	},
+};
+
+/* ---------------------------------------
+	SLOT REASSIGNMENT
+------------------------------------------ */
+
+static PURGING: AtomicBool = AtomicBool::new(false);
+
+// Blocks a signed purge confirmation is accepted
+const CONFIRMATION_VALIDITY: u32 = 15;
+
+/// Step of a slot reassignment
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReassignmentStep {
+	// Keyshares of the previous slot are moved out of the keyshare directory
+	Quarantined,
+	// Keyshares of the new slot are synchronized, peers of the previous slot are checked
+	Confirming,
+	// Every keyshare of the previous slot is confirmed by the peers and removed
+	Purged,
+}
+
+/// Slot reassignment of the enclave, reported in the sync state
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Reassignment {
+	// (ClusterID, SlotID)
+	pub previous_identity: (u32, u32),
+	pub new_identity: (u32, u32),
+	pub detected_block: u32,
+	pub step: ReassignmentStep,
+	pub quarantined: u32,
+	pub purged: u32,
+	pub last_error: Option<String>,
+}
+
+impl Reassignment {
+	// Keyshares of the previous identity, a new reassignment can not mix them up
+	fn quarantine_dir(&self) -> PathBuf {
+		Path::new(REASSIGNMENT_PATH)
+			.join(format!("{}_{}", self.previous_identity.0, self.previous_identity.1))
+	}
+}
+
+async fn update_health_status(state: &SharedState, message: String) {
+	let shared_state_write = &mut state.write().await;
+	debug!("got shared state to write.");
+
+	shared_state_write.set_maintenance(message);
+	debug!("Maintenance state is set.");
+}
+
+// Every step is reported to sentry, a reassignment is a rare act of the technical committee
+fn report_step(message: &str, level: sentry::Level) {
+	match level {
+		sentry::Level::Error => error!(message),
+		sentry::Level::Warning => warn!(message),
+		_ => info!(message),
+	}
+
+	sentry::with_scope(
+		|scope| {
+			scope.set_tag("reassignment", "step");
+		},
+		|| sentry::capture_message(message, level),
+	);
+}
+
+/// Handle a change of the enclave identity, detected by the cluster discovery
+/// A new cluster keeps the keyshares of the slot, a new slot quarantines them and starts a
+/// setup synchronization, they are removed after the peers of the previous slot confirm them
+/// # Arguments
+/// * `state` - The shared state, with the new identity
+/// * `previous` - Previous (ClusterID, SlotID)
+/// * `new` - New (ClusterID, SlotID)
+pub async fn handle_identity_change(state: &SharedState, previous: (u32, u32), new: (u32, u32)) {
+	if previous.1 == new.1 {
+		report_step(
+			&format!(
+				"REASSIGNMENT : cluster changed from {} to {}, keyshares of slot {} are kept and reconciled",
+				previous.0, new.0, new.1
+			),
+			sentry::Level::Warning,
+		);
+		spawn_anti_entropy(state.clone());
+		return;
+	}
+
+	report_step(
+		&format!("REASSIGNMENT : identity changed from {previous:?} to {new:?}, quarantine the keyshares of slot {}", previous.1),
+		sentry::Level::Warning,
+	);
+
+	let reassignment = Reassignment {
+		previous_identity: previous,
+		new_identity: new,
+		detected_block: get_blocknumber(state).await,
+		step: ReassignmentStep::Quarantined,
+		quarantined: 0,
+		purged: 0,
+		last_error: None,
+	};
+
+	let result = quarantine_keyshares(&reassignment);
+
+	// Retries of the previous slot would fetch keyshares the enclave does not hold anymore
+	if let Err(err) = update_sync_state(|sync_state| {
+		sync_state.pending.clear();
+		sync_state.failed.clear();
+		sync_state.crawl_checkpoint = None;
+		Ok(())
+	}) {
+		error!("REASSIGNMENT : can not clear the sync entries of slot {} : {err:?}", previous.1);
+	}
+
+	// Remaining keyshares, empty unless the quarantine failed
+	let availability = query_keyshare_file(SEALPATH.to_string()).unwrap_or_default();
+	let remaining = availability.len();
+	reset_nft_availability(state, availability).await;
+
+	match result {
+		Ok(quarantined) if remaining == 0 => {
+			let record = Reassignment { quarantined, ..reassignment };
+			if let Err(err) = update_sync_state(|sync_state| {
+				sync_state.reassignments.push(record);
+				Ok(())
+			}) {
+				error!(
+					"REASSIGNMENT : can not record the reassignment in the sync state : {err:?}"
+				);
+			}
+
+			report_step(
+				&format!("REASSIGNMENT : {quarantined} keyshares of slot {} are quarantined, entering setup-mode for slot {}", previous.1, new.1),
+				sentry::Level::Info,
+			);
+			let _ = set_sync_state("setup".to_owned());
+		},
+
+		result => {
+			// Keyshares of two slots can not be served, nor be synchronized
+			let message = format!(
+				"Slot reassignment from {previous:?} to {new:?} failed, {remaining} keyshares of the previous slot remain : {:?}",
+				result.err()
+			);
+			report_step(&format!("REASSIGNMENT : {message}"), sentry::Level::Error);
+			update_health_status(state, message).await;
+		},
+	}
+}
+
+// Move the keyshares and the view logs of the previous slot to the quarantine
+fn quarantine_keyshares(reassignment: &Reassignment) -> Result<u32> {
+	let quarantine_dir = reassignment.quarantine_dir();
+	fs::create_dir_all(&quarantine_dir)?;
+
+	let mut quarantined = 0;
+	for dir_entry in fs::read_dir(SEALPATH)? {
+		let path = dir_entry?.path();
+		let extension = path.extension().and_then(std::ffi::OsStr::to_str);
+		if !path.is_file() || !matches!(extension, Some("keyshare") | Some("log")) {
+			continue;
+		}
+
+		let file_name = path.file_name().ok_or_else(|| anyhow!("invalid path {path:?}"))?;
+		fs::rename(&path, quarantine_dir.join(file_name))?;
+
+		if extension == Some("keyshare") {
+			quarantined += 1;
+		}
+	}
+
+	Ok(quarantined)
+}
+
+/// Purge the quarantined keyshares confirmed by the peers, if no purge is running
+/// Purge starts when the setup synchronization of the new slot is complete
+pub fn spawn_reassignment_purge(state: SharedState) {
+	match load_sync_state() {
+		Ok(sync_state)
+			if sync_state.phase == SyncPhase::Running &&
+				sync_state
+					.reassignments
+					.iter()
+					.any(|reassignment| reassignment.step != ReassignmentStep::Purged) => {},
+		_ => return,
+	}
+
+	if PURGING.swap(true, Ordering::SeqCst) {
+		debug!("REASSIGNMENT : previous purge is still running");
+		return;
+	}
+
+	tokio::spawn(async move {
+		if let Err(err) = purge_reassignments(&state).await {
+			report_step(&format!("REASSIGNMENT : purge failed : {err:?}"), sentry::Level::Error);
+		}
+
+		PURGING.store(false, Ordering::SeqCst);
+	});
+}
+
+async fn purge_reassignments(state: &SharedState) -> Result<()> {
+	let reassignments = load_sync_state()?.reassignments;
+
+	for (index, reassignment) in reassignments.into_iter().enumerate() {
+		if reassignment.step == ReassignmentStep::Purged {
+			continue;
+		}
+
+		if let Some(reason) = sealed_writes_paused(state).await {
+			debug!("REASSIGNMENT : purge is paused, {reason}");
+			return Ok(());
+		}
+
+		if reassignment.step == ReassignmentStep::Quarantined {
+			report_step(
+				&format!(
+					"REASSIGNMENT : slot {} is synchronized, confirm the {} keyshares of slot {} with its peers",
+					reassignment.new_identity.1, reassignment.quarantined, reassignment.previous_identity.1
+				),
+				sentry::Level::Info,
+			);
+		}
+
+		let (purged, remaining, last_error) = match purge_confirmed(state, &reassignment).await {
+			Ok((purged, remaining)) => (purged, remaining, None),
+			Err(err) => (0, 1, Some(err.to_string())),
+		};
+
+		let step = if remaining == 0 {
+			let _ = fs::remove_dir_all(reassignment.quarantine_dir());
+			report_step(
+				&format!(
+					"REASSIGNMENT : keyshares of slot {} are confirmed by the peers and removed",
+					reassignment.previous_identity.1
+				),
+				sentry::Level::Info,
+			);
+			ReassignmentStep::Purged
+		} else {
+			debug!("REASSIGNMENT : {purged} keyshares purged, {remaining} wait for confirmation");
+			ReassignmentStep::Confirming
+		};
+
+		update_sync_state(|sync_state| {
+			if let Some(record) = sync_state.reassignments.get_mut(index) {
+				record.step = step;
+				record.purged += purged;
+				record.last_error = last_error;
+			}
+			Ok(())
+		})?;
+	}
+
+	Ok(())
+}
+
+// Remove the quarantined keyshares that enough peers of the previous slot hold
+// Returns the number of purged and remaining keyshares
+async fn purge_confirmed(state: &SharedState, reassignment: &Reassignment) -> Result<(u32, u32)> {
+	let peers = previous_slot_peers(state, reassignment.previous_identity.1).await;
+	if peers.is_empty() {
+		return Err(anyhow!("no enclave holds slot {}", reassignment.previous_identity.1));
+	}
+
+	// A single enclave of the slot is enough if no other exists
+	let required = MIN_PURGE_CONFIRMATIONS.min(peers.len());
+	let client = sync_client()?;
+
+	let quarantine_dir = reassignment.quarantine_dir();
+	let mut keyshares = BTreeMap::<PathBuf, (u32, Availability)>::new();
+	let mut logs = Vec::<(u32, PathBuf)>::new();
+
+	for dir_entry in fs::read_dir(&quarantine_dir)? {
+		let path = dir_entry?.path();
+		if let Ok(keyshare) = parse_keyshare_file(&path) {
+			keyshares.insert(path, keyshare);
+		} else if let Some(nftid) = view_log_nftid(&path) {
+			logs.push((nftid, path));
+		}
+	}
+
+	let nftids: Vec<u32> = keyshares
+		.values()
+		.map(|(nftid, _)| *nftid)
+		.collect::<BTreeSet<u32>>()
+		.into_iter()
+		.collect();
+
+	// Keyshares held by every peer, from its signed confirmation
+	let mut peer_entries = Vec::<BTreeMap<u32, KeyshareEntry>>::new();
+	for enclave in &peers {
+		let mut entries = BTreeMap::new();
+		for page in nftids.chunks(MAX_SYNC_PAGE_SIZE as usize) {
+			match request_confirmation(state, &client, enclave, page).await {
+				Ok(confirmed) => entries.extend(confirmed),
+				Err(err) => {
+					debug!(
+						"REASSIGNMENT : no confirmation from {} : {err:?}",
+						enclave.enclave_account
+					);
+					break;
+				},
+			}
+		}
+		peer_entries.push(entries);
+	}
+
+	// A restore or a maintenance may start while the peers confirm
+	if let Some(reason) = sealed_writes_paused(state).await {
+		return Err(anyhow!("purge is paused, {reason}"));
+	}
+
+	let mut purged = 0;
+	let mut remaining = BTreeSet::<u32>::new();
+
+	for (path, (nftid, availability)) in keyshares {
+		let confirmations = peer_entries
+			.iter()
+			.filter(|entries| {
+				entries.get(&nftid).map_or(false, |entry| confirms(entry, &availability))
+			})
+			.count();
+
+		if confirmations < required {
+			debug!("REASSIGNMENT : nft {nftid} is confirmed by {confirmations}/{required} peers");
+			remaining.insert(nftid);
+			continue;
+		}
+
+		match secure_remove(&path) {
+			Ok(_) => purged += 1,
+			Err(err) => {
+				warn!("REASSIGNMENT : can not remove {path:?} : {err:?}");
+				remaining.insert(nftid);
+			},
+		}
+	}
+
+	// View logs follow the keyshares of their nft
+	for (nftid, path) in logs {
+		if !remaining.contains(&nftid) {
+			if let Err(err) = secure_remove(&path) {
+				warn!("REASSIGNMENT : can not remove {path:?} : {err:?}");
+			}
+		}
+	}
+
+	Ok((purged, remaining.len() as u32))
+}
+
+// Enclaves of the public clusters that hold the previous slot
+async fn previous_slot_peers(state: &SharedState, slot: u32) -> Vec<Enclave> {
+	let self_account = get_accountid(state).await;
+
+	get_clusters(state)
+		.await
+		.into_iter()
+		.filter(|cluster| cluster.cluster_type == ClusterType::Public)
+		.flat_map(|cluster| cluster.enclaves)
+		.filter(|enclave| {
+			enclave.slot == slot && enclave.enclave_account.to_string() != self_account
+		})
+		.collect()
+}
+
+// The keyshare of the peer must be as recent as the quarantined one, a hybrid holds both types
+fn confirms(entry: &KeyshareEntry, availability: &Availability) -> bool {
+	entry.block_number >= availability.block_number &&
+		(entry.nft_type == availability.nft_type || entry.nft_type == NftType::Hybrid)
+}
+
+/* ----------------------------------
+	PURGE CONFIRMATION
+----------------------------------*/
+
+/// Purge confirmation request of a registered enclave
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PurgeConfirmationPacket {
+	enclave_account: String,
+	nftids: String,
+	auth_token: String,
+	signature: String,
+}
+
+/// Keyshares held by the enclave among the requested ones
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PurgeConfirmation {
+	pub enclave_account: String,
+	pub requester: String,
+	// Hash of the requested nftids
+	pub data_hash: String,
+	pub block_number: u32,
+	pub entries: BTreeMap<u32, KeyshareEntry>,
+}
+
+/// Serialized confirmation, signed by the enclave
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PurgeConfirmationResponse {
+	pub confirmation: String,
+	pub signature: String,
+}
+
+fn purge_confirmation_error(message: String) -> axum::response::Response {
+	warn!(message);
+	(StatusCode::BAD_REQUEST, Json(json!({ "error": message }))).into_response()
+}
+
+async fn verify_confirmation_request(
+	state: &SharedState,
+	request: &PurgeConfirmationPacket,
+) -> Result<Vec<u32>, String> {
+	// A reassigned enclave is not in the slot anymore, any registered enclave can ask
+	let registered = get_clusters(state)
+		.await
+		.into_iter()
+		.flat_map(|cluster| cluster.enclaves)
+		.any(|enclave| enclave.enclave_account.to_string() == request.enclave_account);
+
+	if !registered {
+		return Err(format!(
+			"PURGE CONFIRMATION : Requester is not a registered enclave : {}",
+			request.enclave_account
+		));
+	}
+
+	if !verify_signature(
+		&request.enclave_account,
+		request.signature.clone(),
+		request.auth_token.as_bytes(),
+	) {
+		return Err("PURGE CONFIRMATION : Invalid Signature".to_string());
+	}
+
+	let auth = request
+		.auth_token
+		.strip_prefix("<Bytes>")
+		.and_then(|auth| auth.strip_suffix("</Bytes>"))
+		.unwrap_or(&request.auth_token);
+
+	let auth_token: AuthenticationToken = serde_json::from_str(auth).map_err(|err| {
+		format!("PURGE CONFIRMATION : Authentication token is not parsable : {err}")
+	})?;
+
+	let validity = auth_token.is_valid(get_blocknumber(state).await);
+	if !matches!(validity, ValidationResult::Success) {
+		return Err(format!(
+			"PURGE CONFIRMATION : Authentication Token is not valid : {validity:?}"
+		));
+	}
+
+	if auth_token.data_hash != sha256::digest(request.nftids.as_bytes()) {
+		return Err("PURGE CONFIRMATION : Mismatch Data Hash".to_string());
+	}
+
+	let nftids: Vec<u32> = serde_json::from_str(&request.nftids)
+		.map_err(|err| format!("PURGE CONFIRMATION : nftids are not parsable : {err}"))?;
+
+	if nftids.is_empty() || nftids.len() > MAX_SYNC_PAGE_SIZE as usize {
+		return Err(format!(
+			"PURGE CONFIRMATION : between 1 and {MAX_SYNC_PAGE_SIZE} nftids can be requested"
+		));
+	}
+
+	Ok(nftids)
+}
+
+/// Signed list of the requested keyshares held by the enclave, for a reassigned enclave
+/// that removes its quarantined keyshares
+pub async fn purge_confirmation(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<PurgeConfirmationPacket>,
+) -> impl IntoResponse {
+	debug!("PURGE CONFIRMATION : request from {addr}");
+
+	let nftids = match verify_confirmation_request(&state, &request).await {
+		Ok(nftids) => nftids,
+		Err(message) => return purge_confirmation_error(message),
+	};
+
+	let mut entries = BTreeMap::new();
+	for nftid in nftids {
+		if let Some(availability) = get_nft_availability(&state, nftid).await {
+			entries.insert(
+				nftid,
+				KeyshareEntry {
+					block_number: availability.block_number,
+					nft_type: availability.nft_type,
+				},
+			);
+		}
+	}
+
+	let confirmation = PurgeConfirmation {
+		enclave_account: get_accountid(&state).await,
+		requester: request.enclave_account,
+		data_hash: sha256::digest(request.nftids.as_bytes()),
+		block_number: get_blocknumber(&state).await,
+		entries,
+	};
+
+	let confirmation = match serde_json::to_string(&confirmation) {
+		Ok(confirmation) => confirmation,
+		Err(err) =>
+			return purge_confirmation_error(format!(
+				"PURGE CONFIRMATION : can not serialize the confirmation : {err:?}"
+			)),
+	};
+
+	let signature = get_keypair(&state).await.sign(confirmation.as_bytes());
+
+	(
+		StatusCode::OK,
+		Json(PurgeConfirmationResponse { confirmation, signature: format!("0x{:?}", signature) }),
+	)
+		.into_response()
+}
+
+// Signed confirmation of a peer, for the quarantined nftids
+async fn request_confirmation(
+	state: &SharedState,
+	client: &reqwest::Client,
+	enclave: &Enclave,
+	nftids: &[u32],
+) -> Result<BTreeMap<u32, KeyshareEntry>> {
+	let nftids = serde_json::to_string(nftids)?;
+	let data_hash = sha256::digest(nftids.as_bytes());
+	let self_account = get_accountid(state).await;
+
+	let auth_token = serde_json::to_string(&AuthenticationToken {
+		block_number: get_blocknumber(state).await,
+		block_validation: CONFIRMATION_VALIDITY,
+		data_hash: data_hash.clone(),
+	})?;
+
+	let signature = get_keypair(state).await.sign(auth_token.as_bytes());
+
+	let packet = PurgeConfirmationPacket {
+		enclave_account: self_account.clone(),
+		nftids,
+		auth_token,
+		signature: format!("0x{:?}", signature),
+	};
+
+	let request_url =
+		format!("{}/api/backup/purge-confirmation", enclave.enclave_url.trim_end_matches('/'));
+
+	let response = client
+		.post(&request_url)
+		.body(serde_json::to_string(&packet)?)
+		.header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+		.send()
+		.await?;
+
+	let status = response.status();
+	let body = response.text().await?;
+
+	if status != StatusCode::OK {
+		return Err(anyhow!("{request_url} responded {status} : {body}"));
+	}
+
+	let response: PurgeConfirmationResponse = serde_json::from_str(&body)?;
+
+	// Only the registered account of the peer can sign its confirmation
+	let enclave_account = enclave.enclave_account.to_string();
+	if !verify_signature(
+		&enclave_account,
+		response.signature.clone(),
+		response.confirmation.as_bytes(),
+	) {
+		return Err(anyhow!("invalid confirmation signature of {enclave_account}"));
+	}
+
+	let confirmation: PurgeConfirmation = serde_json::from_str(&response.confirmation)?;
+	let current_block_number = get_blocknumber(state).await;
+
+	if confirmation.enclave_account != enclave_account ||
+		confirmation.requester != self_account ||
+		confirmation.data_hash != data_hash
+	{
+		return Err(anyhow!("confirmation of {enclave_account} is for another request"));
+	}
+
+	if confirmation.block_number > current_block_number + MAX_BLOCK_VARIATION ||
+		confirmation.block_number + CONFIRMATION_VALIDITY < current_block_number
+	{
+		return Err(anyhow!(
+			"confirmation of {enclave_account} at block {} is expired",
+			confirmation.block_number
+		));
+	}
+
+	Ok(confirmation.entries)
+}
+
+// View log file name = [nftid].log
+fn view_log_nftid(path: &Path) -> Option<u32> {
+	if path.extension().and_then(std::ffi::OsStr::to_str) != Some("log") {
+		return None;
+	}
+
+	path.file_stem().and_then(std::ffi::OsStr::to_str)?.parse::<u32>().ok()
+}
+
+// Overwrite the sealed file before removing it
+fn secure_remove(path: &Path) -> std::io::Result<()> {
+	let length = fs::metadata(path)?.len();
+
+	let mut file = fs::OpenOptions::new().write(true).open(path)?;
+	file.write_all(&vec![0u8; length as usize])?;
+	file.sync_all()?;
+	drop(file);
+
+	fs::remove_file(path)
+}
+
+/* ----------------------------------
+REASSIGNMENT TEST
+----------------------------------*/
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn quarantine_dir_test() {
+		let reassignment = Reassignment {
+			previous_identity: (2, 3),
+			new_identity: (2, 4),
+			detected_block: 100,
+			step: ReassignmentStep::Quarantined,
+			quarantined: 0,
+			purged: 0,
+			last_error: None,
+		};
+
+		assert_eq!(reassignment.quarantine_dir(), Path::new(REASSIGNMENT_PATH).join("2_3"));
+
+		assert_eq!(view_log_nftid(Path::new("/nft/reassignment/2_3/125.log")), Some(125));
+		assert_eq!(view_log_nftid(Path::new("/nft/reassignment/2_3/nft_125_7.keyshare")), None);
+	}
+
+	#[test]
+	fn confirms_test() {
+		let quarantined = Availability { block_number: 100, nft_type: NftType::Capsule };
+		let entry = |block_number, nft_type| KeyshareEntry { block_number, nft_type };
+
+		assert!(confirms(&entry(100, NftType::Capsule), &quarantined));
+		assert!(confirms(&entry(120, NftType::Hybrid), &quarantined));
+
+		// Older or of another type, the quarantined keyshare is kept
+		assert!(!confirms(&entry(99, NftType::Capsule), &quarantined));
+		assert!(!confirms(&entry(120, NftType::Secret), &quarantined));
+	}
+}
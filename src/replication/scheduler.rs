@@ -0,0 +1,332 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Serialize;
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::{
+	constants::MAX_PEER_BACKOFF,
+	core::runtime::{active_era, operator_metric_score},
+	replication::{
+		audit::{verify_admin_read, AdminReadPacket},
+		sync::Enclave,
+	},
+	server::state::{
+		get_blocknumber, get_chain_api, get_peer_stats, update_peer_stats, SharedState,
+	},
+};
+
+/* ---------------------------------------
+	PEER SCHEDULER
+------------------------------------------ */
+
+// Weights of the peer score, each part is between 0 and 1
+const METRIC_WEIGHT: f64 = 0.4;
+const SUCCESS_WEIGHT: f64 = 0.3;
+const LATENCY_WEIGHT: f64 = 0.2;
+const HEALTH_WEIGHT: f64 = 0.1;
+
+/// Local statistics of a peer enclave
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PeerStats {
+	pub cluster_id: u32,
+	pub enclave_url: String,
+	pub successes: u32,
+	pub failures: u32,
+	pub consecutive_failures: u32,
+	// Moving average of the request latency
+	pub latency_ms: Option<u64>,
+	pub healthy: Option<bool>,
+	pub last_success_block: u32,
+	pub last_failure_block: u32,
+	// The peer is ranked last until this block
+	pub backoff_until_block: u32,
+	// Average metric-server score of the operator, and its era
+	pub metric_score: Option<f64>,
+	pub metric_era: Option<u32>,
+}
+
+/// Peer statistics with the current score
+#[derive(Serialize)]
+pub struct PeerStatsEntry {
+	enclave_account: String,
+	score: f64,
+	in_backoff: bool,
+	#[serde(flatten)]
+	stats: PeerStats,
+}
+
+impl PeerStats {
+	/// Score of the peer, metric-server scores are relative to the best peer
+	pub fn score(&self, best_metric: Option<f64>) -> f64 {
+		// Unknown values are neutral
+		let metric = match (self.metric_score, best_metric) {
+			(Some(metric), Some(best)) if best > 0.0 => metric / best,
+			_ => 0.5,
+		};
+
+		// Laplace smoothing, a new peer starts at 0.5
+		let success =
+			(self.successes as f64 + 1.0) / (self.successes as f64 + self.failures as f64 + 2.0);
+
+		let latency = self.latency_ms.map_or(0.5, |latency| 1.0 / (1.0 + latency as f64 / 1000.0));
+
+		let health = match self.healthy {
+			Some(true) => 1.0,
+			Some(false) => 0.0,
+			None => 0.5,
+		};
+
+		METRIC_WEIGHT * metric +
+			SUCCESS_WEIGHT * success +
+			LATENCY_WEIGHT * latency +
+			HEALTH_WEIGHT * health
+	}
+
+	fn identify(&mut self, cluster_id: u32, enclave: &Enclave) {
+		self.cluster_id = cluster_id;
+		self.enclave_url = enclave.enclave_url.clone();
+	}
+
+	fn update_latency(&mut self, latency: Duration) {
+		let latency = latency.as_millis() as u64;
+		self.latency_ms = Some(match self.latency_ms {
+			Some(average) => (3 * average + latency) / 4,
+			None => latency,
+		});
+	}
+
+	fn success(&mut self, block_number: u32, latency: Duration) {
+		self.successes += 1;
+		self.consecutive_failures = 0;
+		self.healthy = Some(true);
+		self.last_success_block = block_number;
+		self.backoff_until_block = 0;
+		self.update_latency(latency);
+	}
+
+	fn failure(&mut self, block_number: u32) {
+		self.failures += 1;
+		self.consecutive_failures += 1;
+		self.healthy = Some(false);
+		self.last_failure_block = block_number;
+
+		// Exponential backoff, in blocks
+		let backoff = 2u32.saturating_pow(self.consecutive_failures).min(MAX_PEER_BACKOFF);
+		self.backoff_until_block = block_number.saturating_add(backoff);
+	}
+}
+
+fn best_metric(stats: &BTreeMap<String, PeerStats>) -> Option<f64> {
+	stats.values().filter_map(|peer| peer.metric_score).reduce(f64::max)
+}
+
+/// Rank the enclaves of the slot, the best candidate first
+/// Peers in backoff are ranked after the others, they remain the last resort
+/// # Arguments
+/// * `state` - The shared state
+/// * `slot_enclaves` - Enclaves of the same slot, with their cluster id
+/// # Returns
+/// * `Vec<(u32, Enclave)>` - The ranked enclaves
+pub async fn rank_peers(
+	state: &SharedState,
+	slot_enclaves: Vec<(u32, Enclave)>,
+) -> Vec<(u32, Enclave)> {
+	refresh_metric_scores(state, &slot_enclaves).await;
+
+	let stats = get_peer_stats(state).await;
+	let best = best_metric(&stats);
+	let current_block = get_blocknumber(state).await;
+
+	let mut ranked: Vec<(bool, f64, (u32, Enclave))> = slot_enclaves
+		.into_iter()
+		.map(|peer| {
+			let peer_stats =
+				stats.get(&peer.1.enclave_account.to_string()).cloned().unwrap_or_default();
+			(peer_stats.backoff_until_block > current_block, peer_stats.score(best), peer)
+		})
+		.collect();
+
+	ranked.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)));
+
+	debug!(
+		"PEER SCHEDULER : ranked peers : {:?}",
+		ranked.iter().map(|(_, score, peer)| (peer.0, *score)).collect::<Vec<_>>()
+	);
+
+	ranked.into_iter().map(|(_, _, peer)| peer).collect()
+}
+
+// Metric-server scores change once per era
+async fn refresh_metric_scores(state: &SharedState, slot_enclaves: &[(u32, Enclave)]) {
+	let api = get_chain_api(state).await;
+	let storage = match api.storage().at_latest().await {
+		Ok(storage) => storage,
+		Err(err) => {
+			debug!("PEER SCHEDULER : can not get storage for metric scores : {err:?}");
+			return;
+		},
+	};
+
+	let era = match active_era(&storage).await {
+		Ok(Some(era)) => era,
+		Ok(None) => return,
+		Err(err) => {
+			debug!("PEER SCHEDULER : can not get the active era : {err:?}");
+			return;
+		},
+	};
+
+	let stats = get_peer_stats(state).await;
+
+	for (cluster_id, enclave) in slot_enclaves {
+		let enclave_account = enclave.enclave_account.to_string();
+		if stats.get(&enclave_account).and_then(|peer| peer.metric_era) == Some(era) {
+			continue;
+		}
+
+		let metric_score = match operator_metric_score(&storage, era, &enclave.operator_account)
+			.await
+		{
+			Ok(score) => score,
+			Err(err) => {
+				debug!("PEER SCHEDULER : can not get metric score of {enclave_account} : {err:?}");
+				None
+			},
+		};
+
+		update_peer_stats(state, enclave_account, |peer| {
+			peer.identify(*cluster_id, enclave);
+			peer.metric_score = metric_score;
+			peer.metric_era = Some(era);
+		})
+		.await;
+	}
+}
+
+/// Record a successful request to a peer
+pub async fn record_success(state: &SharedState, peer: &(u32, Enclave), latency: Duration) {
+	let block_number = get_blocknumber(state).await;
+	update_peer_stats(state, peer.1.enclave_account.to_string(), |stats| {
+		stats.identify(peer.0, &peer.1);
+		stats.success(block_number, latency);
+	})
+	.await;
+}
+
+/// Record a failed request to a peer, the peer is ranked last for a while
+pub async fn record_failure(state: &SharedState, peer: &(u32, Enclave)) {
+	let block_number = get_blocknumber(state).await;
+	update_peer_stats(state, peer.1.enclave_account.to_string(), |stats| {
+		stats.identify(peer.0, &peer.1);
+		stats.failure(block_number);
+	})
+	.await;
+}
+
+/// Record the health-check of a peer
+pub async fn record_health(
+	state: &SharedState,
+	peer: &(u32, Enclave),
+	healthy: bool,
+	latency: Duration,
+) {
+	if !healthy {
+		return record_failure(state, peer).await;
+	}
+
+	update_peer_stats(state, peer.1.enclave_account.to_string(), |stats| {
+		stats.identify(peer.0, &peer.1);
+		stats.healthy = Some(true);
+		stats.update_latency(latency);
+	})
+	.await;
+}
+
+/// Statistics of the peer enclaves used for synchronization, requested by an admin
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The signed request of an admin, for the "peer-stats" endpoint
+pub async fn peer_stats_handler(
+	State(state): State<SharedState>,
+	Json(request): Json<AdminReadPacket>,
+) -> Response {
+	if let Err((status, message)) = verify_admin_read(&state, &request).await {
+		return peer_stats_error(status, format!("PEER STATS : {message}"));
+	}
+
+	if request.request != "peer-stats" {
+		return peer_stats_error(
+			StatusCode::BAD_REQUEST,
+			"PEER STATS : Request is not a peer-stats request".into(),
+		);
+	}
+
+	let stats = get_peer_stats(&state).await;
+	let best = best_metric(&stats);
+	let current_block = get_blocknumber(&state).await;
+
+	let mut peers: Vec<PeerStatsEntry> = stats
+		.into_iter()
+		.map(|(enclave_account, stats)| PeerStatsEntry {
+			enclave_account,
+			score: stats.score(best),
+			in_backoff: stats.backoff_until_block > current_block,
+			stats,
+		})
+		.collect();
+
+	peers.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+	(StatusCode::OK, Json(json!({ "block_number": current_block, "peers": peers }))).into_response()
+}
+
+fn peer_stats_error(status: StatusCode, message: String) -> Response {
+	warn!(message);
+	(status, Json(json!({ "error": message }))).into_response()
+}
+
+/* ----------------------------------
+PEER SCHEDULER TEST
+----------------------------------*/
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn peer_score_test() {
+		let mut reliable = PeerStats::default();
+		let mut failing = PeerStats::default();
+		let unknown = PeerStats::default();
+
+		for block in 0..4 {
+			reliable.success(block, Duration::from_millis(200));
+		}
+		failing.success(1, Duration::from_millis(200));
+		failing.failure(2);
+		failing.failure(3);
+
+		assert!(reliable.score(None) > unknown.score(None));
+		assert!(unknown.score(None) > failing.score(None));
+
+		// Backoff grows with consecutive failures, up to the maximum
+		assert_eq!(failing.backoff_until_block, 3 + 4);
+		for block in 4..20 {
+			failing.failure(block);
+		}
+		assert_eq!(failing.backoff_until_block, 19 + MAX_PEER_BACKOFF);
+
+		// Metric scores are relative to the best peer
+		reliable.metric_score = Some(50.0);
+		let mut best = unknown.clone();
+		best.metric_score = Some(100.0);
+		assert!(best.score(Some(100.0)) > PeerStats::default().score(Some(100.0)));
+		assert!(reliable.score(Some(100.0)) < reliable.score(Some(50.0)));
+	}
+}
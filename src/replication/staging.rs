@@ -0,0 +1,256 @@
+use std::{
+	fs::File,
+	io::{Read, Write},
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use axum::extract::multipart::Field;
+use ring::digest;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, error, warn};
+
+use crate::{
+	constants::RESTORE_STAGING_PATH,
+	replication::manifest::{BackupFileKind, BackupManifest},
+};
+
+/* ---------------------------------------
+	STAGED RESTORE
+------------------------------------------ */
+
+static RESTORING: AtomicBool = AtomicBool::new(false);
+
+const UPLOAD_FILE: &str = "upload.zip";
+const STAGED_DIR: &str = "files";
+const PREVIOUS_DIR: &str = "previous";
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Only one restore modifies the sealed directory at a time, released when dropped
+pub struct RestoreLock;
+
+impl RestoreLock {
+	/// # Returns
+	/// * `Option<RestoreLock>` - None when another restore is running
+	pub fn acquire() -> Option<RestoreLock> {
+		if RESTORING.swap(true, Ordering::SeqCst) {
+			return None;
+		}
+
+		Some(RestoreLock)
+	}
+}
+
+/// A restore or an upgrade install is modifying the sealed directory
+pub fn is_restoring() -> bool {
+	RESTORING.load(Ordering::SeqCst)
+}
+
+impl Drop for RestoreLock {
+	fn drop(&mut self) {
+		RESTORING.store(false, Ordering::SeqCst);
+	}
+}
+
+/// Upload and extracted files of one restore
+/// The directory is removed when the staging is dropped : on every error path,
+/// and when the request is cancelled by the client.
+/// It is inside the sealed directory, a staged file is moved in place by a rename.
+pub struct RestoreStaging {
+	path: PathBuf,
+}
+
+impl RestoreStaging {
+	pub fn create() -> Result<RestoreStaging> {
+		let path = Path::new(RESTORE_STAGING_PATH).join(format!("{:016x}", rand::random::<u64>()));
+		std::fs::create_dir_all(path.join(STAGED_DIR))?;
+
+		debug!("RESTORE STAGING : create {path:?}");
+		Ok(RestoreStaging { path })
+	}
+
+	pub fn upload_path(&self) -> PathBuf {
+		self.path.join(UPLOAD_FILE)
+	}
+
+	/// Write an uploaded multipart field to the staging, chunk by chunk
+	/// # Arguments
+	/// * `field` - The uploaded backup
+	/// # Returns
+	/// * `(String, u64)` - Hex sha256 digest and size of the upload
+	pub async fn spool_upload(&self, field: &mut Field<'_>) -> Result<(String, u64)> {
+		let mut file = tokio::fs::File::create(self.upload_path()).await?;
+		let mut context = digest::Context::new(&digest::SHA256);
+		let mut size = 0u64;
+
+		while let Some(chunk) =
+			field.chunk().await.map_err(|err| anyhow!("upload is interrupted : {err}"))?
+		{
+			context.update(&chunk);
+			file.write_all(&chunk).await?;
+			size += chunk.len() as u64;
+		}
+
+		file.sync_all().await?;
+
+		Ok((hex::encode(context.finish()), size))
+	}
+
+	/// Extract the files of a verified backup one by one, each digest is checked again
+	/// # Arguments
+	/// * `manifest` - The verified manifest of the upload
+	/// # Returns
+	/// * `Vec<String>` - Staged file names, the enclave account last
+	pub fn stage_backup(&self, manifest: &BackupManifest) -> Result<Vec<String>> {
+		let mut archive = zip::ZipArchive::new(File::open(self.upload_path())?)
+			.map_err(|err| anyhow!("backup is not a zip archive : {err:?}"))?;
+
+		let mut files: Vec<_> = manifest.files.iter().collect();
+		// A failed swap must never leave a restored account with the keyshares of the enclave
+		files.sort_by_key(|file| file.kind == BackupFileKind::EnclaveAccount);
+
+		let mut staged = Vec::new();
+		for file in files {
+			let entry = archive
+				.by_name(&file.name)
+				.map_err(|err| anyhow!("can not read {} : {err:?}", file.name))?;
+			// Never more than one byte past the manifest size, whatever the zip header says
+			let mut entry = entry.take(file.size + 1);
+			let mut output = File::create(self.path.join(STAGED_DIR).join(&file.name))?;
+
+			let mut context = digest::Context::new(&digest::SHA256);
+			let mut buffer = vec![0u8; COPY_CHUNK_SIZE];
+			let mut size = 0u64;
+			loop {
+				let count = entry.read(&mut buffer)?;
+				if count == 0 {
+					break;
+				}
+
+				context.update(&buffer[..count]);
+				output.write_all(&buffer[..count])?;
+				size += count as u64;
+			}
+			output.sync_all()?;
+
+			if size != file.size || hex::encode(context.finish()) != file.sha256 {
+				return Err(anyhow!("digest of {} does not match the manifest", file.name));
+			}
+
+			staged.push(file.name.clone());
+		}
+
+		debug!("RESTORE STAGING : {} files are staged in {:?}", staged.len(), self.path);
+		Ok(staged)
+	}
+
+	/// Move the staged files in place
+	/// Replaced files are kept in the staging until every file is moved,
+	/// the first failure puts them back and removes the files already moved.
+	/// # Arguments
+	/// * `names` - Staged file names, in the order of the swap
+	/// * `target_dir` - The sealed directory
+	pub fn swap_into(&self, names: &[String], target_dir: &str) -> Result<()> {
+		let previous_dir = self.path.join(PREVIOUS_DIR);
+		std::fs::create_dir_all(&previous_dir)?;
+
+		let mut swapped: Vec<(&String, bool)> = Vec::new();
+		for name in names {
+			let target = Path::new(target_dir).join(name);
+			let previous = previous_dir.join(name);
+			let replaced = target.exists();
+
+			let result = if replaced { std::fs::rename(&target, &previous) } else { Ok(()) }
+				.and_then(|_| std::fs::rename(self.path.join(STAGED_DIR).join(name), &target));
+
+			if let Err(err) = result {
+				if replaced && !target.exists() {
+					let _ = std::fs::rename(&previous, &target);
+				}
+				self.rollback(&swapped, target_dir);
+				return Err(anyhow!("can not swap {name} : {err:?}"));
+			}
+
+			swapped.push((name, replaced));
+		}
+
+		Ok(())
+	}
+
+	fn rollback(&self, swapped: &[(&String, bool)], target_dir: &str) {
+		for (name, replaced) in swapped.iter().rev() {
+			let target = Path::new(target_dir).join(name);
+			let result = if *replaced {
+				std::fs::rename(self.path.join(PREVIOUS_DIR).join(name), &target)
+			} else {
+				std::fs::remove_file(&target)
+			};
+
+			if let Err(err) = result {
+				error!("RESTORE STAGING : can not roll {name} back : {err:?}");
+			}
+		}
+
+		warn!("RESTORE STAGING : {} swapped files are rolled back", swapped.len());
+	}
+}
+
+impl Drop for RestoreStaging {
+	fn drop(&mut self) {
+		match std::fs::remove_dir_all(&self.path) {
+			Ok(_) => debug!("RESTORE STAGING : remove {:?}", self.path),
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => {},
+			Err(err) => warn!("RESTORE STAGING : can not remove {:?} : {err:?}", self.path),
+		}
+	}
+}
+
+/// Remove the staging of restores interrupted by a stop of the enclave, they are never swapped
+pub fn clear_restore_staging() -> Result<()> {
+	match std::fs::remove_dir_all(RESTORE_STAGING_PATH) {
+		Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+		_ => Ok(()),
+	}
+}
+
+/* ----------------------------------
+		RESTORE STAGING TEST
+----------------------------------*/
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn swap_rollback_test() {
+		let target_dir = "/tmp/staging_swap_test";
+		let _ = std::fs::remove_dir_all(target_dir);
+		std::fs::create_dir_all(target_dir).unwrap();
+		std::fs::write(format!("{target_dir}/nft_1_100.keyshare"), b"OLD").unwrap();
+
+		let staging = RestoreStaging { path: PathBuf::from("/tmp/staging_swap_test_staging") };
+		std::fs::create_dir_all(staging.path.join(STAGED_DIR)).unwrap();
+		std::fs::write(staging.path.join(STAGED_DIR).join("nft_1_100.keyshare"), b"NEW").unwrap();
+		std::fs::write(staging.path.join(STAGED_DIR).join("nft_2_100.keyshare"), b"NEW").unwrap();
+
+		// The last file was never staged, the swap is rolled back
+		let names: Vec<String> = ["nft_1_100.keyshare", "nft_2_100.keyshare", "missing.log"]
+			.iter()
+			.map(|name| name.to_string())
+			.collect();
+		assert!(staging.swap_into(&names, target_dir).is_err());
+		assert_eq!(std::fs::read(format!("{target_dir}/nft_1_100.keyshare")).unwrap(), b"OLD");
+		assert!(!Path::new(&format!("{target_dir}/nft_2_100.keyshare")).exists());
+
+		// Every staged file is swapped
+		std::fs::write(staging.path.join(STAGED_DIR).join("nft_1_100.keyshare"), b"NEW").unwrap();
+		std::fs::write(staging.path.join(STAGED_DIR).join("nft_2_100.keyshare"), b"NEW").unwrap();
+		assert!(staging.swap_into(&names[..2], target_dir).is_ok());
+		assert_eq!(std::fs::read(format!("{target_dir}/nft_1_100.keyshare")).unwrap(), b"NEW");
+		assert_eq!(std::fs::read(format!("{target_dir}/nft_2_100.keyshare")).unwrap(), b"NEW");
+
+		drop(staging);
+		assert!(!Path::new("/tmp/staging_swap_test_staging").exists());
+	}
+}
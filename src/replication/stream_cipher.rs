@@ -0,0 +1,532 @@
+use std::io;
+
+use aes_gcm::{
+	aead::{generic_array::GenericArray, Aead},
+	Aes256Gcm, KeyInit,
+};
+use anyhow::{anyhow, Result};
+use axum::body::Bytes;
+use futures::Stream;
+use rand::RngCore;
+use tokio::io::AsyncReadExt;
+use tracing::{debug, warn};
+
+/* ---------------------------------------
+	CHUNKED AUTHENTICATED STREAM ENCRYPTION
+------------------------------------------ */
+
+// Stream format :
+// HEADER : MAGIC(4) | VERSION(1) | WRAPPED_KEY_LENGTH(2) | ECIES(CONTENT_KEY)(n) | NONCE_PREFIX(7)
+// SPLIT  : SPLIT_MAGIC(4) | VERSION(1) | SHARE_COUNT(1) |
+//          [WRAPPED_KEY_LENGTH(2) | ECIES(KEY_SHARE)(n)]... | NONCE_PREFIX(7)
+// FRAME  : LAST_FLAG(1) | CIPHERTEXT_LENGTH(4) | AES-256-GCM(CHUNK)
+// Nonce of a frame is NONCE_PREFIX | FRAME_COUNTER(4) | LAST_FLAG(1), reordered, dropped or
+// truncated frames fail the authentication.
+// In a split header the content key is the XOR of all key shares, each share is wrapped for a
+// different custodian and the stream can only be decrypted when all of them are present.
+// A receiver refuses a version it does not know, instead of failing on the frames.
+
+const MAGIC: &[u8; 4] = b"TSE1";
+const SPLIT_MAGIC: &[u8; 4] = b"TSE2";
+// Version of the stream format, written after the magic
+pub const STREAM_VERSION: u8 = 1;
+const CONTENT_KEY_LENGTH: usize = 32;
+// Maximum number of custodians of a split content key
+pub const MAX_KEY_SHARES: usize = 16;
+const NONCE_PREFIX_LENGTH: usize = 7;
+const FRAME_HEADER_LENGTH: usize = 5;
+const TAG_LENGTH: usize = 16;
+
+// Plaintext size of a frame
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+fn frame_nonce(nonce_prefix: &[u8; NONCE_PREFIX_LENGTH], counter: u32, last: bool) -> [u8; 12] {
+	let mut nonce = [0u8; 12];
+	nonce[..NONCE_PREFIX_LENGTH].copy_from_slice(nonce_prefix);
+	nonce[NONCE_PREFIX_LENGTH..11].copy_from_slice(&counter.to_be_bytes());
+	nonce[11] = last as u8;
+	nonce
+}
+
+fn random_key() -> [u8; CONTENT_KEY_LENGTH] {
+	let mut key = [0u8; CONTENT_KEY_LENGTH];
+	rand::rngs::OsRng.fill_bytes(&mut key);
+	key
+}
+
+// Try every private-key on a wrapped key
+fn unwrap_key(private_keys: &[Vec<u8>], wrapped_key: &[u8]) -> Option<Vec<u8>> {
+	private_keys
+		.iter()
+		.find_map(|private_key| ecies::decrypt(private_key, wrapped_key).ok())
+}
+
+/// Encrypt a stream chunk by chunk, for a receiver ECIES public-key
+pub struct StreamEncryptor {
+	cipher: Aes256Gcm,
+	nonce_prefix: [u8; NONCE_PREFIX_LENGTH],
+	counter: u32,
+	finished: bool,
+}
+
+impl StreamEncryptor {
+	/// Create an encryptor with a random content key
+	/// # Arguments
+	/// * `receiver_public_key` - Serialized secp256k1 public-key of the receiver
+	/// # Returns
+	/// * `(StreamEncryptor, Vec<u8>)` - The encryptor and the stream header to send first
+	pub fn new(receiver_public_key: &[u8]) -> Result<(StreamEncryptor, Vec<u8>)> {
+		let content_key = random_key();
+
+		let mut nonce_prefix = [0u8; NONCE_PREFIX_LENGTH];
+		rand::rngs::OsRng.fill_bytes(&mut nonce_prefix);
+
+		let wrapped_key = ecies::encrypt(receiver_public_key, &content_key)
+			.map_err(|err| anyhow!("unable to wrap the content key : {err:?}"))?;
+
+		let cipher = Aes256Gcm::new_from_slice(&content_key)
+			.map_err(|err| anyhow!("invalid content key : {err:?}"))?;
+
+		let mut header =
+			Vec::with_capacity(MAGIC.len() + 3 + wrapped_key.len() + NONCE_PREFIX_LENGTH);
+		header.extend_from_slice(MAGIC);
+		header.push(STREAM_VERSION);
+		header.extend_from_slice(&(wrapped_key.len() as u16).to_be_bytes());
+		header.extend_from_slice(&wrapped_key);
+		header.extend_from_slice(&nonce_prefix);
+
+		Ok((StreamEncryptor { cipher, nonce_prefix, counter: 0, finished: false }, header))
+	}
+
+	/// Create an encryptor whose content key is split between several receivers,
+	/// all of them are needed to decrypt the stream
+	/// # Arguments
+	/// * `receiver_public_keys` - Serialized secp256k1 public-keys of the custodians
+	/// # Returns
+	/// * `(StreamEncryptor, Vec<u8>)` - The encryptor and the stream header to send first
+	pub fn new_split(receiver_public_keys: &[Vec<u8>]) -> Result<(StreamEncryptor, Vec<u8>)> {
+		match receiver_public_keys.len() {
+			0 => return Err(anyhow!("no receiver public-key is provided")),
+			1 => return StreamEncryptor::new(&receiver_public_keys[0]),
+			count if count > MAX_KEY_SHARES =>
+				return Err(anyhow!("too many receivers {count}, maximum is {MAX_KEY_SHARES}")),
+			_ => {},
+		}
+
+		let content_key = random_key();
+
+		let mut nonce_prefix = [0u8; NONCE_PREFIX_LENGTH];
+		rand::rngs::OsRng.fill_bytes(&mut nonce_prefix);
+
+		let mut header = Vec::new();
+		header.extend_from_slice(SPLIT_MAGIC);
+		header.push(STREAM_VERSION);
+		header.push(receiver_public_keys.len() as u8);
+
+		// The last share completes the XOR of the random shares to the content key
+		let mut last_share = content_key;
+		for (index, public_key) in receiver_public_keys.iter().enumerate() {
+			let share = if index + 1 == receiver_public_keys.len() {
+				last_share
+			} else {
+				let share = random_key();
+				last_share.iter_mut().zip(share.iter()).for_each(|(k, s)| *k ^= s);
+				share
+			};
+
+			let wrapped_share = ecies::encrypt(public_key, &share)
+				.map_err(|err| anyhow!("unable to wrap the key share {index} : {err:?}"))?;
+
+			header.extend_from_slice(&(wrapped_share.len() as u16).to_be_bytes());
+			header.extend_from_slice(&wrapped_share);
+		}
+
+		header.extend_from_slice(&nonce_prefix);
+
+		let cipher = Aes256Gcm::new_from_slice(&content_key)
+			.map_err(|err| anyhow!("invalid content key : {err:?}"))?;
+
+		Ok((StreamEncryptor { cipher, nonce_prefix, counter: 0, finished: false }, header))
+	}
+
+	/// Encrypt the next chunk of the stream
+	/// # Arguments
+	/// * `chunk` - Plaintext, up to STREAM_CHUNK_SIZE bytes
+	/// * `last` - Marks the end of the stream
+	pub fn encrypt_chunk(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>> {
+		if self.finished {
+			return Err(anyhow!("stream is already finished"));
+		}
+
+		if chunk.len() > STREAM_CHUNK_SIZE {
+			return Err(anyhow!("chunk is larger than {STREAM_CHUNK_SIZE} bytes"));
+		}
+
+		let nonce = frame_nonce(&self.nonce_prefix, self.counter, last);
+		let ciphertext = self
+			.cipher
+			.encrypt(GenericArray::from_slice(&nonce), chunk)
+			.map_err(|err| anyhow!("unable to encrypt the chunk : {err:?}"))?;
+
+		self.counter = self
+			.counter
+			.checked_add(1)
+			.ok_or_else(|| anyhow!("too many chunks in the stream"))?;
+		self.finished = last;
+
+		let mut frame = Vec::with_capacity(FRAME_HEADER_LENGTH + ciphertext.len());
+		frame.push(last as u8);
+		frame.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+		frame.extend_from_slice(&ciphertext);
+
+		Ok(frame)
+	}
+}
+
+/// Decrypt a stream incrementally, as the bytes are received
+pub struct StreamDecryptor {
+	private_keys: Vec<Vec<u8>>,
+	cipher: Option<Aes256Gcm>,
+	nonce_prefix: [u8; NONCE_PREFIX_LENGTH],
+	counter: u32,
+	buffer: Vec<u8>,
+	finished: bool,
+}
+
+impl StreamDecryptor {
+	/// # Arguments
+	/// * `private_key` - Serialized secp256k1 private-key of the receiver
+	pub fn new(private_key: &[u8]) -> StreamDecryptor {
+		StreamDecryptor::new_split(&[private_key.to_vec()])
+	}
+
+	/// Decryptor for a stream whose content key may be split between custodians
+	/// # Arguments
+	/// * `private_keys` - Serialized secp256k1 private-keys of the custodians, in any order
+	pub fn new_split(private_keys: &[Vec<u8>]) -> StreamDecryptor {
+		StreamDecryptor {
+			private_keys: private_keys.to_vec(),
+			cipher: None,
+			nonce_prefix: [0u8; NONCE_PREFIX_LENGTH],
+			counter: 0,
+			buffer: Vec::new(),
+			finished: false,
+		}
+	}
+
+	/// Feed the received bytes
+	/// # Returns
+	/// * `Vec<u8>` - Plaintext of the frames completed by these bytes
+	pub fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+		if self.finished && !data.is_empty() {
+			return Err(anyhow!("data received after the last frame"));
+		}
+
+		self.buffer.extend_from_slice(data);
+
+		let mut plaintext = Vec::new();
+		let mut consumed = 0;
+
+		if self.cipher.is_none() {
+			match self.parse_header()? {
+				Some(header_length) => consumed = header_length,
+				None => return Ok(plaintext),
+			}
+		}
+
+		while !self.finished {
+			let remaining = &self.buffer[consumed..];
+			if remaining.len() < FRAME_HEADER_LENGTH {
+				break;
+			}
+
+			let last = match remaining[0] {
+				0 => false,
+				1 => true,
+				flag => return Err(anyhow!("invalid frame flag {flag}")),
+			};
+
+			let mut length_bytes = [0u8; 4];
+			length_bytes.copy_from_slice(&remaining[1..FRAME_HEADER_LENGTH]);
+			let length = u32::from_be_bytes(length_bytes) as usize;
+
+			if length > STREAM_CHUNK_SIZE + TAG_LENGTH {
+				return Err(anyhow!("frame length {length} is larger than a chunk"));
+			}
+
+			if remaining.len() < FRAME_HEADER_LENGTH + length {
+				break;
+			}
+
+			let ciphertext = &remaining[FRAME_HEADER_LENGTH..FRAME_HEADER_LENGTH + length];
+			let nonce = frame_nonce(&self.nonce_prefix, self.counter, last);
+
+			let cipher = match &self.cipher {
+				Some(cipher) => cipher,
+				None => return Err(anyhow!("stream header is not parsed")),
+			};
+
+			let chunk = cipher
+				.decrypt(GenericArray::from_slice(&nonce), ciphertext)
+				.map_err(|_| anyhow!("authentication failed for frame {}", self.counter))?;
+
+			plaintext.extend_from_slice(&chunk);
+			consumed += FRAME_HEADER_LENGTH + length;
+
+			self.counter = self
+				.counter
+				.checked_add(1)
+				.ok_or_else(|| anyhow!("too many frames in the stream"))?;
+			self.finished = last;
+		}
+
+		self.buffer.drain(..consumed);
+
+		if self.finished && !self.buffer.is_empty() {
+			return Err(anyhow!("data received after the last frame"));
+		}
+
+		Ok(plaintext)
+	}
+
+	/// Check the end of the received stream
+	pub fn finish(&self) -> Result<()> {
+		if !self.finished {
+			return Err(anyhow!("stream is truncated, the last frame is not received"));
+		}
+
+		Ok(())
+	}
+
+	// Returns the header length when the whole header is received
+	fn parse_header(&mut self) -> Result<Option<usize>> {
+		if self.buffer.len() < MAGIC.len() + 2 {
+			return Ok(None);
+		}
+
+		let split = if self.buffer[..MAGIC.len()] == MAGIC[..] {
+			false
+		} else if self.buffer[..SPLIT_MAGIC.len()] == SPLIT_MAGIC[..] {
+			true
+		} else {
+			return Err(anyhow!("invalid stream magic"));
+		};
+
+		let version = self.buffer[MAGIC.len()];
+		if version != STREAM_VERSION {
+			return Err(anyhow!(
+				"unsupported stream version {version}, supported version is {STREAM_VERSION}"
+			));
+		}
+
+		let (share_count, mut offset) = if split {
+			let count = self.buffer[MAGIC.len() + 1] as usize;
+			if !(2..=MAX_KEY_SHARES).contains(&count) {
+				return Err(anyhow!("invalid number of key shares {count}"));
+			}
+			(count, MAGIC.len() + 2)
+		} else {
+			(1, MAGIC.len() + 1)
+		};
+
+		let mut wrapped_keys = Vec::with_capacity(share_count);
+		for _ in 0..share_count {
+			if self.buffer.len() < offset + 2 {
+				return Ok(None);
+			}
+
+			let wrapped_length =
+				u16::from_be_bytes([self.buffer[offset], self.buffer[offset + 1]]) as usize;
+			offset += 2;
+
+			if self.buffer.len() < offset + wrapped_length {
+				return Ok(None);
+			}
+
+			wrapped_keys.push(offset..offset + wrapped_length);
+			offset += wrapped_length;
+		}
+
+		let header_length = offset + NONCE_PREFIX_LENGTH;
+		if self.buffer.len() < header_length {
+			return Ok(None);
+		}
+
+		let mut content_key = [0u8; CONTENT_KEY_LENGTH];
+		for (index, range) in wrapped_keys.into_iter().enumerate() {
+			let share = match unwrap_key(&self.private_keys, &self.buffer[range]) {
+				Some(share) => share,
+				None if share_count == 1 => return Err(anyhow!("unable to unwrap the content key")),
+				None =>
+					return Err(anyhow!(
+						"unable to unwrap the key share {index}, a custodian private-key is missing"
+					)),
+			};
+
+			if share.len() != CONTENT_KEY_LENGTH {
+				return Err(anyhow!("invalid content key length {}", share.len()));
+			}
+
+			content_key.iter_mut().zip(share.iter()).for_each(|(k, s)| *k ^= s);
+		}
+
+		let cipher = Aes256Gcm::new_from_slice(&content_key)
+			.map_err(|err| anyhow!("invalid content key : {err:?}"))?;
+
+		self.nonce_prefix
+			.copy_from_slice(&self.buffer[header_length - NONCE_PREFIX_LENGTH..header_length]);
+		self.cipher = Some(cipher);
+
+		debug!(
+			"STREAM CIPHER : stream header version {version} is parsed, key shares = {share_count}"
+		);
+
+		Ok(Some(header_length))
+	}
+}
+
+// Temporary plain file, removed when the stream ends or is dropped by a disconnected client
+struct RemoveOnDrop(String);
+
+impl Drop for RemoveOnDrop {
+	fn drop(&mut self) {
+		if let Err(err) = std::fs::remove_file(&self.0) {
+			warn!("STREAM CIPHER : can not remove temporary file {} : {err:?}", self.0);
+		}
+	}
+}
+
+/// Stream a file encrypted chunk by chunk, with constant memory
+/// The file is removed when the stream is finished or dropped
+/// # Arguments
+/// * `file` - The opened plain file
+/// * `file_path` - Path of the plain file, to remove it
+/// * `encryptor` - The stream encryptor
+/// * `header` - The stream header created with the encryptor
+pub fn encrypt_file_stream(
+	file: tokio::fs::File,
+	file_path: String,
+	encryptor: StreamEncryptor,
+	header: Vec<u8>,
+) -> impl Stream<Item = Result<Bytes, io::Error>> {
+	let initial = Some((file, encryptor, Some(header), RemoveOnDrop(file_path)));
+
+	futures::stream::unfold(initial, |state| async move {
+		let (mut file, mut encryptor, header, guard) = state?;
+
+		if let Some(header) = header {
+			return Some((Ok(Bytes::from(header)), Some((file, encryptor, None, guard))));
+		}
+
+		let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+		let mut filled = 0;
+		while filled < STREAM_CHUNK_SIZE {
+			match file.read(&mut buffer[filled..]).await {
+				Ok(0) => break,
+				Ok(n) => filled += n,
+				Err(err) => return Some((Err(err), None)),
+			}
+		}
+
+		// A partial chunk is the end of file
+		let last = filled < STREAM_CHUNK_SIZE;
+
+		match encryptor.encrypt_chunk(&buffer[..filled], last) {
+			Ok(frame) => {
+				let next = if last { None } else { Some((file, encryptor, None, guard)) };
+				Some((Ok(Bytes::from(frame)), next))
+			},
+			Err(err) => Some((Err(io::Error::other(err.to_string())), None)),
+		}
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use ecies::utils::generate_keypair;
+
+	#[test]
+	fn stream_roundtrip_test() {
+		let (sk, pk) = generate_keypair();
+		let (mut encryptor, header) = StreamEncryptor::new(&pk.serialize()).unwrap();
+
+		let data: Vec<u8> = (0..STREAM_CHUNK_SIZE * 2 + 100).map(|i| i as u8).collect();
+		let mut stream = header;
+		for (index, chunk) in data.chunks(STREAM_CHUNK_SIZE).enumerate() {
+			stream.extend(encryptor.encrypt_chunk(chunk, index == 2).unwrap());
+		}
+
+		// Receive in small pieces
+		let mut decryptor = StreamDecryptor::new(&sk.serialize());
+		let mut plaintext = Vec::new();
+		for piece in stream.chunks(1000) {
+			plaintext.extend(decryptor.update(piece).unwrap());
+		}
+
+		assert!(decryptor.finish().is_ok());
+		assert_eq!(plaintext, data);
+	}
+
+	#[test]
+	fn stream_tamper_test() {
+		let (sk, pk) = generate_keypair();
+		let (mut encryptor, header) = StreamEncryptor::new(&pk.serialize()).unwrap();
+
+		let first = encryptor.encrypt_chunk(&[1u8; 100], false).unwrap();
+		let last = encryptor.encrypt_chunk(&[2u8; 100], true).unwrap();
+
+		// Truncated stream
+		let mut decryptor = StreamDecryptor::new(&sk.serialize());
+		decryptor.update(&header).unwrap();
+		decryptor.update(&first).unwrap();
+		assert!(decryptor.finish().is_err());
+
+		// Reordered frames
+		let mut decryptor = StreamDecryptor::new(&sk.serialize());
+		decryptor.update(&header).unwrap();
+		assert!(decryptor.update(&last).is_err());
+
+		// Unknown format version
+		let mut future = header.clone();
+		future[MAGIC.len()] = STREAM_VERSION + 1;
+		let mut decryptor = StreamDecryptor::new(&sk.serialize());
+		assert!(decryptor.update(&future).is_err());
+
+		// Forged last flag
+		let mut forged = first.clone();
+		forged[0] = 1;
+		let mut decryptor = StreamDecryptor::new(&sk.serialize());
+		decryptor.update(&header).unwrap();
+		assert!(decryptor.update(&forged).is_err());
+	}
+
+	#[test]
+	fn stream_split_key_test() {
+		let custodians: Vec<_> = (0..3).map(|_| generate_keypair()).collect();
+		let public_keys: Vec<Vec<u8>> =
+			custodians.iter().map(|(_, pk)| pk.serialize().to_vec()).collect();
+		let private_keys: Vec<Vec<u8>> =
+			custodians.iter().map(|(sk, _)| sk.serialize().to_vec()).collect();
+
+		let (mut encryptor, header) = StreamEncryptor::new_split(&public_keys).unwrap();
+		let mut stream = header.clone();
+		stream.extend(encryptor.encrypt_chunk(&[7u8; 1000], true).unwrap());
+
+		// All custodians, in any order
+		let mut reversed = private_keys.clone();
+		reversed.reverse();
+		let mut decryptor = StreamDecryptor::new_split(&reversed);
+		let plaintext = decryptor.update(&stream).unwrap();
+		assert!(decryptor.finish().is_ok());
+		assert_eq!(plaintext, vec![7u8; 1000]);
+
+		// A missing custodian
+		let mut decryptor = StreamDecryptor::new_split(&private_keys[..2]);
+		assert!(decryptor.update(&header).is_err());
+
+		// A single key can not decrypt a split stream
+		let mut decryptor = StreamDecryptor::new(&private_keys[0]);
+		assert!(decryptor.update(&header).is_err());
+	}
+}
@@ -9,6 +9,7 @@ use std::{
 	net::SocketAddr,
 	os::unix::prelude::PermissionsExt,
 	path::Path,
+	time::Instant,
 };
 
 use axum::{
@@ -23,11 +24,12 @@ use reqwest::tls;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use ecies::{decrypt, encrypt, utils::generate_keypair};
+use ecies::utils::generate_keypair;
 use rand::RngCore;
 
 use subxt::{
 	blocks::{BlockBody, ExtrinsicEvents},
+	events::StaticEvent,
 	ext::sp_core::{
 		crypto::{PublicError, Ss58Codec},
 		sr25519::{self, Signature},
@@ -39,39 +41,49 @@ use subxt::{
 	OnlineClient, PolkadotConfig,
 };
 
-use tokio_util::io::ReaderStream;
-
 use tracing::{debug, error, info, trace, warn};
 use zip::result::ZipError;
 
 use crate::{
-	attestation::ra::{
-		get_quote_content, write_user_report_data, QuoteResponse, QUOTE_REPORT_DATA_LENGTH,
-		QUOTE_REPORT_DATA_OFFSET,
+	attestation::{
+		dcap::{self, ReportBody},
+		policy::verify_enclave_policy,
+		ra::{get_quote_content, write_user_report_data, QuoteResponse},
 	},
 	constants::{
-		ATTESTATION_SERVER_URL, MAX_BLOCK_VARIATION, MAX_VALIDATION_PERIOD, SEALPATH,
-		SYNC_STATE_FILE, VERSION,
+		ATTESTATION_SERVER_URL, CRAWL_CHECKPOINT_INTERVAL, CRAWL_PARALLELISM, MAX_BLOCK_VARIATION,
+		MAX_SYNC_PAGE_SIZE, MAX_VALIDATION_PERIOD, SEALPATH, SYNC_PAGE_SIZE, VERSION,
 	},
 	core::{
-		chain::{
-			ternoa,
-			ternoa::nft::events::{CapsuleSynced, SecretNFTSynced},
-		},
+		chain::{get_verified_chain_time, ternoa},
 		helper::{Availability, NftType},
+		runtime::{
+			enclave_account_operator, enclave_cluster_id, CapsuleShardAdded, CapsuleSynced,
+			SecretNFTSynced, ShardAdded,
+		},
+	},
+	replication::{
+		reassignment::handle_identity_change,
+		scheduler::{rank_peers, record_failure, record_health, record_success},
+		stream_cipher::{encrypt_file_stream, StreamDecryptor, StreamEncryptor},
+		sync_state::{
+			clear_sync_entries, load_sync_state, mark_sync_pending, save_crawl_checkpoint,
+			settle_sync_attempt, update_sync_state, SyncAttempt,
+		},
+		zipdir::{add_list_zip, sorted_keyshares, zip_extract},
 	},
-	replication::zipdir::{add_list_zip, zip_extract},
 	server::{
 		http_server::HealthResponse,
 		state::{
 			get_accountid, get_blocknumber, get_chain_api, get_clusters, get_identity, get_keypair,
-			get_nft_availability, set_chain_api_renew, set_clusters, set_identity,
-			set_nft_availability, SharedState,
+			get_nft_availability, set_chain_api_renew, set_clusters, set_crawl_progress,
+			set_identity, set_nft_availability, SharedState,
 		},
 	},
 };
 
 use anyhow::{anyhow, Result};
+use futures::StreamExt;
 
 /* ---------------------------------------
 	SYNC NEW KEYSHARES TO OTHER ENCLAVES
@@ -129,6 +141,24 @@ pub struct FetchIdResponse {
 	signature: String,
 }
 
+/// Data of the keyshare count token, the request carries no other data
+const KEYSHARE_COUNT_DATA: &str = "keyshare-count";
+
+/// Keyshare Count Request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KeyshareCountPacket {
+	enclave_account: String,
+	auth_token: String,
+	signature: String,
+}
+
+/// Keyshare Count Response
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KeyshareCountResponse {
+	pub keyshare_count: u32,
+	pub block_number: u32,
+}
+
 /* ----------------------------------
 AUTHENTICATION TOKEN IMPLEMENTATION
 ----------------------------------*/
@@ -216,7 +246,7 @@ fn get_signature(signature: String) -> Result<Signature, FromHexError> {
 	}
 }
 
-fn verify_signature(account_id: &str, signature: String, message: &[u8]) -> bool {
+pub fn verify_signature(account_id: &str, signature: String, message: &[u8]) -> bool {
 	match get_public_key(account_id) {
 		Ok(pk) => match get_signature(signature) {
 			Ok(val) => sr25519::Pair::verify(&val, message, &pk),
@@ -240,6 +270,73 @@ async fn update_health_status(state: &SharedState, message: String) {
 	debug!("Maintenance state is set.");
 }
 
+/// Authenticate a request of a slot enclave, its signed token is bound to the data
+/// # Arguments
+/// * `state` - StateConfig
+/// * `addr` - Address of the requester
+/// * `enclave_account` - Account of the requester enclave
+/// * `auth_token` - Serialized authentication token
+/// * `signature` - Signature of the token by the requester
+/// * `data` - Data of the request, its hash is in the token
+/// # Returns
+/// * The requester enclave of the slot and its token
+async fn authenticate_requester(
+	state: &SharedState,
+	addr: SocketAddr,
+	enclave_account: &str,
+	auth_token: &str,
+	signature: &str,
+	data: &str,
+) -> Result<((u32, Enclave), AuthenticationToken), String> {
+	let current_block_number = get_blocknumber(state).await;
+
+	debug!("AUTHENTICATION : START CLUSTER DISCOVERY");
+	let slot_enclaves = slot_discovery(state).await;
+
+	debug!("AUTHENTICATION : VERIFY ACCOUNT ID");
+	let requester = match verify_account_id(slot_enclaves, &enclave_account.to_string()) {
+		Some(enclave) => enclave,
+		None => return Err(format!("Error : Requester is not authorized, address: {}, ", addr)),
+	};
+
+	let mut auth = auth_token.to_string();
+
+	if auth.starts_with("<Bytes>") && auth.ends_with("</Bytes>") {
+		auth = match auth.strip_prefix("<Bytes>") {
+			Some(stripped) => stripped.to_owned(),
+			_ => return Err("Strip Token prefix error".to_string()),
+		};
+
+		auth = match auth.strip_suffix("</Bytes>") {
+			Some(stripped) => stripped.to_owned(),
+			_ => return Err("Strip Token suffix error".to_string()),
+		}
+	}
+
+	let token: AuthenticationToken = match serde_json::from_str(&auth) {
+		Ok(token) => token,
+		Err(err) => return Err(format!("Error : Authentication token is not parsable : {}", err)),
+	};
+
+	debug!("AUTHENTICATION : VERIFY SIGNATURE");
+	if !verify_signature(enclave_account, signature.to_string(), auth_token.as_bytes()) {
+		return Err("Invalid Signature".to_string());
+	}
+
+	debug!("AUTHENTICATION : Validating the authentication token");
+	let validity = token.is_valid(current_block_number);
+	match validity {
+		ValidationResult::Success => debug!("AUTHENTICATION : Authentication token is valid."),
+		_ => return Err(format!("Authentication Token is not valid, or expired : {:?}", validity)),
+	}
+
+	if token.data_hash != sha256::digest(data.as_bytes()) {
+		return Err("Mismatch Data Hash".to_string());
+	}
+
+	Ok((requester, token))
+}
+
 pub async fn error_handler(message: String, _state: &SharedState) -> impl IntoResponse {
 	error!(message);
 	//update_health_status(state, String::new()).await;
@@ -265,89 +362,23 @@ pub async fn sync_keyshares(
 
 	let current_block_number = get_blocknumber(&state).await;
 
-	debug!("SYNC KEYSHARES : START CLUSTER DISCOVERY");
-	let slot_enclaves = slot_discovery(&state).await;
-
-	debug!("SYNC KEYSHARES : VERIFY ACCOUNT ID");
-	let requester = match verify_account_id(slot_enclaves, &request.enclave_account) {
-		Some(enclave) => enclave,
-		None => {
-			let message = format!(
-				"SYNC KEYSHARES : Error : Requester is not authorized, address: {}, ",
-				addr
-			);
-
-			return error_handler(message, &state).await.into_response();
-		},
-	};
-
-	let mut auth = request.auth_token.clone();
-
-	if auth.starts_with("<Bytes>") && auth.ends_with("</Bytes>") {
-		auth = match auth.strip_prefix("<Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return error_handler(
-					"SYNC KEYSHARES : Strip Token prefix error".to_string(),
-					&state,
-				)
-				.await
-				.into_response(),
-		};
-
-		auth = match auth.strip_suffix("</Bytes>") {
-			Some(stripped) => stripped.to_owned(),
-			_ =>
-				return error_handler(
-					"SYNC KEYSHARES : Strip Token suffix error".to_string(),
-					&state,
-				)
+	let (requester, auth_token) = match authenticate_requester(
+		&state,
+		addr,
+		&request.enclave_account,
+		&request.auth_token,
+		&request.signature,
+		&request.nftid_vec,
+	)
+	.await
+	{
+		Ok(authenticated) => authenticated,
+		Err(message) =>
+			return error_handler(format!("SYNC KEYSHARES : {message}"), &state)
 				.await
 				.into_response(),
-		}
-	}
-
-	let auth_token: AuthenticationToken = match serde_json::from_str(&auth) {
-		Ok(token) => token,
-		Err(err) => {
-			let message =
-				format!("SYNC KEYSHARES : Error : Authentication token is not parsable : {}", err);
-			return error_handler(message, &state).await.into_response();
-		},
 	};
 
-	debug!("SYNC KEYSHARES : VERIFY SIGNATURE");
-	if !verify_signature(
-		&request.enclave_account.clone(),
-		request.signature.clone(),
-		request.auth_token.as_bytes(),
-	) {
-		return error_handler("SYNC KEYSHARES : Invalid Signature".to_string(), &state)
-			.await
-			.into_response();
-	}
-
-	debug!("SYNC KEYSHARES : Validating the authentication token");
-	let validity = auth_token.is_valid(current_block_number);
-	match validity {
-		ValidationResult::Success => debug!("SYNC KEYSHARES : Authentication token is valid."),
-		_ => {
-			let message = format!(
-				"SYNC KEYSHARES : Authentication Token is not valid, or expired : {:?}",
-				validity
-			);
-			return error_handler(message, &state).await.into_response();
-		},
-	}
-
-	let hash = sha256::digest(request.nftid_vec.as_bytes());
-
-	if auth_token.data_hash != hash {
-		return error_handler("SYNC KEYSHARES : Mismatch Data Hash".to_string(), &state)
-			.await
-			.into_response();
-	}
-
 	let nftidv: Vec<String> = match serde_json::from_str(&request.nftid_vec) {
 		Ok(v) => v,
 		Err(err) => {
@@ -358,6 +389,18 @@ pub async fn sync_keyshares(
 
 	// [future reliability] check nftids , is empty, are they in range, ...
 
+	// Wildcard is ["*"] for all keyshares, or ["*", page_size, from_nftid] for one page
+	if nftidv.len() > 1 && nftidv[0] == "*" {
+		let valid_page = nftidv.len() == 3 &&
+			matches!(nftidv[1].parse::<u32>(), Ok(size) if size > 0 && size <= MAX_SYNC_PAGE_SIZE) &&
+			nftidv[2].parse::<u32>().is_ok();
+
+		if !valid_page {
+			let message = format!("SYNC KEYSHARES : invalid wildcard pagination : {nftidv:?}");
+			return error_handler(message, &state).await.into_response();
+		}
+	}
+
 	// Create a client
 	let client = match reqwest::Client::builder()
 		// This is for development, will be removed for production certs
@@ -506,145 +549,61 @@ pub async fn sync_keyshares(
 		quote_body
 	);
 
-	let account_keypair = get_keypair(&state).await;
-	let account_id = get_accountid(&state).await;
-	let signature = account_keypair.sign(quote_body.data.as_bytes());
-
-	let attestation_request_body = json!({
-		"account_id": account_id,
-		"data": quote_body.data,
-		"signature": format!("0x{:?}", signature),
-	})
-	.to_string();
-
-	let attest_response = match client
-		.post(ATTESTATION_SERVER_URL)
-		.body(attestation_request_body)
-		.header(header::CONTENT_TYPE, "application/json")
-		.send()
-		.await
+	let report = match verify_requester_quote(
+		&state,
+		&client,
+		&quote_body,
+		&requester.1.enclave_url,
+	)
+	.await
 	{
-		Ok(resp) => resp,
-		Err(err) => {
-			let message = format!(
-					"SYNC KEYSHARES : Attestation : can not get response from attestation server : {err:?}");
-			sentry::with_scope(
-				|scope| {
-					scope.set_tag("sync-keyshare", "attestation");
-				},
-				|| sentry::capture_message(&message, sentry::Level::Error),
-			);
-			return error_handler(message, &state).await.into_response();
-		},
-	};
-
-	let attestation_json = match attest_response.text().await {
-		Ok(resp) => resp,
-		Err(err) => {
-			let message = format!("Error getting attestation response {err:?}");
-			sentry::with_scope(
-				|scope| {
-					scope.set_tag("sync-keyshare", "attestation");
-				},
-				|| sentry::capture_message(&message, sentry::Level::Error),
-			);
-			return error_handler(message, &state).await.into_response();
-		},
+		Ok(report) => report,
+		Err(message) => return error_handler(message, &state).await.into_response(),
 	};
 
-	trace!(
-		"SYNC KEYSHARES : Attestation Result for url : {} is \n {:#?}\n\n",
-		requester.1.enclave_url,
-		attestation_json,
-	);
-
-	let attest_dynamic_json: Value = match serde_json::from_str::<Value>(&attestation_json) {
-		Ok(dj) => dj,
-		Err(err) => {
-			let message =
-				format!("SYNC KEYSHARES : Error deserializing attestation response {err:?}");
-			sentry::with_scope(
-				|scope| {
-					scope.set_tag("sync-keyshare", "attestation");
-				},
-				|| sentry::capture_message(&message, sentry::Level::Error),
-			);
-			return error_handler(message, &state).await.into_response();
-		},
-	};
+	// Only approved enclave binaries can receive keyshares
+	if let Err(message) = verify_enclave_policy(&state, &report).await {
+		let message = format!("SYNC KEYSHARES : {} : {message}", requester.1.enclave_url);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("sync-keyshare", "policy");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return error_handler(message, &state).await.into_response();
+	}
 
-	trace!("SYNC KEYSHARES : Report map : {}", attest_dynamic_json["report"]);
-	let report_body_string = serde_json::to_string(&attest_dynamic_json["report"]);
-	trace!("SYNC KEYSHARES : Stringified report map : {:?}", report_body_string);
+	let report_data = hex::encode(report.report_data);
 
-	let report: String = match report_body_string {
-		Ok(report) => report,
-		Err(err) => {
-			let message = format!(
-				"SYNC KEYSHARES : Error deserializing attestation report as String {err:?}"
-			);
-			sentry::with_scope(
-				|scope| {
-					scope.set_tag("sync-keyshare", "attestation");
-				},
-				|| sentry::capture_message(&message, sentry::Level::Error),
-			);
-			return error_handler(message, &state).await.into_response();
-		},
-	};
+	// Verify Report_Data
 
-	let attestation_server_account: String =
-		match serde_json::from_value(attest_dynamic_json["account"].clone()) {
-			Ok(attest_account) => attest_account,
-			Err(err) => {
-				let message =
-					format!("SYNC KEYSHARES : Error deserializing attestation account {err:?}");
-				sentry::with_scope(
-					|scope| {
-						scope.set_tag("sync-keyshare", "attestation");
-					},
-					|| sentry::capture_message(&message, sentry::Level::Error),
-				);
-				return error_handler(message, &state).await.into_response();
-			},
-		};
+	let token = format!(
+		"{}_{}_{}",
+		request.enclave_account, auth_token.block_number, request.encryption_account
+	);
 
-	let attestation_server_signature: String =
-		match serde_json::from_value(attest_dynamic_json["signature"].clone()) {
-			Ok(report) => report,
-			Err(err) => {
-				let message =
-					format!("SYNC KEYSHARES : Error deserializing attestation signature {err:?}");
-				sentry::with_scope(
-					|scope| {
-						scope.set_tag("sync-keyshare", "attestation");
-					},
-					|| sentry::capture_message(&message, sentry::Level::Error),
-				);
-				return error_handler(message, &state).await.into_response();
-			},
-		};
+	debug!("SYNC KEYSHARES : report_data token = {token}");
 
-	// Verify signature of Attestation Server response
 	if !verify_signature(
-		&attestation_server_account,
-		attestation_server_signature,
-		report.as_bytes(),
+		&request.enclave_account.clone(),
+		report_data.to_string(),
+		token.as_bytes(),
 	) {
-		let message = "SYNC KEYSHARES : Invalid Report Signature".to_string();
+		let message = "SYNC KEYSHARES : Invalid Signature".to_string();
 		sentry::with_scope(
 			|scope| {
-				scope.set_tag("sync-keyshare", "attestation");
+				scope.set_tag("sync-keyshare", "quote");
 			},
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
 		return error_handler(message, &state).await.into_response();
 	}
 
-	if !crate::replication::metric::verify_account_id(&state, &attestation_server_account).await {
-		let message = format!(
-			"SYNC KEYSHARES : Invalid Attestation Server, It is not registered on blockchain , account : {attestation_server_account}"
-		);
+	let parse_token: Vec<&str> = token.split('_').collect();
+	if request.enclave_account != parse_token[0] {
+		let message =
+			"SYNC KEYSHARES : TOKEN : Mismatch between <Requester Account> and <Report Data Token>"
+				.to_string();
 		sentry::with_scope(
 			|scope| {
 				scope.set_tag("sync-keyshare", "attestation");
@@ -652,21 +611,385 @@ pub async fn sync_keyshares(
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
 		return error_handler(message, &state).await.into_response();
-	}
-
-	// Deserialize again to Json
-	let report: Value = match serde_json::from_value(attest_dynamic_json["report"].clone()) {
-		Ok(report) => report,
-		Err(err) => {
-			let message =
-				format!("SYNC KEYSHARES : Error deserializing attestation report as Value {err:?}");
-			sentry::with_scope(
-				|scope| {
-					scope.set_tag("sync-keyshare", "attestation");
-				},
+	} else {
+		match parse_token[1].parse::<u32>() {
+			Ok(token_block) => {
+				if (token_block != auth_token.block_number) ||
+					(current_block_number < token_block) ||
+					(current_block_number - token_block > 5)
+				{
+					let message = format!("SYNC KEYSHARES : TOKEN : Incompatible/Outdated block numbers :\n Current blocknumber: {current_block_number} >~ Token blocknumber: {token_block} == Request blocknumber: {} ?", auth_token.block_number);
+					sentry::with_scope(
+						|scope| {
+							scope.set_tag("sync-keyshare", "attestation");
+						},
+						|| sentry::capture_message(&message, sentry::Level::Error),
+					);
+					return error_handler(message, &state).await.into_response();
+				}
+			},
+
+			Err(err) => {
+				let message = format!(
+					"SYNC KEYSHARES : TOKEN : Can not parse Token Block Number {} , error = {:?}",
+					parse_token[1], err
+				);
+				sentry::with_scope(
+					|scope| {
+						scope.set_tag("sync-keyshare", "attestation");
+					},
+					|| sentry::capture_message(&message, sentry::Level::Error),
+				);
+				return error_handler(message, &state).await.into_response();
+			},
+		} // VALID TOKEN BLOCK
+	} // PARSE TOKEN
+
+	let random_number = rand::rngs::OsRng.next_u32();
+	let backup_file = format!("/temporary/backup_{random_number}.zip");
+
+	debug!("SYNC KEYSHARES : Start zippping file");
+	add_list_zip(SEALPATH, nftidv, &backup_file.clone());
+
+	// Public-Key Encryption
+	let encryption_key = match hex::decode(request.encryption_account) {
+		Ok(key) => key,
+		Err(err) => {
+			let _ = std::fs::remove_file(&backup_file);
+			let message = format!("SYNC KEYSHARES : Invalid encryption account : {err:?}");
+			return error_handler(message, &state).await.into_response();
+		},
+	};
+	trace!("SYNC KEYSHARES : Encryption public key = {:?}", encryption_key);
+
+	let (encryptor, stream_header) = match StreamEncryptor::new(&encryption_key) {
+		Ok(encryptor) => encryptor,
+		Err(err) => {
+			let _ = std::fs::remove_file(&backup_file);
+			return (
+				StatusCode::INTERNAL_SERVER_ERROR,
+				Json(json!({
+					"error": format!("SYNC KEYSHARES : Failed to initialize the stream encryption : {:?}", err)
+				})),
+			)
+				.into_response();
+		},
+	};
+
+	// `File` implements `AsyncRead`
+	debug!("SYNC KEYSHARES : Opening backup file");
+	let file = match tokio::fs::File::open(backup_file.clone()).await {
+		Ok(file) => file,
+		Err(err) => {
+			// The plain archive must not stay on the disk when it is not streamed
+			let _ = std::fs::remove_file(&backup_file);
+			return (
+				StatusCode::INTERNAL_SERVER_ERROR,
+				Json(json!({
+					"error": format!("SYNC KEYSHARES : Backup File not found: {}", err)
+				})),
+			)
+				.into_response();
+		},
+	};
+
+	// The archive is encrypted chunk by chunk while streaming, it is never loaded in memory.
+	// The plain archive is removed when the stream ends or the requester disconnects.
+	debug!("SYNC KEYSHARES : Create encrypted body-stream");
+	let stream = encrypt_file_stream(file, backup_file, encryptor, stream_header);
+	let body = StreamBody::new(stream);
+
+	let headers = [
+		(header::CONTENT_TYPE, "application/octet-stream"),
+		(header::CONTENT_DISPOSITION, "attachment; filename=\"Backup.enc\""),
+	];
+
+	//update_health_status(&state, String::new()).await;
+
+	debug!("SYNC KEYSHARES : Sending the backup data to the client ...");
+	(headers, body).into_response()
+}
+
+/// Verify the quote of a requester, DCAP quotes are verified inside the enclave
+/// and the attestation server is an optional fallback for other quotes
+/// # Arguments
+/// * `state` - The enclave state
+/// * `client` - HTTP client
+/// * `quote_body` - The quote of the requester
+/// * `enclave_url` - URL of the requester
+/// # Returns
+/// * `Result<ReportBody, String>` - Verified report of the quote or the error message
+pub async fn verify_requester_quote(
+	state: &SharedState,
+	client: &reqwest::Client,
+	quote_body: &QuoteResponse,
+	enclave_url: &str,
+) -> Result<ReportBody, String> {
+	let collateral = match dcap::load_collateral() {
+		Ok(collateral) => collateral,
+		Err(err) => {
+			let message = format!("SYNC KEYSHARES : DCAP : can not load the collateral : {err:?}");
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response();
+			return Err(message);
+		},
+	};
+
+	let quote = match hex::decode(&quote_body.data) {
+		Ok(quote) => quote,
+		Err(err) => {
+			let message =
+				format!("SYNC KEYSHARES : Quote : invalid hex quote of {enclave_url} : {err:?}");
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "quote");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(message);
+		},
+	};
+
+	if !dcap::is_dcap_quote(&quote) {
+		if !collateral.attestation_server_fallback {
+			let message = format!(
+				"SYNC KEYSHARES : Quote of {enclave_url} is not a DCAP quote and attestation server fallback is disabled"
+			);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(message);
+		}
+
+		debug!(
+			"SYNC KEYSHARES : Quote of {enclave_url} is not a DCAP quote, using attestation server"
+		);
+		return attestation_server_report(state, client, quote_body, enclave_url).await;
+	}
+
+	let now = match get_verified_chain_time(state).await {
+		Ok(now) => now,
+		Err(err) => {
+			let message = format!("SYNC KEYSHARES : DCAP : can not get the chain time : {err:?}");
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(message);
+		},
+	};
+
+	let verified = dcap::load_root_certificate().and_then(|root_certificate| {
+		dcap::verify_dcap_quote(&quote, &root_certificate, &collateral, now)
+	});
+
+	match verified {
+		Ok(report) => {
+			debug!(
+				"SYNC KEYSHARES : DCAP : verified quote of {enclave_url}, mrenclave = {}",
+				hex::encode(report.mr_enclave)
+			);
+			Ok(report)
+		},
+		Err(err) => {
+			let message = format!(
+				"SYNC KEYSHARES : DCAP : quote verification of {enclave_url} failed : {err:?}"
+			);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			Err(message)
+		},
+	}
+}
+
+/// Verify the quote of a requester with the attestation server
+/// # Arguments
+/// * `state` - The enclave state
+/// * `client` - HTTP client
+/// * `quote_body` - The quote of the requester
+/// * `enclave_url` - URL of the requester
+/// # Returns
+/// * `Result<ReportBody, String>` - Report of the quote verified by the server or the error message
+async fn attestation_server_report(
+	state: &SharedState,
+	client: &reqwest::Client,
+	quote_body: &QuoteResponse,
+	enclave_url: &str,
+) -> Result<ReportBody, String> {
+	let account_keypair = get_keypair(state).await;
+	let account_id = get_accountid(state).await;
+	let signature = account_keypair.sign(quote_body.data.as_bytes());
+
+	let attestation_request_body = json!({
+		"account_id": account_id,
+		"data": quote_body.data,
+		"signature": format!("0x{:?}", signature),
+	})
+	.to_string();
+
+	let attest_response = match client
+		.post(ATTESTATION_SERVER_URL)
+		.body(attestation_request_body)
+		.header(header::CONTENT_TYPE, "application/json")
+		.send()
+		.await
+	{
+		Ok(resp) => resp,
+		Err(err) => {
+			let message = format!(
+					"SYNC KEYSHARES : Attestation : can not get response from attestation server : {err:?}");
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(message);
+		},
+	};
+
+	let attestation_json = match attest_response.text().await {
+		Ok(resp) => resp,
+		Err(err) => {
+			let message = format!("Error getting attestation response {err:?}");
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(message);
+		},
+	};
+
+	trace!(
+		"SYNC KEYSHARES : Attestation Result for url : {} is \n {:#?}\n\n",
+		enclave_url,
+		attestation_json,
+	);
+
+	let attest_dynamic_json: Value = match serde_json::from_str::<Value>(&attestation_json) {
+		Ok(dj) => dj,
+		Err(err) => {
+			let message =
+				format!("SYNC KEYSHARES : Error deserializing attestation response {err:?}");
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(message);
+		},
+	};
+
+	trace!("SYNC KEYSHARES : Report map : {}", attest_dynamic_json["report"]);
+	let report_body_string = serde_json::to_string(&attest_dynamic_json["report"]);
+	trace!("SYNC KEYSHARES : Stringified report map : {:?}", report_body_string);
+
+	let report: String = match report_body_string {
+		Ok(report) => report,
+		Err(err) => {
+			let message = format!(
+				"SYNC KEYSHARES : Error deserializing attestation report as String {err:?}"
+			);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(message);
+		},
+	};
+
+	let attestation_server_account: String =
+		match serde_json::from_value(attest_dynamic_json["account"].clone()) {
+			Ok(attest_account) => attest_account,
+			Err(err) => {
+				let message =
+					format!("SYNC KEYSHARES : Error deserializing attestation account {err:?}");
+				sentry::with_scope(
+					|scope| {
+						scope.set_tag("sync-keyshare", "attestation");
+					},
+					|| sentry::capture_message(&message, sentry::Level::Error),
+				);
+				return Err(message);
+			},
+		};
+
+	let attestation_server_signature: String =
+		match serde_json::from_value(attest_dynamic_json["signature"].clone()) {
+			Ok(report) => report,
+			Err(err) => {
+				let message =
+					format!("SYNC KEYSHARES : Error deserializing attestation signature {err:?}");
+				sentry::with_scope(
+					|scope| {
+						scope.set_tag("sync-keyshare", "attestation");
+					},
+					|| sentry::capture_message(&message, sentry::Level::Error),
+				);
+				return Err(message);
+			},
+		};
+
+	// Verify signature of Attestation Server response
+	if !verify_signature(
+		&attestation_server_account,
+		attestation_server_signature,
+		report.as_bytes(),
+	) {
+		let message = "SYNC KEYSHARES : Invalid Report Signature".to_string();
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("sync-keyshare", "attestation");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return Err(message);
+	}
+
+	if !crate::replication::metric::verify_account_id(state, &attestation_server_account).await {
+		let message = format!(
+			"SYNC KEYSHARES : Invalid Attestation Server, It is not registered on blockchain , account : {attestation_server_account}"
+		);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("sync-keyshare", "attestation");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return Err(message);
+	}
+
+	// Deserialize again to Json
+	let report: Value = match serde_json::from_value(attest_dynamic_json["report"].clone()) {
+		Ok(report) => report,
+		Err(err) => {
+			let message =
+				format!("SYNC KEYSHARES : Error deserializing attestation report as Value {err:?}");
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(message);
 		},
 	};
 
@@ -676,7 +999,7 @@ pub async fn sync_keyshares(
 	if report["exit status"] != "0" {
 		let message = format!(
 			"SYNC KEYSHARES : Attestation IAS report failed :: Requester: {} , Report : {report}",
-			requester.1.enclave_url
+			enclave_url
 		);
 		sentry::with_scope(
 			|scope| {
@@ -684,7 +1007,7 @@ pub async fn sync_keyshares(
 			},
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
-		return error_handler(message, &state).await.into_response();
+		return Err(message);
 	} // FAILED ATTESTATION REPORT
 
 	// Deserialize the quote
@@ -701,7 +1024,7 @@ pub async fn sync_keyshares(
 					},
 					|| sentry::capture_message(&message, sentry::Level::Error),
 				);
-				return error_handler(message, &state).await.into_response();
+				return Err(message);
 			},
 		},
 
@@ -713,7 +1036,7 @@ pub async fn sync_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return error_handler(message, &state).await.into_response();
+			return Err(message);
 		},
 	};
 
@@ -728,207 +1051,113 @@ pub async fn sync_keyshares(
 			},
 			|| sentry::capture_message(&message, sentry::Level::Error),
 		);
-		return error_handler(message, &state).await.into_response();
+		return Err(message);
 	}
 
-	let report_data: String = quote
-		.chars()
-		.skip(QUOTE_REPORT_DATA_OFFSET * 2)
-		.take(QUOTE_REPORT_DATA_LENGTH * 2)
-		.collect();
-
-	if report_data.len() < 128 {
-		trace!("SYNC KEYSHARES : quote-body in report = {quote}");
-		let message =
-			format!("SYNC KEYSHARES : Failed to get 'report_data; from th quote : {}", quote);
-		sentry::with_scope(
-			|scope| {
-				scope.set_tag("sync-keyshare", "attestation");
-			},
-			|| sentry::capture_message(&message, sentry::Level::Error),
-		);
-		return error_handler(message, &state).await.into_response();
-	} // FAILED EXTRACTING REPORT DATA
-
-	// Verify Report_Data
-
-	let token = format!(
-		"{}_{}_{}",
-		request.enclave_account, auth_token.block_number, request.encryption_account
-	);
-
-	debug!("SYNC KEYSHARES : report_data token = {token}");
-
-	if !verify_signature(
-		&request.enclave_account.clone(),
-		report_data.to_string(),
-		token.as_bytes(),
-	) {
-		let message = "SYNC KEYSHARES : Invalid Signature".to_string();
-		sentry::with_scope(
-			|scope| {
-				scope.set_tag("sync-keyshare", "quote");
-			},
-			|| sentry::capture_message(&message, sentry::Level::Error),
-		);
-		return error_handler(message, &state).await.into_response();
-	}
+	// Report of the quote verified by the attestation server
+	let report = hex::decode(quote)
+		.map_err(anyhow::Error::from)
+		.and_then(|quote| dcap::parse_report_body(&quote));
 
-	let parse_token: Vec<&str> = token.split('_').collect();
-	if request.enclave_account != parse_token[0] {
-		let message =
-			"SYNC KEYSHARES : TOKEN : Mismatch between <Requester Account> and <Report Data Token>"
-				.to_string();
-		sentry::with_scope(
-			|scope| {
-				scope.set_tag("sync-keyshare", "attestation");
-			},
-			|| sentry::capture_message(&message, sentry::Level::Error),
-		);
-		return error_handler(message, &state).await.into_response();
-	} else {
-		match parse_token[1].parse::<u32>() {
-			Ok(token_block) => {
-				if (token_block != auth_token.block_number) ||
-					(current_block_number < token_block) ||
-					(current_block_number - token_block > 5)
-				{
-					let message = format!("SYNC KEYSHARES : TOKEN : Incompatible/Outdated block numbers :\n Current blocknumber: {current_block_number} >~ Token blocknumber: {token_block} == Request blocknumber: {} ?", auth_token.block_number);
-					sentry::with_scope(
-						|scope| {
-							scope.set_tag("sync-keyshare", "attestation");
-						},
-						|| sentry::capture_message(&message, sentry::Level::Error),
-					);
-					return error_handler(message, &state).await.into_response();
-				}
-			},
-
-			Err(err) => {
-				let message = format!(
-					"SYNC KEYSHARES : TOKEN : Can not parse Token Block Number {} , error = {:?}",
-					parse_token[1], err
-				);
-				sentry::with_scope(
-					|scope| {
-						scope.set_tag("sync-keyshare", "attestation");
-					},
-					|| sentry::capture_message(&message, sentry::Level::Error),
-				);
-				return error_handler(message, &state).await.into_response();
-			},
-		} // VALID TOKEN BLOCK
-	} // PARSE TOKEN
-
-	let random_number = rand::rngs::OsRng.next_u32();
-	let backup_file = format!("/temporary/backup_{random_number}.zip");
-
-	debug!("SYNC KEYSHARES : Start zippping file");
-	add_list_zip(SEALPATH, nftidv, &backup_file.clone());
-
-	let zip_data = match fs::read(backup_file.clone()) {
-		Ok(data) => data,
-		Err(err) =>
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": format!("SYNC KEYSHARES : Backup File not found: {}", err)
-				})),
-			)
-				.into_response(),
-	};
-
-	// Public-Key Encryption
-	let encryption_key = hex::decode(request.encryption_account).unwrap();
-	trace!("SYNC KEYSHARES : Encryption public key = {:?}", encryption_key);
-	debug!("SYNC KEYSHARES : Encryption zip data length = {}", zip_data.len());
-	let encrypted_zip_data = match encrypt(&encryption_key, &zip_data) {
-		Ok(encrypted) => encrypted,
-		Err(err) =>
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": format!("SYNC KEYSHARES : Failed to encrypt the zip data : {:?}", err)
-				})),
-			)
-				.into_response(),
-	};
-
-	// Remove Plain Data
-	match std::fs::remove_file(backup_file) {
-		Ok(_) => {
-			trace!("SYNC KEYSHARES : Successfully removed previous zip file")
-		},
+	match report {
+		Ok(report) => Ok(report),
 		Err(err) => {
+			trace!("SYNC KEYSHARES : quote-body in report = {quote}");
 			let message =
-				format!("SYNC KEYSHARES : Error : Can not remove previous backup file : {}", err);
-			warn!(message);
-		},
+				format!("SYNC KEYSHARES : Failed to get the report from the quote : {err:?}");
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("sync-keyshare", "attestation");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			Err(message)
+		}, // FAILED EXTRACTING REPORT
 	}
+}
 
-	// Writing to files is necessary to live enough for async stream
-	// ZIP-file Garbage Collection is needed
-	let encrypted_backup_file = format!("/temporary/encrypted_backup_{random_number}.zip");
-	match std::fs::write(encrypted_backup_file.clone(), encrypted_zip_data) {
-		Ok(_) => trace!("SYNC KEYSHARES : Successfully write encrypted zip data to streamfile"),
-		Err(err) =>
-			return Json(json!({
-				"error":
-					format!(
-						"SYNC KEYSHARES : Failed to write encrypted zip data to stream file : {}",
-						err
-					)
-			}))
-			.into_response(),
+/// Keyshare Count (Server Side)
+/// Number of keyshares served by wildcard synchronization, reported during paginated setup
+/// The requester is authenticated like a sync-keyshare request, the token is bound to the route
+/// name
+/// # Arguments
+/// * `state` - StateConfig
+/// * `request` - Signed authentication token of a slot enclave
+pub async fn keyshare_count(
+	State(state): State<SharedState>,
+	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	Json(request): Json<KeyshareCountPacket>,
+) -> impl IntoResponse {
+	debug!("KEYSHARE COUNT : START");
+
+	if let Err(message) = authenticate_requester(
+		&state,
+		addr,
+		&request.enclave_account,
+		&request.auth_token,
+		&request.signature,
+		KEYSHARE_COUNT_DATA,
+	)
+	.await
+	{
+		return error_handler(format!("KEYSHARE COUNT : {message}"), &state)
+			.await
+			.into_response();
 	}
 
-	// `File` implements `AsyncRead`
-	debug!("SYNC KEYSHARES : Opening encrypted backup file");
-	let file = match tokio::fs::File::open(encrypted_backup_file).await {
-		Ok(file) => file,
-		Err(err) =>
-			return (
-				StatusCode::INTERNAL_SERVER_ERROR,
-				Json(json!({
-					"error": format!("SYNC KEYSHARES : Encrypted backup File not found: {}", err)
-				})),
-			)
-				.into_response(),
-	};
-
-	// convert the `AsyncRead` into a `Stream`
-	debug!("SYNC KEYSHARES : Create reader-stream");
-	let stream = ReaderStream::new(file);
-
-	// convert the `Stream` into an `axum::body::HttpBody`
-	debug!("SYNC KEYSHARES : Create body-stream");
-	let body = StreamBody::new(stream);
-
-	let headers = [
-		(header::CONTENT_TYPE, "text/toml; charset=utf-8"),
-		(header::CONTENT_DISPOSITION, "attachment; filename=\"Backup.zip\""),
-	];
+	let block_number = get_blocknumber(&state).await;
+	let keyshare_count = sorted_keyshares(SEALPATH).len() as u32;
 
-	//update_health_status(&state, String::new()).await;
-
-	debug!("SYNC KEYSHARES : Sending the backup data to the client ...");
-	(headers, body).into_response()
+	(StatusCode::OK, Json(KeyshareCountResponse { keyshare_count, block_number })).into_response()
 }
 
 /* --------------------------------
 	FETCH KEYSHARES FROM ENCLAVES
 ----------------------------------- */
 
+// Failure of fetching keyshares from a target enclave
+enum FetchError {
+	// Target enclave failed, the next enclave of the slot can be tried
+	Target(String),
+	// Local failure, synchronization can not continue
+	Local(String),
+}
+
+// Result of a single synchronization pass over the enclaves of the slot
+#[derive(Debug, Default)]
+pub struct FetchOutcome {
+	// The block number the target enclave is synchronized to
+	pub synced_block: u32,
+	// Nfts that are not received, they are queued for the retry worker
+	pub pending: Vec<u32>,
+}
+
+impl From<u32> for FetchOutcome {
+	fn from(synced_block: u32) -> Self {
+		FetchOutcome { synced_block, pending: Vec::new() }
+	}
+}
+
+/// Fetch the keyshares of the new nfts from the enclaves of the same slot
+/// Every peer is tried once, the nfts that are not received are returned as pending and retried
+/// by the background worker
+/// # Arguments
+/// * `state` - The shared state
+/// * `new_nft_map` - The new nfts, an empty map fetches all keyshares of the slot
+/// # Returns
+/// * `FetchOutcome` - The synchronized block and the pending nfts
 pub async fn fetch_keyshares(
 	state: &SharedState,
 	new_nft_map: &HashMap<u32, SyncedNFT>,
-) -> Result<u32, anyhow::Error> {
+) -> Result<FetchOutcome, anyhow::Error> {
 	debug!("\n\t----\nFETCH KEYSHARES : START\n\t----\n");
 
-	let mut last_synced = 0u32;
+	// If HashMap is empty, then it is called by a setup syncronization
+	if new_nft_map.is_empty() {
+		return fetch_all_keyshares(state).await.map(FetchOutcome::from);
+	}
+
 	let current_block_number = get_blocknumber(state).await;
-	let account_id = get_accountid(state).await;
-	let account_keypair = get_keypair(state).await;
 
 	// (clustse, slot)
 	let enclave_identity = match get_identity(state).await {
@@ -958,38 +1187,9 @@ pub async fn fetch_keyshares(
 		// Ignore it, if it is in current cluster
 		.filter(|(_, cluster)| cluster.cluster_id == enclave_identity.0)
 		.map(|kv| kv.0.to_string())
-		.collect();
-
-	// Encode nftid to String
-	// If HashMap is empty, then it is called by a setup syncronization
-	let nftids_request = if new_nft_map.is_empty() {
-		// Empty nftid vector is used with Admin_bulk backup, that's why we use wildcard for
-		// synchronization It is the first time running enclave
-		// TODO [reliability] Pagination request is needed i.e ["*", 100, 2] page size is 100,
-		// offset 2 TODO : for pagination, a new endpoint needed to report the number of keyshares
-		// stored on target enclave.
-		match serde_json::to_string(&vec!["*".to_string()]) {
-			Ok(strg) => strg,
-			Err(err) => {
-				let message = format!(
-					"FETCH KEYSHARES : Error : can not convert Wildcard to string! : {err:?}"
-				);
-				error!(message);
-				return Err(anyhow!(message));
-			},
-		}
-	} else if !new_nftid_vec_str.is_empty() {
-		// Normal : there are some nftid in the list
-		match serde_json::to_string(&new_nftid_vec_str) {
-			Ok(strng) => strng,
-			Err(err) => {
-				let message =
-					format!("FETCH KEYSHARES : Error : can not convert NFTIDs to string : {err:?}");
-				error!(message);
-				return Err(anyhow!(message));
-			},
-		}
-	} else {
+		.collect();
+
+	if new_nftid_vec_str.is_empty() {
 		// nftids are all filtered out : they are already stored on this cluster
 		let message =
 			"FETCH KEYSHARES : the new nft is ORIGINALLY stored on this cluster".to_string();
@@ -1040,8 +1240,421 @@ pub async fn fetch_keyshares(
 			}
 		}
 
-		return Ok(current_block_number);
-	};
+		return Ok(current_block_number.into());
+	}
+
+	// The available enclaves in the same slot of current enclave, with their clusterid
+	debug!("FETCH KEYSHARES : START SLOT DISCOVERY");
+	let slot_enclaves = slot_discovery(state).await;
+	if slot_enclaves.is_empty() {
+		return no_slot_enclaves(state, current_block_number).await.map(FetchOutcome::from);
+	}
+
+	// Check other enclaves for new NFT keyshares
+	let nft_clusters: Vec<u32> = new_nft_map.clone().into_values().map(|c| c.cluster_id).collect();
+	debug!("FETCH KEYSHARES : nfts-cluster {:?}\n", nft_clusters);
+
+	// Requested nfts are pending until they are received or queued for retry
+	let requested: HashMap<u32, SyncedNFT> = new_nft_map
+		.iter()
+		.filter(|(_, nft)| nft.cluster_id != enclave_identity.0)
+		.map(|(nftid, nft)| (*nftid, nft.clone()))
+		.collect();
+
+	if let Err(err) = mark_sync_pending(&requested, current_block_number) {
+		warn!("FETCH KEYSHARES : can not record the pending nfts in the sync state : {err:?}");
+	}
+
+	// Local keyshares before the synchronization, a keyshare is received when it changes
+	let mut remaining = Vec::<(u32, Option<(u32, NftType)>)>::new();
+	for nftid in requested.keys() {
+		let local = get_nft_availability(state, *nftid).await;
+		remaining.push((*nftid, local.map(|av| (av.block_number, av.nft_type))));
+	}
+
+	let mut attempt =
+		SyncAttempt { nftids: requested.keys().copied().collect(), ..Default::default() };
+
+	let result =
+		fetch_from_peers(state, slot_enclaves, &nft_clusters, &mut remaining, &mut attempt).await;
+
+	if let Err(err) = &result {
+		attempt.last_error = Some(err.to_string());
+	}
+
+	let missing: Vec<u32> = remaining.iter().map(|(nftid, _)| *nftid).collect();
+	if !missing.is_empty() {
+		// The retry worker and the anti-entropy reconciliation recover them later
+		warn!(
+			"FETCH KEYSHARES : keyshares of nfts {:?} are not received from any enclave of the slot",
+			missing
+		);
+	}
+
+	if let Err(err) = settle_sync_attempt(&missing, &attempt, current_block_number) {
+		warn!("FETCH KEYSHARES : can not record the failed nfts in the sync state : {err:?}");
+	}
+
+	result.map(|synced_block| FetchOutcome {
+		synced_block: synced_block.unwrap_or(0),
+		pending: missing,
+	})
+}
+
+// Fetch the remaining nfts from the ranked peers of the slot, every peer is tried once
+// Returns the block number the last healthy peer is synchronized to
+async fn fetch_from_peers(
+	state: &SharedState,
+	slot_enclaves: Vec<(u32, Enclave)>,
+	nft_clusters: &[u32],
+	remaining: &mut Vec<(u32, Option<(u32, NftType)>)>,
+	attempt: &mut SyncAttempt,
+) -> Result<Option<u32>, anyhow::Error> {
+	let mut last_synced = None;
+	let current_block_number = get_blocknumber(state).await;
+
+	let client = sync_client()?;
+
+	// Enclaves of the clusters that originally store the nfts are ranked first, the other
+	// enclaves of the slot may have synchronized them already
+	let mut peers = rank_peers(state, slot_enclaves).await;
+	peers.sort_by_key(|(cluster_id, _)| !nft_clusters.contains(cluster_id));
+
+	for peer in &peers {
+		if remaining.is_empty() {
+			break;
+		}
+
+		let (cluster_id, enclave) = peer;
+		debug!("FETCH KEYSHARES : Fetch from enclave : \n Cluster: {} \n Slot: {}\n Operator: {}\n Enclave_Account: {}\n URL: {}\n\n",
+			cluster_id, enclave.slot,enclave.operator_account,enclave.enclave_account,enclave.enclave_url);
+
+		let mut enclave_url = enclave.enclave_url.clone();
+		while enclave_url.ends_with('/') {
+			enclave_url.pop();
+		}
+
+		attempt.last_peer = Some(enclave.enclave_account.to_string());
+
+		let started = Instant::now();
+		match sync_target_health(&client, &enclave_url).await {
+			Some(synced_block) => {
+				record_health(state, peer, true, started.elapsed()).await;
+				last_synced = Some(synced_block);
+			},
+			None => {
+				record_health(state, peer, false, started.elapsed()).await;
+				attempt.last_error = Some(format!("health-check failed on {enclave_url}"));
+				continue; // Next Peer
+			},
+		}
+
+		let nftid_vec: Vec<String> = remaining.iter().map(|(nftid, _)| nftid.to_string()).collect();
+		let nftids_request = match serde_json::to_string(&nftid_vec) {
+			Ok(strng) => strng,
+			Err(err) => {
+				let message =
+					format!("FETCH KEYSHARES : Error : can not convert NFTIDs to string : {err:?}");
+				error!(message);
+				return Err(anyhow!(message));
+			},
+		};
+
+		// Authentication token is valid for a few blocks, every attempt has its own request
+		let (request_body, encryption_private_key) =
+			create_fetch_request(state, nftids_request).await?;
+
+		// The retry worker and the block loop may fetch at the same block
+		let random_number = rand::rngs::OsRng.next_u32();
+		let backup_file = format!("{SEALPATH}/backup_{current_block_number}_{random_number}.zip");
+
+		let started = Instant::now();
+		match download_keyshares(
+			state,
+			&client,
+			&enclave_url,
+			request_body,
+			&encryption_private_key,
+			&backup_file,
+		)
+		.await
+		{
+			Ok(_) => {
+				debug!("FETCH KEYSHARES : keyshares are fetched from {enclave_url}");
+				record_success(state, peer, started.elapsed()).await;
+			},
+			Err(FetchError::Target(message)) => {
+				error!(message);
+				record_failure(state, peer).await;
+				attempt.last_error = Some(message);
+				continue; // Next Peer
+			},
+			Err(FetchError::Local(message)) => return Err(anyhow!(message)),
+		}
+
+		// Keep the nfts that are not received, a peer may have a part of the list
+		let mut still_missing = Vec::new();
+		for (nftid, before) in remaining.drain(..) {
+			let after = get_nft_availability(state, nftid)
+				.await
+				.map(|av| (av.block_number, av.nft_type));
+			if after == before {
+				still_missing.push((nftid, before));
+			}
+		}
+		*remaining = still_missing;
+
+		if !remaining.is_empty() {
+			attempt.last_error = Some(format!("keyshares are not provided by {enclave_url}"));
+		}
+	}
+
+	Ok(last_synced)
+}
+
+/// Fetch all keyshares of the slot, page by page, for the setup synchronization
+/// Progress is stored in the sync state after every page, a restarted setup resumes from the last
+/// completed page. The setup is complete once this enclave stores at least the keyshare count
+/// reported by the target
+/// # Arguments
+/// * `state` - The shared state
+/// # Returns
+/// * `u32` - The block number the target enclave is synchronized to
+async fn fetch_all_keyshares(state: &SharedState) -> Result<u32, anyhow::Error> {
+	debug!("FETCH ALL KEYSHARES : START");
+
+	let current_block_number = get_blocknumber(state).await;
+
+	if get_identity(state).await.is_none() {
+		let message =
+			"FETCH ALL KEYSHARES : Error : No identity : Current enclave is not registered yet"
+				.to_string();
+		error!(message);
+		return Err(anyhow!(message));
+	}
+
+	debug!("FETCH ALL KEYSHARES : START SLOT DISCOVERY");
+	let slot_enclaves = slot_discovery(state).await;
+	if slot_enclaves.is_empty() {
+		return no_slot_enclaves(state, current_block_number).await;
+	}
+
+	let client = sync_client()?;
+
+	// Wildcard synchronization needs one enclave of the slot, from any cluster
+	for peer in rank_peers(state, slot_enclaves).await {
+		let (cluster_id, enclave) = &peer;
+		debug!(
+			"FETCH ALL KEYSHARES : Fetch from enclave : Cluster: {} , Slot: {}, URL: {}",
+			cluster_id, enclave.slot, enclave.enclave_url
+		);
+
+		let mut enclave_url = enclave.enclave_url.clone();
+		while enclave_url.ends_with('/') {
+			enclave_url.pop();
+		}
+
+		let started = Instant::now();
+		let last_synced = match sync_target_health(&client, &enclave_url).await {
+			Some(synced_block) => {
+				record_health(state, &peer, true, started.elapsed()).await;
+				synced_block
+			},
+			None => {
+				record_health(state, &peer, false, started.elapsed()).await;
+				continue; // Next Cluster
+			},
+		};
+
+		let keyshare_count =
+			match fetch_keyshare_count(state, &client, &enclave_url).await {
+				Ok(count) => count,
+				Err(err) => {
+					error!("FETCH ALL KEYSHARES : Can not get keyshare count of {enclave_url} : {err:?}");
+					continue; // Next Cluster
+				},
+			};
+
+		// Resume from the first nftid after the last completed page
+		let mut from_nftid = setup_progress(&get_sync_state()?);
+
+		info!(
+			"FETCH ALL KEYSHARES : {keyshare_count} keyshares on {enclave_url}, starting from nftid {from_nftid}"
+		);
+
+		let mut target_failed = false;
+
+		// Pages follow the nftids, keyshares stored on the target during the setup do not shift
+		// them
+		loop {
+			let nftids_request = match serde_json::to_string(&vec![
+				"*".to_string(),
+				SYNC_PAGE_SIZE.to_string(),
+				from_nftid.to_string(),
+			]) {
+				Ok(strg) => strg,
+				Err(err) => {
+					let message = format!(
+						"FETCH ALL KEYSHARES : Error : can not convert Wildcard to string! : {err:?}"
+					);
+					error!(message);
+					return Err(anyhow!(message));
+				},
+			};
+
+			// Authentication token is valid for a few blocks, every page has its own request
+			let (request_body, encryption_private_key) =
+				create_fetch_request(state, nftids_request).await?;
+
+			let random_number = rand::rngs::OsRng.next_u32();
+			let backup_file = format!(
+				"{SEALPATH}/backup_{current_block_number}_{from_nftid}_{random_number}.zip"
+			);
+
+			let started = Instant::now();
+			match download_keyshares(
+				state,
+				&client,
+				&enclave_url,
+				request_body,
+				&encryption_private_key,
+				&backup_file,
+			)
+			.await
+			{
+				Ok(last_nftid) => {
+					record_success(state, &peer, started.elapsed()).await;
+
+					// An empty page is the end of the keyshares of the target
+					let next_nftid = match last_nftid.and_then(|nftid| nftid.checked_add(1)) {
+						Some(next_nftid) => next_nftid,
+						None => break,
+					};
+
+					info!(
+						"FETCH ALL KEYSHARES : page from nftid {from_nftid} of {enclave_url} is completed, {} of {keyshare_count} keyshares are stored",
+						sorted_keyshares(SEALPATH).len()
+					);
+					from_nftid = next_nftid;
+					let _ = set_sync_state(format!("setup_{from_nftid}"));
+				},
+				Err(FetchError::Target(message)) => {
+					error!(message);
+					record_failure(state, &peer).await;
+					target_failed = true;
+					break;
+				},
+				Err(FetchError::Local(message)) => return Err(anyhow!(message)),
+			}
+		}
+
+		if target_failed {
+			continue; // Next Cluster, from the last completed page
+		}
+
+		// Keyshares added to the target during the setup only add to the count of this enclave
+		let stored_count = sorted_keyshares(SEALPATH).len() as u32;
+		if stored_count < keyshare_count {
+			error!(
+				"FETCH ALL KEYSHARES : {stored_count} keyshares are stored, {enclave_url} serves {keyshare_count}"
+			);
+			record_failure(state, &peer).await;
+			// The next enclave of the slot fetches all pages again
+			let _ = set_sync_state("setup".to_string());
+			continue; // Next Cluster
+		}
+
+		return Ok(last_synced);
+	}
+
+	let message = "FETCH ALL KEYSHARES : No enclave of the slot completed the synchronization";
+	error!(message);
+	Err(anyhow!(message))
+}
+
+/// Fetch the given keyshares from one enclave of the slot, used by anti-entropy reconciliation
+/// # Arguments
+/// * `state` - The shared state
+/// * `enclave_url` - URL of the enclave holding the keyshares
+/// * `nftids` - The missing or outdated nftids
+pub async fn pull_keyshares(
+	state: &SharedState,
+	enclave_url: &str,
+	nftids: &[u32],
+) -> Result<(), anyhow::Error> {
+	let nftid_vec: Vec<String> = nftids.iter().map(|nftid| nftid.to_string()).collect();
+	let nftids_request = serde_json::to_string(&nftid_vec)?;
+
+	let (request_body, encryption_private_key) =
+		create_fetch_request(state, nftids_request).await?;
+
+	let client = sync_client()?;
+	let random_number = rand::rngs::OsRng.next_u32();
+	let backup_file =
+		format!("{SEALPATH}/backup_reconcile_{}_{random_number}.zip", get_blocknumber(state).await);
+
+	match download_keyshares(
+		state,
+		&client,
+		enclave_url,
+		request_body,
+		&encryption_private_key,
+		&backup_file,
+	)
+	.await
+	{
+		Ok(_) => Ok(()),
+		Err(FetchError::Target(message)) | Err(FetchError::Local(message)) => Err(anyhow!(message)),
+	}
+}
+
+// Slot discovery found no other enclave
+async fn no_slot_enclaves(
+	state: &SharedState,
+	current_block_number: u32,
+) -> Result<u32, anyhow::Error> {
+	// TODO : What about first cluster? should it continue as the Primary cluster in
+	// running-mode? otherwise we should have two clusters registered before starting
+	// enclaves with sync capability.
+	if get_identity(state).await.is_some() {
+		warn!("FETCH KEYSHARES : No other similar slots found in other clusters, is this primary cluster?");
+		Ok(current_block_number)
+	} else {
+		// not registered
+		error!("FETCH KEYSHARES : This enclave is not registered yet.");
+		Err(anyhow!("FETCH KEYSHARES : Slot discovery failed because of not-registered enclave"))
+	}
+}
+
+pub fn sync_client() -> Result<reqwest::Client, reqwest::Error> {
+	reqwest::Client::builder()
+		// This is for development, will be removed for production certs
+		.danger_accept_invalid_certs(!cfg!(any(feature = "mainnet", feature = "alphanet")))
+		.https_only(true)
+		// WebPKI
+		//.use_rustls_tls()
+		//.use_native_tls()
+		// .min_tls_version(if cfg!(any(feature = "mainnet", feature = "alphanet")) {
+		// 	tls::Version::TLS_1_3
+		// } else {
+		// 	tls::Version::TLS_1_0
+		// })
+		.build()
+}
+
+/// Create the signed and attested request body for the sync-keyshare endpoint
+/// # Arguments
+/// * `state` - The shared state
+/// * `nftids_request` - Serialized vector of nftids, or a wildcard
+/// # Returns
+/// * `(String, [u8; 32])` - The request body and the private key to decrypt the response
+async fn create_fetch_request(
+	state: &SharedState,
+	nftids_request: String,
+) -> Result<(String, [u8; 32]), anyhow::Error> {
+	let current_block_number = get_blocknumber(state).await;
+	let account_id = get_accountid(state).await;
+	let account_keypair = get_keypair(state).await;
 
 	let nftid_hash = sha256::digest(nftids_request.as_bytes());
 
@@ -1154,10 +1767,10 @@ pub async fn fetch_keyshares(
 		encryption_account: encryption_public_key,
 	};
 
-	let request_body = match serde_json::to_string(&request) {
+	match serde_json::to_string(&request) {
 		Ok(body) => {
 			trace!("FETCH KEYSHARES : Request Body : {:#?}\n", body);
-			body
+			Ok((body, encryption_private_key))
 		},
 		Err(err) => {
 			let message =
@@ -1169,264 +1782,277 @@ pub async fn fetch_keyshares(
 				},
 				|| sentry::capture_message(&message, sentry::Level::Error),
 			);
-			return Err(anyhow!(message));
+			Err(anyhow!(message))
+		},
+	}
+}
+
+/// Health-check a syncing target enclave
+/// # Arguments
+/// * `client` - The http client
+/// * `enclave_url` - Url of the target enclave, without trailing slash
+/// # Returns
+/// * `Option<u32>` - The block number the target is synchronized to, None if it is not ready
+async fn sync_target_health(client: &reqwest::Client, enclave_url: &str) -> Option<u32> {
+	let request_url = enclave_url.to_string() + "/api/health";
+
+	debug!("FETCH KEYSHARES : HEALTH CHECK");
+	debug!("FETCH KEYSHARES : request url : {}", request_url);
+	let health_response = match client.clone().get(request_url.clone()).send().await {
+		Ok(res) => res,
+		Err(err) => {
+			error!(
+					"FETCH KEYSHARES : Error getting health-check response from syncing target enclave : {} : \n{:#?}",
+					request_url, err
+				);
+			debug!("FETCH KEYSHARES : continue with next syncing target enclave");
+			return None;
 		},
 	};
+	// Analyze the Response
+	let health_status = health_response.status();
 
-	// The available enclaves in the same slot of current enclave, with their clusterid
-	debug!("FETCH KEYSHARES : START SLOT DISCOVERY");
-	let slot_enclaves = slot_discovery(state).await;
-	if slot_enclaves.is_empty() {
-		// TODO : What about first cluster? should it continue as the Primary cluster in
-		// running-mode? otherwise we should have two clusters registered before starting
-		// enclaves with sync capability.
-		if get_identity(state).await.is_some() {
-			warn!("FETCH KEYSHARES : No other similar slots found in other clusters, is this primary cluster?");
-			return Ok(current_block_number);
-		} else {
-			// not registered
-			error!("FETCH KEYSHARES : This enclave is not registered yet.");
-			return Err(anyhow!(
-				"FETCH KEYSHARES : Slot discovery failed because of not-registered enclave"
-			));
-		}
-	}
+	trace!("FETCH KEYSHARES : HEALTH CHECK : health response : {:#?}\n", health_response);
 
-	// Check other enclaves for new NFT keyshares
-	let nft_clusters: Vec<u32> = new_nft_map.clone().into_values().map(|c| c.cluster_id).collect();
-	debug!("FETCH KEYSHARES : nfts-cluster {:?}\n", nft_clusters);
+	let response_body: HealthResponse = match health_response.json().await {
+		Ok(body) => body,
+		Err(err) => {
+			let message = format!(
+				"FETCH KEYSHARES : Healthcheck : can not deserialize the body : {} : {:#?}",
+				enclave_url, err
+			);
+			warn!(message);
+			return None;
+		},
+	};
 
-	let client = reqwest::Client::builder()
-		// This is for development, will be removed for production certs
-		.danger_accept_invalid_certs(!cfg!(any(feature = "mainnet", feature = "alphanet")))
-		.https_only(true)
-		// WebPKI
-		//.use_rustls_tls()
-		//.use_native_tls()
-		// .min_tls_version(if cfg!(any(feature = "mainnet", feature = "alphanet")) {
-		// 	tls::Version::TLS_1_3
-		// } else {
-		// 	tls::Version::TLS_1_0
-		// })
-		.build()?;
+	debug!(
+		"FETCH KEYSHARES : Health-Check Result for url : {} is \n{:#?}",
+		enclave_url, response_body
+	);
 
-	// [future reliability] : use metric-server ranking instead of simple loop
-	for (cluster_id, enclave) in slot_enclaves {
-		debug!("FETCH KEYSHARES : Fetch from enclave : \n Cluster: {} \n Slot: {}\n Operator: {}\n Enclave_Account: {}\n URL: {}\n\n", 
-			cluster_id, enclave.slot,enclave.operator_account,enclave.enclave_account,enclave.enclave_url);
-		// Is the 'enclave' of 'slot_enclave' in the cluster that nftid is "originally" stored?
-		// We can remove this condition if we want to search whole the slot
-		// It is faster for Runtime synchronization
-		// It may be problematic for First time Synchronization
-		// Because it is possible that original enclave is down now.
-		if !new_nft_map.is_empty() && !nft_clusters.contains(&cluster_id) {
-			debug!(
-				"FETCH KEYSHARES : NFTs does not belong to cluster {}, continue to next cluster",
-				cluster_id
+	if health_status != StatusCode::OK {
+		let message = format!(
+			"FETCH KEYSHARES : Healthcheck Failed on url: {}, status : {:#?}, reason : {}",
+			enclave_url, health_status, response_body.description
+		);
+		error!(message);
+		return None;
+	}
+
+	match response_body.sync_state.parse::<u32>() {
+		Ok(blk) => Some(blk),
+		Err(_) => {
+			let message = format!(
+				"FETCH KEYSHARES : Healthcheck Parse Error on url: {}, status : {:#?}, sync_state : {}",
+				enclave_url, health_status, response_body.sync_state
 			);
-			continue; // Next Cluster
-		}
+			error!(message);
+			None
+		},
+	}
+}
 
-		let mut enclave_url = enclave.enclave_url.clone();
-		while enclave_url.ends_with('/') {
-			enclave_url.pop();
-		}
+/// Get the number of keyshares served by wildcard synchronization of a target enclave
+async fn fetch_keyshare_count(
+	state: &SharedState,
+	client: &reqwest::Client,
+	enclave_url: &str,
+) -> Result<u32, anyhow::Error> {
+	let request_url = enclave_url.to_string() + "/api/backup/keyshare-count";
+	debug!("FETCH KEYSHARES : request url : {}", request_url);
 
-		let request_url = enclave_url.clone() + "/api/health";
+	let auth = AuthenticationToken {
+		block_number: get_blocknumber(state).await,
+		block_validation: 15,
+		data_hash: sha256::digest(KEYSHARE_COUNT_DATA.as_bytes()),
+		quote_hash: String::new(),
+	};
 
-		debug!("FETCH KEYSHARES : HEALTH CHECK");
-		debug!("FETCH KEYSHARES : request url : {}", request_url);
-		let health_response = match client.clone().get(request_url.clone()).send().await {
-			Ok(res) => res,
-			Err(err) => {
-				error!(
-						"FETCH KEYSHARES : Error getting health-check response from syncing target enclave : {} : \n{:#?}",
-						request_url, err
-					);
-				debug!("FETCH KEYSHARES : continue with next syncing target enclave");
-				continue; // Next Cluster
-			},
-		};
-		// Analyze the Response
-		let health_status = health_response.status();
+	let auth_token = serde_json::to_string(&auth)?;
+	let signature = get_keypair(state).await.sign(auth_token.as_bytes());
 
-		trace!("FETCH KEYSHARES : HEALTH CHECK : health response : {:#?}\n", health_response);
-		//debug!("FETCH KEYSHARES : HEALTH CHECK : health response : {:?}\n",
-		// health_response.text().await?);
+	let request = KeyshareCountPacket {
+		enclave_account: get_accountid(state).await,
+		auth_token,
+		signature: format!("{}{:?}", "0x", signature),
+	};
 
-		let response_body: HealthResponse = match health_response.json().await {
-			Ok(body) => body,
-			Err(err) => {
-				let message = format!(
-					"FETCH KEYSHARES : Healthcheck : can not deserialize the body : {} : {:#?}",
-					enclave.enclave_url, err
-				);
-				warn!(message);
-				continue; // Next Cluster
-			},
-		};
+	let response = client.post(request_url).json(&request).send().await?;
 
-		debug!(
-			"FETCH KEYSHARES : Health-Check Result for url : {} is \n{:#?}",
-			enclave.enclave_url, response_body
-		);
+	if response.status() != StatusCode::OK {
+		return Err(anyhow!("keyshare-count response status : {}", response.status()));
+	}
 
-		if health_status != StatusCode::OK {
-			let message = format!(
-				"FETCH KEYSHARES : Healthcheck Failed on url: {}, status : {:#?}, reason : {}",
-				enclave.enclave_url, health_status, response_body.description
-			);
-			error!(message);
-			continue; // Next Cluster
-		} else {
-			last_synced = match response_body.sync_state.parse::<u32>() {
-				Ok(blk) => blk,
-				Err(_) => {
-					let message = format!(
-						"FETCH KEYSHARES : Healthcheck Parse Error on url: {}, status : {:#?}, sync_state : {}",
-						enclave.enclave_url, health_status, response_body.sync_state
-					);
-					error!(message);
-					continue; // Next Cluster
-				},
-			};
-		}
+	let count: KeyshareCountResponse = response.json().await?;
 
-		let request_url = enclave_url.clone() + "/api/backup/sync-keyshare";
+	Ok(count.keyshare_count)
+}
 
-		debug!("FETCH KEYSHARES : request for nft-keyshares");
-		debug!("FETCH KEYSHARES : request url : {}", request_url);
+/// Post a sync-keyshare request to the target enclave, decrypt and extract the received keyshares
+/// # Arguments
+/// * `state` - The shared state
+/// * `client` - The http client
+/// * `enclave_url` - Url of the target enclave, without trailing slash
+/// * `request_body` - The request created by create_fetch_request
+/// * `encryption_private_key` - The private key to decrypt the response
+/// * `backup_file` - Temporary zip file on the disk
+/// # Returns
+/// * The largest received nftid, None if the archive had no keyshare
+async fn download_keyshares(
+	state: &SharedState,
+	client: &reqwest::Client,
+	enclave_url: &str,
+	request_body: String,
+	encryption_private_key: &[u8],
+	backup_file: &str,
+) -> Result<Option<u32>, FetchError> {
+	let request_url = enclave_url.to_string() + "/api/backup/sync-keyshare";
+
+	debug!("FETCH KEYSHARES : request for nft-keyshares");
+	debug!("FETCH KEYSHARES : request url : {}", request_url);
+
+	let fetch_response = client
+		.clone()
+		.post(request_url)
+		.body(request_body)
+		.header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+		.send()
+		.await;
 
-		let fetch_response = client
-			.clone()
-			.post(request_url)
-			.body(request_body.clone())
-			.header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
-			.send()
-			.await;
+	let mut fetch_response = match fetch_response {
+		Ok(res) => res,
+		Err(err) =>
+			return Err(FetchError::Target(format!(
+				"FETCH KEYSHARES : Fetch response error: {:#?}",
+				err
+			))),
+	};
 
-		let fetch_response = match fetch_response {
-			Ok(res) => res,
-			Err(err) => {
-				error!("FETCH KEYSHARES : Fetch response error: {:#?}", err);
-				continue; // Next Cluster
-				 //return Err(anyhow!(err));
-			},
-		};
+	if fetch_response.status() != StatusCode::OK {
+		return Err(FetchError::Target(format!(
+			"FETCH KEYSHARES : Fetch response status : {:#?}",
+			fetch_response.status()
+		)));
+	}
 
-		if fetch_response.status() != StatusCode::OK {
-			error!("FETCH KEYSHARES : Fetch response status : {:#?}", fetch_response.status());
-			continue; // Next Cluster
-			 //return Err(anyhow!(err));
-		}
+	let fetch_headers = fetch_response.headers();
+	trace!("FETCH KEYSHARES : zip response header : {:?}", fetch_headers);
 
-		let fetch_headers = fetch_response.headers();
-		trace!("FETCH KEYSHARES : zip response header : {:?}", fetch_headers);
+	let mut zipfile = match std::fs::File::create(backup_file) {
+		Ok(file) => file,
+		Err(err) => {
+			let message = format!("FETCH KEYSHARES : Can not create file on disk : {}", err);
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("fetch-keyshare", "disk");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			return Err(FetchError::Local(message));
+		},
+	};
 
-		let fetch_body_bytes = fetch_response.bytes().await?;
-		trace!("FETCH KEYSHARES : zip body length : {}", fetch_body_bytes.len());
+	// Decrypt the body chunk by chunk as it is received, the archive is never loaded in memory
+	let mut decryptor = StreamDecryptor::new(encryption_private_key);
+	let mut received_length = 0usize;
 
-		let backup_file = format!("{SEALPATH}/backup_{current_block_number}.zip");
-		let mut zipfile = match std::fs::File::create(backup_file.clone()) {
-			Ok(file) => file,
+	loop {
+		let chunk = match fetch_response.chunk().await {
+			Ok(Some(chunk)) => chunk,
+			Ok(None) => break,
 			Err(err) => {
-				let message = format!("FETCH KEYSHARES : Can not create file on disk : {}", err);
-				error!(message);
-				sentry::with_scope(
-					|scope| {
-						scope.set_tag("fetch-keyshare", "disk");
-					},
-					|| sentry::capture_message(&message, sentry::Level::Error),
-				);
-				return Err(anyhow!(message));
+				let _ = remove_file(backup_file);
+				return Err(FetchError::Target(format!(
+					"FETCH KEYSHARES : Can not read the fetch response body : {err:?}"
+				)));
 			},
 		};
 
-		let decrypt_zip_data = match decrypt(&encryption_private_key, &fetch_body_bytes) {
-			Ok(decrypted) => decrypted,
+		received_length += chunk.len();
+
+		let plain_data = match decryptor.update(&chunk) {
+			Ok(plain) => plain,
 			Err(err) => {
 				let message =
 					format!("FETCH KEYSHARES : Can not decrypt the received file : {:?}", err);
-				error!(message);
 				sentry::with_scope(
 					|scope| {
 						scope.set_tag("fetch-keyshare", "decrypt");
 					},
 					|| sentry::capture_message(&message, sentry::Level::Error),
 				);
-
-				match std::fs::remove_file(backup_file.clone()) {
-					Ok(_) => {
-						debug!("FETCH KEYSHARES : removed fetch zip file")
-					},
-					Err(err) => {
-						let message = format!(
-							"FETCH KEYSHARES : Error : Can not remove fetched zip file : {}",
-							err
-						);
-						warn!(message);
-					},
-				}
-
-				continue; // Next Cluster
-				 //return Err(anyhow!(err));
+				let _ = remove_file(backup_file);
+				return Err(FetchError::Target(message));
 			},
 		};
 
-		match zipfile.write_all(&decrypt_zip_data) {
-			Ok(_) => debug!("FETCH KEYSHARES : decrypted fetch data is stored to zip file."),
-			Err(err) => {
-				let message = format!(
-					"FETCH KEYSHARES : Error writing received nft zip file to disk{:#?}",
-					err
-				);
-				error!(message);
-				sentry::with_scope(
-					|scope| {
-						scope.set_tag("fetch-keyshare", "disk");
-					},
-					|| sentry::capture_message(&message, sentry::Level::Error),
-				);
-				return Err(anyhow!(message));
-			},
+		if let Err(err) = zipfile.write_all(&plain_data) {
+			let message =
+				format!("FETCH KEYSHARES : Error writing received nft zip file to disk{:#?}", err);
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("fetch-keyshare", "disk");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			let _ = remove_file(backup_file);
+			return Err(FetchError::Local(message));
 		}
+	}
 
-		// Check if keyshares are invalid
-		match sync_zip_extract(state, &backup_file).await {
-			Ok(_) => debug!("FETCH KEYSHARES : zip_extract success"),
-			Err(err) => {
-				let message = format!("FETCH KEYSHARES : extracting zip file : {err:?}");
-				error!(message);
-				sentry::with_scope(
-					|scope| {
-						scope.set_tag("fetch-keyshare", "zip");
-					},
-					|| sentry::capture_message(&message, sentry::Level::Error),
-				);
-				//return Err(anyhow!(message));
-			},
-		}
+	trace!("FETCH KEYSHARES : encrypted body length : {}", received_length);
 
-		match remove_file(backup_file) {
-			Ok(_) => debug!("FETCH KEYSHARES : remove zip file successful"),
-			Err(err) => {
-				let message = format!(
-					"FETCH KEYSHARES : Backup success with Error in removing zip file, {:?}",
-					err
-				);
-				error!(message);
-				sentry::with_scope(
-					|scope| {
-						scope.set_tag("nft-retrieve-keyshare", "disk");
-					},
-					|| sentry::capture_message(&message, sentry::Level::Warning),
-				);
-				//return Err(anyhow!(message));
-			},
-		};
+	if let Err(err) = decryptor.finish() {
+		let _ = remove_file(backup_file);
+		return Err(FetchError::Target(format!(
+			"FETCH KEYSHARES : Received file is incomplete : {err:?}"
+		)));
 	}
 
-	Ok(last_synced)
+	debug!("FETCH KEYSHARES : decrypted fetch data is stored to zip file.");
+
+	// Check if keyshares are invalid
+	let last_nftid = match sync_zip_extract(state, backup_file).await {
+		Ok(last_nftid) => {
+			debug!("FETCH KEYSHARES : zip_extract success");
+			last_nftid
+		},
+		Err(err) => {
+			let message = format!("FETCH KEYSHARES : extracting zip file : {err:?}");
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("fetch-keyshare", "zip");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+			// A page that can not be read must not end the paginated setup
+			let _ = remove_file(backup_file);
+			return Err(FetchError::Target(message));
+		},
+	};
+
+	match remove_file(backup_file) {
+		Ok(_) => debug!("FETCH KEYSHARES : remove zip file successful"),
+		Err(err) => {
+			let message = format!(
+				"FETCH KEYSHARES : Backup success with Error in removing zip file, {:?}",
+				err
+			);
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("nft-retrieve-keyshare", "disk");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Warning),
+			);
+		},
+	};
+
+	Ok(last_nftid)
 }
 
 /* ----------------------------
@@ -1534,10 +2160,28 @@ pub async fn cluster_discovery(state: &SharedState) -> Result<bool, anyhow::Erro
 
 	// Update self-identity if changed, for the new enclave is vital, then unlikely.
 	debug!("CLUSTER DISCOVERY : SELF-IDENTITY");
+	// The sealed identity detects a reassignment while the enclave was stopped
+	let sealed_identity = load_sync_state().ok().and_then(|sync_state| sync_state.identity);
+	let previous_identity = get_identity(state).await.or(sealed_identity);
 	let identity = self_identity(state).await;
 
 	set_identity(state, identity).await;
 
+	if identity != sealed_identity {
+		if let Err(err) = update_sync_state(|sync_state| {
+			sync_state.identity = identity;
+			Ok(())
+		}) {
+			debug!("CLUSTER DISCOVERY : can not seal the identity : {err:?}");
+		}
+	}
+
+	if let (Some(previous), Some(new)) = (previous_identity, identity) {
+		if previous != new {
+			handle_identity_change(state, previous, new).await;
+		}
+	}
+
 	Ok(identity.is_some())
 }
 
@@ -1575,45 +2219,11 @@ pub async fn self_identity(state: &SharedState) -> Option<(u32, u32)> {
 
 					Some(identity) =>
 						if identity.1 != enclave.slot {
-							error!("\n*****\nERROR! SLOT HAS BEEN CHANGED. IT IS DANGEROUS ACT BY TC. ENCLAVE MUST WIPE EVERYTHING.\n*****\n");
-							warn!("WIPE EVERYTHING ...");
-
-							let read_dir = match fs::read_dir("/nft") {
-								Ok(rd) => rd,
-								Err(err) => {
-									error!(
-										"SELF-IDENTITY : CAN NOT READ THE SEAL DIRECTORY {:?}",
-										err
-									);
-									return None;
-								},
-							};
-
-							for dir_entry in read_dir {
-								let path = match dir_entry {
-									Ok(de) => de.path(),
-									Err(err) => {
-										error!("SELF-IDENTITY : CAN NOT GET A PATH IN THE SEAL DIRECTORY ENTRY {:?}",err);
-										return None;
-									},
-								};
-								let extension = match path.extension() {
-									Some(ext) => ext,
-									None => {
-										error!("SELF-IDENTITY : CAN NOT GET EXTENTION OF AN ENTRY PATH OF THE SEAL DIRECTORY {:?}",path);
-										return None;
-									},
-								};
-								if extension == OsStr::new("keyshare") ||
-									extension == OsStr::new("log")
-								{
-									warn!("SELF-IDENTITY : REMOVING : {:?}", path);
-									let _ = fs::remove_file(path);
-								}
-							}
-
-							debug!("SELF-IDENTITY : back to setup mode with new identity");
-							let _ = set_sync_state("setup".to_owned());
+							// Keyshares of the previous slot are handled by the reassignment
+							warn!(
+								"SELF-IDENTITY : SLOT HAS BEEN CHANGED FROM {} TO {} BY TECHNICAL COMMITTEE.",
+								identity.1, enclave.slot
+							);
 							return Some((cluster.id, enclave.slot));
 						} else if identity.0 != cluster.id {
 							warn!("SELF-IDENTITY : DANGEROUS ACT FROM TECHNICAL COMMITTEE, CHANGING CLUSTER AT RUNTIME.");
@@ -1680,9 +2290,48 @@ pub async fn slot_discovery(state: &SharedState) -> Vec<(u32, Enclave)> {
 /* --------------------------------------
 	 EVENTS CRAWLER (Maintenace Mode)
 ----------------------------------------- */
+/// Progress of the running crawl, reported in the health-check
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CrawlProgress {
+	pub from_block: u32,
+	pub to_block: u32,
+	// Last parsed block, blocks are parsed in order
+	pub crawled_block: u32,
+	pub blocks_per_second: f64,
+}
+
+type CrawledBody = BlockBody<PolkadotConfig, OnlineClient<PolkadotConfig>>;
+
+// Download the body of a block, None if the block hash is not found
+async fn fetch_block_body(
+	api: &OnlineClient<PolkadotConfig>,
+	block_counter: u32,
+) -> Result<(u32, Option<CrawledBody>)> {
+	let block_number = BlockNumber::from(block_counter);
+	let block_hash = match api.rpc().block_hash(Some(block_number)).await? {
+		Some(hash) => hash,
+		None => return Ok((block_counter, None)),
+	};
+
+	// Read the block from blockchain, and extract its body
+	let block = api.blocks().at(block_hash).await?;
+	let body = block.body().await?;
+
+	Ok((block_counter, Some(body)))
+}
+
 // Detect new NFT synced event and look for corresponding enclaves-slot containing the keyshare
 // It is part of "Running Enclave Synchronization"
 // Result : HashMap of all <NFTID, ClusterID>.
+/// Crawl the blocks for synced nfts, blocks are downloaded in parallel and parsed in order
+/// The nfts found so far and the crawled block are sealed periodically in the sync state, a
+/// restarted enclave resumes from the checkpoint
+/// # Arguments
+/// * `state` - The shared state
+/// * `from_block_num` - First block of the range
+/// * `to_block_num` - Last block of the range
+/// # Returns
+/// * `HashMap<u32, SyncedNFT>` - The synced nfts, with their cluster and block
 pub async fn crawl_sync_events(
 	state: &SharedState,
 	from_block_num: u32,
@@ -1695,32 +2344,94 @@ pub async fn crawl_sync_events(
 	// Storage to find the cluster of an enclave which contains specific NFTID
 	let storage_api = api.storage().at_latest().await?;
 
+	let started = Instant::now();
+	let mut progress = CrawlProgress {
+		from_block: from_block_num,
+		to_block: to_block_num,
+		crawled_block: from_block_num.saturating_sub(1),
+		blocks_per_second: 0.0,
+	};
+	set_crawl_progress(state, Some(progress.clone())).await;
+
+	let result = crawl_blocks(
+		state,
+		&api,
+		&storage_api,
+		from_block_num,
+		to_block_num,
+		started,
+		&mut progress,
+	)
+	.await;
+
+	set_crawl_progress(state, None).await;
+
+	if let Ok(nftid_cluster_map) = &result {
+		info!(
+			"CRAWLER : {} blocks crawled in {} seconds, {} synced nfts found",
+			to_block_num.saturating_sub(from_block_num) + 1,
+			started.elapsed().as_secs(),
+			nftid_cluster_map.len()
+		);
+	}
+
+	result
+}
+
+async fn crawl_blocks(
+	state: &SharedState,
+	api: &OnlineClient<PolkadotConfig>,
+	storage_api: &Storage<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+	from_block_num: u32,
+	to_block_num: u32,
+	started: Instant,
+	progress: &mut CrawlProgress,
+) -> Result<HashMap<u32, SyncedNFT>, anyhow::Error> {
 	// Hashmap for fetch nftid-cluste
 	let mut nftid_cluster_map = HashMap::<u32, SyncedNFT>::new();
+	// Found since the last checkpoint
+	let mut unsaved_nfts = HashMap::<u32, SyncedNFT>::new();
 
-	for block_counter in from_block_num..=to_block_num {
-		// Find block hash
+	// Buffered stream keeps the order of the blocks
+	let mut blocks = futures::stream::iter(from_block_num..=to_block_num)
+		.map(|block_counter| fetch_block_body(api, block_counter))
+		.buffered(CRAWL_PARALLELISM);
+
+	while let Some(fetched) = blocks.next().await {
+		let (block_counter, body) = fetched?;
 		debug!("CRAWLER : block number = {}", block_counter);
-		let block_number = BlockNumber::from(block_counter);
-		let block_hash = match api.rpc().block_hash(Some(block_number)).await? {
-			Some(hash) => hash,
+
+		let body = match body {
+			Some(body) => body,
 			None => {
 				set_chain_api_renew(state, true).await;
 				return Err(anyhow!("CRAWLER : error getting block hash."));
 			},
 		};
 
-		// Read the block from blockchain
-		let block = api.blocks().at(block_hash).await?;
-
-		// Extract block body
-		let body = block.body().await?;
+		let (parsed, _) = parse_block_body(state, block_counter, body, storage_api).await?;
+		unsaved_nfts.extend(parsed.clone());
+		nftid_cluster_map.extend(parsed);
 
-		// Extract block events
-		//let events = block.events().await?;
+		let crawled = block_counter - from_block_num + 1;
+		progress.crawled_block = block_counter;
+		progress.blocks_per_second = crawled as f64 / started.elapsed().as_secs_f64().max(1.0);
+		set_crawl_progress(state, Some(progress.clone())).await;
 
-		let (parsed, _) = parse_block_body(state, block_counter, body, &storage_api).await?;
-		nftid_cluster_map.extend(parsed);
+		if crawled % CRAWL_CHECKPOINT_INTERVAL == 0 && block_counter < to_block_num {
+			match save_crawl_checkpoint(block_counter, &unsaved_nfts) {
+				Ok(_) => {
+					debug!(
+						"CRAWLER : checkpoint at block {block_counter}, {:.1} blocks per second",
+						progress.blocks_per_second
+					);
+					unsaved_nfts.clear();
+				},
+				Err(err) => warn!(
+					"CRAWLER : can not save the checkpoint at block {block_counter} : {err:?}"
+				),
+			}
+		}
 	}
 
 	Ok(nftid_cluster_map)
@@ -1731,10 +2442,20 @@ pub async fn crawl_sync_events(
 ----------------------------------------- */
 #[derive(Debug, Clone)]
 pub struct SyncedNFT {
-	cluster_id: u32,
-	block_number: u32,
+	pub cluster_id: u32,
+	pub block_number: u32,
 }
 
+/// Parse the events of a block for synced nfts and cluster updates
+/// Events are matched whatever the extrinsic that emitted them, calls wrapped in utility.batch,
+/// proxy.proxy or multisig are detected like top-level calls
+/// # Arguments
+/// * `state` - The shared state
+/// * `block_number` - Number of the block
+/// * `body` - Body of the block
+/// * `storage` - Storage to find the cluster of the enclaves
+/// # Returns
+/// * `(HashMap<u32, SyncedNFT>, bool)` - The synced nfts, and whether clusters are updated
 pub async fn parse_block_body(
 	state: &SharedState,
 	block_number: u32,
@@ -1751,7 +2472,6 @@ pub async fn parse_block_body(
 			Ok(ext) => ext,
 			Err(err) => {
 				error!("BLOCK-PARSER : ERROR Parsing extrinsic in block.{block_number} : {err:?}");
-				//continue;
 				// Runtime Upgrade will change the metadata
 				// RPC connection reset will be needed
 				set_chain_api_renew(state, true).await;
@@ -1759,257 +2479,189 @@ pub async fn parse_block_body(
 			},
 		};
 
-		let pallet = match ext.pallet_name() {
-			Ok(pallet) => pallet,
+		// Events of the extrinsic, including the events of its wrapped calls
+		let events = match ext.events().await {
+			Ok(events) => events,
 			Err(err) => {
-				error!("BLOCK-PARSER : ERROR Parsing pallet in block.{block_number} : {err:?}");
-				//continue;
-				// Runtime Upgrade will change the metadata
-				// RPC connection reset will be needed
+				error!("BLOCK-PARSER : ERROR Reading events of extrinsic {} in block.{block_number} : {err:?}", ext.index());
 				set_chain_api_renew(state, true).await;
 				return Err(err.into())
 			},
 		};
 
-		let call =
-			match ext.variant_name() {
-				Ok(call) => call,
-				Err(err) => {
-					error!("BLOCK-PARSER : ERROR Parsing call variant in block.{block_number} : {err:?}");
-					//continue;
-					// Runtime Upgrade will change the metadata
-					// RPC connection reset will be needed
-					set_chain_api_renew(state, true).await;
-					return Err(err.into())
-				},
-			};
-		//debug!(" - crawler extrinsic = {} : {}", pallet, call);
-
-		match pallet.to_uppercase().as_str() {
-			"NFT" => {
-				let events = ext.events().await?;
-				match call.to_uppercase().as_str() {
-					// Capsule
-					"ADD_CAPSULE_SHARD" => {
-						// Capsule Synced Detected?
-						match find_events_capsule_synced(&events) {
-							Some(nftid) => {
-								// Get one of enclaves AccountId32
-								match find_event_capsule_shard_added(&events, nftid) {
-									Some(enclave_account) => {
-										let enclave_operator_address = ternoa::storage().tee().enclave_account_operator(enclave_account.clone());
-										let enclave_operator_account = match storage.fetch(&enclave_operator_address).await? {
-											Some(id) => id,
-											None => {
-												error!("BLOCK-PARSER : NFT : ADD_CAPSULE_SHARD : ERROR : Can not get 'operator account' from enclave account {}, for capsule NFT_ID: {}", enclave_account.to_string(), nftid);
-												continue
-											},
-										};
-
-										let enclave_cluster_address = ternoa::storage().tee().enclave_cluster_id(enclave_operator_account.clone());
-										let cluster_id = match storage.fetch(&enclave_cluster_address).await? {
-											Some(id) => id,
-											None => {
-												error!("BLOCK-PARSER : NFT : ADD_CAPSULE_SHARD : ERROR : Can not get 'cluster_id' from operator {}, for capsule NFT_ID: {}", enclave_operator_account.to_string(), nftid);
-												continue
-											},
-										};
-										new_nft.insert(nftid, SyncedNFT {block_number, cluster_id});
-										info!("BLOCK-PARSER : NFT : ADD_CAPSULE_SHARD : CAPSULE SYNCED EVENT DETECTED, Cluster_ID {}, NFT_ID: {}", cluster_id, nftid);
-									},
-									None => warn!("BLOCK-PARSER : NFT : ADD_CAPSULE_SHARD : ERROR : CAPSULE SYNCED EVENT DETECTED, BUT there is not corresponding CapsuleShardAdded event for nft_id: {}", nftid),
-								}
-							},
-							None => debug!(
-							"BLOCK-PARSER : NFT : ADD_CAPSULE_SHARD : Capsule Synced Event *NOT* Detected for addCapsuleShard Extrinsic"
-						),
-						}
-					}, // end - capsule shard
-
-					// Secret
-					"ADD_SECRET_SHARD" => {
-						// Secret-NFT Synced Detected?
-						match find_events_secret_synced(&events) {
-							Some(nftid) => {
-								// Get one of enclaves AccountId32
-								match find_event_secret_shard_added(&events, nftid) {
-									Some(enclave_account) => {
-										let enclave_operator_address = ternoa::storage().tee().enclave_account_operator(enclave_account.clone());
-										let enclave_operator_account = match storage.fetch(&enclave_operator_address).await? {
-											Some(id) => id,
-											None => {
-												error!("BLOCK-PARSER : NFT : ADD_SECRET_SHARD : ERROR : Can not get operator account from enclave account {}, for secret NFT_ID: {}", enclave_account.to_string(), nftid);
-												continue
-											},
-										};
-
-										let enclave_cluster_address = ternoa::storage().tee().enclave_cluster_id(enclave_operator_account.clone());
-										let cluster_id = match storage.fetch(&enclave_cluster_address).await? {
-											Some(id) => id,
-											None => {
-												error!("BLOCK-PARSER : NFT : ADD_SECRET_SHARD : ERROR : Can not get cluster_id from enclave account {}, for secret NFT_ID: {}", enclave_account.to_string(), nftid);
-												continue
-											},
-										};
-										new_nft.insert(nftid, SyncedNFT {block_number, cluster_id});
-										info!("BLOCK-PARSER : NFT : ADD_SECRET_SHARD : Secret-NFT Synced Event Detected, Cluster_ID {}, NFT_ID: {}", cluster_id, nftid);
-									},
-									None => debug!("BLOCK-PARSER : NFT : ADD_SECRET_SHARD : Secret-NFT Synced Event Detected, but there is not corresponding ShardAdded event for nft_id: {}", nftid),
-								}
-							},
-							None => debug!("BLOCK-PARSER : NFT : ADD_SECRET_SHARD : Secret-NFT Synced Event NOT Detected for addSecretShard Extrinsic"),
-						}
-					}, // end - secret shard
-
-					_ => debug!("BLOCK-PARSER : NFT : extrinsic is not about shards : {}", call),
-				} // end - call
-			}, // end - NFT pallet
-
-			// If the extrinsic pallet is TC
-			"TECHNICALCOMMITTEE" => {
-				let events = ext.events().await?;
-				for evnt in events.iter() {
-					let event = evnt?;
-					let pallet = event.pallet_name();
-
-					// If the event is TEE
-					if pallet.to_uppercase().as_str() == "TEE" {
-						// [decision] : There may be Metric Server updates that we should exclude
-						update_cluster_data = true;
-						info!("BLOCK-PARSER : TECHNICALCOMMITTEE : TechnicalCommittee extrinsic for TEE detected");
-					}
-				}
-			},
+		let sync_events = decode_sync_events(&events)?;
 
-			// If the extrinsic pallet is TEE and it is successfull
-			"TEE" => {
-				let events = ext.events().await?;
-				for evnt in events.iter() {
-					let event = evnt?;
-					let pallet = event.pallet_name();
-					let variant = event.variant_name();
-					// If the event is successful
-					if pallet.to_uppercase().as_str() == "SYSTEM" && variant.to_uppercase().as_str() == "EXTRINSICSUCCESS" {
-						// [question] : Check if this condition is meaningful
-						update_cluster_data = false;
-						debug!("BLOCK-PARSER : TEE : tee-pallet extrinsic detected, it should wait for TC.");
-					}
-				}
-			},
+		// Capsule and Secret
+		for (nftid, nft_type, enclave_account) in synced_shards(&sync_events) {
+			match enclave_account {
+				Some(enclave_account) =>
+					if let Some(cluster_id) = shard_enclave_cluster(storage, &enclave_account, nftid).await? {
+						new_nft.insert(nftid, SyncedNFT { block_number, cluster_id });
+						info!("BLOCK-PARSER : NFT : {nft_type:?} SYNCED EVENT DETECTED, Cluster_ID {}, NFT_ID: {}", cluster_id, nftid);
+					},
+				None => warn!("BLOCK-PARSER : NFT : ERROR : {nft_type:?} SYNCED EVENT DETECTED, BUT there is not corresponding shard added event for nft_id: {}", nftid),
+			}
+		}
 
-			"TIMESTAMP" => continue,
+		// TEE
+		let pallets: Vec<String> =
+			sync_events.iter().map(|event| event.pallet().to_string()).collect();
 
-			_ => trace!("BLOCK-PARSER : [not a nft, tc, tee or timestamp pallet] extrinsic Pallet = {} call = {}", pallet, call),
-		} // end - match pallet
+		if is_approved_tee_change(&pallets) {
+			// [decision] : There may be Metric Server updates that we should exclude
+			update_cluster_data = true;
+			info!("BLOCK-PARSER : TEE : approved TEE change detected in extrinsic {}", ext.index());
+		}
 	} // end - extrinsics loop
 
 	Ok((new_nft, update_cluster_data))
 }
 
+// Cluster of the enclave that added the shard of a synced nft
+async fn shard_enclave_cluster(
+	storage: &Storage<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+	enclave_account: &AccountId32,
+	nftid: u32,
+) -> Result<Option<u32>> {
+	let enclave_operator_account = match enclave_account_operator(storage, enclave_account).await? {
+		Some(id) => id,
+		None => {
+			error!("BLOCK-PARSER : NFT : ERROR : Can not get 'operator account' from enclave account {}, for NFT_ID: {}", enclave_account, nftid);
+			return Ok(None)
+		},
+	};
+
+	match enclave_cluster_id(storage, &enclave_operator_account).await? {
+		Some(id) => Ok(Some(id)),
+		None => {
+			error!("BLOCK-PARSER : NFT : ERROR : Can not get 'cluster_id' from operator {}, for NFT_ID: {}", enclave_operator_account, nftid);
+			Ok(None)
+		},
+	}
+}
+
+// TEE changes of enclaves and clusters are effective when the technical committee (or sudo)
+// executes them, requests of the operators wait for this approval
+fn is_approved_tee_change(pallets: &[String]) -> bool {
+	let tee_event = pallets.iter().any(|pallet| pallet == "TEE");
+	let approved = pallets.iter().any(|pallet| pallet == "TECHNICALCOMMITTEE" || pallet == "SUDO");
+
+	tee_event && approved
+}
+
 /* -----------------------
 	HELPER FUNCTIONS
 --------------------------*/
 
-// Return list of nftids that are synced in this block
-pub fn find_events_capsule_synced(events: &ExtrinsicEvents<PolkadotConfig>) -> Option<u32> {
-	// Get events for the latest block:
-	let cevt = events.find::<CapsuleSynced>();
-	for e in cevt {
-		match e {
-			Ok(ev) => {
-				debug!("FIND_EVENTS_CAPSULE_SYNCED - capsule synced: nft_id: {:?}", ev.nft_id);
-				return Some(ev.nft_id);
-			},
-			Err(err) => {
-				debug!("FIND_EVENTS_CAPSULE_SYNCED - error reading capsule synced : {err:?}");
-			},
-		}
-	}
-	None
+// Event of an extrinsic, a wrapped call emits the same events as a top-level one
+#[derive(Debug, Clone, PartialEq)]
+enum SyncEvent {
+	SecretSynced(u32),
+	CapsuleSynced(u32),
+	SecretShardAdded(u32, AccountId32),
+	CapsuleShardAdded(u32, AccountId32),
+	// Any other event, by its upper-case pallet name
+	Other(String),
 }
 
-// Return list of nftids that are synced in this block
-pub fn find_events_secret_synced(events: &ExtrinsicEvents<PolkadotConfig>) -> Option<u32> {
-	// Get events for the latest block:
-	let sevt = events.find::<SecretNFTSynced>();
-
-	for e in sevt {
-		match e {
-			Ok(ev) => {
-				debug!("FIND_EVENTS_SECRET_SYNCED - secret synced: nft_id: {:?}", ev.nft_id);
-				return Some(ev.nft_id);
-			},
-			Err(err) => {
-				debug!("FIND_EVENTS_SECRET_SYNCED - error reading secret synced : {err:?}");
-			},
+impl SyncEvent {
+	fn pallet(&self) -> &str {
+		match self {
+			SyncEvent::Other(pallet) => pallet,
+			_ => "NFT",
 		}
 	}
-	None
 }
 
-// Return enclave Account, it can be used to find the cluster
-pub fn find_event_capsule_shard_added(
+// Decode the events of an extrinsic, an event that can not be decoded is kept by its pallet
+fn decode_sync_events(
 	events: &ExtrinsicEvents<PolkadotConfig>,
-	nftid: u32,
-) -> Option<AccountId32> {
-	let acevt = events.find::<ternoa::nft::events::CapsuleShardAdded>();
-
-	for e in acevt {
-		match e {
-			Ok(ev) =>
-				if ev.nft_id == nftid {
-					debug!("FIND_EVENT_CAPSULE_SHARD_ADDED - found a capsule added for given nftid : {}", nftid);
-					return Some(ev.enclave);
-				},
+) -> Result<Vec<SyncEvent>, subxt::Error> {
+	let mut sync_events = Vec::new();
+
+	for event in events.iter() {
+		let event = event?;
+
+		let decoded = match (event.pallet_name(), event.variant_name()) {
+			(SecretNFTSynced::PALLET, SecretNFTSynced::EVENT) => event
+				.as_event::<SecretNFTSynced>()
+				.map(|ev| ev.map(|ev| SyncEvent::SecretSynced(ev.nft_id))),
+			(CapsuleSynced::PALLET, CapsuleSynced::EVENT) => event
+				.as_event::<CapsuleSynced>()
+				.map(|ev| ev.map(|ev| SyncEvent::CapsuleSynced(ev.nft_id))),
+			(ShardAdded::PALLET, ShardAdded::EVENT) => event
+				.as_event::<ShardAdded>()
+				.map(|ev| ev.map(|ev| SyncEvent::SecretShardAdded(ev.nft_id, ev.enclave))),
+			(CapsuleShardAdded::PALLET, CapsuleShardAdded::EVENT) => event
+				.as_event::<CapsuleShardAdded>()
+				.map(|ev| ev.map(|ev| SyncEvent::CapsuleShardAdded(ev.nft_id, ev.enclave))),
+			_ => Ok(None),
+		};
+
+		let sync_event = match decoded {
+			Ok(Some(sync_event)) => sync_event,
+			Ok(None) => SyncEvent::Other(event.pallet_name().to_uppercase()),
 			Err(err) => {
-				debug!("FIND_EVENT_CAPSULE_SHARD_ADDED - error reading capsule added : {:?}", err);
+				debug!(
+					"DECODE_SYNC_EVENTS - error reading {}.{} : {err:?}",
+					event.pallet_name(),
+					event.variant_name()
+				);
+				SyncEvent::Other(event.pallet_name().to_uppercase())
 			},
-		}
+		};
+
+		sync_events.push(sync_event);
 	}
 
-	None
+	Ok(sync_events)
 }
 
-// Return enclave Account, it can be used to find the cluster
-pub fn find_event_secret_shard_added(
-	events: &ExtrinsicEvents<PolkadotConfig>,
-	nftid: u32,
-) -> Option<AccountId32> {
-	let asevt = events.find::<ternoa::nft::events::ShardAdded>();
-
-	for e in asevt {
-		match e {
-			Ok(ev) =>
-				if ev.nft_id == nftid {
-					debug!(
-						"FIND_EVENT_SECRET_SHARD_ADDED - found a secret added for given nftid : {}",
-						nftid
-					);
-					return Some(ev.enclave);
-				},
-			Err(err) => {
-				debug!("FIND_EVENT_SECRET_SHARD_ADDED - error reading secret added : {:?}", err);
-			},
-		}
-	}
+// Synced nfts of an extrinsic with the enclave that added their shard, a batch may sync several
+// nfts
+fn synced_shards(sync_events: &[SyncEvent]) -> Vec<(u32, NftType, Option<AccountId32>)> {
+	let shard_enclave = |nftid: u32, nft_type: NftType| {
+		sync_events.iter().find_map(|event| match (event, nft_type) {
+			(SyncEvent::SecretShardAdded(id, enclave), NftType::Secret) |
+			(SyncEvent::CapsuleShardAdded(id, enclave), NftType::Capsule)
+				if *id == nftid =>
+				Some(enclave.clone()),
+			_ => None,
+		})
+	};
 
-	None
+	sync_events
+		.iter()
+		.filter_map(|event| match event {
+			SyncEvent::SecretSynced(nftid) => Some((*nftid, NftType::Secret)),
+			SyncEvent::CapsuleSynced(nftid) => Some((*nftid, NftType::Capsule)),
+			_ => None,
+		})
+		.map(|(nftid, nft_type)| (nftid, nft_type, shard_enclave(nftid, nft_type)))
+		.collect()
 }
 
-// Read Sync State File
+// Read Sync State File, as the bare string of the phase
 pub fn get_sync_state() -> Result<String> {
-	match std::fs::read_to_string(SYNC_STATE_FILE) {
-		Ok(state) => Ok(state),
-		Err(err) => Err(err.into()),
+	Ok(load_sync_state()?.legacy())
+}
+
+// Setup-mode sync state, with the first nftid of the next page to fetch : "setup_1500"
+pub fn is_setup_state(sync_state: &str) -> bool {
+	sync_state == "setup" || sync_state.starts_with("setup_")
+}
+
+// First nftid not fetched yet by an interrupted setup synchronization
+pub fn setup_progress(sync_state: &str) -> u32 {
+	match sync_state.strip_prefix("setup_") {
+		Some(progress) => progress.parse::<u32>().unwrap_or(0),
+		None => 0,
 	}
 }
 
-// Write to Sync State File
+// Write the phase to Sync State File, pending and failed NFTs are kept
 pub fn set_sync_state(state: String) -> Result<()> {
-	let mut statefile =
-		std::fs::OpenOptions::new().write(true).truncate(true).open(SYNC_STATE_FILE)?;
-
-	let _len = statefile.write(state.as_bytes())?;
+	update_sync_state(|sync_state| sync_state.apply_legacy(&state))?;
 
 	Ok(())
 }
@@ -2024,7 +2676,7 @@ use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 pub async fn sync_zip_extract(
 	state: &SharedState,
 	zip_file_name: &str,
-) -> Result<(), async_zip::error::ZipError> {
+) -> Result<Option<u32>, async_zip::error::ZipError> {
 	let infile = match tokio::fs::File::open(zip_file_name).await {
 		Ok(file) => file,
 		Err(err) => {
@@ -2043,6 +2695,11 @@ pub async fn sync_zip_extract(
 		},
 	};
 
+	// Largest nftid of the archive, the cursor of a paginated setup
+	let mut last_nftid: Option<u32> = None;
+	// Extracted nfts, their retry entries are cleared
+	let mut received = Vec::<u32>::new();
+
 	for index in 0..reader.file().entries().len() {
 		let entry =
 			match reader.file().entries().get(index) {
@@ -2174,6 +2831,8 @@ pub async fn sync_zip_extract(
 			},
 		};
 
+		last_nftid = last_nftid.max(Some(nftid));
+
 		match get_nft_availability(state, nftid).await {
 			// NEW NFT KEY
 			None => {
@@ -2262,6 +2921,7 @@ pub async fn sync_zip_extract(
 
 				// UPDATE MAP
 				set_nft_availability(state, (nftid, availability)).await;
+				received.push(nftid);
 			},
 
 			// UPDATE CAPSULE/HYBRID KEY
@@ -2349,6 +3009,7 @@ pub async fn sync_zip_extract(
 						),
 					)
 					.await;
+					received.push(nftid);
 				} else if name_parts[0] == "capsule" && av.nft_type == NftType::Capsule {
 					if av.block_number >= keyshare_blocknumber {
 						// OUTDATED SYNCING FILE
@@ -2426,6 +3087,7 @@ pub async fn sync_zip_extract(
 					};
 
 					set_nft_availability(state, (nftid, availability)).await;
+					received.push(nftid);
 
 					let old_file_path =
 						format!("{SEALPATH}/capsule_{nftid}_{}.keyshare", av.block_number);
@@ -2443,7 +3105,14 @@ pub async fn sync_zip_extract(
 		}; // AVAILABILITY CONDITION
 	} // FILE in ZIP-ARCHIVE
 
-	Ok(())
+	// Exhausted entries are not retried anymore, they are removed when the keyshare lands
+	if !received.is_empty() {
+		if let Err(err) = clear_sync_entries(&received) {
+			warn!("FETCH KEYSHARES : ZIP EXTRACT : can not clear the received nfts from the sync state : {err:?}");
+		}
+	}
+
+	Ok(last_nftid)
 }
 
 /* -----------------------------
@@ -2467,6 +3136,7 @@ mod test {
 	use tracing_subscriber::FmtSubscriber; // for `oneshot` and `ready`
 
 	use crate::{
+		constants::NFT_CACHE_SIZE,
 		core::{chain::create_chain_api, helper},
 		server::state::StateConfig,
 	};
@@ -2491,7 +3161,7 @@ mod test {
 			BTreeMap::<u32, helper::Availability>::new(),
 		)));
 
-		let mut app = match crate::server::http_server::http_server().await {
+		let mut app = match crate::server::http_server::http_server(NFT_CACHE_SIZE).await {
 			Ok(r) => r,
 			Err(err) => {
 				error!("Error creating http server {}", err);
@@ -2560,4 +3230,78 @@ mod test {
 				.unwrap();
 		println!("\n A tee event has happened, fetch the cluster data? : {}\n", tee_events);
 	}
+
+	#[test]
+	fn setup_state_progress_test() {
+		assert!(is_setup_state("setup"));
+		assert!(is_setup_state("setup_1500"));
+		assert!(!is_setup_state("12345"));
+		assert!(!is_setup_state(""));
+
+		assert_eq!(setup_progress("setup"), 0);
+		assert_eq!(setup_progress("setup_1500"), 1500);
+		assert_eq!(setup_progress("setup_corrupted"), 0);
+	}
+
+	#[test]
+	fn approved_tee_change_test() {
+		let pallets =
+			|names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+		// Technical committee proposal, possibly wrapped in a batch or a multisig
+		assert!(is_approved_tee_change(&pallets(&["TECHNICALCOMMITTEE", "TEE", "SYSTEM"])));
+		assert!(is_approved_tee_change(&pallets(&["MULTISIG", "TEE", "SUDO", "SYSTEM"])));
+
+		// Operator requests wait for the approval
+		assert!(!is_approved_tee_change(&pallets(&["TEE", "SYSTEM"])));
+		assert!(!is_approved_tee_change(&pallets(&["TECHNICALCOMMITTEE", "SYSTEM"])));
+	}
+
+	#[test]
+	fn wrapped_calls_events_test() {
+		let enclave = AccountId32::from([1u8; 32]);
+		let other = |pallet: &str| SyncEvent::Other(pallet.to_string());
+		let pallets = |events: &[SyncEvent]| {
+			events.iter().map(|event| event.pallet().to_string()).collect::<Vec<_>>()
+		};
+
+		// sudo(utility.batch([tee.approve_enclave, nft.add_secret_shard, nft.add_capsule_shard]))
+		let events = vec![
+			other("TEE"),
+			other("UTILITY"),
+			SyncEvent::SecretShardAdded(7, enclave.clone()),
+			SyncEvent::SecretSynced(7),
+			other("UTILITY"),
+			SyncEvent::CapsuleShardAdded(8, enclave.clone()),
+			SyncEvent::CapsuleSynced(8),
+			other("UTILITY"),
+			other("UTILITY"),
+			other("SUDO"),
+			other("TRANSACTIONPAYMENT"),
+			other("SYSTEM"),
+		];
+
+		assert_eq!(
+			synced_shards(&events),
+			vec![
+				(7, NftType::Secret, Some(enclave.clone())),
+				(8, NftType::Capsule, Some(enclave.clone())),
+			]
+		);
+		assert!(is_approved_tee_change(&pallets(&events)));
+
+		// A shard of the other type does not match the synced nft
+		let events = vec![
+			SyncEvent::CapsuleShardAdded(9, enclave.clone()),
+			SyncEvent::SecretSynced(9),
+			other("UTILITY"),
+			other("SYSTEM"),
+		];
+		assert_eq!(synced_shards(&events), vec![(9, NftType::Secret, None)]);
+
+		// utility.batch of an operator, the TEE change waits for the approval
+		let events = vec![other("TEE"), other("UTILITY"), other("UTILITY"), other("SYSTEM")];
+		assert!(synced_shards(&events).is_empty());
+		assert!(!is_approved_tee_change(&pallets(&events)));
+	}
 }
@@ -0,0 +1,496 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex,
+	},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	constants::{MAX_SYNC_ATTEMPTS, MAX_SYNC_BACKOFF, SYNC_STATE_FILE},
+	replication::{
+		audit::{verify_admin_read, AdminReadPacket},
+		maintenance::sealed_writes_paused,
+		manifest::BackupChainHead,
+		reassignment::Reassignment,
+		sync::{fetch_keyshares, SyncedNFT},
+	},
+	server::state::{get_blocknumber, SharedState},
+};
+
+/* ---------------------------------------
+	STRUCTURED SYNC STATE
+------------------------------------------ */
+
+// Only one task at a time can read-modify-write the state file
+static SYNC_STATE_LOCK: Mutex<()> = Mutex::new(());
+
+static RETRYING: AtomicBool = AtomicBool::new(false);
+
+/// Synchronization phase of the enclave
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPhase {
+	// Enclave is not registered on chain
+	#[default]
+	Unregistered,
+	// First synchronization of all the keyshares of the slot
+	Setup,
+	// Synchronized, new keyshares are fetched block by block
+	Running,
+}
+
+/// Keyshare of an NFT that is not synchronized yet
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct NftSyncEntry {
+	pub cluster_id: u32,
+	pub block_number: u32,
+	pub attempts: u32,
+	pub last_peer: Option<String>,
+	pub last_error: Option<String>,
+	pub last_attempt_block: u32,
+	// The retry worker skips the entry until this block
+	pub retry_after_block: u32,
+}
+
+/// Sync state, sealed in SYNC_STATE_FILE
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SyncState {
+	pub phase: SyncPhase,
+	// First nftid not fetched yet by an interrupted setup synchronization
+	#[serde(default)]
+	pub setup_progress: u32,
+	// Last block whose new keyshares are all fetched or queued for retry
+	#[serde(default)]
+	pub last_synced_block: u32,
+	#[serde(default)]
+	pub pending: BTreeMap<u32, NftSyncEntry>,
+	#[serde(default)]
+	pub failed: BTreeMap<u32, NftSyncEntry>,
+	#[serde(default)]
+	pub last_peer: Option<String>,
+	// Block an interrupted crawl has reached, its nfts are queued in pending
+	#[serde(default)]
+	pub crawl_checkpoint: Option<u32>,
+	// (ClusterID, SlotID) of the enclave
+	#[serde(default)]
+	pub identity: Option<(u32, u32)>,
+	// Slot reassignments, keyshares of the previous slots wait for the peers to be removed
+	#[serde(default)]
+	pub reassignments: Vec<Reassignment>,
+	// Last restored admin backup, restored increments must continue it
+	#[serde(default)]
+	pub backup_chain: Option<BackupChainHead>,
+}
+
+impl SyncState {
+	/// Parse the state file, the bare strings of previous versions are converted
+	pub fn parse(content: &str) -> Result<SyncState> {
+		let content = content.trim();
+		if content.starts_with('{') {
+			return Ok(serde_json::from_str(content)?);
+		}
+
+		let mut sync_state = SyncState::default();
+		sync_state.apply_legacy(content)?;
+
+		Ok(sync_state)
+	}
+
+	/// Bare string of the state : "", "setup", "setup_1500" or the last synced block
+	pub fn legacy(&self) -> String {
+		match self.phase {
+			SyncPhase::Unregistered => String::new(),
+			SyncPhase::Setup if self.setup_progress == 0 => "setup".to_string(),
+			SyncPhase::Setup => format!("setup_{}", self.setup_progress),
+			SyncPhase::Running => self.last_synced_block.to_string(),
+		}
+	}
+
+	/// Update the phase from a bare string, NFT entries are kept
+	pub fn apply_legacy(&mut self, legacy: &str) -> Result<()> {
+		if legacy.is_empty() {
+			self.phase = SyncPhase::Unregistered;
+			self.setup_progress = 0;
+		} else if legacy == "setup" || legacy.starts_with("setup_") {
+			self.phase = SyncPhase::Setup;
+			self.setup_progress = legacy
+				.strip_prefix("setup_")
+				.and_then(|progress| progress.parse::<u32>().ok())
+				.unwrap_or(0);
+		} else {
+			let block_number = legacy
+				.parse::<u32>()
+				.map_err(|err| anyhow!("invalid sync state '{legacy}' : {err:?}"))?;
+			self.phase = SyncPhase::Running;
+			self.setup_progress = 0;
+			self.last_synced_block = block_number;
+
+			if matches!(self.crawl_checkpoint, Some(checkpoint) if checkpoint <= block_number) {
+				self.crawl_checkpoint = None;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// First block to crawl after a restart
+	pub fn resume_block(&self) -> u32 {
+		self.crawl_checkpoint
+			.map_or(self.last_synced_block, |checkpoint| checkpoint.max(self.last_synced_block))
+	}
+
+	/// Failed entries that can be retried at the block
+	pub fn retryable(&self, block_number: u32) -> HashMap<u32, SyncedNFT> {
+		self.failed
+			.iter()
+			.filter(|(_, entry)| {
+				entry.attempts < MAX_SYNC_ATTEMPTS && entry.retry_after_block <= block_number
+			})
+			.map(|(nftid, entry)| {
+				(
+					*nftid,
+					SyncedNFT { cluster_id: entry.cluster_id, block_number: entry.block_number },
+				)
+			})
+			.collect()
+	}
+
+	fn mark_pending(&mut self, nfts: &HashMap<u32, SyncedNFT>, block_number: u32) {
+		for (nftid, nft) in nfts {
+			// A retried entry keeps its attempts
+			let mut entry = self.failed.remove(nftid).unwrap_or_default();
+			entry.cluster_id = nft.cluster_id;
+			entry.block_number = nft.block_number;
+			entry.last_attempt_block = block_number;
+			self.pending.insert(*nftid, entry);
+		}
+	}
+
+	fn settle(&mut self, missing: &[u32], attempt: &SyncAttempt, block_number: u32) {
+		for nftid in &attempt.nftids {
+			let mut entry = match self.pending.remove(nftid) {
+				Some(entry) => entry,
+				None => continue,
+			};
+
+			if !missing.contains(nftid) {
+				continue;
+			}
+
+			entry.attempts += 1;
+			entry.last_peer = attempt.last_peer.clone();
+			entry.last_error = attempt.last_error.clone();
+			entry.last_attempt_block = block_number;
+			entry.retry_after_block = block_number
+				.saturating_add(2u32.saturating_pow(entry.attempts).min(MAX_SYNC_BACKOFF));
+			self.failed.insert(*nftid, entry);
+		}
+
+		if attempt.last_peer.is_some() {
+			self.last_peer = attempt.last_peer.clone();
+		}
+	}
+
+	// Received NFTs are not pending or failed anymore, whatever their attempts
+	fn clear(&mut self, nftids: &[u32]) {
+		for nftid in nftids {
+			self.pending.remove(nftid);
+			self.failed.remove(nftid);
+		}
+	}
+
+	// An interrupted fetch leaves its entries pending, they are retried as failed ones
+	fn recover_pending(&mut self) -> usize {
+		let pending = std::mem::take(&mut self.pending);
+		let count = pending.len();
+		self.failed.extend(pending);
+		count
+	}
+}
+
+/// Outcome of fetching a list of NFTs from the peers of the slot
+#[derive(Debug, Default)]
+pub struct SyncAttempt {
+	pub nftids: Vec<u32>,
+	pub last_peer: Option<String>,
+	pub last_error: Option<String>,
+}
+
+/// Read the sync state file
+pub fn load_sync_state() -> Result<SyncState> {
+	let content = std::fs::read_to_string(SYNC_STATE_FILE)?;
+	SyncState::parse(&content)
+}
+
+fn seal_sync_state(sync_state: &SyncState) -> Result<()> {
+	let temporary_file = format!("{SYNC_STATE_FILE}.tmp");
+	std::fs::write(&temporary_file, serde_json::to_string(sync_state)?)?;
+	std::fs::rename(&temporary_file, SYNC_STATE_FILE)?;
+
+	Ok(())
+}
+
+/// Read, modify and seal the sync state file
+/// # Arguments
+/// * `update` - Modification of the state
+/// # Returns
+/// * `SyncState` - The sealed state
+pub fn update_sync_state<F>(update: F) -> Result<SyncState>
+where
+	F: FnOnce(&mut SyncState) -> Result<()>,
+{
+	let _guard = SYNC_STATE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+	let mut sync_state = load_sync_state()?;
+	update(&mut sync_state)?;
+	seal_sync_state(&sync_state)?;
+
+	Ok(sync_state)
+}
+
+/// Record the NFTs that are requested from the peers
+pub fn mark_sync_pending(nfts: &HashMap<u32, SyncedNFT>, block_number: u32) -> Result<()> {
+	update_sync_state(|sync_state| {
+		sync_state.mark_pending(nfts, block_number);
+		Ok(())
+	})
+	.map(|_| ())
+}
+
+/// Remove the received NFTs, the missing ones are queued for retry
+pub fn settle_sync_attempt(
+	missing: &[u32],
+	attempt: &SyncAttempt,
+	block_number: u32,
+) -> Result<()> {
+	update_sync_state(|sync_state| {
+		sync_state.settle(missing, attempt, block_number);
+		Ok(())
+	})
+	.map(|_| ())
+}
+
+/// Remove the NFTs whose keyshares are received, including the exhausted ones
+pub fn clear_sync_entries(nftids: &[u32]) -> Result<()> {
+	update_sync_state(|sync_state| {
+		sync_state.clear(nftids);
+		Ok(())
+	})
+	.map(|_| ())
+}
+
+/// Seal the block a crawl has reached, with the NFTs found since the previous checkpoint
+pub fn save_crawl_checkpoint(block_number: u32, nfts: &HashMap<u32, SyncedNFT>) -> Result<()> {
+	update_sync_state(|sync_state| {
+		sync_state.mark_pending(nfts, block_number);
+		sync_state.crawl_checkpoint = Some(block_number);
+		Ok(())
+	})
+	.map(|_| ())
+}
+
+/// Queue the NFTs left pending by a previous run for retry
+pub fn recover_pending_entries() -> Result<usize> {
+	let mut recovered = 0;
+	update_sync_state(|sync_state| {
+		recovered = sync_state.recover_pending();
+		Ok(())
+	})?;
+
+	Ok(recovered)
+}
+
+/* ---------------------------------------
+	RETRY WORKER
+------------------------------------------ */
+
+/// Retry the failed NFTs in the background, if no retry is running
+pub fn spawn_sync_retry(state: SharedState) {
+	if RETRYING.swap(true, Ordering::SeqCst) {
+		debug!("SYNC RETRY : previous retry is still running");
+		return;
+	}
+
+	tokio::spawn(async move {
+		match retry_failed_keyshares(&state).await {
+			Ok(0) => debug!("SYNC RETRY : no failed keyshare to retry"),
+			Ok(count) => info!("SYNC RETRY : {count} failed keyshares are recovered"),
+			Err(err) => {
+				let message = format!("SYNC RETRY : retry failed : {err:?}");
+				error!(message);
+				sentry::with_scope(
+					|scope| {
+						scope.set_tag("sync-retry", "failed-nfts");
+					},
+					|| sentry::capture_message(&message, sentry::Level::Error),
+				);
+			},
+		}
+
+		RETRYING.store(false, Ordering::SeqCst);
+	});
+}
+
+async fn retry_failed_keyshares(state: &SharedState) -> Result<usize> {
+	let sync_state = load_sync_state()?;
+	if sync_state.phase != SyncPhase::Running {
+		return Ok(0);
+	}
+
+	if let Some(reason) = sealed_writes_paused(state).await {
+		debug!("SYNC RETRY : retry is paused, {reason}");
+		return Ok(0);
+	}
+
+	let block_number = get_blocknumber(state).await;
+	let retryable = sync_state.retryable(block_number);
+	if retryable.is_empty() {
+		return Ok(0);
+	}
+
+	let exhausted = sync_state
+		.failed
+		.values()
+		.filter(|entry| entry.attempts >= MAX_SYNC_ATTEMPTS)
+		.count();
+	if exhausted > 0 {
+		warn!("SYNC RETRY : {exhausted} keyshares reached the maximum attempts, only anti-entropy can recover them");
+	}
+
+	debug!("SYNC RETRY : retry nfts {:?}", retryable.keys().collect::<Vec<_>>());
+	let outcome = fetch_keyshares(state, &retryable).await?;
+	if !outcome.pending.is_empty() {
+		debug!("SYNC RETRY : nfts {:?} are still pending", outcome.pending);
+	}
+
+	Ok(retryable.len() - outcome.pending.len())
+}
+
+/* ---------------------------------------
+	SYNC STATE ENDPOINT
+------------------------------------------ */
+
+/// Detailed sync state, with the pending and failed NFTs, requested by an admin
+/// # Arguments
+/// * `state` - The enclave state
+/// * `request` - The signed request of an admin, for the "sync-state" endpoint
+pub async fn sync_state_handler(
+	State(state): State<SharedState>,
+	Json(request): Json<AdminReadPacket>,
+) -> Response {
+	if let Err((status, message)) = verify_admin_read(&state, &request).await {
+		warn!("SYNC STATE : {message}");
+		return (status, Json(json!({ "error": message }))).into_response();
+	}
+
+	if request.request != "sync-state" {
+		warn!("SYNC STATE : Request is not a sync-state request");
+		return (StatusCode::BAD_REQUEST, Json(json!({"error": "not a sync-state request"})))
+			.into_response();
+	}
+
+	match load_sync_state() {
+		Ok(sync_state) => (
+			StatusCode::OK,
+			Json(json!({
+				"block_number": get_blocknumber(&state).await,
+				"sync_state": sync_state,
+			})),
+		),
+		Err(err) => {
+			error!("SYNC STATE : can not read the sync state : {err:?}");
+			(
+				StatusCode::INTERNAL_SERVER_ERROR,
+				Json(json!({"error": "can not read the sync state"})),
+			)
+		},
+	}
+	.into_response()
+}
+
+/* ----------------------------------
+SYNC STATE TEST
+----------------------------------*/
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn legacy_sync_state_test() {
+		for legacy in ["", "setup", "setup_1500", "123456"] {
+			assert_eq!(SyncState::parse(legacy).unwrap().legacy(), legacy);
+		}
+
+		assert_eq!(SyncState::parse("setup").unwrap().phase, SyncPhase::Setup);
+		assert_eq!(SyncState::parse("123456").unwrap().last_synced_block, 123456);
+		assert!(SyncState::parse("corrupted").is_err());
+
+		// Structured state round-trip
+		let mut sync_state = SyncState::parse("100").unwrap();
+		sync_state.apply_legacy("200").unwrap();
+		let content = serde_json::to_string(&sync_state).unwrap();
+		assert_eq!(SyncState::parse(&content).unwrap(), sync_state);
+
+		// A crawl checkpoint is cleared once the synced block reaches it
+		sync_state.crawl_checkpoint = Some(700);
+		assert_eq!(sync_state.resume_block(), 700);
+		sync_state.apply_legacy("500").unwrap();
+		assert_eq!(sync_state.crawl_checkpoint, Some(700));
+		sync_state.apply_legacy("800").unwrap();
+		assert_eq!((sync_state.crawl_checkpoint, sync_state.resume_block()), (None, 800));
+	}
+
+	#[test]
+	fn failed_entries_test() {
+		let mut sync_state = SyncState::parse("100").unwrap();
+		let nfts = HashMap::from([
+			(1, SyncedNFT { cluster_id: 2, block_number: 90 }),
+			(2, SyncedNFT { cluster_id: 2, block_number: 95 }),
+		]);
+
+		sync_state.mark_pending(&nfts, 100);
+		assert_eq!(sync_state.pending.len(), 2);
+
+		let attempt = SyncAttempt {
+			nftids: vec![1, 2],
+			last_peer: Some("peer".to_string()),
+			last_error: Some("timeout".to_string()),
+		};
+		sync_state.settle(&[2], &attempt, 100);
+
+		assert!(sync_state.pending.is_empty());
+		assert_eq!(sync_state.failed.len(), 1);
+		let entry = &sync_state.failed[&2];
+		assert_eq!((entry.attempts, entry.retry_after_block), (1, 102));
+		assert_eq!(entry.last_peer.as_deref(), Some("peer"));
+
+		// Backoff delays the retry
+		assert!(sync_state.retryable(101).is_empty());
+		assert_eq!(sync_state.retryable(102).len(), 1);
+
+		// A retry keeps the attempts
+		let retryable = sync_state.retryable(102);
+		sync_state.mark_pending(&retryable, 102);
+		assert_eq!(sync_state.pending[&2].attempts, 1);
+		assert_eq!(sync_state.recover_pending(), 1);
+		assert_eq!(sync_state.failed[&2].attempts, 1);
+
+		// An exhausted entry is removed when its keyshare is received
+		sync_state.failed.get_mut(&2).unwrap().attempts = MAX_SYNC_ATTEMPTS;
+		assert!(sync_state.retryable(u32::MAX).is_empty());
+		sync_state.clear(&[2]);
+		assert!(sync_state.failed.is_empty());
+	}
+}
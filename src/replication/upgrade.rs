@@ -1,14 +1,1372 @@
 /*
+	Migration of an enclave account to a new enclave binary
 
-1. We will introduce Admin enclaves
-2. The operator that wants to upgrade enclave binary will:
-	2-1. Construct payload with its own enclave account key
-	2-2. Sign the payload with operator private key
-	2-3. Send the signed message to Admin TEE enclave.
-	2-4. Admin TEE enclave will expose an API to receive this message.
-3. The operator will install and start the new enclave binary
-4. The new enclave binary will request admin TEE for the key backup. Need an UI where operator can trigger this.
-5. The Admin TEE will do remote attestation of the enclave . If succeeds, it will return the payload signed with the public key of the operator.
-6. The enclave will replace the existing account key , with the backup received from Admin TEE.
-
- */
+	1. The operator of the enclave signs an escrow request and sends it to an admin enclave.
+	2. The admin enclave requests the account from the running enclave, with its own quote.
+	   The running enclave checks the operator request, attests the admin enclave
+	   and hands the account over, encrypted for the key bound in the quote.
+	   The admin enclave seals the escrow and confirms it to the running enclave, which makes
+	   the handover final. An escrow which can not be confirmed is dropped.
+	3. The operator installs and starts the new enclave binary, then signs a release request
+	   and sends it to the new enclave.
+	4. The new enclave requests the account from the admin enclave, with its own quote.
+	   The admin enclave attests the new binary against the enclave policy
+	   and releases the account, encrypted for the key bound in the quote.
+	5. The new enclave replaces its account with the released one, then confirms the install
+	   to the admin enclave, which removes the escrow.
+
+	Once handed over, the running enclave stays read-only and stops its extrinsics.
+	Until the admin enclave confirms the escrow, the operator can roll the handover back.
+	An escrow which is never confirmed is removed at its expiry.
+*/
+
+use std::{
+	collections::BTreeMap,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex,
+	},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+	extract::State,
+	http::{header, StatusCode},
+	response::{IntoResponse, Response},
+	Json,
+};
+use ecies::utils::generate_keypair;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use subxt::ext::sp_core::{crypto::Ss58Codec, sr25519, Pair};
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	attestation::{
+		dcap::ReportBody,
+		policy::verify_enclave_policy,
+		ra::{get_quote_content, write_user_report_data, QuoteResponse},
+	},
+	constants::{
+		ENCLAVE_ACCOUNT_FILE, UPGRADE_ESCROW_EXPIRATION, UPGRADE_ESCROW_FILE, UPGRADE_HANDOVER_FILE,
+	},
+	replication::{
+		maintenance::{enter_maintenance, MaintenanceMode, MaintenanceState},
+		metric,
+		staging::RestoreLock,
+		sync::{
+			self, cluster_discovery, sync_client, verify_requester_quote, verify_signature,
+			ClusterType, Enclave, ValidationResult,
+		},
+	},
+	server::state::{
+		get_accountid, get_blocknumber, get_clusters, get_identity, get_keypair,
+		get_maintenance_mode, set_keypair, SharedState,
+	},
+};
+
+/* ---------------------------------------
+	ENCLAVE UPGRADE
+------------------------------------------ */
+
+// Read-modify-seal of the escrow file
+static ESCROW_LOCK: Mutex<()> = Mutex::new(());
+
+// The account of this enclave is handed over, loaded from the sealed handover at start
+static HANDED_OVER: AtomicBool = AtomicBool::new(false);
+
+// Handover, confirmation and rollback of the account are serialized
+static HANDOVER_LOCK: AsyncMutex<()> = AsyncMutex::const_new(());
+
+const INSTALLED_DATA: &str = "upgrade-installed";
+const ESCROWED_DATA: &str = "upgrade-escrowed";
+
+/// Step of the migration authorized by the operator
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpgradeStep {
+	// The admin enclave takes the account of the running enclave in escrow
+	Escrow,
+	// The new enclave receives the escrowed account
+	Release,
+	// The running enclave takes its account back, before the escrow is confirmed
+	Rollback,
+}
+
+/// Migration request of an operator
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UpgradeRequest {
+	pub step: UpgradeStep,
+	// Registered account of the upgraded enclave
+	pub enclave_account: String,
+	// Admin enclave keeping the escrow
+	pub admin_account: String,
+}
+
+/// Migration request signed by the operator
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UpgradeAuthorization {
+	operator_address: String,
+	// Serialized UpgradeRequest
+	request: String,
+	auth_token: String,
+	signature: String,
+}
+
+/// Request of an enclave for an account, the quote binds the encryption key to the requester
+#[derive(Serialize, Deserialize)]
+pub struct AttestedUpgradePacket {
+	enclave_account: String,
+	authorization: UpgradeAuthorization,
+	quote: String,
+	encryption_account: String,
+	auth_token: String,
+	signature: String,
+}
+
+/// Account encrypted for the requester, signed by the sending enclave
+#[derive(Serialize, Deserialize)]
+pub struct SealedAccountResponse {
+	data: String,
+	signature: String,
+}
+
+/// Confirmation of an install or of an escrow, signed with the installed account
+/// or with the account of the admin enclave
+#[derive(Serialize, Deserialize)]
+pub struct InstalledPacket {
+	enclave_account: String,
+	auth_token: String,
+	signature: String,
+}
+
+/// Handover of the account of this enclave
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UpgradeHandover {
+	pub admin_account: String,
+	pub block_number: u32,
+	// The admin enclave did not confirm the escrow yet, the operator can roll it back
+	#[serde(default)]
+	pub pending: bool,
+	// Mode of the enclave before the handover, restored by a rollback
+	#[serde(default)]
+	pub previous_mode: MaintenanceState,
+}
+
+/// Account kept by an admin enclave until the new binary is started
+/// The escrow file is not a backup file, escrowed accounts never leave the admin enclave
+/// unencrypted.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UpgradeEscrow {
+	pub enclave_account: String,
+	pub operator_account: String,
+	phrase: String,
+	pub escrow_block: u32,
+	// A release is repeated only for the enclave that received the account
+	pub released_to: Option<String>,
+	pub released_block: Option<u32>,
+}
+
+impl UpgradeEscrow {
+	/// An escrow is kept UPGRADE_ESCROW_EXPIRATION blocks, released or not
+	pub fn is_expired(&self, block_number: u32) -> bool {
+		self.escrow_block.saturating_add(UPGRADE_ESCROW_EXPIRATION) < block_number
+	}
+
+	/// Release the account to a new enclave
+	/// # Arguments
+	/// * `operator` - Operator authorizing the release
+	/// * `requester` - Account of the new enclave
+	/// * `block_number` - Current block number
+	/// # Returns
+	/// * `String` - Seed phrase of the escrowed account
+	pub fn release_to(
+		&mut self,
+		operator: &str,
+		requester: &str,
+		block_number: u32,
+	) -> Result<String, String> {
+		if self.operator_account != operator {
+			return Err(format!(
+				"account {} was escrowed by operator {}",
+				self.enclave_account, self.operator_account
+			));
+		}
+
+		match &self.released_to {
+			Some(released) if released != requester =>
+				Err(format!("account {} is already released to {released}", self.enclave_account)),
+
+			Some(_) => Ok(self.phrase.clone()),
+
+			None => {
+				self.released_to = Some(requester.to_string());
+				self.released_block = Some(block_number);
+				Ok(self.phrase.clone())
+			},
+		}
+	}
+}
+
+/// Keypair of a seed phrase, when it is the phrase of the expected account
+/// # Arguments
+/// * `phrase` - Seed phrase of the account
+/// * `enclave_account` - Expected account
+pub fn account_keypair(phrase: &str, enclave_account: &str) -> Result<sr25519::Pair, String> {
+	let keypair = match sr25519::Pair::from_phrase(phrase, None) {
+		Ok((keypair, _seed)) => keypair,
+		Err(err) => return Err(format!("account phrase is not valid : {err:?}")),
+	};
+
+	if keypair.public().to_ss58check() != enclave_account {
+		return Err(format!("account phrase is not the phrase of {enclave_account}"));
+	}
+
+	Ok(keypair)
+}
+
+fn load_escrows() -> Result<BTreeMap<String, UpgradeEscrow>> {
+	if !std::path::Path::new(UPGRADE_ESCROW_FILE).exists() {
+		return Ok(BTreeMap::new());
+	}
+
+	let content = std::fs::read_to_string(UPGRADE_ESCROW_FILE)?;
+	Ok(serde_json::from_str(&content)?)
+}
+
+fn seal_escrows(escrows: &BTreeMap<String, UpgradeEscrow>) -> Result<()> {
+	let temporary_file = format!("{UPGRADE_ESCROW_FILE}.tmp");
+	std::fs::write(&temporary_file, serde_json::to_string(escrows)?)?;
+	std::fs::rename(&temporary_file, UPGRADE_ESCROW_FILE)?;
+
+	Ok(())
+}
+
+// The escrow file is sealed again only when the update succeeds, expired escrows are
+// never updated
+fn update_escrows<T, F>(block_number: u32, update: F) -> Result<T, String>
+where
+	F: FnOnce(&mut BTreeMap<String, UpgradeEscrow>) -> Result<T, String>,
+{
+	let _guard = ESCROW_LOCK.lock().map_err(|_| "escrow lock is poisoned".to_string())?;
+
+	let mut escrows =
+		load_escrows().map_err(|err| format!("can not read the escrows : {err:?}"))?;
+	escrows.retain(|_, escrow| !escrow.is_expired(block_number));
+	let result = update(&mut escrows)?;
+	seal_escrows(&escrows).map_err(|err| format!("can not seal the escrows : {err:?}"))?;
+
+	Ok(result)
+}
+
+/// Remove the expired escrows with their account phrase
+/// # Arguments
+/// * `block_number` - Current block number
+/// # Returns
+/// * `usize` - Number of removed escrows
+pub fn expire_escrows(block_number: u32) -> Result<usize, String> {
+	if !std::path::Path::new(UPGRADE_ESCROW_FILE).exists() {
+		return Ok(0);
+	}
+
+	let _guard = ESCROW_LOCK.lock().map_err(|_| "escrow lock is poisoned".to_string())?;
+
+	let mut escrows =
+		load_escrows().map_err(|err| format!("can not read the escrows : {err:?}"))?;
+	let count = escrows.len();
+	escrows.retain(|_, escrow| !escrow.is_expired(block_number));
+
+	let expired = count - escrows.len();
+	if expired > 0 {
+		seal_escrows(&escrows).map_err(|err| format!("can not seal the escrows : {err:?}"))?;
+		info!("ENCLAVE UPGRADE : {expired} expired escrows are removed");
+	}
+
+	Ok(expired)
+}
+
+/// The account of this enclave is handed over to an admin enclave
+pub fn is_handed_over() -> bool {
+	HANDED_OVER.load(Ordering::SeqCst)
+}
+
+/// Read the sealed handover at start
+pub fn load_handover() -> Result<Option<UpgradeHandover>> {
+	if !std::path::Path::new(UPGRADE_HANDOVER_FILE).exists() {
+		return Ok(None);
+	}
+
+	let content = std::fs::read_to_string(UPGRADE_HANDOVER_FILE)?;
+	let handover: UpgradeHandover = serde_json::from_str(&content)?;
+	HANDED_OVER.store(true, Ordering::SeqCst);
+
+	Ok(Some(handover))
+}
+
+fn seal_handover(handover: &UpgradeHandover) -> Result<()> {
+	let temporary_file = format!("{UPGRADE_HANDOVER_FILE}.tmp");
+	std::fs::write(&temporary_file, serde_json::to_string(handover)?)?;
+	std::fs::rename(&temporary_file, UPGRADE_HANDOVER_FILE)?;
+
+	Ok(())
+}
+
+// Seal the handover before the account leaves, the enclave goes read-only
+// The handover is pending until the admin enclave confirms the escrow
+async fn hand_over(state: &SharedState, admin_account: &str) -> Result<()> {
+	// A repeated handover keeps the mode before the first one
+	let previous_mode = match load_handover()? {
+		Some(handover) => handover.previous_mode,
+		None => get_maintenance_mode(state).await,
+	};
+
+	let handover = UpgradeHandover {
+		admin_account: admin_account.to_string(),
+		block_number: get_blocknumber(state).await,
+		pending: true,
+		previous_mode,
+	};
+
+	seal_handover(&handover)?;
+	HANDED_OVER.store(true, Ordering::SeqCst);
+
+	let reason = format!("account is handed over to admin enclave {admin_account}");
+	enter_maintenance(state, MaintenanceMode::ReadOnly, reason).await?;
+
+	Ok(())
+}
+
+fn escrowed_data_hash(enclave_account: &str) -> String {
+	sha256::digest(format!("{ESCROWED_DATA}_{enclave_account}").as_bytes())
+}
+
+fn installed_data_hash(admin_account: &str) -> String {
+	sha256::digest(format!("{INSTALLED_DATA}_{admin_account}").as_bytes())
+}
+
+// Every enclave registered on chain
+async fn registered_enclave(state: &SharedState, enclave_account: &str) -> Option<Enclave> {
+	get_clusters(state)
+		.await
+		.into_iter()
+		.flat_map(|cluster| cluster.enclaves)
+		.find(|enclave| enclave.enclave_account.to_string() == enclave_account)
+}
+
+async fn admin_enclave(state: &SharedState, enclave_account: &str) -> Option<Enclave> {
+	get_clusters(state)
+		.await
+		.into_iter()
+		.filter(|cluster| cluster.cluster_type == ClusterType::Admin)
+		.flat_map(|cluster| cluster.enclaves)
+		.find(|enclave| enclave.enclave_account.to_string() == enclave_account)
+}
+
+fn upgrade_error(status: StatusCode, message: String) -> Response {
+	warn!(message);
+	(status, Json(json!({ "error": message }))).into_response()
+}
+
+/* ----------------------------------
+		AUTHORIZATION
+----------------------------------*/
+
+/// Verify the operator authorization of a migration step
+/// # Arguments
+/// * `state` - The enclave state
+/// * `authorization` - The signed request of the operator
+/// * `step` - Expected step of the migration
+/// # Returns
+/// * `(UpgradeRequest, Enclave)` - The request and the registered enclave of the operator
+async fn verify_authorization(
+	state: &SharedState,
+	authorization: &UpgradeAuthorization,
+	step: UpgradeStep,
+) -> Result<(UpgradeRequest, Enclave), String> {
+	if !verify_signature(
+		&authorization.operator_address,
+		authorization.signature.clone(),
+		authorization.auth_token.as_bytes(),
+	) {
+		return Err("invalid operator signature".to_string());
+	}
+
+	let auth = authorization
+		.auth_token
+		.strip_prefix("<Bytes>")
+		.and_then(|auth| auth.strip_suffix("</Bytes>"))
+		.unwrap_or(&authorization.auth_token);
+
+	let auth_token: metric::AuthenticationToken = serde_json::from_str(auth)
+		.map_err(|err| format!("operator authentication token is not parsable : {err}"))?;
+
+	let validity = auth_token.is_valid(get_blocknumber(state).await);
+	if !matches!(validity, ValidationResult::Success) {
+		return Err(format!("operator authentication token is not valid : {validity:?}"));
+	}
+
+	if auth_token.data_hash != sha256::digest(authorization.request.as_bytes()) {
+		return Err("mismatch data hash of the operator request".to_string());
+	}
+
+	let request: UpgradeRequest = serde_json::from_str(&authorization.request)
+		.map_err(|err| format!("operator request is not parsable : {err}"))?;
+
+	if request.step != step {
+		return Err(format!("operator request is for the {:?} step", request.step));
+	}
+
+	let enclave = match registered_enclave(state, &request.enclave_account).await {
+		Some(enclave) => enclave,
+		None =>
+			return Err(format!("enclave {} is not registered on chain", request.enclave_account)),
+	};
+
+	if enclave.operator_account.to_string() != authorization.operator_address {
+		return Err(format!(
+			"{} is not the operator of enclave {}",
+			authorization.operator_address, request.enclave_account
+		));
+	}
+
+	Ok((request, enclave))
+}
+
+// Confirmation of a step, signed with the account of the sender
+fn verify_confirmation(
+	packet: &InstalledPacket,
+	block_number: u32,
+	data_hash: &str,
+) -> Result<(), String> {
+	if !verify_signature(
+		&packet.enclave_account,
+		packet.signature.clone(),
+		packet.auth_token.as_bytes(),
+	) {
+		return Err("invalid signature".to_string());
+	}
+
+	let auth_token: metric::AuthenticationToken = serde_json::from_str(&packet.auth_token)
+		.map_err(|err| format!("token is not parsable : {err}"))?;
+
+	let validity = auth_token.is_valid(block_number);
+	if !matches!(validity, ValidationResult::Success) {
+		return Err(format!("token is not valid : {validity:?}"));
+	}
+
+	if auth_token.data_hash != data_hash {
+		return Err("mismatch data hash".to_string());
+	}
+
+	Ok(())
+}
+
+/* ----------------------------------
+		ATTESTED REQUESTS
+----------------------------------*/
+
+/// Request an account with a quote of this enclave
+/// The report data is the signature of the account, block and ephemeral encryption key.
+/// # Arguments
+/// * `state` - The enclave state
+/// * `authorization` - The operator authorization of the migration step
+/// # Returns
+/// * `(String, [u8; 32])` - Serialized request and the private encryption key
+async fn create_attested_request(
+	state: &SharedState,
+	authorization: UpgradeAuthorization,
+) -> Result<(String, [u8; 32])> {
+	let block_number = get_blocknumber(state).await;
+	let account_id = get_accountid(state).await;
+	let keypair = get_keypair(state).await;
+
+	let (private_key, public_key) = generate_keypair();
+	let encryption_account = hex::encode(public_key.serialize());
+
+	let user_data =
+		keypair.sign(format!("{account_id}_{block_number}_{encryption_account}").as_bytes());
+	write_user_report_data(None, &user_data.0)?;
+
+	let quote =
+		get_quote_content().map_err(|err| anyhow!("can not generate the quote : {err:?}"))?;
+	let quote = serde_json::to_string(&QuoteResponse { block_number, data: hex::encode(quote) })?;
+
+	let auth_token = serde_json::to_string(&sync::AuthenticationToken {
+		block_number,
+		block_validation: 15,
+		data_hash: sha256::digest(serde_json::to_string(&authorization)?.as_bytes()),
+		quote_hash: sha256::digest(quote.as_bytes()),
+	})?;
+	let signature = format!("0x{}", hex::encode(keypair.sign(auth_token.as_bytes()).0));
+
+	let packet = AttestedUpgradePacket {
+		enclave_account: account_id,
+		authorization,
+		quote,
+		encryption_account,
+		auth_token,
+		signature,
+	};
+
+	Ok((serde_json::to_string(&packet)?, private_key.serialize()))
+}
+
+/// Verify the request of an enclave, it must run a binary allowed by the enclave policy
+/// # Arguments
+/// * `state` - The enclave state
+/// * `packet` - The attested request
+/// * `enclave_url` - URL or account of the requester, for the messages
+/// # Returns
+/// * `ReportBody` - Verified report of the requester
+async fn verify_attested_request(
+	state: &SharedState,
+	packet: &AttestedUpgradePacket,
+	enclave_url: &str,
+) -> Result<ReportBody, String> {
+	if !verify_signature(
+		&packet.enclave_account,
+		packet.signature.clone(),
+		packet.auth_token.as_bytes(),
+	) {
+		return Err("invalid requester signature".to_string());
+	}
+
+	let auth_token: sync::AuthenticationToken = serde_json::from_str(&packet.auth_token)
+		.map_err(|err| format!("authentication token is not parsable : {err}"))?;
+
+	let block_number = get_blocknumber(state).await;
+	let validity = auth_token.is_valid(block_number);
+	if !matches!(validity, ValidationResult::Success) {
+		return Err(format!("authentication token is not valid : {validity:?}"));
+	}
+
+	let authorization = serde_json::to_string(&packet.authorization)
+		.map_err(|err| format!("can not serialize the authorization : {err:?}"))?;
+	if auth_token.data_hash != sha256::digest(authorization.as_bytes()) {
+		return Err("mismatch data hash".to_string());
+	}
+
+	if auth_token.quote_hash != sha256::digest(packet.quote.as_bytes()) {
+		return Err("mismatch quote hash".to_string());
+	}
+
+	let quote: QuoteResponse = serde_json::from_str(&packet.quote)
+		.map_err(|err| format!("quote is not parsable : {err}"))?;
+
+	let client =
+		sync_client().map_err(|err| format!("unable to build a Reqwest client : {err:?}"))?;
+	let report = verify_requester_quote(state, &client, &quote, enclave_url).await?;
+
+	verify_enclave_policy(state, &report).await?;
+
+	// Only the attested enclave can decrypt the account
+	let token = format!(
+		"{}_{}_{}",
+		packet.enclave_account, auth_token.block_number, packet.encryption_account
+	);
+	if !verify_signature(&packet.enclave_account, hex::encode(report.report_data), token.as_bytes())
+	{
+		return Err("report data does not sign the encryption key".to_string());
+	}
+
+	Ok(report)
+}
+
+/// Encrypt an account for the attested requester
+/// # Arguments
+/// * `state` - The enclave state
+/// * `phrase` - Seed phrase of the account
+/// * `encryption_account` - Hex public key bound in the quote of the requester
+async fn sealed_account(
+	state: &SharedState,
+	phrase: &str,
+	encryption_account: &str,
+) -> Result<SealedAccountResponse, String> {
+	let public_key = hex::decode(encryption_account)
+		.map_err(|err| format!("invalid encryption account : {err:?}"))?;
+
+	let data = ecies::encrypt(&public_key, phrase.as_bytes())
+		.map_err(|err| format!("unable to encrypt the account : {err:?}"))?;
+	let data = hex::encode(data);
+
+	let signature = get_keypair(state).await.sign(data.as_bytes());
+
+	Ok(SealedAccountResponse { data, signature: format!("0x{}", hex::encode(signature.0)) })
+}
+
+/// Post an attested request and decrypt the received account
+/// # Arguments
+/// * `request_url` - Endpoint of the sending enclave
+/// * `body` - The attested request
+/// * `private_key` - Private encryption key of the request
+/// * `sender` - Account of the sending enclave
+/// * `enclave_account` - Expected account
+/// # Returns
+/// * `(String, sr25519::Pair)` - Seed phrase and keypair of the received account
+async fn receive_account(
+	request_url: &str,
+	body: String,
+	private_key: &[u8],
+	sender: &str,
+	enclave_account: &str,
+) -> Result<(String, sr25519::Pair), String> {
+	let client =
+		sync_client().map_err(|err| format!("unable to build a Reqwest client : {err:?}"))?;
+
+	let response = client
+		.post(request_url)
+		.body(body)
+		.header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+		.send()
+		.await
+		.map_err(|err| format!("{request_url} is not reachable : {err:?}"))?;
+
+	let status = response.status();
+	if status != StatusCode::OK {
+		let reason = response.text().await.unwrap_or_default();
+		return Err(format!("{request_url} refused the request : {status} : {reason}"));
+	}
+
+	let sealed: SealedAccountResponse = response
+		.json()
+		.await
+		.map_err(|err| format!("response of {request_url} is not parsable : {err:?}"))?;
+
+	if !verify_signature(sender, sealed.signature.clone(), sealed.data.as_bytes()) {
+		return Err(format!("response of {request_url} is not signed by {sender}"));
+	}
+
+	let data =
+		hex::decode(&sealed.data).map_err(|err| format!("invalid encrypted account : {err:?}"))?;
+	let phrase = ecies::decrypt(private_key, &data)
+		.map_err(|err| format!("unable to decrypt the account : {err:?}"))
+		.and_then(|phrase| {
+			String::from_utf8(phrase).map_err(|_| "account phrase is not utf-8".to_string())
+		})?;
+
+	let keypair = account_keypair(&phrase, enclave_account)?;
+
+	Ok((phrase, keypair))
+}
+
+fn install_account(phrase: &str) -> Result<()> {
+	let temporary_file = format!("{ENCLAVE_ACCOUNT_FILE}.tmp");
+	std::fs::write(&temporary_file, phrase)?;
+	std::fs::rename(&temporary_file, ENCLAVE_ACCOUNT_FILE)?;
+
+	Ok(())
+}
+
+// The admin enclave removes the escrow once the installed account signs the confirmation
+async fn confirm_install(
+	state: &SharedState,
+	admin_url: &str,
+	admin_account: &str,
+) -> Result<(), String> {
+	let request_url = format!("{admin_url}/api/upgrade/installed");
+	send_confirmation(state, &request_url, installed_data_hash(admin_account)).await
+}
+
+// The handed over enclave makes its handover final once the admin enclave signs the escrow
+async fn confirm_escrow(
+	state: &SharedState,
+	enclave_url: &str,
+	enclave_account: &str,
+) -> Result<(), String> {
+	let request_url = format!("{enclave_url}/api/upgrade/escrowed");
+	send_confirmation(state, &request_url, escrowed_data_hash(enclave_account)).await
+}
+
+// Confirmation signed with the account of this enclave
+async fn send_confirmation(
+	state: &SharedState,
+	request_url: &str,
+	data_hash: String,
+) -> Result<(), String> {
+	let keypair = get_keypair(state).await;
+
+	let auth_token = serde_json::to_string(&metric::AuthenticationToken {
+		block_number: get_blocknumber(state).await,
+		block_validation: 15,
+		data_hash,
+	})
+	.map_err(|err| format!("can not serialize the token : {err:?}"))?;
+
+	let packet = InstalledPacket {
+		enclave_account: keypair.public().to_ss58check(),
+		signature: format!("0x{}", hex::encode(keypair.sign(auth_token.as_bytes()).0)),
+		auth_token,
+	};
+
+	let client =
+		sync_client().map_err(|err| format!("unable to build a Reqwest client : {err:?}"))?;
+
+	let response = client
+		.post(request_url)
+		.json(&packet)
+		.send()
+		.await
+		.map_err(|err| format!("{request_url} is not reachable : {err:?}"))?;
+
+	let status = response.status();
+	if status != StatusCode::OK {
+		let reason = response.text().await.unwrap_or_default();
+		return Err(format!("{request_url} refused the confirmation : {status} : {reason}"));
+	}
+
+	Ok(())
+}
+
+/* ----------------------------------
+		UPGRADE ENDPOINTS
+----------------------------------*/
+
+/// Take the account of an enclave in escrow (Admin enclave)
+/// The account is requested from the running enclave, with the quote of this enclave.
+/// # Arguments
+/// * `state` - The enclave state
+/// * `authorization` - Escrow request signed by the operator of the enclave
+pub async fn upgrade_escrow_handler(
+	State(state): State<SharedState>,
+	Json(authorization): Json<UpgradeAuthorization>,
+) -> impl IntoResponse {
+	debug!("ENCLAVE UPGRADE : escrow request of operator {}", authorization.operator_address);
+
+	let account_id = get_accountid(&state).await;
+	if admin_enclave(&state, &account_id).await.is_none() {
+		let message = "ENCLAVE UPGRADE : Escrow : this enclave is not an admin enclave".to_string();
+		return upgrade_error(StatusCode::FORBIDDEN, message);
+	}
+
+	let (request, enclave) = match verify_authorization(&state, &authorization, UpgradeStep::Escrow)
+		.await
+	{
+		Ok(verified) => verified,
+		Err(err) =>
+			return upgrade_error(StatusCode::FORBIDDEN, format!("ENCLAVE UPGRADE : Escrow : {err}")),
+	};
+
+	if request.admin_account != account_id {
+		let message = format!(
+			"ENCLAVE UPGRADE : Escrow : request is for admin enclave {}",
+			request.admin_account
+		);
+		return upgrade_error(StatusCode::BAD_REQUEST, message);
+	}
+
+	let operator_account = authorization.operator_address.clone();
+	let (body, private_key) = match create_attested_request(&state, authorization).await {
+		Ok(request) => request,
+		Err(err) => {
+			let message =
+				format!("ENCLAVE UPGRADE : Escrow : can not create the request : {err:?}");
+			return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		},
+	};
+
+	let mut enclave_url = enclave.enclave_url.clone();
+	while enclave_url.ends_with('/') {
+		enclave_url.pop();
+	}
+
+	let phrase = match receive_account(
+		&format!("{enclave_url}/api/upgrade/handover"),
+		body,
+		&private_key,
+		&request.enclave_account,
+		&request.enclave_account,
+	)
+	.await
+	{
+		Ok((phrase, _keypair)) => phrase,
+		Err(err) =>
+			return upgrade_error(
+				StatusCode::BAD_GATEWAY,
+				format!("ENCLAVE UPGRADE : Escrow : {err}"),
+			),
+	};
+
+	let escrow_block = get_blocknumber(&state).await;
+	let escrow = UpgradeEscrow {
+		enclave_account: request.enclave_account.clone(),
+		operator_account,
+		phrase,
+		escrow_block,
+		released_to: None,
+		released_block: None,
+	};
+
+	// A new escrow of the account replaces the previous one
+	if let Err(err) = update_escrows(escrow_block, |escrows| {
+		escrows.insert(escrow.enclave_account.clone(), escrow.clone());
+		Ok(())
+	}) {
+		let message = format!("ENCLAVE UPGRADE : Escrow : {err}");
+		error!(message);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("enclave-upgrade", "escrow");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	// The handover is final once the escrow is sealed, an unconfirmed escrow is never released
+	if let Err(err) = confirm_escrow(&state, &enclave_url, &escrow.enclave_account).await {
+		let dropped =
+			update_escrows(escrow_block, |escrows| match escrows.get(&escrow.enclave_account) {
+				Some(sealed)
+					if sealed.escrow_block == escrow_block && sealed.released_to.is_none() =>
+					Ok(escrows.remove(&escrow.enclave_account)),
+				_ => Ok(None),
+			});
+		if let Err(drop_err) = dropped {
+			error!("ENCLAVE UPGRADE : Escrow : can not drop the unconfirmed escrow : {drop_err}");
+		}
+
+		let message = format!(
+			"ENCLAVE UPGRADE : Escrow : escrow is not confirmed to {}, it is dropped : {err}",
+			escrow.enclave_account
+		);
+		return upgrade_error(StatusCode::BAD_GATEWAY, message);
+	}
+
+	info!(
+		"ENCLAVE UPGRADE : account {} of operator {} is in escrow since block {escrow_block}",
+		escrow.enclave_account, escrow.operator_account
+	);
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"enclave_account": escrow.enclave_account,
+			"operator_account": escrow.operator_account,
+			"escrow_block": escrow_block,
+		})),
+	)
+		.into_response()
+}
+
+/// Hand the account of this enclave over to an attested admin enclave (Upgraded enclave)
+/// # Arguments
+/// * `state` - The enclave state
+/// * `packet` - Attested request of the admin enclave, with the escrow request of the operator
+pub async fn upgrade_handover_handler(
+	State(state): State<SharedState>,
+	Json(packet): Json<AttestedUpgradePacket>,
+) -> impl IntoResponse {
+	debug!("ENCLAVE UPGRADE : handover request of {}", packet.enclave_account);
+
+	let request =
+		match verify_authorization(&state, &packet.authorization, UpgradeStep::Escrow).await {
+			Ok((request, _enclave)) => request,
+			Err(err) =>
+				return upgrade_error(
+					StatusCode::FORBIDDEN,
+					format!("ENCLAVE UPGRADE : Handover : {err}"),
+				),
+		};
+
+	if request.enclave_account != get_accountid(&state).await {
+		let message = format!(
+			"ENCLAVE UPGRADE : Handover : request is for enclave {}",
+			request.enclave_account
+		);
+		return upgrade_error(StatusCode::FORBIDDEN, message);
+	}
+
+	// Only the admin enclave chosen by the operator receives the account
+	let admin = match admin_enclave(&state, &packet.enclave_account).await {
+		Some(admin) if admin.enclave_account.to_string() == request.admin_account => admin,
+		_ => {
+			let message = format!(
+				"ENCLAVE UPGRADE : Handover : requester {} is not the admin enclave {}",
+				packet.enclave_account, request.admin_account
+			);
+			return upgrade_error(StatusCode::FORBIDDEN, message);
+		},
+	};
+
+	if let Err(err) = verify_attested_request(&state, &packet, &admin.enclave_url).await {
+		let message = format!("ENCLAVE UPGRADE : Handover : {} : {err}", admin.enclave_url);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("enclave-upgrade", "attestation");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return upgrade_error(StatusCode::FORBIDDEN, message);
+	}
+
+	let phrase = match std::fs::read_to_string(ENCLAVE_ACCOUNT_FILE) {
+		Ok(phrase) => phrase,
+		Err(err) => {
+			let message =
+				format!("ENCLAVE UPGRADE : Handover : can not read the enclave account : {err:?}");
+			return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		},
+	};
+
+	let sealed = match sealed_account(&state, &phrase, &packet.encryption_account).await {
+		Ok(sealed) => sealed,
+		Err(err) =>
+			return upgrade_error(
+				StatusCode::BAD_REQUEST,
+				format!("ENCLAVE UPGRADE : Handover : {err}"),
+			),
+	};
+
+	let _handover_lock = HANDOVER_LOCK.lock().await;
+
+	// A confirmed escrow is only released by its admin enclave
+	match load_handover() {
+		Ok(Some(handover))
+			if !handover.pending && handover.admin_account != request.admin_account =>
+		{
+			let message = format!(
+				"ENCLAVE UPGRADE : Handover : account is in escrow in admin enclave {}",
+				handover.admin_account
+			);
+			return upgrade_error(StatusCode::CONFLICT, message);
+		},
+		Ok(_) => {},
+		Err(err) => {
+			let message =
+				format!("ENCLAVE UPGRADE : Handover : can not read the handover : {err:?}");
+			return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		},
+	}
+
+	// Two enclaves never write with the same account
+	if let Err(err) = hand_over(&state, &request.admin_account).await {
+		let message = format!("ENCLAVE UPGRADE : Handover : can not seal the handover : {err:?}");
+		error!(message);
+		return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	info!(
+		"ENCLAVE UPGRADE : account is handed over to admin enclave {}, the enclave is read-only",
+		request.admin_account
+	);
+	(StatusCode::OK, Json(sealed)).into_response()
+}
+
+/// Make the handover final once the admin enclave sealed the escrow (Upgraded enclave)
+/// # Arguments
+/// * `state` - The enclave state
+/// * `packet` - Confirmation signed with the account of the admin enclave
+pub async fn upgrade_escrowed_handler(
+	State(state): State<SharedState>,
+	Json(packet): Json<InstalledPacket>,
+) -> impl IntoResponse {
+	debug!("ENCLAVE UPGRADE : escrow confirmation of {}", packet.enclave_account);
+
+	let block_number = get_blocknumber(&state).await;
+	let data_hash = escrowed_data_hash(&get_accountid(&state).await);
+	if let Err(err) = verify_confirmation(&packet, block_number, &data_hash) {
+		return upgrade_error(StatusCode::FORBIDDEN, format!("ENCLAVE UPGRADE : Escrowed : {err}"));
+	}
+
+	let _handover_lock = HANDOVER_LOCK.lock().await;
+
+	let mut handover = match load_handover() {
+		Ok(Some(handover)) if handover.admin_account == packet.enclave_account => handover,
+		Ok(_) => {
+			let message = format!(
+				"ENCLAVE UPGRADE : Escrowed : account is not handed over to {}",
+				packet.enclave_account
+			);
+			return upgrade_error(StatusCode::CONFLICT, message);
+		},
+		Err(err) => {
+			let message =
+				format!("ENCLAVE UPGRADE : Escrowed : can not read the handover : {err:?}");
+			return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		},
+	};
+
+	if handover.pending {
+		handover.pending = false;
+		if let Err(err) = seal_handover(&handover) {
+			let message =
+				format!("ENCLAVE UPGRADE : Escrowed : can not seal the handover : {err:?}");
+			error!(message);
+			return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		}
+	}
+
+	info!(
+		"ENCLAVE UPGRADE : escrow is confirmed by admin enclave {}, the handover is final",
+		handover.admin_account
+	);
+	(StatusCode::OK, Json(json!({ "admin_account": handover.admin_account }))).into_response()
+}
+
+/// Take back a handed over account whose escrow is not confirmed (Upgraded enclave)
+/// The enclave returns to its mode before the handover.
+/// # Arguments
+/// * `state` - The enclave state
+/// * `authorization` - Rollback request signed by the operator of this enclave
+pub async fn upgrade_rollback_handler(
+	State(state): State<SharedState>,
+	Json(authorization): Json<UpgradeAuthorization>,
+) -> impl IntoResponse {
+	debug!("ENCLAVE UPGRADE : rollback request of operator {}", authorization.operator_address);
+
+	let request = match verify_authorization(&state, &authorization, UpgradeStep::Rollback).await {
+		Ok((request, _enclave)) => request,
+		Err(err) =>
+			return upgrade_error(
+				StatusCode::FORBIDDEN,
+				format!("ENCLAVE UPGRADE : Rollback : {err}"),
+			),
+	};
+
+	if request.enclave_account != get_accountid(&state).await {
+		let message = format!(
+			"ENCLAVE UPGRADE : Rollback : request is for enclave {}",
+			request.enclave_account
+		);
+		return upgrade_error(StatusCode::FORBIDDEN, message);
+	}
+
+	let _handover_lock = HANDOVER_LOCK.lock().await;
+
+	let handover = match load_handover() {
+		Ok(Some(handover)) => handover,
+		Ok(None) => {
+			let message = "ENCLAVE UPGRADE : Rollback : account is not handed over".to_string();
+			return upgrade_error(StatusCode::CONFLICT, message);
+		},
+		Err(err) => {
+			let message =
+				format!("ENCLAVE UPGRADE : Rollback : can not read the handover : {err:?}");
+			return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		},
+	};
+
+	if !handover.pending {
+		let message = format!(
+			"ENCLAVE UPGRADE : Rollback : escrow is confirmed by admin enclave {}, the handover is final",
+			handover.admin_account
+		);
+		return upgrade_error(StatusCode::CONFLICT, message);
+	}
+
+	if handover.admin_account != request.admin_account {
+		let message = format!(
+			"ENCLAVE UPGRADE : Rollback : account is handed over to admin enclave {}",
+			handover.admin_account
+		);
+		return upgrade_error(StatusCode::BAD_REQUEST, message);
+	}
+
+	if let Err(err) = std::fs::remove_file(UPGRADE_HANDOVER_FILE) {
+		let message = format!("ENCLAVE UPGRADE : Rollback : can not remove the handover : {err:?}");
+		error!(message);
+		return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+	HANDED_OVER.store(false, Ordering::SeqCst);
+
+	let previous = handover.previous_mode;
+	if let Err(err) = enter_maintenance(&state, previous.mode, previous.reason).await {
+		let message = format!("ENCLAVE UPGRADE : Rollback : can not restore the mode : {err:?}");
+		error!(message);
+		return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	info!("ENCLAVE UPGRADE : handover to admin enclave {} is rolled back", handover.admin_account);
+	(StatusCode::OK, Json(json!({ "enclave_account": request.enclave_account }))).into_response()
+}
+
+/// Release an escrowed account to an attested new enclave (Admin enclave)
+/// # Arguments
+/// * `state` - The enclave state
+/// * `packet` - Attested request of the new enclave, with the release request of the operator
+pub async fn upgrade_release_handler(
+	State(state): State<SharedState>,
+	Json(packet): Json<AttestedUpgradePacket>,
+) -> impl IntoResponse {
+	debug!("ENCLAVE UPGRADE : release request of {}", packet.enclave_account);
+
+	let account_id = get_accountid(&state).await;
+	if admin_enclave(&state, &account_id).await.is_none() {
+		let message =
+			"ENCLAVE UPGRADE : Release : this enclave is not an admin enclave".to_string();
+		return upgrade_error(StatusCode::FORBIDDEN, message);
+	}
+
+	let request =
+		match verify_authorization(&state, &packet.authorization, UpgradeStep::Release).await {
+			Ok((request, _enclave)) => request,
+			Err(err) =>
+				return upgrade_error(
+					StatusCode::FORBIDDEN,
+					format!("ENCLAVE UPGRADE : Release : {err}"),
+				),
+		};
+
+	if request.admin_account != account_id {
+		let message = format!(
+			"ENCLAVE UPGRADE : Release : request is for admin enclave {}",
+			request.admin_account
+		);
+		return upgrade_error(StatusCode::BAD_REQUEST, message);
+	}
+
+	// The new binary must be allowed by the enclave policy of the admin enclave
+	if let Err(err) = verify_attested_request(&state, &packet, &packet.enclave_account).await {
+		let message = format!("ENCLAVE UPGRADE : Release : {} : {err}", packet.enclave_account);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("enclave-upgrade", "attestation");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return upgrade_error(StatusCode::FORBIDDEN, message);
+	}
+
+	let block_number = get_blocknumber(&state).await;
+	let operator = packet.authorization.operator_address.clone();
+	let released =
+		update_escrows(block_number, |escrows| match escrows.get_mut(&request.enclave_account) {
+			Some(escrow) => escrow.release_to(&operator, &packet.enclave_account, block_number),
+			None => Err(format!("account {} is not in escrow", request.enclave_account)),
+		});
+
+	let phrase = match released {
+		Ok(phrase) => phrase,
+		Err(err) =>
+			return upgrade_error(StatusCode::CONFLICT, format!("ENCLAVE UPGRADE : Release : {err}")),
+	};
+
+	match sealed_account(&state, &phrase, &packet.encryption_account).await {
+		Ok(sealed) => {
+			info!(
+				"ENCLAVE UPGRADE : account {} is released to {}",
+				request.enclave_account, packet.enclave_account
+			);
+			(StatusCode::OK, Json(sealed)).into_response()
+		},
+		Err(err) =>
+			upgrade_error(StatusCode::BAD_REQUEST, format!("ENCLAVE UPGRADE : Release : {err}")),
+	}
+}
+
+/// Remove a released escrow once the new enclave installed the account (Admin enclave)
+/// # Arguments
+/// * `state` - The enclave state
+/// * `packet` - Confirmation signed with the installed account
+pub async fn upgrade_installed_handler(
+	State(state): State<SharedState>,
+	Json(packet): Json<InstalledPacket>,
+) -> impl IntoResponse {
+	debug!("ENCLAVE UPGRADE : install confirmation of {}", packet.enclave_account);
+
+	let block_number = get_blocknumber(&state).await;
+	let data_hash = installed_data_hash(&get_accountid(&state).await);
+	if let Err(err) = verify_confirmation(&packet, block_number, &data_hash) {
+		return upgrade_error(StatusCode::FORBIDDEN, format!("ENCLAVE UPGRADE : Installed : {err}"));
+	}
+
+	let removed = update_escrows(block_number, |escrows| {
+		match escrows.get(&packet.enclave_account) {
+			Some(escrow) if escrow.released_to.is_some() => {},
+			Some(_) => return Err(format!("account {} is not released", packet.enclave_account)),
+			None => return Err(format!("account {} is not in escrow", packet.enclave_account)),
+		}
+
+		Ok(escrows.remove(&packet.enclave_account))
+	});
+
+	match removed {
+		Ok(_) => {
+			info!(
+				"ENCLAVE UPGRADE : escrow of {} is removed, it is installed",
+				packet.enclave_account
+			);
+			(StatusCode::OK, Json(json!({ "enclave_account": packet.enclave_account })))
+				.into_response()
+		},
+		Err(err) =>
+			upgrade_error(StatusCode::CONFLICT, format!("ENCLAVE UPGRADE : Installed : {err}")),
+	}
+}
+
+/// Replace the account of this new enclave with the escrowed account (New enclave)
+/// # Arguments
+/// * `state` - The enclave state
+/// * `authorization` - Release request signed by the operator of the upgraded enclave
+pub async fn upgrade_install_handler(
+	State(state): State<SharedState>,
+	Json(authorization): Json<UpgradeAuthorization>,
+) -> impl IntoResponse {
+	debug!("ENCLAVE UPGRADE : install request of operator {}", authorization.operator_address);
+
+	// A registered enclave keeps its account
+	if let Some(identity) = get_identity(&state).await {
+		let message = format!(
+			"ENCLAVE UPGRADE : Install : this enclave is registered in cluster {} slot {}",
+			identity.0, identity.1
+		);
+		return upgrade_error(StatusCode::CONFLICT, message);
+	}
+
+	let request = match verify_authorization(&state, &authorization, UpgradeStep::Release).await {
+		Ok((request, _enclave)) => request,
+		Err(err) =>
+			return upgrade_error(
+				StatusCode::FORBIDDEN,
+				format!("ENCLAVE UPGRADE : Install : {err}"),
+			),
+	};
+
+	let admin = match admin_enclave(&state, &request.admin_account).await {
+		Some(admin) => admin,
+		None => {
+			let message = format!(
+				"ENCLAVE UPGRADE : Install : {} is not an admin enclave",
+				request.admin_account
+			);
+			return upgrade_error(StatusCode::BAD_REQUEST, message);
+		},
+	};
+
+	// The enclave account is not replaced during a restore
+	let _restore_lock = match RestoreLock::acquire() {
+		Some(lock) => lock,
+		None => {
+			let message = "ENCLAVE UPGRADE : Install : a restore is running".to_string();
+			return upgrade_error(StatusCode::CONFLICT, message);
+		},
+	};
+
+	let (body, private_key) = match create_attested_request(&state, authorization).await {
+		Ok(request) => request,
+		Err(err) => {
+			let message =
+				format!("ENCLAVE UPGRADE : Install : can not create the request : {err:?}");
+			return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+		},
+	};
+
+	let mut admin_url = admin.enclave_url.clone();
+	while admin_url.ends_with('/') {
+		admin_url.pop();
+	}
+
+	let (phrase, keypair) = match receive_account(
+		&format!("{admin_url}/api/upgrade/release"),
+		body,
+		&private_key,
+		&request.admin_account,
+		&request.enclave_account,
+	)
+	.await
+	{
+		Ok(account) => account,
+		Err(err) =>
+			return upgrade_error(
+				StatusCode::BAD_GATEWAY,
+				format!("ENCLAVE UPGRADE : Install : {err}"),
+			),
+	};
+
+	if let Err(err) = install_account(&phrase) {
+		let message =
+			format!("ENCLAVE UPGRADE : Install : can not seal the enclave account : {err:?}");
+		error!(message);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("enclave-upgrade", "install");
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+		return upgrade_error(StatusCode::INTERNAL_SERVER_ERROR, message);
+	}
+
+	set_keypair(&state, keypair).await;
+	info!("ENCLAVE UPGRADE : enclave account {} is installed", request.enclave_account);
+
+	if let Err(err) = confirm_install(&state, &admin_url, &request.admin_account).await {
+		warn!("ENCLAVE UPGRADE : Install : escrow is removed at its expiry : {err}");
+	}
+
+	match cluster_discovery(&state).await {
+		Ok(res) => debug!("ENCLAVE UPGRADE : CLUSTER DISCOVERY FOR NEW IDENTITY : {res}"),
+		Err(err) => error!("ENCLAVE UPGRADE : CLUSTER DISCOVERY FAILED : {err}"),
+	}
+
+	(
+		StatusCode::OK,
+		Json(json!({
+			"enclave_account": request.enclave_account,
+			"identity": get_identity(&state).await,
+		})),
+	)
+		.into_response()
+}
+
+/* ----------------------------------
+		ENCLAVE UPGRADE TEST
+----------------------------------*/
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn account_keypair_test() {
+		let (keypair, phrase, _seed) = sr25519::Pair::generate_with_phrase(None);
+		let account = keypair.public().to_ss58check();
+
+		assert!(account_keypair(&phrase, &account).is_ok());
+
+		let (other, _, _) = sr25519::Pair::generate_with_phrase(None);
+		assert!(account_keypair(&phrase, &other.public().to_ss58check()).is_err());
+		assert!(account_keypair("not a phrase", &account).is_err());
+	}
+
+	#[test]
+	fn escrow_release_test() {
+		let mut escrow = UpgradeEscrow {
+			enclave_account: "enclave".to_string(),
+			operator_account: "operator".to_string(),
+			phrase: "phrase".to_string(),
+			escrow_block: 100,
+			released_to: None,
+			released_block: None,
+		};
+
+		// Only the operator of the escrow releases it
+		assert!(escrow.release_to("intruder", "new-enclave", 110).is_err());
+		assert_eq!(escrow.released_to, None);
+
+		assert_eq!(escrow.release_to("operator", "new-enclave", 110), Ok("phrase".to_string()));
+		assert_eq!(escrow.released_block, Some(110));
+
+		// A failed install is retried by the same enclave, another enclave is refused
+		assert_eq!(escrow.release_to("operator", "new-enclave", 120), Ok("phrase".to_string()));
+		assert_eq!(escrow.released_block, Some(110));
+		assert!(escrow.release_to("operator", "other-enclave", 130).is_err());
+
+		// The phrase is not kept after the expiry
+		assert!(!escrow.is_expired(100 + UPGRADE_ESCROW_EXPIRATION));
+		assert!(escrow.is_expired(101 + UPGRADE_ESCROW_EXPIRATION));
+	}
+
+	#[test]
+	fn handover_confirmation_test() {
+		// A handover sealed before the confirmations is final
+		let sealed = r#"{"admin_account":"admin","block_number":100}"#;
+		let handover: UpgradeHandover = serde_json::from_str(sealed).unwrap();
+		assert!(!handover.pending);
+		assert_eq!(handover.previous_mode.mode, MaintenanceMode::Normal);
+
+		// Escrow confirmations are bound to the handed over account
+		assert_ne!(escrowed_data_hash("enclave"), escrowed_data_hash("other-enclave"));
+		assert_ne!(escrowed_data_hash("enclave"), installed_data_hash("enclave"));
+	}
+}
@@ -60,8 +60,8 @@ where
 		let file_ext = match path.extension().and_then(std::ffi::OsStr::to_str) {
 			Some(ext) => ext,
 			None => {
-				// exception for seal-path entry
-				if path == Path::new(SEALPATH) {
+				// exception for seal-path entry, and its sub-directories
+				if path == Path::new(SEALPATH) || path.is_dir() {
 					continue;
 				}
 
@@ -167,6 +167,22 @@ fn doit(
 	let path = Path::new(dst_file);
 	let file = File::create(path)?;
 
+	// Paginated wildcard for Synching in setup mode : ["*", page_size, from_nftid]
+	if list.len() == 3 && list[0] == "*" {
+		let (page_size, from_nftid) = match (list[1].parse::<usize>(), list[2].parse::<u32>()) {
+			(Ok(page_size), Ok(from_nftid)) => (page_size, from_nftid),
+			_ => return Err(ZipError::InvalidArchive("invalid wildcard pagination")),
+		};
+
+		debug!("\t ZIPDIR => wildcard page : size = {page_size}, from nftid = {from_nftid}");
+
+		let mut page = keyshares_page(src_dir, page_size, from_nftid).into_iter();
+
+		zip_dir(&mut page, vec!["*".to_string()], src_dir, file, method)?;
+
+		return Ok(());
+	}
+
 	let walkdir = WalkDir::new(src_dir).max_depth(1);
 	let it = walkdir.into_iter();
 
@@ -175,10 +191,136 @@ fn doit(
 	Ok(())
 }
 
+/// A file written into an archive, with the digest of the written bytes
+#[derive(Clone, Debug)]
+pub struct ArchivedFile {
+	pub name: String,
+	pub sha256: String,
+	pub size: u64,
+}
+
+/// Compresses the listed files of a directory, followed by a manifest of them
+/// The manifest is built from the bytes actually written, and appended as the last entry
+/// # Arguments
+/// * `src_dir` - Directory of the files
+/// * `file_names` - Names of the files to archive
+/// * `dst_file` - Path of the archive
+/// * `manifest_name` - Name of the manifest entry
+/// * `build_manifest` - Serializes the manifest of the archived files
+pub fn add_files_zip_with_manifest<F>(
+	src_dir: &str,
+	file_names: &[String],
+	dst_file: &str,
+	manifest_name: &str,
+	build_manifest: F,
+) -> zip::result::ZipResult<Vec<ArchivedFile>>
+where
+	F: FnOnce(&[ArchivedFile]) -> Result<Vec<u8>, String>,
+{
+	if !Path::new(src_dir).is_dir() {
+		return Err(ZipError::FileNotFound);
+	}
+
+	let file = File::create(Path::new(dst_file))?;
+	let mut zip = zip::ZipWriter::new(file);
+	let options = FileOptions::default()
+		.compression_method(METHOD_DEFLATED)
+		.unix_permissions(0o755);
+
+	let mut archived = Vec::with_capacity(file_names.len());
+	for file_name in file_names {
+		let data = fs::read(Path::new(src_dir).join(file_name))?;
+
+		trace!("\t ZIPDIR => adding file {:?} with manifest ...", file_name);
+		zip.start_file(file_name.clone(), options)?;
+		zip.write_all(&data)?;
+
+		archived.push(ArchivedFile {
+			name: file_name.clone(),
+			sha256: sha256::digest(data.as_slice()),
+			size: data.len() as u64,
+		});
+	}
+
+	let manifest = build_manifest(&archived).map_err(|err| ZipError::Io(io::Error::other(err)))?;
+	zip.start_file(manifest_name, options)?;
+	zip.write_all(&manifest)?;
+
+	zip.finish()?;
+	Ok(archived)
+}
+
+/// Keyshare files of a directory, ordered by nftid
+/// New nftids are appended to the end, the order is stable between wildcard pages
+pub fn sorted_keyshares(src_dir: &str) -> Vec<DirEntry> {
+	let mut keyshares: Vec<(u32, DirEntry)> = WalkDir::new(src_dir)
+		.max_depth(1)
+		.into_iter()
+		.filter_map(|e| e.ok())
+		.filter(|entry| {
+			entry.path().is_file() &&
+				entry.path().extension().and_then(std::ffi::OsStr::to_str) == Some("keyshare")
+		})
+		.map(|entry| (keyshare_nftid(&entry), entry))
+		.collect();
+
+	keyshares.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.file_name().cmp(b.1.file_name())));
+
+	keyshares.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Keyshares of a wildcard page, from an nftid in nftid order
+/// The keyshares of an nftid are never split, the next page starts after its last nftid
+/// # Arguments
+/// * `src_dir` - Directory of the keyshares
+/// * `page_size` - Number of keyshares, exceeded to complete the last nftid
+/// * `from_nftid` - First nftid of the page
+pub fn keyshares_page(src_dir: &str, page_size: usize, from_nftid: u32) -> Vec<DirEntry> {
+	let mut page: Vec<DirEntry> = Vec::new();
+
+	for entry in sorted_keyshares(src_dir) {
+		let nftid = keyshare_nftid(&entry);
+		if nftid < from_nftid {
+			continue;
+		}
+
+		if page.len() >= page_size && page.last().map(keyshare_nftid) != Some(nftid) {
+			break;
+		}
+
+		page.push(entry);
+	}
+
+	page
+}
+
+// Keyshare file name = [nft/capsule]_[nftid]_[blocknumber].keyshare
+fn keyshare_nftid(entry: &DirEntry) -> u32 {
+	entry
+		.path()
+		.file_stem()
+		.and_then(std::ffi::OsStr::to_str)
+		.and_then(|name| name.split('_').nth(1))
+		.and_then(|nftid| nftid.parse::<u32>().ok())
+		.unwrap_or(u32::MAX)
+}
+
 /* ----------------------------
 		EXTRACT ARCHIVE
 -------------------------------*/
 pub fn zip_extract(filename: &str, outdir: &str) -> Result<(), ZipError> {
+	zip_extract_filtered(filename, outdir, |_| true)
+}
+
+/// Extract the entries of an archive accepted by the filter
+/// # Arguments
+/// * `filename` - Path of the archive
+/// * `outdir` - Destination directory
+/// * `filter` - Receives the entry name, returns false to skip the entry
+pub fn zip_extract_filtered<F>(filename: &str, outdir: &str, filter: F) -> Result<(), ZipError>
+where
+	F: Fn(&str) -> bool,
+{
 	let fname = std::path::Path::new(filename);
 
 	let infile = match fs::File::open(fname) {
@@ -228,7 +370,7 @@ pub fn zip_extract(filename: &str, outdir: &str) -> Result<(), ZipError> {
 
 		let fullpath = Path::new(&fullpath_str);
 
-		if (*file.name()).contains("__MACOSX") {
+		if (*file.name()).contains("__MACOSX") || !filter(file.name()) {
 			continue;
 		}
 
@@ -309,4 +451,31 @@ mod test {
 		add_dir_zip("/tmp", "/tmp/zip/backup1.zip");
 		let _ = zip_extract("/tmp/zip/backup1.zip", "/tmp/test1/");
 	}
+
+	#[test]
+	fn keyshares_page_test() {
+		let src_dir = "/tmp/keyshares_page";
+		let _ = fs::remove_dir_all(src_dir);
+		fs::create_dir_all(src_dir).unwrap();
+		for name in ["nft_3_10", "nft_7_12", "capsule_7_15", "nft_20_11", "capsule_42_13"] {
+			fs::write(format!("{src_dir}/{name}.keyshare"), "share").unwrap();
+		}
+
+		let names = |page: Vec<DirEntry>| -> Vec<String> {
+			page.iter()
+				.map(|entry| entry.file_name().to_string_lossy().to_string())
+				.collect()
+		};
+
+		// The two keyshares of nftid 7 stay in the same page
+		let first = names(keyshares_page(src_dir, 2, 0));
+		assert_eq!(first, ["nft_3_10.keyshare", "capsule_7_15.keyshare", "nft_7_12.keyshare"]);
+
+		let second = names(keyshares_page(src_dir, 2, 8));
+		assert_eq!(second, ["nft_20_11.keyshare", "capsule_42_13.keyshare"]);
+
+		assert!(keyshares_page(src_dir, 2, 43).is_empty());
+
+		let _ = fs::remove_dir_all(src_dir);
+	}
 }
@@ -3,6 +3,7 @@
 #![allow(unused_variables)]
 
 use std::{
+	collections::HashMap,
 	fs::File,
 	io::Write,
 	path::PathBuf,
@@ -19,6 +20,7 @@ use axum::{
 	error_handling::HandleErrorLayer,
 	extract::{DefaultBodyLimit, State},
 	http::{StatusCode, Uri},
+	middleware,
 	response::IntoResponse,
 	routing::{get, post},
 	BoxError, Json, Router,
@@ -39,49 +41,125 @@ use serde_json::{json, Value};
 use tracing::{debug, error, info, trace, warn};
 
 use crate::{
-	attestation::ra::ra_get_quote,
+	attestation::{
+		policy::{
+			get_enclave_policy_handler, load_enclave_policy, seed_enclave_policy,
+			set_enclave_policy_handler,
+		},
+		ra::ra_get_quote,
+	},
 	constants::{
-		CONTENT_LENGTH_LIMIT, ENCLAVE_ACCOUNT_FILE, RETRY_COUNT, RETRY_DELAY, SEALPATH,
-		SYNC_STATE_FILE, VERSION,
+		ANTI_ENTROPY_INTERVAL, BULK_REQUEST_TIMEOUT, CONTENT_LENGTH_LIMIT, ENCLAVE_ACCOUNT_FILE,
+		LOW_BALANCE_WARNING, MAX_FINALITY_LAG, MIN_BALANCE_FOR_STORE, REQUEST_TIMEOUT, RETRY_COUNT,
+		RETRY_DELAY, SEALPATH, SYNC_RETRY_INTERVAL, SYNC_STATE_FILE, VERSION,
 	},
 	core::{
 		capsule::{
 			capsule_get_views, capsule_remove_keyshare, capsule_retrieve_keyshare,
 			capsule_set_keyshare, is_capsule_available,
 		},
-		chain::create_chain_api,
+		chain::{
+			clear_ownership_cache, create_chain_api, get_enclave_balance,
+			invalidate_ownership_cache, prune_ownership_cache,
+		},
+		finality::{spawn_justification_listener, FinalityVerifier, JustificationStore},
 		helper,
 		nft::{
 			is_nft_available, nft_get_views, nft_remove_keyshare, nft_retrieve_keyshare,
 			nft_store_keyshare,
 		},
+		runtime::spawn_runtime_updater,
 	},
 	replication::{
-		admin_nftid::admin_backup_push_id,
+		admin_nftid::{admin_backup_push_id, admin_backup_push_id_dry_run},
 		metric::{metric_reconcilliation, set_crawl_block},
 		sync::{
-			cluster_discovery, crawl_sync_events, fetch_keyshares, get_sync_state,
-			parse_block_body, set_sync_state, sync_keyshares, SyncedNFT,
+			cluster_discovery, crawl_sync_events, fetch_keyshares, get_sync_state, is_setup_state,
+			keyshare_count, parse_block_body, set_sync_state, sync_keyshares, CrawlProgress,
+			SyncedNFT,
 		},
 	},
 	server::state::{
-		get_accountid, get_blocknumber, get_chain_rpc_renew, get_identity, get_maintenance,
-		get_nft_availability_map_len, get_nonce, get_processed_block, get_version, reset_nonce,
-		set_blocknumber, set_chain_api, set_chain_api_renew, set_processed_block, SharedState,
-		StateConfig,
+		get_accountid, get_backup_status, get_balance, get_blocknumber, get_chain_rpc_renew,
+		get_crawl_progress, get_identity, get_maintenance, get_maintenance_mode,
+		get_nft_availability_map_len, get_nonce, get_processed_block, get_runtime_status,
+		get_version, reset_nonce, set_admin_quorum, set_balance, set_blocknumber, set_chain_api,
+		set_chain_api_renew, set_enclave_policy, set_finalized_root, set_maintenance_mode,
+		set_ownership_cache, set_processed_block, SharedState, StateConfig,
 	},
 };
 
 use crate::replication::{
-	admin_bulk::{admin_backup_fetch_bulk, admin_backup_push_bulk},
+	admin_bulk::{admin_backup_fetch_bulk, admin_backup_push_bulk, admin_backup_push_bulk_dry_run},
 	admin_nftid::admin_backup_fetch_id,
+	admin_quorum::{
+		approve_proposal_handler, create_proposal_handler, get_proposal_handler,
+		get_quorum_handler, load_quorum_policy, set_quorum_policy_handler,
+	},
+	anti_entropy::{anti_entropy_digests, anti_entropy_entries, spawn_anti_entropy},
+	audit::{audit_log_handler, open_audit_log},
+	backup_schedule::{
+		get_backup_schedule_handler, load_backup_schedule, set_backup_schedule_handler,
+		start_backup_scheduler, BackupStatus,
+	},
+	maintenance::{
+		enforce_maintenance, get_maintenance_handler, load_maintenance_mode, sealed_writes_paused,
+		set_maintenance_handler, MaintenanceMode, MaintenanceState,
+	},
+	reassignment::{purge_confirmation, spawn_reassignment_purge},
+	scheduler::peer_stats_handler,
+	staging::clear_restore_staging,
+	sync_state::{load_sync_state, recover_pending_entries, spawn_sync_retry, sync_state_handler},
+	upgrade::{
+		expire_escrows, load_handover, upgrade_escrow_handler, upgrade_escrowed_handler,
+		upgrade_handover_handler, upgrade_install_handler, upgrade_installed_handler,
+		upgrade_release_handler, upgrade_rollback_handler,
+	},
 };
 
 use super::{server_common, state::get_chain_api};
 
 /// http server app
-pub async fn http_server() -> Result<Router, Error> {
-	let state_config = initialize_enclave_state().await?;
+pub async fn http_server(cache_size: usize) -> Result<Router, Error> {
+	let state_config = initialize_enclave_state(cache_size).await?;
+
+	// Staged files of a restore interrupted by a stop of the enclave are never swapped in
+	if let Err(err) = clear_restore_staging() {
+		warn!("ENCLAVE START : can not clear the restore staging : {err:?}");
+	}
+
+	// A handed over account is owned by the new enclave, this one stays read-only
+	match load_handover() {
+		Ok(Some(handover)) => warn!(
+			"ENCLAVE START : account is handed over to admin enclave {} at block {}",
+			handover.admin_account, handover.block_number
+		),
+		Ok(None) => {},
+		Err(err) => error!("ENCLAVE START : can not read the upgrade handover : {err:?}"),
+	}
+
+	// The schedule and its custodians are set by the admin quorum, never by the host
+	let backup_schedule = match load_backup_schedule() {
+		Ok(schedule) => schedule,
+		Err(err) => {
+			error!("ENCLAVE START : can not read the backup schedule : {err:?}");
+			None
+		},
+	};
+
+	if let Some(schedule) = backup_schedule {
+		info!("ENCLAVE START : start the scheduler of local backups.");
+		if let Err(err) = start_backup_scheduler(Arc::clone(&state_config), Some(schedule)).await {
+			let message = format!("ENCLAVE START : scheduled backups are disabled : {err:?}");
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("scheduled-backup", "start");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+		}
+	}
 
 	info!("ENCLAVE START : define the CORS layer.");
 	let cors_layer = CorsLayer::new()
@@ -98,6 +176,18 @@ pub async fn http_server() -> Result<Router, Error> {
 		.layer(sentry_tower::SentryHttpLayer::with_transaction());
 
 	info!("ENCLAVE START : define the end-points");
+	// Whole keyshare store transfers, a restore or an upgrade outlasts the request timeout
+	let bulk_routes = Router::new()
+		.route("/api/backup/push-bulk", post(admin_backup_push_bulk))
+		.layer(DefaultBodyLimit::max(CONTENT_LENGTH_LIMIT))
+		.route("/api/upgrade/escrow", post(upgrade_escrow_handler))
+		.route("/api/upgrade/install", post(upgrade_install_handler))
+		.layer(
+			ServiceBuilder::new()
+				.layer(HandleErrorLayer::new(handle_timeout_error))
+				.timeout(Duration::from_secs(BULK_REQUEST_TIMEOUT)),
+		);
+
 	let http_app = Router::new()
 		.fallback(fallback)
 		// STATE API
@@ -106,8 +196,9 @@ pub async fn http_server() -> Result<Router, Error> {
 		// CENTRALIZED BACKUP API
 		.route("/api/backup/fetch-id", post(admin_backup_fetch_id))
 		.route("/api/backup/push-id", post(admin_backup_push_id))
+		.route("/api/backup/push-id/dry-run", post(admin_backup_push_id_dry_run))
 		.route("/api/backup/fetch-bulk", post(admin_backup_fetch_bulk))
-		.route("/api/backup/push-bulk", post(admin_backup_push_bulk))
+		.route("/api/backup/push-bulk/dry-run", post(admin_backup_push_bulk_dry_run))
 		.layer(DefaultBodyLimit::max(CONTENT_LENGTH_LIMIT))
 		// NFT SECRET-SHARING API
 		.route("/api/secret-nft/get-views-log/:nft_id", get(nft_get_views))
@@ -123,14 +214,42 @@ pub async fn http_server() -> Result<Router, Error> {
 		.route("/api/capsule-nft/remove-keyshare", post(capsule_remove_keyshare))
 		// SYNCHRONIZATION
 		.route("/api/backup/sync-keyshare", post(sync_keyshares))
+		.route("/api/backup/keyshare-count", post(keyshare_count))
+		.route("/api/backup/anti-entropy/digests", post(anti_entropy_digests))
+		.route("/api/backup/anti-entropy/entries", post(anti_entropy_entries))
+		.route("/api/backup/purge-confirmation", post(purge_confirmation))
+		.route("/api/backup/peer-stats", post(peer_stats_handler))
+		.route("/api/backup/sync-state", post(sync_state_handler))
+		.route("/api/attestation/enclave-policy", get(get_enclave_policy_handler))
+		.route("/api/attestation/set-enclave-policy", post(set_enclave_policy_handler))
+		// ADMIN QUORUM API
+		.route("/api/admin/quorum-policy", get(get_quorum_handler))
+		.route("/api/admin/set-quorum-policy", post(set_quorum_policy_handler))
+		.route("/api/admin/proposal/create", post(create_proposal_handler))
+		.route("/api/admin/proposal/approve", post(approve_proposal_handler))
+		.route("/api/admin/proposal/:proposal_id", get(get_proposal_handler))
+		.route("/api/admin/audit-log", post(audit_log_handler))
+		.route("/api/admin/maintenance", get(get_maintenance_handler))
+		.route("/api/admin/set-maintenance", post(set_maintenance_handler))
+		.route("/api/admin/backup-schedule", get(get_backup_schedule_handler))
+		.route("/api/admin/set-backup-schedule", post(set_backup_schedule_handler))
+		// ENCLAVE UPGRADE API
+		.route("/api/upgrade/handover", post(upgrade_handover_handler))
+		.route("/api/upgrade/escrowed", post(upgrade_escrowed_handler))
+		.route("/api/upgrade/rollback", post(upgrade_rollback_handler))
+		.route("/api/upgrade/release", post(upgrade_release_handler))
+		.route("/api/upgrade/installed", post(upgrade_installed_handler))
 		// METRIC SERVER
 		.route("/api/metric/interval-nft-list", post(metric_reconcilliation))
 		.route("/api/metric/set-crawl-block", post(set_crawl_block))
 		.layer(
 			ServiceBuilder::new()
 				.layer(HandleErrorLayer::new(handle_timeout_error))
-				.timeout(Duration::from_secs(30)),
+				.timeout(Duration::from_secs(REQUEST_TIMEOUT)),
 		)
+		.merge(bulk_routes)
+		// Mode of the enclave is enforced before any handler
+		.route_layer(middleware::from_fn_with_state(Arc::clone(&state_config), enforce_maintenance))
 		.layer(monitor_layer)
 		.layer(cors_layer)
 		.with_state(Arc::clone(&state_config.clone()));
@@ -204,6 +323,26 @@ pub struct HealthResponse {
 	pub version: String,
 	pub description: String,
 	pub enclave_address: String,
+	#[serde(default)]
+	pub runtime_version: u32,
+	#[serde(default)]
+	pub enclave_balance: Option<u128>,
+	// NFTs being fetched, and NFTs waiting for a retry
+	#[serde(default)]
+	pub pending_nfts: u32,
+	#[serde(default)]
+	pub failed_nfts: u32,
+	// Progress and rate of the catch-up crawl
+	#[serde(default)]
+	pub crawl_progress: Option<CrawlProgress>,
+	// Time and result of the last scheduled backup
+	#[serde(default)]
+	pub last_backup: Option<BackupStatus>,
+	// Mode switched by the admins, and its reason
+	#[serde(default)]
+	pub maintenance_mode: MaintenanceMode,
+	#[serde(default)]
+	pub maintenance_reason: String,
 }
 
 /// Health check endpoint
@@ -229,14 +368,20 @@ async fn get_health_status(State(state): State<SharedState>) -> impl IntoRespons
 			let block_number = get_blocknumber(&state).await;
 			let binary_version = get_version(&state).await;
 			let enclave_address = get_accountid(&state).await;
-			let sync_state = match get_sync_state() {
-				Ok(st) => st,
+			let (sync_state, pending_nfts, failed_nfts) = match load_sync_state() {
+				Ok(st) => (st.legacy(), st.pending.len() as u32, st.failed.len() as u32),
 				Err(err) => {
 					error!("Healthcheck handler error : unable to read the sync state");
-					"Unknown".to_string()
+					("Unknown".to_string(), 0, 0)
 				},
 			};
 			let secrets_number = Some(get_nft_availability_map_len(&state).await);
+			let crawl_progress = get_crawl_progress(&state).await;
+			let last_backup = get_backup_status(&state).await;
+			let (runtime_version, _) = get_runtime_status(&state).await;
+			let enclave_balance = get_balance(&state).await;
+			let MaintenanceState { mode: maintenance_mode, reason: maintenance_reason, .. } =
+				get_maintenance_mode(&state).await;
 
 			let chain = if cfg!(feature = "mainnet") {
 				"mainnet".to_string()
@@ -260,6 +405,14 @@ async fn get_health_status(State(state): State<SharedState>) -> impl IntoRespons
 					block_number,
 					version: binary_version,
 					enclave_address,
+					runtime_version,
+					enclave_balance,
+					pending_nfts,
+					failed_nfts,
+					crawl_progress,
+					last_backup,
+					maintenance_mode,
+					maintenance_reason,
 				}),
 			)
 				.into_response()
@@ -281,8 +434,8 @@ async fn evalueate_health_status(
 
 	trace!("Healthcheck : get public key.");
 	// TODO [error handling] : ADD RPC PROBLEM/TIMEOUT
-	let sync_state = match get_sync_state() {
-		Ok(st) => st,
+	let (sync_state, pending_nfts, failed_nfts) = match load_sync_state() {
+		Ok(st) => (st.legacy(), st.pending.len() as u32, st.failed.len() as u32),
 		Err(err) => {
 			error!("Healthcheck : error : unable to get sync state");
 			return None;
@@ -292,8 +445,29 @@ async fn evalueate_health_status(
 	trace!("Healthcheck handler : get availability map");
 	let secrets_number = Some(get_nft_availability_map_len(state).await);
 
+	trace!("Healthcheck handler : get crawl progress");
+	let crawl_progress = get_crawl_progress(state).await;
+
+	trace!("Healthcheck handler : get last backup");
+	let last_backup = get_backup_status(state).await;
+
 	trace!("Healthcheck handler : get maintenance");
 	let maintenance = get_maintenance(state).await;
+	let MaintenanceState { mode: maintenance_mode, reason: maintenance_reason, .. } =
+		get_maintenance_mode(state).await;
+
+	// A full maintenance is reported like a running backup, a read-only enclave stays healthy
+	let maintenance = if maintenance.is_empty() && maintenance_mode == MaintenanceMode::Full {
+		maintenance_reason.clone()
+	} else {
+		maintenance
+	};
+
+	trace!("Healthcheck handler : get runtime status");
+	let (runtime_version, runtime_degraded) = get_runtime_status(state).await;
+
+	trace!("Healthcheck handler : get enclave balance");
+	let enclave_balance = get_balance(state).await;
 
 	let chain = if cfg!(feature = "mainnet") {
 		"mainnet".to_string()
@@ -319,6 +493,38 @@ async fn evalueate_health_status(
 				version: binary_version,
 				description: maintenance,
 				enclave_address,
+				runtime_version,
+				enclave_balance,
+				pending_nfts,
+				failed_nfts,
+				crawl_progress,
+				last_backup,
+				maintenance_mode,
+				maintenance_reason,
+			}),
+		));
+	}
+
+	if !runtime_degraded.is_empty() {
+		trace!("Healthcheck handler : degraded mode");
+		return Some((
+			StatusCode::SERVICE_UNAVAILABLE,
+			Json(HealthResponse {
+				chain,
+				sync_state,
+				secrets_number,
+				block_number,
+				version: binary_version,
+				description: runtime_degraded,
+				enclave_address,
+				runtime_version,
+				enclave_balance,
+				pending_nfts,
+				failed_nfts,
+				crawl_progress,
+				last_backup,
+				maintenance_mode,
+				maintenance_reason,
 			}),
 		));
 	}
@@ -326,9 +532,10 @@ async fn evalueate_health_status(
 	trace!("Healthcheck handler : get sync status");
 	let status = match sync_state.as_str() {
 		"" => StatusCode::PARTIAL_CONTENT,
-		"setup" => StatusCode::RESET_CONTENT,
 		_ =>
-			if sync_state.parse::<u32>().is_ok() {
+			if is_setup_state(&sync_state) {
+				StatusCode::RESET_CONTENT
+			} else if sync_state.parse::<u32>().is_ok() {
 				StatusCode::OK
 			} else {
 				StatusCode::NOT_ACCEPTABLE
@@ -347,10 +554,49 @@ async fn evalueate_health_status(
 			version: binary_version,
 			description: "SGX server is running!".to_string(),
 			enclave_address,
+			runtime_version,
+			enclave_balance,
+			pending_nfts,
+			failed_nfts,
+			crawl_progress,
+			last_backup,
+			maintenance_mode,
+			maintenance_reason,
 		}),
 	))
 }
 
+/* ------------------------------
+	ENCLAVE BALANCE
+------------------------------ */
+
+// Alert once when the enclave balance falls below a threshold
+fn evaluate_balance(previous_balance: u128, free_balance: u128) {
+	if free_balance < MIN_BALANCE_FOR_STORE && previous_balance >= MIN_BALANCE_FOR_STORE {
+		let message = format!(
+			"ENCLAVE BALANCE : {free_balance} is below the minimum {MIN_BALANCE_FOR_STORE}, new keyshares are refused."
+		);
+		error!(message);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("enclave-balance", free_balance.to_string());
+			},
+			|| sentry::capture_message(&message, sentry::Level::Error),
+		);
+	} else if free_balance < LOW_BALANCE_WARNING && previous_balance >= LOW_BALANCE_WARNING {
+		let message = format!(
+			"ENCLAVE BALANCE : {free_balance} is below {LOW_BALANCE_WARNING}, send CAPS to the enclave account."
+		);
+		warn!(message);
+		sentry::with_scope(
+			|scope| {
+				scope.set_tag("enclave-balance", free_balance.to_string());
+			},
+			|| sentry::capture_message(&message, sentry::Level::Warning),
+		);
+	}
+}
+
 /*
 	Initialize the enclave :
 	- Creating/Fetching the confidential keypair
@@ -358,7 +604,7 @@ async fn evalueate_health_status(
 	- Check the synchronization state of secrets from the last start
 */
 
-async fn initialize_enclave_state() -> Result<SharedState, Error> {
+async fn initialize_enclave_state(cache_size: usize) -> Result<SharedState, Error> {
 	// Confidential Keypair of Enclave
 	// The public key part of the keypair is the Identity of enclave i.e for registeration on chain
 	// Also used for signing all communications
@@ -442,10 +688,72 @@ async fn initialize_enclave_state() -> Result<SharedState, Error> {
 		keyshare_list,
 	)));
 
+	// Verified NFT ownership storages, invalidated by finalized block events
+	set_ownership_cache(&state_config, cache_size).await;
+
+	// Allow-list of peer enclave binaries for synchronization
+	match load_enclave_policy() {
+		Ok(Some(policy)) => set_enclave_policy(&state_config, Some(policy)).await,
+		// Upgrades of the same signer are allowed until the admins approve a policy
+		Ok(None) => match seed_enclave_policy() {
+			Ok(policy) => set_enclave_policy(&state_config, Some(policy)).await,
+			Err(err) => warn!("ENCLAVE START : unable to seed the enclave policy : {err:?}"),
+		},
+		Err(err) => warn!("ENCLAVE START : unable to load the enclave policy : {err:?}"),
+	}
+
+	// Quorum of the destructive admin operations
+	match load_quorum_policy() {
+		Ok(policy) => set_admin_quorum(&state_config, policy).await,
+		Err(err) => warn!("ENCLAVE START : unable to load the admin quorum policy : {err:?}"),
+	}
+
+	// Mode switched by the admin quorum, kept across restarts
+	match load_maintenance_mode() {
+		Ok(Some(maintenance)) => {
+			if maintenance.mode != MaintenanceMode::Normal {
+				warn!(
+					"ENCLAVE START : enclave is in {:?} maintenance : {}",
+					maintenance.mode, maintenance.reason
+				);
+			}
+			set_maintenance_mode(&state_config, maintenance).await;
+		},
+		Ok(None) => {},
+		Err(err) => warn!("ENCLAVE START : unable to load the maintenance mode : {err:?}"),
+	}
+
+	// Trail of the privileged requests, a broken chain is reported and the trail continues
+	match open_audit_log() {
+		Ok(head) => info!("ENCLAVE START : admin audit trail has {} entries", head.count),
+		Err(err) => {
+			let message = format!("ENCLAVE START : admin audit trail : {err:?}");
+			error!(message);
+			sentry::with_scope(
+				|scope| {
+					scope.set_tag("admin-audit", "open");
+				},
+				|| sentry::capture_message(&message, sentry::Level::Error),
+			);
+		},
+	}
+
+	// Follow runtime upgrades, an incompatible runtime puts the enclave in degraded mode
+	spawn_runtime_updater(state_config.clone());
+
 	// Update the shared-state with chain block states
 	set_blocknumber(&state_config, current_block_number).await;
 	set_processed_block(&state_config, last_processed_block).await;
 
+	// Enclave account pays for the oracle extrinsics, it is read at the finalized block
+	match get_enclave_balance(&state_config).await {
+		Ok(free_balance) => {
+			set_balance(&state_config, free_balance).await;
+			evaluate_balance(u128::MAX, free_balance);
+		},
+		Err(err) => warn!("ENCLAVE START : unable to get enclave balance : {err:?}"),
+	}
+
 	// Search all of clusters and registered enclaves from the blockchain
 	// Also checks if this enclave has been registered on chain
 	info!("ENCLAVE START : Initialization Cluster Discovery.");
@@ -465,15 +773,18 @@ async fn initialize_enclave_state() -> Result<SharedState, Error> {
 	// The Sync State may have multiple states :
 	// 1- file does not exists : first time enclave starting
 	// 2- file is empty : enclave is not registered on chain
-	// 3- file contains "setup" string : enclave has been stopped during synchronization
+	// 3- file contains "setup" string : enclave has been stopped during synchronization, with the
+	// number of fetched keyshares i.e "setup_1500" to resume the paginated synchronization
 	// 4- file contains a blocknumber : the enclave is synchronized the data up to the blocknumber
 	// that contains NFT
+	// The sealed file is structured, with the phase above and the pending/failed nfts, bare
+	// strings of previous versions are converted
 	info!("ENCLAVE START : check for sync.state file from previous run ...");
 
 	if std::path::Path::new(&SYNC_STATE_FILE).exists() {
 		debug!("ENCLAVE START : previous sync.state file exists");
 		// Resuming enclave
-		let past_state = match std::fs::read_to_string(SYNC_STATE_FILE) {
+		let past_state = match get_sync_state() {
 			Ok(state) => state,
 			Err(err) => {
 				error!("ENCLAVE START : Error reading enclave's last state file: {err:?}");
@@ -483,9 +794,20 @@ async fn initialize_enclave_state() -> Result<SharedState, Error> {
 
 		debug!("ENCLAVE START : previous sync.state file content : '{}'", past_state);
 
+		// Keyshares being fetched when the enclave stopped are retried by the retry worker
+		match recover_pending_entries() {
+			Ok(0) => {},
+			Ok(count) => info!(
+				"ENCLAVE START : {count} interrupted nft synchronizations are queued for retry"
+			),
+			Err(err) => warn!(
+				"ENCLAVE START : can not recover the pending nfts of the sync state : {err:?}"
+			),
+		}
+
 		if !past_state.is_empty() {
 			debug!("ENCLAVE START : previous sync.state is not empty ...");
-			if past_state == "setup" {
+			if is_setup_state(&past_state) {
 				debug!("ENCLAVE START : SETUP-MODE : fetching keyshares ...");
 				// Th enclave has been stopped at the middle of fetching data from another enclave
 				// Do it again!
@@ -536,6 +858,19 @@ async fn initialize_enclave_state() -> Result<SharedState, Error> {
 					synced_block_number
 				);
 
+				// An interrupted crawl resumes from its checkpoint, the nfts it has found are
+				// queued for retry
+				let synced_block_number = match load_sync_state() {
+					Ok(sync_state) if sync_state.resume_block() > synced_block_number => {
+						info!(
+							"ENCLAVE START : RUNTIME-MODE : resume the crawl from checkpoint {}",
+							sync_state.resume_block()
+						);
+						sync_state.resume_block()
+					},
+					_ => synced_block_number,
+				};
+
 				// Retry if syncing failed
 				for _sync_retry in 0..RETRY_COUNT {
 					let current_block_hash = chain_api.rpc().finalized_head().await?;
@@ -646,6 +981,40 @@ async fn subscribe_block_events(state_config: SharedState) {
 
 	// New thread to track latest block
 	tokio::spawn(async move {
+		// GRANDPA verification of finalized headers, anchored at the pinned checkpoint
+		// Storage proofs are refused until a state-root is verified
+		let justifications: JustificationStore = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+		spawn_justification_listener(state_config.clone(), justifications.clone());
+
+		let mut finality = None;
+		for retry in 0..RETRY_COUNT {
+			match FinalityVerifier::new(&chain_api, justifications.clone()).await {
+				Ok(verifier) => {
+					finality = Some(verifier);
+					break;
+				},
+				Err(err) => {
+					error!("-- Subscription Task : Unable to initialize GRANDPA verifier, retry {retry} : {err:?}");
+					std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()));
+				},
+			}
+		}
+
+		let mut finality = match finality {
+			Some(verifier) => verifier,
+			None => {
+				let message = "-- Subscription Task : GRANDPA verifier is not available, block tracking stopped".to_string();
+				error!(message);
+				sentry::capture_message(&message, sentry::Level::Error);
+				return;
+			},
+		};
+
+		// The checkpoint is trusted, its state-root serves storage proofs until the next
+		// verification
+		let (anchor_number, anchor_hash, anchor_root) = finality.last_verified();
+		set_finalized_root(&state_config, anchor_number, anchor_hash, anchor_root).await;
+
 		// Subscribe to all finalized blocks:
 		let mut blocks_sub = match chain_api.blocks().subscribe_finalized().await {
 			Ok(sub) => sub,
@@ -736,8 +1105,60 @@ async fn subscribe_block_events(state_config: SharedState) {
 
 			let block_number = block.header().number;
 
+			// The header must extend the tracked finalized chain
+			let chain_api = get_chain_api(&state_config).await;
+			let (last_imported_number, _) = finality.last_imported();
+			match finality.import_header(&chain_api, block.header()).await {
+				Ok(true) =>
+					if block_number > last_imported_number + 1 {
+						// Events of the walked back headers are not evaluated
+						clear_ownership_cache(&state_config, block_number).await;
+					} else {
+						invalidate_ownership_cache(&state_config, &block).await;
+					},
+				Ok(false) => {
+					debug!("-- Subscription Task : block {block_number} is already imported");
+					continue;
+				},
+				Err(err) => {
+					error!("-- Subscription Task : Unable to import finalized header {err:?}");
+					sentry::capture_message(
+						format!("Subscription Task : Unable to import finalized header {err:?}")
+							.as_str(),
+						sentry::Level::Error,
+					);
+					set_chain_api_renew(&state_config, true).await;
+					continue;
+				},
+			}
+
+			// Enclave time and state-root only move to GRANDPA verified headers.
 			// Write to ShareState block, necessary to prevent Read SharedState
-			set_blocknumber(&state_config, block_number).await;
+			match finality.try_finalize(&chain_api).await {
+				Ok(Some((verified_number, verified_hash, state_root))) => {
+					set_blocknumber(&state_config, verified_number).await;
+					// Storage proofs of ownership are verified against this finalized state-root
+					set_finalized_root(&state_config, verified_number, verified_hash, state_root)
+						.await;
+					prune_ownership_cache(&state_config, verified_number).await;
+				},
+				Ok(None) =>
+					if finality.verified_elapsed().as_secs() > MAX_FINALITY_LAG {
+						let message = format!(
+							"-- Subscription Task : No verified justification since block {}",
+							finality.last_verified().0
+						);
+						warn!(message);
+						sentry::capture_message(&message, sentry::Level::Warning);
+					},
+				Err(err) => {
+					error!("-- Subscription Task : GRANDPA verification failed {err:?}");
+					sentry::capture_message(
+						format!("Subscription Task : GRANDPA verification failed {err:?}").as_str(),
+						sentry::Level::Error,
+					);
+				},
+			}
 
 			// For block number update, we should reset the nonce as well
 			// It is used as a batch of extrinsics for every block
@@ -753,6 +1174,37 @@ async fn subscribe_block_events(state_config: SharedState) {
 				get_nonce(&state_config).await
 			);
 
+			// Enclave account pays for the oracle extrinsics
+			match get_enclave_balance(&state_config).await {
+				Ok(free_balance) => {
+					let previous_balance = get_balance(&state_config).await.unwrap_or(u128::MAX);
+					set_balance(&state_config, free_balance).await;
+					evaluate_balance(previous_balance, free_balance);
+				},
+				Err(err) => warn!("-- Subscription Task : unable to get enclave balance : {err:?}"),
+			}
+
+			// Events, crawls and the synced block only move to GRANDPA verified headers,
+			// blocks skipped before their verification are crawled with the next verified one
+			let (block_number, verified_hash, _) = finality.last_verified();
+			if block_number <= get_processed_block(&state_config).await {
+				trace!("-- Subscription Task : block {block_number} is already processed");
+				continue;
+			}
+
+			let block = if block.hash() == verified_hash {
+				block
+			} else {
+				match chain_api.blocks().at(verified_hash).await {
+					Ok(verified_block) => verified_block,
+					Err(err) => {
+						warn!("-- Subscription Task : Unable to get verified block {block_number} : {err:?}");
+						set_chain_api_renew(&state_config, true).await;
+						continue;
+					},
+				}
+			};
+
 			// Extract block body
 			let body = match block.body().await {
 				Ok(body) => {
@@ -771,6 +1223,21 @@ async fn subscribe_block_events(state_config: SharedState) {
 
 			let storage_api = block.storage();
 
+			// Block bodies are not parsed with an incompatible runtime,
+			// missed blocks are crawled when the enclave leaves the degraded mode
+			let (_, runtime_degraded) = get_runtime_status(&state_config).await;
+			if !runtime_degraded.is_empty() {
+				warn!("-- Subscription Task : Degraded mode, block {block_number} is not parsed");
+				continue;
+			}
+
+			// Keyshares are not written during a restore or a maintenance,
+			// missed blocks are crawled when the enclave leaves it
+			if let Some(reason) = sealed_writes_paused(&state_config).await {
+				debug!("-- Subscription Task : block {block_number} is not parsed, {reason}");
+				continue;
+			}
+
 			let (new_nft, is_tee_events) =
 				match parse_block_body(&state_config, block_number, body, &storage_api).await {
 					Ok(tuple) => {
@@ -798,7 +1265,7 @@ async fn subscribe_block_events(state_config: SharedState) {
 							},
 						};
 
-						if sync_state == "setup" {
+						if is_setup_state(&sync_state) {
 							// Here is Identity discovery, thus the first synchronization of all
 							// files. An empty HashMap is the wildcard signal to fetch all keyshares
 							// from nearby enclave
@@ -848,24 +1315,25 @@ async fn subscribe_block_events(state_config: SharedState) {
 					block_number
 				);
 
-				for _retry in 0..RETRY_COUNT {
-					match fetch_keyshares(&state_config.clone(), &new_nft).await {
-						Ok(_) => {
-							let _ = set_sync_state(block_number.to_string());
+				// A single pass, the nfts that are not received are retried in the background
+				match fetch_keyshares(&state_config.clone(), &new_nft).await {
+					Ok(outcome) => {
+						let _ = set_sync_state(block_number.to_string());
+						if outcome.pending.is_empty() {
 							debug!("\t-- Subscription Task : NEW-NFT : Synchronization of Keyshares complete.");
-							break;
-						},
-						Err(err) => {
-							error!("\t-- Subscription Task : NEW-NFT : Error during running-mode nft-based syncing : {err:?}");
-							debug!("\t-- Subscription Task : NEW-NFT : wait before retry");
-							std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()));
-						},
-					} // FETCH
-				} // RETRY FETCH
+						} else {
+							warn!(
+								"\t-- Subscription Task : NEW-NFT : nfts {:?} are queued for retry",
+								outcome.pending
+							);
+						}
+					},
+					Err(err) => {
+						error!("\t-- Subscription Task : NEW-NFT : Error during running-mode nft-based syncing : {err:?}");
+					},
+				} // FETCH
 			}
 
-			// TODO? : Regular check to use Indexer/Dictionary for missing NFTs?! (with any reason)
-
 			// Read from sync file
 			let sync_state = match get_sync_state() {
 				Ok(st) => st,
@@ -893,31 +1361,28 @@ async fn subscribe_block_events(state_config: SharedState) {
 							);
 
 							if !cluster_nft_map.is_empty() {
-								for _retry in 0..RETRY_COUNT {
-									match fetch_keyshares(&state_config.clone(), &cluster_nft_map)
-										.await
-									{
-										Ok(_) => {
-											info!("\t-- Subscription Task : Crawl check : Success runtime-mode fetching crawled blocks from {} to {} .", last_processed_block, block_number);
-											let _ = set_sync_state(block_number.to_string());
-											break;
-										},
+								// A single pass, the nfts that are not received are retried in the
+								// background
+								match fetch_keyshares(&state_config.clone(), &cluster_nft_map).await
+								{
+									Ok(outcome) => {
+										info!("\t-- Subscription Task : Crawl check : Success runtime-mode fetching crawled blocks from {} to {} .", last_processed_block, block_number);
+										if !outcome.pending.is_empty() {
+											warn!("\t-- Subscription Task : Crawl check : nfts {:?} are queued for retry", outcome.pending);
+										}
+										let _ = set_sync_state(block_number.to_string());
+									},
 
-										Err(err) => {
-											error!(
-												"\t-- Subscription Task : Crawl check : Error during running-mode nft-based syncing : {:?}",
-												err
-											);
-											// We can not proceed to next nft-based sync.
-											// Because it'll update the syncing state
-											// A retry id needed in next block
-											debug!("\t-- Subscription Task : Crawl check : Fetch Keyshares : wait before retry");
-											std::thread::sleep(std::time::Duration::from_secs(
-												RETRY_DELAY.into(),
-											));
-										},
-									} //Fetch
-								} //Retry Fetch
+									Err(err) => {
+										// We can not proceed to next nft-based sync.
+										// Because it'll update the syncing state
+										// A retry id needed in next block
+										error!(
+											"\t-- Subscription Task : Crawl check : Error during running-mode nft-based syncing : {:?}",
+											err
+										);
+									},
+								} //Fetch
 							} else {
 								debug!("\t-- Subscription Task : Crawl check : no new event detected in past blocks");
 								let _ = set_sync_state(last_processed_block.to_string());
@@ -932,8 +1397,6 @@ async fn subscribe_block_events(state_config: SharedState) {
 							// We can not proceed to next nft-based sync.
 							// Because it'll update the syncing state
 							// A retry id needed in next block
-							debug!("\t-- Subscription Task : Crawl check : wait before retry");
-							std::thread::sleep(std::time::Duration::from_secs(RETRY_DELAY.into()));
 							continue;
 						},
 					} // EVENTS CRAWLER
@@ -952,6 +1415,22 @@ async fn subscribe_block_events(state_config: SharedState) {
 				continue;
 			}
 
+			// Anti-entropy : missed events are recovered from the enclaves of the same slot
+			if block_number % ANTI_ENTROPY_INTERVAL == 0 {
+				spawn_anti_entropy(state_config.clone());
+			}
+
+			// Failed nfts are retried in the background, they do not hold the synced block back
+			if block_number % SYNC_RETRY_INTERVAL == 0 {
+				spawn_sync_retry(state_config.clone());
+				spawn_reassignment_purge(state_config.clone());
+
+				// Escrowed accounts whose install is never confirmed
+				if let Err(err) = expire_escrows(block_number) {
+					warn!("\t-- Subscription Task : escrow expiry failed : {err}");
+				}
+			}
+
 			// Update runtime block tracking variable
 			trace!("\t-- Subscription Task : update last processed block");
 			set_processed_block(&state_config, block_number).await;
@@ -1,11 +1,27 @@
-use std::{collections::BTreeMap, sync::Arc};
-use subxt::{ext::sp_core::sr25519, tx::PairSigner};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::{Arc, Mutex},
+};
+use subxt::{
+	ext::sp_core::{sr25519, H256},
+	tx::PairSigner,
+};
 
 use tokio::sync::RwLock;
 
 use crate::{
-	core::{chain::DefaultApi, helper},
-	replication::sync::Cluster,
+	attestation::policy::EnclavePolicy,
+	core::{
+		chain::{DefaultApi, OwnershipCache},
+		helper,
+	},
+	replication::{
+		admin_quorum::{AdminProposal, AdminQuorumPolicy},
+		backup_schedule::BackupStatus,
+		maintenance::MaintenanceState,
+		scheduler::PeerStats,
+		sync::{Cluster, CrawlProgress},
+	},
 };
 
 pub type SharedState = Arc<RwLock<StateConfig>>;
@@ -20,6 +36,12 @@ pub struct StateConfig {
 	enclave_signer: PairSigner<subxt::PolkadotConfig, sr25519::Pair>,
 	// If enclave is in maintenance mode, this field will contain a proper description
 	maintenance: String,
+	// Mode switched by the admin quorum, enforced on every route
+	maintenance_mode: MaintenanceState,
+	// Spec version of the chain runtime, followed by the runtime updater
+	spec_version: u32,
+	// If the chain runtime is incompatible, this field will contain a proper description
+	runtime_degraded: String,
 	// RPC connection to the blockchain public node
 	rpc_client: DefaultApi,
 	// If the RPC connection is lost, this flag is set to activate reconn
@@ -28,12 +50,32 @@ pub struct StateConfig {
 	current_block: u32,
 	// Nonce for Enclave transactions
 	nonce: u64,
+	// Free balance of the enclave account, it pays for the oracle extrinsics, None until it is
+	// read
+	enclave_balance: Option<u128>,
 	// Map of registered clusters on chain
 	clusters: Vec<Cluster>,
 	// Identity of enclave is a tuple: (ClusterID, SlotID)
 	identity: Option<(u32, u32)>,
 	binary_version: String,
 	last_processed_block: u32,
+	// Progress of the running crawl, None if the enclave is not catching up
+	crawl_progress: Option<CrawlProgress>,
+	// Last scheduled local backup, None if no backup has run since the start
+	backup_status: Option<BackupStatus>,
+	// Number, hash and state-root of the last finalized header, storage proofs are checked
+	// against it
+	finalized_root: Option<(u32, H256, H256)>,
+	// Cache of verified NFT ownership storages, None if the cache is disabled
+	ownership_cache: Option<Arc<Mutex<OwnershipCache>>>,
+	// Local statistics of the peer enclaves, by enclave account
+	peer_stats: BTreeMap<String, PeerStats>,
+	// Allow-list of peer enclave binaries, None if admins have not set a policy
+	enclave_policy: Option<EnclavePolicy>,
+	// Quorum of the destructive admin operations, None for a majority of the chain admins
+	admin_quorum: Option<AdminQuorumPolicy>,
+	// Proposals of destructive admin operations waiting for approvals, by proposal id
+	admin_proposals: BTreeMap<String, AdminProposal>,
 	// Hashmap of all the NFTs stored on the current enclave, and their kind
 	// (Secret/Capsule/Hybrid)
 	nft_block_map: BTreeMap<u32, helper::Availability>,
@@ -60,11 +102,23 @@ impl StateConfig {
 			enclave_account: public_key,
 			enclave_signer: PairSigner::new(enclave_key),
 			maintenance,
+			maintenance_mode: MaintenanceState::default(),
+			spec_version: 0,
+			runtime_degraded: String::new(),
 			rpc_client,
 			rpc_renew: false,
 			current_block: 0,
 			last_processed_block: 0,
+			crawl_progress: None,
+			backup_status: None,
+			finalized_root: None,
+			ownership_cache: None,
+			enclave_policy: None,
+			admin_quorum: None,
+			admin_proposals: BTreeMap::new(),
+			peer_stats: BTreeMap::new(),
 			nonce: 0,
+			enclave_balance: None,
 			clusters: Vec::<Cluster>::new(),
 			identity: None,
 			binary_version,
@@ -107,6 +161,24 @@ impl StateConfig {
 		self.maintenance = message;
 	}
 
+	pub fn get_maintenance_mode(&self) -> MaintenanceState {
+		self.maintenance_mode.clone()
+	}
+
+	pub fn set_maintenance_mode(&mut self, maintenance: MaintenanceState) {
+		self.maintenance_mode = maintenance;
+	}
+
+	pub fn get_runtime_status(&self) -> (u32, String) {
+		// Tuple : (SpecVersion, Degraded description)
+		(self.spec_version, self.runtime_degraded.clone())
+	}
+
+	pub fn set_runtime_status(&mut self, spec_version: u32, degraded: String) {
+		self.spec_version = spec_version;
+		self.runtime_degraded = degraded;
+	}
+
 	pub fn get_rpc_client(&self) -> DefaultApi {
 		self.rpc_client.clone()
 	}
@@ -139,6 +211,44 @@ impl StateConfig {
 		self.last_processed_block
 	}
 
+	pub fn set_crawl_progress(&mut self, progress: Option<CrawlProgress>) {
+		self.crawl_progress = progress;
+	}
+
+	pub fn get_crawl_progress(&self) -> Option<CrawlProgress> {
+		self.crawl_progress.clone()
+	}
+
+	pub fn set_backup_status(&mut self, status: Option<BackupStatus>) {
+		self.backup_status = status;
+	}
+
+	pub fn get_backup_status(&self) -> Option<BackupStatus> {
+		self.backup_status.clone()
+	}
+
+	pub fn set_finalized_root(&mut self, block_number: u32, block_hash: H256, state_root: H256) {
+		self.finalized_root = Some((block_number, block_hash, state_root));
+	}
+
+	pub fn get_finalized_root(&self) -> Option<(u32, H256, H256)> {
+		// Tuple : (BlockNumber, BlockHash, StateRoot)
+		self.finalized_root
+	}
+
+	pub fn get_ownership_cache(&self) -> Option<Arc<Mutex<OwnershipCache>>> {
+		self.ownership_cache.clone()
+	}
+
+	pub fn set_ownership_cache(&mut self, cache_size: usize) {
+		// Zero size disables the cache
+		self.ownership_cache = if cache_size > 0 {
+			Some(Arc::new(Mutex::new(OwnershipCache::new(cache_size))))
+		} else {
+			None
+		};
+	}
+
 	pub fn get_nonce(&self) -> u64 {
 		self.nonce
 	}
@@ -155,6 +265,14 @@ impl StateConfig {
 		};
 	}
 
+	pub fn get_balance(&self) -> Option<u128> {
+		self.enclave_balance
+	}
+
+	pub fn set_balance(&mut self, free_balance: u128) {
+		self.enclave_balance = Some(free_balance);
+	}
+
 	pub fn get_binary_version(&self) -> String {
 		self.binary_version.clone()
 	}
@@ -177,6 +295,66 @@ impl StateConfig {
 		self.identity = identity;
 	}
 
+	pub fn get_peer_stats(&self) -> BTreeMap<String, PeerStats> {
+		self.peer_stats.clone()
+	}
+
+	pub fn update_peer_stats<F: FnOnce(&mut PeerStats)>(
+		&mut self,
+		enclave_account: String,
+		update: F,
+	) {
+		update(self.peer_stats.entry(enclave_account).or_default());
+	}
+
+	pub fn get_enclave_policy(&self) -> Option<EnclavePolicy> {
+		self.enclave_policy.clone()
+	}
+
+	pub fn set_enclave_policy(&mut self, policy: Option<EnclavePolicy>) {
+		self.enclave_policy = policy;
+	}
+
+	pub fn get_admin_quorum(&self) -> Option<AdminQuorumPolicy> {
+		self.admin_quorum.clone()
+	}
+
+	pub fn set_admin_quorum(&mut self, policy: Option<AdminQuorumPolicy>) {
+		self.admin_quorum = policy;
+	}
+
+	pub fn get_admin_proposal(&self, proposal_id: &str) -> Option<AdminProposal> {
+		self.admin_proposals.get(proposal_id).cloned()
+	}
+
+	pub fn get_admin_proposal_count(&self) -> usize {
+		self.admin_proposals.len()
+	}
+
+	pub fn insert_admin_proposal(&mut self, proposal: AdminProposal) {
+		self.admin_proposals.insert(proposal.id.clone(), proposal);
+	}
+
+	pub fn remove_admin_proposal(&mut self, proposal_id: &str) -> Option<AdminProposal> {
+		self.admin_proposals.remove(proposal_id)
+	}
+
+	pub fn remove_expired_admin_proposals(&mut self, block_number: u32) {
+		self.admin_proposals.retain(|_, proposal| proposal.expiry_block >= block_number);
+	}
+
+	// Approvals are added in place, concurrent approvals of a proposal are all kept
+	pub fn add_admin_approval(
+		&mut self,
+		proposal_id: &str,
+		admin_account: String,
+		signature: String,
+	) -> Option<AdminProposal> {
+		let proposal = self.admin_proposals.get_mut(proposal_id)?;
+		proposal.approvals.insert(admin_account, signature);
+		Some(proposal.clone())
+	}
+
 	pub fn get_nft_availability(&self, nftid: u32) -> Option<&helper::Availability> {
 		tracing::trace!("\nAVAILABILITY : LOW LEVEL : GET : MAP : {:#?}", self.nft_block_map);
 		self.nft_block_map.get(&nftid)
@@ -186,6 +364,26 @@ impl StateConfig {
 		self.nft_block_map.clone()
 	}
 
+	pub fn get_nft_availability_range(
+		&self,
+		range: std::ops::RangeInclusive<u32>,
+	) -> BTreeMap<u32, helper::Availability> {
+		self.nft_block_map.range(range).map(|(nftid, av)| (*nftid, *av)).collect()
+	}
+
+	pub fn get_nft_changed_since(&self, block_number: u32) -> BTreeSet<u32> {
+		// Availability block is the last change of the nft keyshares
+		self.nft_block_map
+			.iter()
+			.filter(|(_, av)| av.block_number > block_number)
+			.map(|(nftid, _)| *nftid)
+			.collect()
+	}
+
+	pub fn nft_availability_map(&self) -> &BTreeMap<u32, helper::Availability> {
+		&self.nft_block_map
+	}
+
 	pub fn get_nft_availability_map_len(&self) -> u32 {
 		self.nft_block_map.len() as u32
 	}
@@ -255,6 +453,11 @@ pub async fn get_nonce(state: &SharedState) -> u64 {
 	shared_state_read.get_nonce()
 }
 
+pub async fn get_balance(state: &SharedState) -> Option<u128> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_balance()
+}
+
 pub async fn get_chain_rpc_renew(state: &SharedState) -> bool {
 	let shared_state_read = state.read().await;
 	shared_state_read.get_rpc_renew()
@@ -285,21 +488,94 @@ pub async fn get_processed_block(state: &SharedState) -> u32 {
 	shared_state_read.get_processed_block()
 }
 
+pub async fn get_crawl_progress(state: &SharedState) -> Option<CrawlProgress> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_crawl_progress()
+}
+
+pub async fn get_backup_status(state: &SharedState) -> Option<BackupStatus> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_backup_status()
+}
+
+pub async fn get_finalized_root(state: &SharedState) -> Option<(u32, H256, H256)> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_finalized_root()
+}
+
+pub async fn get_ownership_cache(state: &SharedState) -> Option<Arc<Mutex<OwnershipCache>>> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_ownership_cache()
+}
+
+pub async fn get_peer_stats(state: &SharedState) -> BTreeMap<String, PeerStats> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_peer_stats()
+}
+
+pub async fn get_enclave_policy(state: &SharedState) -> Option<EnclavePolicy> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_enclave_policy()
+}
+
+pub async fn get_admin_quorum(state: &SharedState) -> Option<AdminQuorumPolicy> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_admin_quorum()
+}
+
+pub async fn get_admin_proposal(state: &SharedState, proposal_id: &str) -> Option<AdminProposal> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_admin_proposal(proposal_id)
+}
+
+pub async fn get_admin_proposal_count(state: &SharedState) -> usize {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_admin_proposal_count()
+}
+
 pub async fn get_maintenance(state: &SharedState) -> String {
 	let shared_state_read = state.read().await;
 	shared_state_read.get_maintenance()
 }
 
+pub async fn get_maintenance_mode(state: &SharedState) -> MaintenanceState {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_maintenance_mode()
+}
+
+pub async fn get_runtime_status(state: &SharedState) -> (u32, String) {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_runtime_status()
+}
+
 pub async fn get_nft_availability(state: &SharedState, nftid: u32) -> Option<helper::Availability> {
 	let shared_state_read = state.read().await;
 	shared_state_read.get_nft_availability(nftid).copied()
 }
 
+pub async fn get_nft_availability_map(state: &SharedState) -> BTreeMap<u32, helper::Availability> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_nft_availability_map()
+}
+
 pub async fn get_nft_availability_map_len(state: &SharedState) -> u32 {
 	let shared_state_read = state.read().await;
 	shared_state_read.get_nft_availability_map_len()
 }
 
+pub async fn get_nft_availability_range(
+	state: &SharedState,
+	range: std::ops::RangeInclusive<u32>,
+) -> BTreeMap<u32, helper::Availability> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_nft_availability_range(range)
+}
+
+pub async fn get_nft_changed_since(state: &SharedState, block_number: u32) -> BTreeSet<u32> {
+	let shared_state_read = state.read().await;
+	shared_state_read.get_nft_changed_since(block_number)
+}
+
 /* ---------------
  WRITE HELPERS
 ----------------*/
@@ -314,6 +590,88 @@ pub async fn set_processed_block(state: &SharedState, block_number: u32) {
 	shared_state_write.set_processed_block(block_number);
 }
 
+pub async fn set_crawl_progress(state: &SharedState, progress: Option<CrawlProgress>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_crawl_progress(progress);
+}
+
+pub async fn set_backup_status(state: &SharedState, status: Option<BackupStatus>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_backup_status(status);
+}
+
+pub async fn set_finalized_root(
+	state: &SharedState,
+	block_number: u32,
+	block_hash: H256,
+	state_root: H256,
+) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_finalized_root(block_number, block_hash, state_root);
+}
+
+pub async fn set_ownership_cache(state: &SharedState, cache_size: usize) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_ownership_cache(cache_size);
+}
+
+pub async fn update_peer_stats<F: FnOnce(&mut PeerStats)>(
+	state: &SharedState,
+	enclave_account: String,
+	update: F,
+) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.update_peer_stats(enclave_account, update);
+}
+
+pub async fn set_enclave_policy(state: &SharedState, policy: Option<EnclavePolicy>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_enclave_policy(policy);
+}
+
+pub async fn set_admin_quorum(state: &SharedState, policy: Option<AdminQuorumPolicy>) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_admin_quorum(policy);
+}
+
+pub async fn set_maintenance_mode(state: &SharedState, maintenance: MaintenanceState) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_maintenance_mode(maintenance);
+}
+
+pub async fn insert_admin_proposal(state: &SharedState, proposal: AdminProposal) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.insert_admin_proposal(proposal);
+}
+
+pub async fn remove_admin_proposal(
+	state: &SharedState,
+	proposal_id: &str,
+) -> Option<AdminProposal> {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.remove_admin_proposal(proposal_id)
+}
+
+pub async fn remove_expired_admin_proposals(state: &SharedState, block_number: u32) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.remove_expired_admin_proposals(block_number);
+}
+
+pub async fn add_admin_approval(
+	state: &SharedState,
+	proposal_id: &str,
+	admin_account: String,
+	signature: String,
+) -> Option<AdminProposal> {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.add_admin_approval(proposal_id, admin_account, signature)
+}
+
+pub async fn set_runtime_status(state: &SharedState, spec_version: u32, degraded: String) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_runtime_status(spec_version, degraded);
+}
+
 pub async fn set_keypair(state: &SharedState, keypair: sr25519::Pair) {
 	let shared_state_write = &mut state.write().await;
 	shared_state_write.set_key(keypair);
@@ -329,6 +687,11 @@ pub async fn reset_nonce(state: &SharedState) {
 	shared_state_write.reset_nonce().await;
 }
 
+pub async fn set_balance(state: &SharedState, free_balance: u128) {
+	let shared_state_write = &mut state.write().await;
+	shared_state_write.set_balance(free_balance);
+}
+
 pub async fn set_clusters(state: &SharedState, clusters: Vec<Cluster>) {
 	let shared_state_write = &mut state.write().await;
 	shared_state_write.set_clusters(clusters);
@@ -0,0 +1,132 @@
+#!/usr/bin/env python3
+# Recorded DCAP fixture of the dcap.rs tests : a test PCK chain, its CRLs and an SGX v3 quote
+# The chain mirrors the Intel one (Root CA -> Processor CA -> PCK) with test keys.
+import datetime, hashlib, json, os, struct
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.hazmat.primitives.asymmetric.utils import decode_dss_signature
+from cryptography.x509.oid import NameOID
+
+OUT = os.path.dirname(os.path.abspath(__file__))
+NOT_BEFORE = datetime.datetime(2024, 1, 1, tzinfo=datetime.timezone.utc)
+NOT_AFTER = datetime.datetime(2034, 1, 1, tzinfo=datetime.timezone.utc)
+FMSPC = bytes.fromhex("00906ED50000")
+INTEL_QE_MRSIGNER = bytes.fromhex("8c4f5775d796503e96137f77c68a829a0056ac8ded70140b081b094490c57bff")
+
+
+def der(tag, content):
+    length = len(content)
+    if length < 0x80:
+        return bytes([tag, length]) + content
+    encoded = length.to_bytes((length.bit_length() + 7) // 8, "big")
+    return bytes([tag, 0x80 | len(encoded)]) + encoded + content
+
+
+def oid(dotted):
+    parts = [int(part) for part in dotted.split(".")]
+    body = bytes([parts[0] * 40 + parts[1]])
+    for part in parts[2:]:
+        chunk = [part & 0x7F]
+        part >>= 7
+        while part:
+            chunk.insert(0, 0x80 | (part & 0x7F))
+            part >>= 7
+        body += bytes(chunk)
+    return der(0x06, body)
+
+
+def entry(dotted, value):
+    return der(0x30, oid(dotted) + value)
+
+
+def sgx_extension(svn, pce_svn):
+    tcb = b"".join(entry(f"1.2.840.113741.1.13.1.2.{i}", der(0x02, bytes([svn]))) for i in range(1, 17))
+    tcb += entry("1.2.840.113741.1.13.1.2.17", der(0x02, bytes([pce_svn])))
+    tcb += entry("1.2.840.113741.1.13.1.2.18", der(0x04, bytes([svn] * 16)))
+    return der(0x30, entry("1.2.840.113741.1.13.1.1", der(0x04, bytes(16))) +
+               entry("1.2.840.113741.1.13.1.2", der(0x30, tcb)) +
+               entry("1.2.840.113741.1.13.1.4", der(0x04, FMSPC)))
+
+
+def name(common_name):
+    return x509.Name([x509.NameAttribute(NameOID.COMMON_NAME, common_name),
+                      x509.NameAttribute(NameOID.ORGANIZATION_NAME, "Test SGX")])
+
+
+def certificate(subject, key, issuer, issuer_key, serial, ca, extension=None):
+    builder = (x509.CertificateBuilder().subject_name(subject).issuer_name(issuer)
+               .public_key(key.public_key()).serial_number(serial)
+               .not_valid_before(NOT_BEFORE).not_valid_after(NOT_AFTER)
+               .add_extension(x509.BasicConstraints(ca=ca, path_length=None), critical=True))
+    if extension:
+        builder = builder.add_extension(
+            x509.UnrecognizedExtension(x509.ObjectIdentifier("1.2.840.113741.1.13.1"), extension),
+            critical=False)
+    return builder.sign(issuer_key, hashes.SHA256())
+
+
+def crl(issuer, issuer_key, revoked):
+    builder = (x509.CertificateRevocationListBuilder().issuer_name(issuer)
+               .last_update(NOT_BEFORE).next_update(NOT_AFTER))
+    for serial in revoked:
+        builder = builder.add_revoked_certificate(
+            x509.RevokedCertificateBuilder().serial_number(serial).revocation_date(NOT_BEFORE).build())
+    return builder.sign(issuer_key, hashes.SHA256()).public_bytes(serialization.Encoding.PEM).decode()
+
+
+def raw_signature(key, message):
+    r, s = decode_dss_signature(key.sign(message, ec.ECDSA(hashes.SHA256())))
+    return r.to_bytes(32, "big") + s.to_bytes(32, "big")
+
+
+def report_body(mr_enclave, mr_signer, prod_id, svn, report_data):
+    body = bytearray(384)
+    body[48] = 0x05  # INIT | MODE64BIT, not debug
+    body[64:96] = mr_enclave
+    body[128:160] = mr_signer
+    body[256:260] = struct.pack("<HH", prod_id, svn)
+    body[320:384] = report_data
+    return bytes(body)
+
+
+root_key, processor_key, pck_key, attestation_key = (ec.generate_private_key(ec.SECP256R1()) for _ in range(4))
+root_name, processor_name = name("Test SGX Root CA"), name("Test SGX PCK Processor CA")
+
+root = certificate(root_name, root_key, root_name, root_key, 1, True)
+processor = certificate(processor_name, processor_key, root_name, root_key, 2, True)
+pck = certificate(name("Test SGX PCK Certificate"), pck_key, processor_name, processor_key, 0x5C4, False,
+                  sgx_extension(svn=7, pce_svn=13))
+
+# Quote v3 : header, enclave report, ECDSA signature data
+header = struct.pack("<HHIHH", 3, 2, 0, 8, 13) + bytes.fromhex("939a7233f79c4ca9940a0db3957f0607") + bytes(20)
+isv_report = report_body(bytes([0xE1] * 32), bytes([0x5D] * 32), 12, 103, bytes(range(64)))
+
+attestation_public = attestation_key.public_key().public_bytes(
+    serialization.Encoding.X962, serialization.PublicFormat.UncompressedPoint)[1:]
+qe_auth = bytes(range(32))
+qe_report = report_body(bytes([0x0E] * 32), INTEL_QE_MRSIGNER, 1, 8,
+                        hashlib.sha256(attestation_public + qe_auth).digest() + bytes(32))
+
+chain = b"".join(cert.public_bytes(serialization.Encoding.PEM) for cert in (pck, processor, root)) + b"\0"
+signature_data = (raw_signature(attestation_key, header + isv_report) + attestation_public + qe_report +
+                  raw_signature(pck_key, qe_report) + struct.pack("<H", len(qe_auth)) + qe_auth +
+                  struct.pack("<HI", 5, len(chain)) + chain)
+quote = header + isv_report + struct.pack("<I", len(signature_data)) + signature_data
+
+level = lambda svn, pcesvn, status: {
+    "tcb": {"sgxtcbcomponents": [{"svn": svn}] * 16, "pcesvn": pcesvn}, "tcbStatus": status}
+collateral = {
+    "pck_crls": [crl(root_name, root_key, []), crl(processor_name, processor_key, [])],
+    "tcb_info": [{"fmspc": FMSPC.hex().upper(), "tcbLevels": [level(7, 13, "UpToDate"), level(5, 11, "OutOfDate")]}],
+}
+
+with open(os.path.join(OUT, "quote.dat"), "wb") as file:
+    file.write(quote)
+with open(os.path.join(OUT, "root_ca.der"), "wb") as file:
+    file.write(root.public_bytes(serialization.Encoding.DER))
+with open(os.path.join(OUT, "collateral.json"), "w") as file:
+    json.dump(collateral, file, indent=2)
+with open(os.path.join(OUT, "revoked_pck_crl.pem"), "w") as file:
+    file.write(crl(processor_name, processor_key, [pck.serial_number]))